@@ -0,0 +1,516 @@
+## Navigation
+
+nav-start = Start
+nav-persons = List Persons
+nav-genres = List Genres
+nav-partitions = List Partitions
+nav-ensembles = Ensembles
+nav-loans = Loans
+nav-jobs = Jobs
+nav-admin = Administration
+nav-account = My account
+nav-logout = Log out
+nav-about = About
+
+## Page titles
+
+title-start = Start
+title-error = Error!
+title-search = Partition search
+title-persons = Persons
+title-genres = Genres
+title-partitions = Partitions
+title-about = About ...
+title-login = Log in
+title-admin = Administration
+title-signup = Sign up
+title-account = My account
+title-password = Forgotten password
+title-ensembles = Ensembles
+title-loans = Inter-library loans
+title-jobs = Background jobs
+title-job = Job #{ $id }
+title-webhooks = Webhooks
+title-webhook = Webhook #{ $id }
+title-shared = Shared catalogue: { $name }
+
+## Messages
+
+flash-success = Success
+flash-info = Information
+flash-warning = Warning
+flash-error = Error
+msg-db-access-failed = Fail to access database.
+msg-login-ok = You are logged in.
+msg-login-failed = Wrong email address or password.
+msg-login-required = Please log in to access this page.
+msg-logout-ok = You are logged out.
+msg-login-unverified = Please confirm your address first with the link you received by email.
+msg-login-awaiting-approval = Your account is still waiting for an administrator's approval.
+msg-signup-email-invalid = This email address is not valid.
+msg-password-short = The password must be at least { $min } characters long.
+msg-password-mismatch = The two passwords are not the same.
+msg-signup-check-mail = A confirmation link was sent to your address: open it to activate the account.
+msg-signup-failed = The signup failed, please try again later.
+msg-signup-link-invalid = This confirmation link is not valid or has expired.
+msg-signup-verified = Your address is confirmed, you can log in.
+msg-signup-awaiting-approval = Your address is confirmed; an administrator still has to approve the account.
+msg-user-approved = Account { $email } approved.
+msg-user-rejected = Account { $email } rejected and deleted.
+msg-user-not-awaiting = This account is not waiting for approval.
+msg-user-approve-failed = This account could not be changed.
+msg-account-saved = Your account is saved.
+msg-account-failed = The account could not be saved.
+msg-account-password-wrong = The current password is wrong.
+msg-account-password-changed = Your password is changed.
+msg-reset-check-mail = If this address has an account, a link to change the password was just sent to it.
+msg-reset-failed = The reset failed, please try again later.
+msg-reset-link-invalid = This link is not valid or has expired: ask for a new one.
+msg-reset-done = Your password is changed, you can log in.
+msg-token-created = The token is created.
+msg-token-name-missing = Give the token a name.
+msg-token-unknown = This token does not exist.
+msg-token-revoked = The token is revoked.
+msg-token-failed = The token could not be saved.
+msg-ensemble-chosen = You are now working in the "{ $name }" library.
+msg-ensemble-not-allowed = You are not a member of this ensemble.
+msg-ensemble-name-missing = Enter the name of the ensemble.
+msg-ensemble-added = The ensemble "{ $name }" is created.
+msg-ensemble-exists = The ensemble "{ $name }" already exists.
+msg-ensemble-add-failed = The ensemble could not be created.
+msg-member-unknown = No account uses the address { $email }.
+msg-member-already = { $email } is already a member of this ensemble.
+msg-member-added = { $email } is now a member of the ensemble.
+msg-member-removed = The member is removed from the ensemble.
+msg-loan-requested = Loan request for "{ $title }" sent to { $name }.
+msg-loan-not-shared = This partition is not shared with your library.
+msg-loan-already = Your library already has an open request for this partition.
+msg-loan-accepted = Loan of "{ $title }" to { $name } accepted.
+msg-loan-declined = Loan of "{ $title }" to { $name } declined.
+msg-loan-returned = "{ $title }" was returned by { $name }.
+msg-loan-unknown = This request does not await this answer from your library.
+msg-loan-failed = The loan request could not be saved.
+msg-member-failed = The members of the ensemble could not be changed.
+msg-restore-failed = The restore failed, nothing was changed in the database.
+msg-restore-ok = Restore complete: { $persons } person(s), { $genres } genre(s), { $partitions } partition(s), { $users } user(s) and { $files } file(s) added; { $existing } row(s) already present.
+msg-job-cancelled = Job #{ $id } cancelled.
+msg-job-not-cancelled = Job #{ $id } is already over.
+msg-reindex-queued = The search indexes are being rebuilt (job #{ $id }).
+msg-reindex-failed = Unable to start rebuilding the search indexes.
+msg-webhook-created = The subscription is created.
+msg-webhook-modified = The subscription is modified.
+msg-webhook-deleted = The subscription is deleted.
+msg-webhook-unknown = This subscription does not exist.
+msg-webhook-url-invalid = The address must be an "http://" or "https://" URL, without a user name.
+msg-webhook-events-missing = Choose at least one event.
+msg-webhook-test-queued = A "ping" event is about to be sent; the result shows in the log.
+msg-webhook-test-failed = The test event could not be sent.
+msg-search-results = { $count ->
+        [0] No partition found.
+        [one] One partition found.
+       *[other] { $count } partitions found.
+    }
+msg-person-added = Person successfully added.
+msg-person-modified = Person successfully modified.
+msg-person-deleted = Person successfully deleted.
+msg-person-delete-failed = Failed to delete person.
+msg-person-add-failed = Failed to add person.
+msg-person-modify-failed = Failed to modify person.
+msg-genre-added = Genre successfully added.
+msg-genre-modified = Genre successfully modified.
+msg-genre-deleted = Genre successfully deleted.
+msg-genre-delete-failed = Failed to delete genre.
+msg-genre-add-failed = Failed to add genre.
+msg-genre-modify-failed = Failed to modify genre.
+msg-partition-added = Partition successfully added.
+msg-partition-modified = Partition successfully modified.
+msg-partition-deleted = Partition successfully deleted.
+msg-partition-delete-failed = Failed to delete partition.
+msg-partition-add-failed = Failed to add partition.
+msg-partition-modify-failed = Failed to modify partition.
+msg-person-exists = The musician "{ $name }" already exists: here it is.
+msg-genre-exists = The genre "{ $name }" already exists: here it is.
+msg-genre-common = Only an administrator can change or delete a genre shared by all ensembles.
+msg-partition-exists = This composer already has a partition "{ $name }": here it is.
+msg-bulk-none = No partition selected.
+msg-bulk-value-missing = Enter the genre, the composer or the tag.
+msg-bulk-genre = { $count ->
+        [0] No partition changed.
+        [one] One partition moved to genre "{ $name }".
+       *[other] { $count } partitions moved to genre "{ $name }".
+    }
+msg-bulk-composer = { $count ->
+        [0] No partition changed.
+        [one] One partition assigned to { $name }.
+       *[other] { $count } partitions assigned to { $name }.
+    }
+msg-bulk-tag = { $count ->
+        [0] The partitions already had the tag "{ $name }".
+        [one] Tag "{ $name }" added to one partition.
+       *[other] Tag "{ $name }" added to { $count } partitions.
+    }
+msg-bulk-deleted = { $count ->
+        [0] No partition deleted.
+        [one] One partition deleted.
+       *[other] { $count } partitions deleted.
+    }
+msg-bulk-shared = { $count ->
+        [0] No partition shared.
+        [one] One partition shared with the other ensembles.
+       *[other] { $count } partitions shared with the other ensembles.
+    }
+msg-bulk-unshared = { $count ->
+        [0] No partition changed.
+        [one] One partition is no longer shared.
+       *[other] { $count } partitions are no longer shared.
+    }
+msg-bulk-conflict = { $name } already has a partition with the same title: nothing was changed.
+msg-bulk-failed = The action on the partitions failed: nothing was changed.
+msg-unknown-person = Unknown musician: "{ $name }".
+msg-unknown-person-suggest = Unknown musician: "{ $name }". Did you mean { $suggestions }?
+msg-unknown-genre = Unknown genre: "{ $name }".
+msg-unknown-genre-suggest = Unknown genre: "{ $name }". Did you mean { $suggestions }?
+msg-import-ok = Partition "{ $title }" imported from the MusicXML file.
+msg-import-not-musicxml = "{ $name }" is not a MusicXML file (.musicxml, .xml or .mxl).
+msg-import-unreadable = The file "{ $name }" could not be read as a MusicXML score.
+msg-import-no-composer = The file does not name the composer: enter it in the form.
+msg-import-failed = Unable to import the MusicXML file.
+msg-file-added = File "{ $name }" added to the partition.
+msg-file-not-supported = "{ $name }" is neither a PDF, an image nor a MusicXML file.
+msg-file-failed = Unable to add the file.
+msg-incipit-saved = Incipit saved.
+msg-incipit-removed = Incipit removed.
+msg-incipit-invalid = Unreadable incipit ({ $error }): write the opening bars in ABC, for example "K:G GABc d2 B2 |".
+msg-incipit-failed = Unable to save the incipit.
+
+## Common forms
+
+btn-add = Add
+btn-find = Search
+btn-reset = Clear search
+btn-modify = Modify
+btn-delete = Delete
+btn-login = Log in
+btn-download = Download
+btn-restore = Restore
+btn-apply = Apply
+btn-signup = Create the account
+btn-approve = Approve
+btn-reject = Reject
+btn-save = Save
+btn-change-password = Change the password
+btn-send-link = Send the link
+btn-create-token = Create the token
+btn-revoke = Revoke
+btn-choose = Choose
+btn-remove = Remove
+btn-add-member = Add member
+btn-create-ensemble = Create ensemble
+btn-share = Share
+btn-unshare = Stop sharing
+btn-borrow = Request a loan
+btn-accept = Accept
+btn-decline = Decline
+btn-returned = Returned
+btn-import = Import
+btn-add-file = Add
+btn-cancel = Cancel
+btn-reindex = Rebuild
+btn-test = Test
+
+## Start page
+
+start-have-account = You already have a user account:
+start-login = Login
+start-no-account = You don't have a user account yet, create one:
+start-signup = Sign Up
+start-loans-pending = { $count ->
+        [one] One loan request awaits your answer.
+       *[other] { $count } loan requests await your answer.
+    }
+
+## Statistics (start page)
+
+stats-title = The catalogue in figures
+stats-partitions = { $count ->
+        [0] No partition
+        [one] One partition
+       *[other] { $count } partitions
+    }
+stats-persons = { $count ->
+        [0] No musician
+        [one] One musician
+       *[other] { $count } musicians
+    }
+stats-genres = { $count ->
+        [0] No genre
+        [one] One genre
+       *[other] { $count } genres
+    }
+stats-by-genre = Partitions by genre
+stats-by-person = Most represented composers
+stats-most-borrowed = Most borrowed partitions
+stats-growth = Catalogue growth
+stats-recent = Recent additions
+stats-empty = No data yet.
+
+## Persons
+
+persons-add = Add a person:
+persons-add-placeholder = enter full name ...
+persons-find = Find partitions by a person:
+persons-find-placeholder = Enter the person's name ...
+persons-list = Persons
+persons-full-name = Full name
+
+## Genres
+
+genres-add = Add a genre:
+genres-add-placeholder = enter genre ...
+genres-find = Find partitions in a genre:
+genres-find-placeholder = Enter the genre to find ...
+genres-list = Genres
+genres-type = Type
+genres-private = only for "{ $name }"
+genres-private-mark = (private)
+
+## Partitions
+
+partitions-add = Add a partition:
+partitions-title-label = enter the title:
+partitions-title-placeholder = enter title ...
+partitions-musician-label = choose musician:
+partitions-musician-choose = type the musician's name ...
+partitions-genre-label = choose genre:
+partitions-genre-choose = type the genre ...
+partitions-create-missing = create the musician or genre if missing
+partitions-import = Import a MusicXML file:
+partitions-import-file-label = .musicxml or .mxl file:
+partitions-import-from-file = read from the file ...
+partitions-find = Find a partition:
+partitions-find-title = Enter the title ...
+partitions-find-author = Enter the author ...
+partitions-find-genre = Enter the genre ...
+partitions-find-melody = Melody in ABC (GABc) ...
+partitions-list = Partitions
+bulk-change-genre = Change genre
+bulk-change-composer = Change composer
+bulk-add-tag = Add a tag
+bulk-export = Export as CSV
+bulk-share = Share with the other ensembles
+bulk-unshare = Stop sharing
+bulk-delete = Delete
+bulk-value-placeholder = genre, composer or tag ...
+partitions-print = Print the list of partitions:
+btn-print = Print
+
+## Detail pages
+
+detail-title = Title
+detail-composer = Composer
+detail-genre = Genre
+detail-tags = Tags
+detail-creator = { $role ->
+        [lyricist] Lyricist
+        [poet] Poet
+        [arranger] Arranger
+        [translator] Translator
+        [composer] Composer
+       *[other] { $role }
+    }
+detail-instrumentation = Instrumentation
+detail-key = Key
+detail-time = Time signature
+detail-files = Files
+detail-files-help = PDF, image or MusicXML: the thumbnail of the first page appears shortly after the upload.
+detail-incipit = Incipit
+detail-incipit-placeholder = opening bars in ABC: K:G GABc d2 B2 |
+detail-shared = Sharing
+detail-shared-yes = Shown to the other ensembles, who can borrow it.
+detail-shared-no = Kept to this library.
+detail-related = Other partitions by { $name }
+detail-no-related = No other partition by this composer.
+detail-person-partitions = Partitions by { $name }
+detail-genre-partitions = Partitions in genre { $name }
+detail-no-partitions = No partition.
+detail-back-partitions = Back to the list of partitions
+detail-back-persons = Back to the list of persons
+detail-back-genres = Back to the list of genres
+btn-details = Details
+
+## About
+
+about-heading = Website managing a database of Persons
+about-author = Written by Léon GENGOUX with Rocket and Postgresql
+
+## Login
+
+login-email = Email address:
+login-password = Password:
+login-forgot = Forgot your password?
+signup-confirm = Confirm the password:
+signup-password-hint = At least { $min } characters.
+
+## Account
+
+account-profile = Profile
+account-display-name = Display name:
+account-locale = Language:
+account-locale-browser = The browser's
+account-password = Password
+account-current-password = Current password:
+account-new-password = New password:
+account-api-tokens = API tokens
+account-api-tokens-text = For scripts: the request carries the token in the "Authorization: Bearer …" header (see /api/persons, /api/genres, /api/partitions).
+account-token-copy = Copy this token now, it will not be shown again:
+account-token-name = Name
+account-token-scope = Rights
+account-token-created = Created
+account-token-expires = Expiry
+account-token-used = Last used
+account-token-ensemble = Library
+account-token-for = The new token will act on the "{ $name }" library.
+account-token-never = Never
+account-token-days = in { $days } days
+account-scope-read = Read only
+account-scope-write = Read and add to the catalogue
+password-forgot-text = Enter your account's address: you will receive a link to choose a new password.
+
+## Administration
+
+admin-logged-as = Logged in as { $email }.
+admin-backup = Catalogue backup
+admin-backup-text = A zip archive with every table and the uploaded files.
+admin-restore = Restore
+admin-restore-text = Rows already present are kept, the others are added with new numbers.
+admin-reindex = Search indexes
+admin-reindex-text = Recomputes the search keys of the names and rebuilds the indexes, in the background.
+admin-webhooks = Webhooks
+admin-webhooks-text = The sites subscribed to the changes of the chosen library's catalogue.
+admin-awaiting = Accounts awaiting approval
+admin-awaiting-text = These addresses are confirmed and waiting for your approval.
+admin-ensembles = Ensembles
+admin-ensembles-text = Each ensemble has its own library; its members only see its partitions. Composers and common genres are shared.
+admin-ensemble-name = Name of the new ensemble
+admin-member-email = Member's address
+
+## Mails
+
+mail-verify-subject = Confirm your address
+mail-verify-body =
+    Hello,
+
+    To activate your account on the score catalogue, open this link:
+    { $link }
+
+    The link is valid for { $hours } hours. If you did not ask for an account,
+    ignore this message.
+mail-approved-subject = Your account is approved
+mail-approved-body =
+    Hello,
+
+    Your account { $email } is approved, you can log in:
+    { $link }
+mail-reset-subject = New password
+mail-reset-body =
+    Hello,
+
+    To choose a new password, open this link:
+    { $link }
+
+    The link is valid for { $hours ->
+        [one] one hour
+       *[other] { $hours } hours
+    } and works only once. If you did not ask for anything, ignore this message:
+    your password does not change.
+
+## Errors
+
+error-404-heading = 404: Hey! There's nothing here.
+error-404-text = The page at address { $path } does not exist!
+
+## Ensembles
+
+ensemble-current = Library: { $name }
+ensemble-change = (change)
+ensembles-text = The libraries you can browse.
+ensembles-chosen = current library
+
+## Loans
+
+loans-libraries = Shared catalogues of the other ensembles
+loans-shared-count = { $count ->
+        [one] one partition
+       *[other] { $count } partitions
+    }
+loans-no-libraries = No other ensemble shares partitions yet.
+loans-received = Requests received
+loans-sent = Requests sent
+loans-none = No request.
+loans-borrower = Borrower
+loans-owner = Library
+loans-message = Message
+loans-date = Date
+loans-status = Status
+loan-status-pending = pending
+loan-status-accepted = on loan
+loan-status-declined = declined
+loan-status-returned = returned
+shared-text = The partitions this ensemble shares; loan requests are made on behalf of the { $name } library.
+shared-empty = This ensemble shares no partition with your library.
+shared-message-placeholder = Message (dates, number of copies ...)
+shared-back = Back to loans
+jobs-list = Background jobs of the library
+jobs-none = No job.
+jobs-kind = Job
+jobs-created = Created
+jobs-status = Status
+jobs-attempts = Attempts
+jobs-error = Last error
+jobs-finished = Finished
+jobs-run-at = Scheduled
+jobs-retry-at = next attempt at { $date }
+jobs-payload = Data
+jobs-back = Back to the jobs
+job-kind-thumbnail = File thumbnail
+job-kind-reindex = Search indexes rebuild
+job-kind-webhook = Webhook delivery
+job-status-pending = pending
+job-status-running = running
+job-status-done = done
+job-status-failed = failed
+job-status-cancelled = cancelled
+webhooks-list = Subscriptions of the library
+webhooks-none = No subscription.
+webhooks-text = Each chosen change is sent as a POST (JSON) to the address, signed with the secret (X-Webhook-Signature header: HMAC-SHA256 of the body).
+webhooks-new = New subscription
+webhooks-url = Address
+webhooks-secret = Secret
+webhooks-secret-help = Empty: a random secret is drawn.
+webhooks-secret-keep = Empty: the secret does not change.
+webhooks-events = Events
+webhooks-active = Active
+webhooks-suspended = suspended
+webhooks-created = Created
+webhooks-deliveries = Delivery log
+webhooks-deliveries-none = No delivery.
+webhooks-event = Event
+webhooks-status = Status
+webhooks-attempts = Attempts
+webhooks-response = Response
+webhooks-error = Error
+webhooks-job = Job
+webhooks-back = Back to the subscriptions
+webhook-event-partition-created = partition added
+webhook-event-partition-updated = partition modified
+webhook-event-partition-deleted = partition deleted
+webhook-event-person-created = person added
+webhook-event-person-updated = person modified
+webhook-event-person-deleted = person deleted
+webhook-event-genre-created = genre added
+webhook-event-genre-updated = genre modified
+webhook-event-genre-deleted = genre deleted
+webhook-event-ping = test
+delivery-status-pending = pending
+delivery-status-delivered = received
+delivery-status-failed = failed
@@ -0,0 +1,516 @@
+## Navigation
+
+nav-start = Accueil
+nav-persons = Personnes
+nav-genres = Genres
+nav-partitions = Partitions
+nav-ensembles = Ensembles
+nav-loans = Prêts
+nav-jobs = Tâches
+nav-admin = Administration
+nav-account = Mon compte
+nav-logout = Déconnexion
+nav-about = A propos
+
+## Titres des pages
+
+title-start = Accueil
+title-error = Erreur !
+title-search = Recherche de partitions
+title-persons = Liste des Personnes
+title-genres = Liste des Genres
+title-partitions = Liste des Partitions
+title-about = A propos de ...
+title-login = Connexion
+title-admin = Administration
+title-signup = Créer un compte
+title-account = Mon compte
+title-password = Mot de passe oublié
+title-ensembles = Ensembles
+title-loans = Prêts entre bibliothèques
+title-jobs = Tâches de fond
+title-job = Tâche n° { $id }
+title-webhooks = Webhooks
+title-webhook = Webhook n° { $id }
+title-shared = Catalogue partagé : { $name }
+
+## Messages
+
+flash-success = Succès
+flash-info = Information
+flash-warning = Attention
+flash-error = Erreur
+msg-db-access-failed = Impossible d'accéder à la base de données.
+msg-login-ok = Vous êtes connecté.
+msg-login-failed = Adresse ou mot de passe incorrect.
+msg-login-required = Veuillez vous connecter pour accéder à cette page.
+msg-logout-ok = Vous êtes déconnecté.
+msg-login-unverified = Confirmez d'abord votre adresse avec le lien reçu par mail.
+msg-login-awaiting-approval = Votre compte attend encore l'accord d'un administrateur.
+msg-signup-email-invalid = Cette adresse e-mail n'est pas valide.
+msg-password-short = Le mot de passe doit faire au moins { $min } caractères.
+msg-password-mismatch = Les deux mots de passe ne sont pas identiques.
+msg-signup-check-mail = Un lien de confirmation a été envoyé à votre adresse : ouvrez-le pour activer le compte.
+msg-signup-failed = L'inscription a échoué, veuillez réessayer plus tard.
+msg-signup-link-invalid = Ce lien de confirmation n'est pas valide ou a expiré.
+msg-signup-verified = Votre adresse est confirmée, vous pouvez vous connecter.
+msg-signup-awaiting-approval = Votre adresse est confirmée ; un administrateur doit encore accepter le compte.
+msg-user-approved = Le compte { $email } est accepté.
+msg-user-rejected = Le compte { $email } est refusé et supprimé.
+msg-user-not-awaiting = Ce compte n'attend pas d'acceptation.
+msg-user-approve-failed = Impossible de modifier ce compte.
+msg-account-saved = Votre compte est enregistré.
+msg-account-failed = Impossible d'enregistrer le compte.
+msg-account-password-wrong = Le mot de passe actuel est incorrect.
+msg-account-password-changed = Votre mot de passe est changé.
+msg-reset-check-mail = Si cette adresse a un compte, un lien pour changer le mot de passe vient d'y être envoyé.
+msg-reset-failed = La réinitialisation a échoué, veuillez réessayer plus tard.
+msg-reset-link-invalid = Ce lien n'est pas valide ou a expiré : demandez-en un nouveau.
+msg-reset-done = Votre mot de passe est changé, vous pouvez vous connecter.
+msg-token-created = Le jeton est créé.
+msg-token-name-missing = Donnez un nom au jeton.
+msg-token-unknown = Ce jeton n'existe pas.
+msg-token-revoked = Le jeton est révoqué.
+msg-token-failed = Impossible d'enregistrer le jeton.
+msg-ensemble-chosen = Vous travaillez maintenant dans la bibliothèque « { $name } ».
+msg-ensemble-not-allowed = Vous ne faites pas partie de cet ensemble.
+msg-ensemble-name-missing = Indiquez le nom de l'ensemble.
+msg-ensemble-added = L'ensemble « { $name } » est créé.
+msg-ensemble-exists = L'ensemble « { $name } » existe déjà.
+msg-ensemble-add-failed = L'ensemble n'a pas pu être créé.
+msg-member-unknown = Aucun compte n'utilise l'adresse { $email }.
+msg-member-already = { $email } fait déjà partie de cet ensemble.
+msg-member-added = { $email } fait maintenant partie de l'ensemble.
+msg-member-removed = Le membre est retiré de l'ensemble.
+msg-loan-requested = Demande de prêt de « { $title } » envoyée à { $name }.
+msg-loan-not-shared = Cette partition n'est pas partagée avec votre bibliothèque.
+msg-loan-already = Votre bibliothèque a déjà une demande en cours pour cette partition.
+msg-loan-accepted = Prêt de « { $title } » à { $name } accepté.
+msg-loan-declined = Prêt de « { $title } » à { $name } refusé.
+msg-loan-returned = « { $title } » est rendue par { $name }.
+msg-loan-unknown = Cette demande n'attend pas cette réponse de votre bibliothèque.
+msg-loan-failed = La demande de prêt n'a pas pu être enregistrée.
+msg-member-failed = Les membres de l'ensemble n'ont pas pu être modifiés.
+msg-restore-failed = La restauration a échoué, rien n'a été modifié dans la base.
+msg-restore-ok = Restauration terminée : { $persons } personne(s), { $genres } genre(s), { $partitions } partition(s), { $users } utilisateur(s) et { $files } fichier(s) ajoutés ; { $existing } ligne(s) déjà présente(s).
+msg-job-cancelled = La tâche n° { $id } est annulée.
+msg-job-not-cancelled = La tâche n° { $id } est déjà finie.
+msg-reindex-queued = La reconstruction des index est lancée (tâche n° { $id }).
+msg-reindex-failed = La reconstruction des index n'a pas pu être lancée.
+msg-webhook-created = L'abonnement est créé.
+msg-webhook-modified = L'abonnement est modifié.
+msg-webhook-deleted = L'abonnement est supprimé.
+msg-webhook-unknown = Cet abonnement n'existe pas.
+msg-webhook-url-invalid = L'adresse doit être une URL « http:// » ou « https:// », sans nom d'utilisateur.
+msg-webhook-events-missing = Choisissez au moins un événement.
+msg-webhook-test-queued = Un événement « ping » va être envoyé ; le résultat s'affiche dans le journal.
+msg-webhook-test-failed = L'événement de test n'a pas pu être envoyé.
+msg-search-results = { $count ->
+        [0] Aucune partition trouvée.
+        [one] Une partition trouvée.
+       *[other] { $count } partitions trouvées.
+    }
+msg-person-added = Personne ajoutée.
+msg-person-modified = Personne modifiée.
+msg-person-deleted = Personne effacée.
+msg-person-delete-failed = Impossible d'effacer la personne.
+msg-person-add-failed = Impossible d'ajouter la personne.
+msg-person-modify-failed = Impossible de modifier la personne.
+msg-genre-added = Genre ajouté.
+msg-genre-modified = Genre modifié.
+msg-genre-deleted = Genre effacé.
+msg-genre-delete-failed = Impossible d'effacer le genre.
+msg-genre-add-failed = Impossible d'ajouter le genre.
+msg-genre-modify-failed = Impossible de modifier le genre.
+msg-partition-added = Partition ajoutée.
+msg-partition-modified = Partition modifiée.
+msg-partition-deleted = Partition effacée.
+msg-partition-delete-failed = Impossible d'effacer la partition.
+msg-partition-add-failed = Impossible d'ajouter la partition.
+msg-partition-modify-failed = Impossible de modifier la partition.
+msg-person-exists = Le musicien « { $name } » existe déjà : le voici.
+msg-genre-exists = Le genre « { $name } » existe déjà : le voici.
+msg-genre-common = Seul un administrateur peut modifier ou supprimer un genre commun à tous les ensembles.
+msg-partition-exists = Ce compositeur a déjà une partition « { $name } » : la voici.
+msg-bulk-none = Aucune partition cochée.
+msg-bulk-value-missing = Indiquez le genre, le compositeur ou l'étiquette.
+msg-bulk-genre = { $count ->
+        [0] Aucune partition modifiée.
+        [one] Une partition passée dans le genre « { $name } ».
+       *[other] { $count } partitions passées dans le genre « { $name } ».
+    }
+msg-bulk-composer = { $count ->
+        [0] Aucune partition modifiée.
+        [one] Une partition attribuée à { $name }.
+       *[other] { $count } partitions attribuées à { $name }.
+    }
+msg-bulk-tag = { $count ->
+        [0] Les partitions portaient déjà l'étiquette « { $name } ».
+        [one] Étiquette « { $name } » ajoutée à une partition.
+       *[other] Étiquette « { $name } » ajoutée à { $count } partitions.
+    }
+msg-bulk-deleted = { $count ->
+        [0] Aucune partition supprimée.
+        [one] Une partition supprimée.
+       *[other] { $count } partitions supprimées.
+    }
+msg-bulk-shared = { $count ->
+        [0] Aucune partition partagée.
+        [one] Une partition partagée avec les autres ensembles.
+       *[other] { $count } partitions partagées avec les autres ensembles.
+    }
+msg-bulk-unshared = { $count ->
+        [0] Aucune partition modifiée.
+        [one] Une partition n'est plus partagée.
+       *[other] { $count } partitions ne sont plus partagées.
+    }
+msg-bulk-conflict = { $name } a déjà une partition de même titre : rien n'a été modifié.
+msg-bulk-failed = L'action sur les partitions a échoué : rien n'a été modifié.
+msg-unknown-person = Musicien inconnu : « { $name } ».
+msg-unknown-person-suggest = Musicien inconnu : « { $name } ». Vouliez-vous dire { $suggestions } ?
+msg-unknown-genre = Genre inconnu : « { $name } ».
+msg-unknown-genre-suggest = Genre inconnu : « { $name } ». Vouliez-vous dire { $suggestions } ?
+msg-import-ok = Partition « { $title } » importée du fichier MusicXML.
+msg-import-not-musicxml = « { $name } » n'est pas un fichier MusicXML (.musicxml, .xml ou .mxl).
+msg-import-unreadable = Le fichier « { $name } » n'a pas pu être lu comme une partition MusicXML.
+msg-import-no-composer = Le fichier ne nomme pas le compositeur : indiquez-le dans le formulaire.
+msg-import-failed = Impossible d'importer le fichier MusicXML.
+msg-file-added = Fichier « { $name } » ajouté à la partition.
+msg-file-not-supported = « { $name } » n'est ni un PDF, ni une image, ni un fichier MusicXML.
+msg-file-failed = Impossible d'ajouter le fichier.
+msg-incipit-saved = Incipit enregistré.
+msg-incipit-removed = Incipit effacé.
+msg-incipit-invalid = Incipit illisible ({ $error }) : notez les premières mesures en ABC, par exemple « K:G GABc d2 B2 | ».
+msg-incipit-failed = Impossible d'enregistrer l'incipit.
+
+## Formulaires communs
+
+btn-add = Ajouter
+btn-find = Chercher
+btn-reset = Effacer la recherche
+btn-modify = Modifier
+btn-delete = Effacer
+btn-login = Se connecter
+btn-download = Télécharger
+btn-restore = Restaurer
+btn-apply = Appliquer
+btn-signup = Créer le compte
+btn-approve = Accepter
+btn-reject = Refuser
+btn-save = Enregistrer
+btn-change-password = Changer le mot de passe
+btn-send-link = Envoyer le lien
+btn-create-token = Créer le jeton
+btn-revoke = Révoquer
+btn-choose = Choisir
+btn-remove = Retirer
+btn-add-member = Ajouter le membre
+btn-create-ensemble = Créer l'ensemble
+btn-share = Partager
+btn-unshare = Ne plus partager
+btn-borrow = Demander le prêt
+btn-accept = Accepter
+btn-decline = Refuser
+btn-returned = Rendue
+btn-import = Importer
+btn-add-file = Ajouter
+btn-cancel = Annuler
+btn-reindex = Reconstruire
+btn-test = Tester
+
+## Page d'accueil
+
+start-have-account = Vous disposez d'un compte utilisateur :
+start-login = Connexion
+start-no-account = Vous n'avez pas de compte utilisateur, créez-en un :
+start-signup = Créer un compte
+start-loans-pending = { $count ->
+        [one] Une demande de prêt attend votre réponse.
+       *[other] { $count } demandes de prêt attendent votre réponse.
+    }
+
+## Statistiques (page d'accueil)
+
+stats-title = Le catalogue en chiffres
+stats-partitions = { $count ->
+        [0] Aucune partition
+        [one] Une partition
+       *[other] { $count } partitions
+    }
+stats-persons = { $count ->
+        [0] Aucun musicien
+        [one] Un musicien
+       *[other] { $count } musiciens
+    }
+stats-genres = { $count ->
+        [0] Aucun genre
+        [one] Un genre
+       *[other] { $count } genres
+    }
+stats-by-genre = Partitions par genre
+stats-by-person = Les compositeurs les plus représentés
+stats-most-borrowed = Les partitions les plus prêtées
+stats-growth = Évolution du catalogue
+stats-recent = Derniers ajouts
+stats-empty = Pas encore de données.
+
+## Personnes
+
+persons-add = Ajouter une Personne :
+persons-add-placeholder = entrer nom et prénom ...
+persons-find = Chercher les partitions d'une personne :
+persons-find-placeholder = Entrer le nom de la personne ...
+persons-list = Liste des Personnes
+persons-full-name = Nom et Prénom
+
+## Genres
+
+genres-add = Ajouter un Genre :
+genres-add-placeholder = entrer genre ...
+genres-find = Chercher les partitions d'un genre :
+genres-find-placeholder = Entrer le genre à chercher ...
+genres-list = Liste des Genres
+genres-type = Type
+genres-private = réservé à « { $name } »
+genres-private-mark = (réservé)
+
+## Partitions
+
+partitions-add = Ajouter une Partition :
+partitions-title-label = entrer le titre :
+partitions-title-placeholder = entrer titre ...
+partitions-musician-label = choisir musicien :
+partitions-musician-choose = entrer le nom du musicien ...
+partitions-genre-label = choisir genre :
+partitions-genre-choose = entrer le genre ...
+partitions-create-missing = créer le musicien ou le genre s'il n'existe pas
+partitions-import = Importer un fichier MusicXML :
+partitions-import-file-label = fichier .musicxml ou .mxl :
+partitions-import-from-file = lu dans le fichier ...
+partitions-find = Chercher une partition :
+partitions-find-title = Entrer le titre ...
+partitions-find-author = Entrer l'auteur ...
+partitions-find-genre = Entrer le genre ...
+partitions-find-melody = Mélodie en ABC (GABc) ...
+partitions-list = Liste des Partitions
+bulk-change-genre = Changer le genre
+bulk-change-composer = Changer le compositeur
+bulk-add-tag = Ajouter une étiquette
+bulk-export = Exporter en CSV
+bulk-share = Partager avec les autres ensembles
+bulk-unshare = Ne plus partager
+bulk-delete = Supprimer
+bulk-value-placeholder = genre, compositeur ou étiquette ...
+partitions-print = Imprimer la liste des partitions :
+btn-print = Imprimer
+
+## Pages de détail
+
+detail-title = Titre
+detail-composer = Compositeur
+detail-genre = Genre
+detail-tags = Étiquettes
+detail-creator = { $role ->
+        [lyricist] Parolier
+        [poet] Poète
+        [arranger] Arrangeur
+        [translator] Traducteur
+        [composer] Compositeur
+       *[other] { $role }
+    }
+detail-instrumentation = Effectif
+detail-key = Tonalité
+detail-time = Mesure
+detail-files = Fichiers
+detail-files-help = PDF, image ou MusicXML : la vignette de la première page apparaît peu après l'envoi.
+detail-incipit = Incipit
+detail-incipit-placeholder = premières mesures en ABC : K:G GABc d2 B2 |
+detail-shared = Partage
+detail-shared-yes = Montrée aux autres ensembles, qui peuvent l'emprunter.
+detail-shared-no = Réservée à cette bibliothèque.
+detail-related = Autres partitions de { $name }
+detail-no-related = Aucune autre partition de ce compositeur.
+detail-person-partitions = Partitions de { $name }
+detail-genre-partitions = Partitions du genre { $name }
+detail-no-partitions = Aucune partition.
+detail-back-partitions = Retour à la liste des partitions
+detail-back-persons = Retour à la liste des personnes
+detail-back-genres = Retour à la liste des genres
+btn-details = Détails
+
+## A propos
+
+about-heading = Site internet de gestion d'une base de données de Personnes
+about-author = Ecrit par Léon GENGOUX Avec Rocket et Postgresql
+
+## Connexion
+
+login-email = Adresse e-mail :
+login-password = Mot de passe :
+login-forgot = Mot de passe oublié ?
+signup-confirm = Confirmez le mot de passe :
+signup-password-hint = Au moins { $min } caractères.
+
+## Compte
+
+account-profile = Profil
+account-display-name = Nom affiché :
+account-locale = Langue :
+account-locale-browser = Celle du navigateur
+account-password = Mot de passe
+account-current-password = Mot de passe actuel :
+account-new-password = Nouveau mot de passe :
+account-api-tokens = Jetons d'API
+account-api-tokens-text = Pour les scripts : la requête porte le jeton dans l'en-tête « Authorization: Bearer … » (voir /api/persons, /api/genres, /api/partitions).
+account-token-copy = Copiez ce jeton maintenant, il ne sera plus affiché :
+account-token-name = Nom
+account-token-scope = Droits
+account-token-created = Créé le
+account-token-expires = Expiration
+account-token-used = Dernière utilisation
+account-token-ensemble = Bibliothèque
+account-token-for = Le nouveau jeton agira sur la bibliothèque « { $name } ».
+account-token-never = Jamais
+account-token-days = dans { $days } jours
+account-scope-read = Lecture seule
+account-scope-write = Lecture et ajouts au catalogue
+password-forgot-text = Indiquez l'adresse de votre compte : vous recevrez un lien pour choisir un nouveau mot de passe.
+
+## Administration
+
+admin-logged-as = Connecté en tant que { $email }.
+admin-backup = Sauvegarde du catalogue
+admin-backup-text = Une archive zip avec toutes les tables et les fichiers envoyés.
+admin-restore = Restauration
+admin-restore-text = Les lignes déjà présentes sont gardées, les autres sont ajoutées avec de nouveaux numéros.
+admin-reindex = Index de recherche
+admin-reindex-text = Recalcule les clés de recherche des noms et reconstruit les index, en tâche de fond.
+admin-webhooks = Webhooks
+admin-webhooks-text = Les sites abonnés aux changements du catalogue de la bibliothèque choisie.
+admin-awaiting = Comptes en attente
+admin-awaiting-text = Ces adresses sont confirmées et attendent votre accord.
+admin-ensembles = Ensembles
+admin-ensembles-text = Chaque ensemble a sa bibliothèque ; ses membres n'y voient que ses partitions. Les compositeurs et les genres communs sont partagés.
+admin-ensemble-name = Nom du nouvel ensemble
+admin-member-email = Adresse du membre
+
+## Mails
+
+mail-verify-subject = Confirmez votre adresse
+mail-verify-body =
+    Bonjour,
+
+    Pour activer votre compte sur le catalogue de partitions, ouvrez ce lien :
+    { $link }
+
+    Le lien est valable { $hours } heures. Si vous n'avez pas demandé de compte,
+    ignorez ce message.
+mail-approved-subject = Votre compte est accepté
+mail-approved-body =
+    Bonjour,
+
+    Votre compte { $email } est accepté, vous pouvez vous connecter :
+    { $link }
+mail-reset-subject = Nouveau mot de passe
+mail-reset-body =
+    Bonjour,
+
+    Pour choisir un nouveau mot de passe, ouvrez ce lien :
+    { $link }
+
+    Le lien est valable { $hours ->
+        [one] une heure
+       *[other] { $hours } heures
+    } et ne sert qu'une fois. Si vous n'avez rien demandé, ignorez ce message :
+    votre mot de passe ne change pas.
+
+## Erreurs
+
+error-404-heading = 404: Hé! Il n'y a rien ici.
+error-404-text = La page à l'adresse : { $path } n'existe pas !
+
+## Ensembles
+
+ensemble-current = Bibliothèque : { $name }
+ensemble-change = (changer)
+ensembles-text = Les bibliothèques que vous pouvez consulter.
+ensembles-chosen = bibliothèque actuelle
+
+## Prêts
+
+loans-libraries = Catalogues partagés des autres ensembles
+loans-shared-count = { $count ->
+        [one] une partition
+       *[other] { $count } partitions
+    }
+loans-no-libraries = Aucun autre ensemble ne partage de partitions pour l'instant.
+loans-received = Demandes reçues
+loans-sent = Demandes envoyées
+loans-none = Aucune demande.
+loans-borrower = Emprunteur
+loans-owner = Bibliothèque
+loans-message = Message
+loans-date = Date
+loans-status = État
+loan-status-pending = en attente
+loan-status-accepted = prêtée
+loan-status-declined = refusée
+loan-status-returned = rendue
+shared-text = Les partitions que cet ensemble partage ; les demandes de prêt sont faites au nom de la bibliothèque { $name }.
+shared-empty = Cet ensemble ne partage aucune partition avec votre bibliothèque.
+shared-message-placeholder = Message (dates, nombre d'exemplaires ...)
+shared-back = Retour aux prêts
+jobs-list = Tâches de fond de la bibliothèque
+jobs-none = Aucune tâche.
+jobs-kind = Tâche
+jobs-created = Créée le
+jobs-status = État
+jobs-attempts = Essais
+jobs-error = Dernière erreur
+jobs-finished = Finie le
+jobs-run-at = Prévue le
+jobs-retry-at = nouvel essai le { $date }
+jobs-payload = Données
+jobs-back = Retour aux tâches
+job-kind-thumbnail = Vignette d'un fichier
+job-kind-reindex = Reconstruction des index
+job-kind-webhook = Envoi d'un webhook
+job-status-pending = en attente
+job-status-running = en cours
+job-status-done = faite
+job-status-failed = échouée
+job-status-cancelled = annulée
+webhooks-list = Abonnements de la bibliothèque
+webhooks-none = Aucun abonnement.
+webhooks-text = Chaque changement choisi est envoyé en POST (JSON) à l'adresse, signé avec le secret (en-tête X-Webhook-Signature : HMAC-SHA256 du corps).
+webhooks-new = Nouvel abonnement
+webhooks-url = Adresse
+webhooks-secret = Secret
+webhooks-secret-help = Vide : un secret est tiré au hasard.
+webhooks-secret-keep = Vide : le secret ne change pas.
+webhooks-events = Événements
+webhooks-active = Actif
+webhooks-suspended = suspendu
+webhooks-created = Créé le
+webhooks-deliveries = Journal des envois
+webhooks-deliveries-none = Aucun envoi.
+webhooks-event = Événement
+webhooks-status = État
+webhooks-attempts = Essais
+webhooks-response = Réponse
+webhooks-error = Erreur
+webhooks-job = Tâche
+webhooks-back = Retour aux abonnements
+webhook-event-partition-created = partition ajoutée
+webhook-event-partition-updated = partition modifiée
+webhook-event-partition-deleted = partition supprimée
+webhook-event-person-created = personne ajoutée
+webhook-event-person-updated = personne modifiée
+webhook-event-person-deleted = personne supprimée
+webhook-event-genre-created = genre ajouté
+webhook-event-genre-updated = genre modifié
+webhook-event-genre-deleted = genre supprimé
+webhook-event-ping = test
+delivery-status-pending = en attente
+delivery-status-delivered = reçu
+delivery-status-failed = échoué
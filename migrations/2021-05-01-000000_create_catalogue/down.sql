@@ -0,0 +1,3 @@
+DROP TABLE partitions;
+DROP TABLE genres;
+DROP TABLE persons;
@@ -0,0 +1,18 @@
+-- les tables du catalogue, telles qu'elles existaient avant les migrations ;
+-- IF NOT EXISTS pour ne pas toucher aux bases déjà en service
+CREATE TABLE IF NOT EXISTS persons (
+    id SERIAL PRIMARY KEY,
+    full_name VARCHAR NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS genres (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS partitions (
+    id SERIAL PRIMARY KEY,
+    person_id INTEGER NOT NULL REFERENCES persons (id),
+    title VARCHAR NOT NULL,
+    genre_id INTEGER NOT NULL REFERENCES genres (id)
+);
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS partitions_title_trgm_idx;
+DROP INDEX IF EXISTS genres_name_trgm_idx;
+DROP INDEX IF EXISTS persons_full_name_trgm_idx;
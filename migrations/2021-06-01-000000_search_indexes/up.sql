@@ -0,0 +1,10 @@
+-- index trigrammes pour l'autocomplétion et la recherche :
+-- ils servent aussi bien à ILIKE 'abc%' / '%abc%' qu'à l'opérateur de similarité %
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX IF NOT EXISTS persons_full_name_trgm_idx
+    ON persons USING gin (full_name gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS genres_name_trgm_idx
+    ON genres USING gin (name gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS partitions_title_trgm_idx
+    ON partitions USING gin (title gin_trgm_ops);
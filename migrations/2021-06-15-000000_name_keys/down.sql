@@ -0,0 +1,2 @@
+ALTER TABLE genres DROP COLUMN name_key;
+ALTER TABLE persons DROP COLUMN full_name_key;
@@ -0,0 +1,14 @@
+-- colonnes "ombre" pour les recherches par nom : minuscules, sans accents,
+-- espaces réduits ; elles sont calculées par l'application (models::name_key)
+-- et remplies au démarrage pour les lignes existantes
+ALTER TABLE persons ADD COLUMN IF NOT EXISTS full_name_key VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE genres ADD COLUMN IF NOT EXISTS name_key VARCHAR NOT NULL DEFAULT '';
+
+CREATE INDEX IF NOT EXISTS persons_full_name_key_idx ON persons (full_name_key);
+CREATE INDEX IF NOT EXISTS genres_name_key_idx ON genres (name_key);
+
+-- pour les suggestions "vouliez-vous dire"
+CREATE INDEX IF NOT EXISTS persons_full_name_key_trgm_idx
+    ON persons USING gin (full_name_key gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS genres_name_key_trgm_idx
+    ON genres USING gin (name_key gin_trgm_ops);
@@ -0,0 +1 @@
+ALTER TABLE partitions DROP COLUMN created_at;
@@ -0,0 +1,5 @@
+-- date d'entrée dans le catalogue, pour les statistiques ;
+-- les partitions déjà présentes prennent la date de la migration
+ALTER TABLE partitions ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+CREATE INDEX IF NOT EXISTS partitions_created_at_idx ON partitions (created_at);
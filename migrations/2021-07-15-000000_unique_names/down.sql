@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS partitions_person_title_unique;
+DROP INDEX IF EXISTS genres_name_key_unique;
+DROP INDEX IF EXISTS persons_full_name_key_unique;
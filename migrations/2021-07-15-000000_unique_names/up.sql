@@ -0,0 +1,39 @@
+-- un seul musicien ou genre par nom (comparé sur les clés, voir models::name_key)
+-- et une seule partition d'un titre donné (sans tenir compte de la casse)
+-- pour un même compositeur.
+--
+-- les doublons déjà présents sont fusionnés dans la ligne la plus ancienne :
+-- leurs partitions lui sont rattachées, puis ils sont supprimés ; les partitions
+-- en double ne gardent que la plus ancienne.
+-- les clés encore vides (lignes d'avant 2021-06-15) sont remplies au démarrage ;
+-- une ligne dont la clé est déjà prise est alors fusionnée dans celle qui la
+-- porte (repo::update_name_keys)
+
+UPDATE partitions SET person_id = d.keep
+FROM (SELECT id, min(id) OVER (PARTITION BY full_name_key) AS keep
+      FROM persons WHERE full_name_key <> '') d
+WHERE partitions.person_id = d.id AND d.id <> d.keep;
+
+DELETE FROM persons
+WHERE full_name_key <> ''
+  AND id NOT IN (SELECT min(id) FROM persons GROUP BY full_name_key);
+
+UPDATE partitions SET genre_id = d.keep
+FROM (SELECT id, min(id) OVER (PARTITION BY name_key) AS keep
+      FROM genres WHERE name_key <> '') d
+WHERE partitions.genre_id = d.id AND d.id <> d.keep;
+
+DELETE FROM genres
+WHERE name_key <> ''
+  AND id NOT IN (SELECT min(id) FROM genres GROUP BY name_key);
+
+DELETE FROM partitions a
+USING partitions b
+WHERE a.person_id = b.person_id AND lower(a.title) = lower(b.title) AND a.id > b.id;
+
+CREATE UNIQUE INDEX persons_full_name_key_unique
+    ON persons (full_name_key) WHERE full_name_key <> '';
+CREATE UNIQUE INDEX genres_name_key_unique
+    ON genres (name_key) WHERE name_key <> '';
+CREATE UNIQUE INDEX partitions_person_title_unique
+    ON partitions (person_id, lower(title));
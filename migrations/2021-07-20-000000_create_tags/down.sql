@@ -0,0 +1,2 @@
+DROP TABLE partition_tags;
+DROP TABLE tags;
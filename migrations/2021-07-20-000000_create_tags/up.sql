@@ -0,0 +1,15 @@
+-- étiquettes libres posées sur les partitions ("Noël", "à travailler" ...) ;
+-- comme pour les musiciens et les genres, le nom est comparé sur sa clé
+CREATE TABLE tags (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    name_key VARCHAR NOT NULL UNIQUE
+);
+
+CREATE TABLE partition_tags (
+    partition_id INTEGER NOT NULL REFERENCES partitions (id) ON DELETE CASCADE,
+    tag_id INTEGER NOT NULL REFERENCES tags (id) ON DELETE CASCADE,
+    PRIMARY KEY (partition_id, tag_id)
+);
+
+CREATE INDEX partition_tags_tag_id_idx ON partition_tags (tag_id);
@@ -0,0 +1,15 @@
+-- état des comptes : 'pending' (adresse pas encore confirmée), 'approval'
+-- (confirmée, attend un administrateur) ou 'active' ; les comptes existants
+-- restent actifs
+ALTER TABLE users ADD COLUMN status VARCHAR NOT NULL DEFAULT 'active';
+
+-- jetons à usage unique envoyés par mail (confirmation de l'adresse ...) ;
+-- seul leur hachage est gardé
+CREATE TABLE user_tokens (
+    token_hash VARCHAR PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    purpose VARCHAR NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX user_tokens_user_id_idx ON user_tokens (user_id);
@@ -0,0 +1,3 @@
+ALTER TABLE user_tokens DROP COLUMN created_at;
+ALTER TABLE users DROP COLUMN locale;
+ALTER TABLE users DROP COLUMN display_name;
@@ -0,0 +1,7 @@
+-- nom affiché et langue préférée de chaque compte ('' : selon le navigateur)
+ALTER TABLE users ADD COLUMN display_name VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE users ADD COLUMN locale VARCHAR NOT NULL DEFAULT '';
+
+-- la date de création des jetons limite le nombre de demandes
+-- de réinitialisation du mot de passe
+ALTER TABLE user_tokens ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now();
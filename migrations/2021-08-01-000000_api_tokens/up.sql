@@ -0,0 +1,15 @@
+-- jetons personnels pour l'API (/api) : `scope` vaut 'read' (lecture seule)
+-- ou 'write' (modification du catalogue) ; sans date d'expiration, le jeton
+-- vaut jusqu'à ce qu'il soit révoqué. Seul le hachage du jeton est gardé.
+CREATE TABLE api_tokens (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    name VARCHAR NOT NULL,
+    token_hash VARCHAR NOT NULL UNIQUE,
+    scope VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ
+);
+
+CREATE INDEX api_tokens_user_id_idx ON api_tokens (user_id);
@@ -0,0 +1,15 @@
+ALTER TABLE api_tokens DROP COLUMN ensemble_id;
+ALTER TABLE genres DROP COLUMN ensemble_id;
+
+-- les partitions en double (même titre dans deux ensembles) ne gardent que la plus ancienne
+DELETE FROM partitions a
+USING partitions b
+WHERE a.person_id = b.person_id AND lower(a.title) = lower(b.title) AND a.id > b.id;
+
+DROP INDEX partitions_person_title_unique;
+CREATE UNIQUE INDEX partitions_person_title_unique
+    ON partitions (person_id, lower(title));
+
+ALTER TABLE partitions DROP COLUMN ensemble_id;
+DROP TABLE ensemble_members;
+DROP TABLE ensembles;
@@ -0,0 +1,42 @@
+-- plusieurs ensembles (chœurs, orchestres ...) dans la même instance, chacun
+-- avec sa bibliothèque : chaque partition appartient à un ensemble ; un genre
+-- est commun à tous (ensemble_id NULL) ou propre à un ensemble ; les
+-- compositeurs restent communs à tous.
+--
+-- le catalogue existant devient celui d'un premier ensemble, dont tous les
+-- comptes existants sont membres ; un jeton d'API agit sur un seul ensemble.
+CREATE TABLE ensembles (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL
+);
+
+CREATE UNIQUE INDEX ensembles_name_unique ON ensembles (lower(name));
+
+CREATE TABLE ensemble_members (
+    ensemble_id INTEGER NOT NULL REFERENCES ensembles (id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    PRIMARY KEY (ensemble_id, user_id)
+);
+
+CREATE INDEX ensemble_members_user_id_idx ON ensemble_members (user_id);
+
+INSERT INTO ensembles (name) VALUES ('Bibliothèque');
+INSERT INTO ensemble_members (ensemble_id, user_id)
+SELECT (SELECT min(id) FROM ensembles), id FROM users;
+
+ALTER TABLE partitions ADD COLUMN ensemble_id INTEGER REFERENCES ensembles (id);
+UPDATE partitions SET ensemble_id = (SELECT min(id) FROM ensembles);
+ALTER TABLE partitions ALTER COLUMN ensemble_id SET NOT NULL;
+CREATE INDEX partitions_ensemble_id_idx ON partitions (ensemble_id);
+
+-- deux ensembles peuvent avoir chacun la même partition
+DROP INDEX partitions_person_title_unique;
+CREATE UNIQUE INDEX partitions_person_title_unique
+    ON partitions (ensemble_id, person_id, lower(title));
+
+-- les noms de genres restent uniques dans toute l'instance
+ALTER TABLE genres ADD COLUMN ensemble_id INTEGER REFERENCES ensembles (id) ON DELETE CASCADE;
+
+ALTER TABLE api_tokens ADD COLUMN ensemble_id INTEGER REFERENCES ensembles (id) ON DELETE CASCADE;
+UPDATE api_tokens SET ensemble_id = (SELECT min(id) FROM ensembles);
+ALTER TABLE api_tokens ALTER COLUMN ensemble_id SET NOT NULL;
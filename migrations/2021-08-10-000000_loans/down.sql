@@ -0,0 +1,2 @@
+DROP TABLE loans;
+ALTER TABLE partitions DROP COLUMN shared;
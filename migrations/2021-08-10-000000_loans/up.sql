@@ -0,0 +1,27 @@
+-- un ensemble peut montrer des partitions de sa bibliothèque aux autres
+-- ensembles (`shared`), qui peuvent demander à les emprunter.
+--
+-- une demande de prêt va de 'pending' (en attente) à 'accepted' ou
+-- 'declined', décidé par la bibliothèque qui possède la partition ; un prêt
+-- accepté passe à 'returned' quand la partition est rendue.
+ALTER TABLE partitions ADD COLUMN shared BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE loans (
+    id SERIAL PRIMARY KEY,
+    partition_id INTEGER NOT NULL REFERENCES partitions (id) ON DELETE CASCADE,
+    -- l'ensemble qui emprunte
+    ensemble_id INTEGER NOT NULL REFERENCES ensembles (id) ON DELETE CASCADE,
+    requested_by INTEGER REFERENCES users (id) ON DELETE SET NULL,
+    message VARCHAR NOT NULL DEFAULT '',
+    status VARCHAR NOT NULL DEFAULT 'pending',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    decided_at TIMESTAMPTZ
+);
+
+CREATE INDEX loans_partition_id_idx ON loans (partition_id);
+CREATE INDEX loans_ensemble_id_idx ON loans (ensemble_id);
+
+-- un ensemble n'a qu'une demande en cours (en attente ou prêt pas encore
+-- rendu) pour une même partition
+CREATE UNIQUE INDEX loans_open_unique
+    ON loans (partition_id, ensemble_id) WHERE status IN ('pending', 'accepted');
@@ -0,0 +1,3 @@
+DROP TABLE partition_creators;
+DROP TABLE partition_details;
+DROP TABLE partition_files;
@@ -0,0 +1,33 @@
+-- ce que l'import d'un fichier MusicXML apporte à une partition :
+--   partition_files     les fichiers gardés avec la partition (dans le
+--                       dossier `upload_dir`, `path` y est relatif)
+--   partition_details   effectif, armure et mesure
+--   partition_creators  les autres créateurs (parolier, arrangeur ...),
+--                       le compositeur restant partitions.person_id
+CREATE TABLE partition_files (
+    id SERIAL PRIMARY KEY,
+    partition_id INTEGER NOT NULL REFERENCES partitions (id) ON DELETE CASCADE,
+    -- le nom du fichier envoyé
+    name VARCHAR NOT NULL,
+    path VARCHAR NOT NULL UNIQUE,
+    content_type VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX partition_files_partition_id_idx ON partition_files (partition_id);
+
+CREATE TABLE partition_details (
+    partition_id INTEGER PRIMARY KEY REFERENCES partitions (id) ON DELETE CASCADE,
+    instrumentation VARCHAR NOT NULL DEFAULT '',
+    key_signature VARCHAR NOT NULL DEFAULT '',
+    time_signature VARCHAR NOT NULL DEFAULT ''
+);
+
+CREATE TABLE partition_creators (
+    partition_id INTEGER NOT NULL REFERENCES partitions (id) ON DELETE CASCADE,
+    person_id INTEGER NOT NULL REFERENCES persons (id) ON DELETE CASCADE,
+    role VARCHAR NOT NULL,
+    PRIMARY KEY (partition_id, person_id, role)
+);
+
+CREATE INDEX partition_creators_person_id_idx ON partition_creators (person_id);
@@ -0,0 +1,4 @@
+DROP INDEX partition_details_incipit_key_trgm_idx;
+ALTER TABLE partition_details
+    DROP COLUMN incipit_key,
+    DROP COLUMN incipit;
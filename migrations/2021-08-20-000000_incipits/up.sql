@@ -0,0 +1,8 @@
+-- l'incipit d'une partition en notation ABC (voir abc.rs), et la suite de
+-- ses intervalles (",2,2,-4,") pour la recherche mélodique
+ALTER TABLE partition_details
+    ADD COLUMN incipit VARCHAR NOT NULL DEFAULT '',
+    ADD COLUMN incipit_key VARCHAR NOT NULL DEFAULT '';
+
+CREATE INDEX partition_details_incipit_key_trgm_idx
+    ON partition_details USING gin (incipit_key gin_trgm_ops);
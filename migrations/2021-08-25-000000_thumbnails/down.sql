@@ -0,0 +1 @@
+ALTER TABLE partition_files DROP COLUMN thumbnail;
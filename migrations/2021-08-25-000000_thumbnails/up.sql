@@ -0,0 +1,3 @@
+-- vrai quand la vignette de la première page du fichier (PDF ou image) est
+-- dans le cache des vignettes (voir thumbnails.rs)
+ALTER TABLE partition_files ADD COLUMN thumbnail BOOLEAN NOT NULL DEFAULT false;
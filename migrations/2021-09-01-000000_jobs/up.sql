@@ -0,0 +1,25 @@
+-- les tâches longues faites en arrière-plan (voir jobs.rs) : vignettes,
+-- reconstruction des index ...
+--
+-- une tâche va de 'pending' (en attente de `run_at`) à 'running', puis à
+-- 'done' ; une erreur la remet en attente un peu plus tard, jusqu'à
+-- `max_attempts` essais, puis elle passe à 'failed'. Une tâche annulée
+-- ('cancelled') n'est plus reprise.
+CREATE TABLE jobs (
+    id SERIAL PRIMARY KEY,
+    kind VARCHAR NOT NULL,
+    -- l'ensemble pour qui la tâche est faite, NULL pour l'administration
+    ensemble_id INTEGER REFERENCES ensembles (id) ON DELETE CASCADE,
+    payload JSONB NOT NULL DEFAULT '{}',
+    status VARCHAR NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL DEFAULT 3,
+    -- la dernière erreur
+    error VARCHAR NOT NULL DEFAULT '',
+    run_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    finished_at TIMESTAMPTZ
+);
+
+CREATE INDEX jobs_pending_idx ON jobs (run_at) WHERE status = 'pending';
+CREATE INDEX jobs_ensemble_id_idx ON jobs (ensemble_id);
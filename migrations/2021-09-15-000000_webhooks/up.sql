@@ -0,0 +1,35 @@
+-- les abonnements des sites qui suivent le catalogue d'un ensemble (voir
+-- webhooks.rs) : chaque changement d'un des `events` leur est envoyé en
+-- POST à `url`, signé avec `secret`
+CREATE TABLE webhooks (
+    id SERIAL PRIMARY KEY,
+    ensemble_id INTEGER NOT NULL REFERENCES ensembles (id) ON DELETE CASCADE,
+    url VARCHAR NOT NULL,
+    secret VARCHAR NOT NULL,
+    events VARCHAR[] NOT NULL DEFAULT '{}',
+    active BOOLEAN NOT NULL DEFAULT true,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX webhooks_ensemble_id_idx ON webhooks (ensemble_id);
+
+-- le journal des envois : un par abonnement et par événement, envoyé (et
+-- repris) par une tâche de fond ; 'pending' tant qu'il reste des essais,
+-- puis 'delivered' ou 'failed'
+CREATE TABLE webhook_deliveries (
+    id SERIAL PRIMARY KEY,
+    webhook_id INTEGER NOT NULL REFERENCES webhooks (id) ON DELETE CASCADE,
+    event VARCHAR NOT NULL,
+    data JSONB NOT NULL DEFAULT '{}',
+    status VARCHAR NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    -- la réponse du site au dernier essai
+    response_status INTEGER,
+    response_body VARCHAR NOT NULL DEFAULT '',
+    error VARCHAR NOT NULL DEFAULT '',
+    job_id INTEGER REFERENCES jobs (id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    delivered_at TIMESTAMPTZ
+);
+
+CREATE INDEX webhook_deliveries_webhook_id_idx ON webhook_deliveries (webhook_id);
@@ -0,0 +1,3 @@
+DROP INDEX genres_name_key_unique;
+CREATE UNIQUE INDEX genres_name_key_unique
+    ON genres (name_key) WHERE name_key <> '';
@@ -0,0 +1,8 @@
+-- un nom de genre est unique parmi les genres communs et parmi ceux de chaque
+-- ensemble : le genre réservé d'un ensemble ne bloque plus les autres (ni ne
+-- se révèle à eux par le refus). Un ensemble peut ainsi avoir son genre du
+-- même nom qu'un genre commun ; c'est le sien qui est pris (voir
+-- repo::get_genre_by_name).
+DROP INDEX genres_name_key_unique;
+CREATE UNIQUE INDEX genres_name_key_unique
+    ON genres (COALESCE(ensemble_id, 0), name_key) WHERE name_key <> '';
@@ -0,0 +1,937 @@
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+
+// ABC notation
+//
+// l'incipit d'une partition (ses premières mesures) noté en ABC :
+//   M:3/4
+//   L:1/8
+//   K:G
+//   GABc d2 | e2 d2 B2 |
+// les en-têtes sont facultatifs (K:C et L:1/8 par défaut, sans chiffrage).
+// On en tire le dessin SVG des premières mesures (render_svg) et la suite des
+// intervalles entre les notes (melody_key), qui sert à la recherche
+// mélodique : une mélodie se retrouve quelle que soit sa tonalité.
+//
+// seule une partie de la notation est lue : notes, altérations, octaves,
+// durées, rythmes pointés (> <), silences, barres et accords. Ornements,
+// annotations, notes d'agrément, liaisons et n-olets sont ignorés.
+
+// les mesures dessinées
+const MAX_BARS: usize = 4;
+
+// une durée est une fraction de ronde dont les deux termes (réduits) ne
+// dépassent pas cette borne : L:1/1024 ou A1024 au plus
+const MAX_LENGTH_PART: u32 = 1024;
+
+// interligne de la portée, en pixels
+const SPACE: f64 = 8.0;
+const STAFF_TOP: f64 = 40.0;
+const STAFF_BOTTOM: f64 = STAFF_TOP + 4.0 * SPACE;
+const STEM: f64 = 3.5 * SPACE;
+
+// demi-tons au-dessus de do pour chaque degré, de do à si
+const SEMITONES: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+// l'ordre des dièses (fa, do, sol ...), à l'envers celui des bémols
+const SHARPS: [i32; 7] = [3, 0, 4, 1, 5, 2, 6];
+
+#[derive(Debug)]
+pub enum AbcError {
+    // pas une seule note
+    Empty,
+    // un en-tête illisible : K:, M: ou L:
+    Header(String),
+    // une durée hors des bornes (MAX_LENGTH_PART)
+    Length,
+}
+
+impl fmt::Display for AbcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AbcError::Empty => write!(f, "no notes"),
+            AbcError::Header(line) => write!(f, "unreadable header `{}`", line),
+            AbcError::Length => write!(f, "a note length is too long or too short"),
+        }
+    }
+}
+
+impl std::error::Error for AbcError {}
+
+// une durée, en fraction de ronde
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Length {
+    num: u32,
+    den: u32,
+}
+
+impl Length {
+    fn new(num: u32, den: u32) -> Length {
+        fn gcd(a: u32, b: u32) -> u32 {
+            if b == 0 {
+                a
+            } else {
+                gcd(b, a % b)
+            }
+        }
+        let divisor = gcd(num, den).max(1);
+        Length {
+            num: num / divisor,
+            den: den / divisor,
+        }
+    }
+
+    // refusée plutôt que de déborder, ou de dessiner une note de 1/65536
+    fn bounded(num: u32, den: u32) -> Result<Length, AbcError> {
+        let length = Length::new(num, den);
+        if length.num == 0 || length.num > MAX_LENGTH_PART || length.den > MAX_LENGTH_PART {
+            return Err(AbcError::Length);
+        }
+        Ok(length)
+    }
+
+    fn times(self, other: Length) -> Result<Length, AbcError> {
+        match (
+            self.num.checked_mul(other.num),
+            self.den.checked_mul(other.den),
+        ) {
+            (Some(num), Some(den)) => Length::bounded(num, den),
+            _ => Err(AbcError::Length),
+        }
+    }
+
+    fn value(self) -> f64 {
+        f64::from(self.num) / f64::from(self.den)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Meter {
+    Common,
+    Cut,
+    Fraction(u32, u32),
+}
+
+impl Meter {
+    fn length(self) -> Length {
+        match self {
+            Meter::Common => Length::new(1, 1),
+            Meter::Cut => Length::new(1, 1),
+            Meter::Fraction(beats, beat_type) => Length::new(beats, beat_type.max(1)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Clef {
+    Treble,
+    Bass,
+}
+
+impl Clef {
+    // le degré de la ligne du bas (0 : do central)
+    fn bottom_step(self) -> i32 {
+        match self {
+            Clef::Treble => 2,
+            Clef::Bass => -10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Pitch {
+    // degré diatonique : 0 pour C (do central), 7 pour c, -7 pour C,
+    step: i32,
+    // altération écrite : 1 dièse, -1 bémol, 0 bécarre
+    accidental: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Symbol {
+    // une note, ou un accord
+    Note {
+        pitches: Vec<Pitch>,
+        length: Length,
+        tie: bool,
+    },
+    Rest(Length),
+    Bar,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Incipit {
+    // armure : nombre de dièses (positif) ou de bémols (négatif)
+    fifths: i32,
+    meter: Option<Meter>,
+    clef: Option<Clef>,
+    symbols: Vec<Symbol>,
+}
+
+// ***********************************************************************************************
+// Parse
+
+pub fn parse(text: &str) -> Result<Incipit, AbcError> {
+    let mut fifths = 0;
+    let mut meter = None;
+    let mut unit = None;
+    let mut clef = None;
+    let mut body = String::new();
+
+    for line in text.lines() {
+        // % : commentaire jusqu'à la fin de la ligne
+        let line = line.split('%').next().unwrap_or_default().trim();
+        let bytes = line.as_bytes();
+        if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+            let value = line[2..].trim();
+            match bytes[0] {
+                b'K' => {
+                    let (key, key_clef) =
+                        parse_key(value).ok_or_else(|| AbcError::Header(line.to_string()))?;
+                    fifths = key;
+                    clef = key_clef;
+                }
+                b'M' => {
+                    meter = parse_meter(value).ok_or_else(|| AbcError::Header(line.to_string()))?
+                }
+                b'L' => {
+                    let length =
+                        parse_fraction(value).ok_or_else(|| AbcError::Header(line.to_string()))?;
+                    unit = Some(Length::bounded(length.num, length.den)?);
+                }
+                // titre, compositeur, paroles ... : déjà connus par ailleurs
+                _ => {}
+            }
+        } else {
+            body.push_str(line);
+            body.push(' ');
+        }
+    }
+
+    // la durée par défaut dépend du chiffrage, comme dans le standard ABC
+    let unit = unit.unwrap_or_else(|| match meter {
+        Some(meter) if meter.length().value() < 0.75 => Length::new(1, 16),
+        _ => Length::new(1, 8),
+    });
+    let symbols = parse_body(&body, unit)?;
+    if !symbols.iter().any(|s| matches!(s, Symbol::Note { .. })) {
+        return Err(AbcError::Empty);
+    }
+
+    Ok(Incipit {
+        fifths,
+        meter,
+        clef,
+        symbols,
+    })
+}
+
+// K:G, K:Bb, K:F#m, K:D dor, K:Am clef=bass ... : l'armure et la clé
+fn parse_key(value: &str) -> Option<(i32, Option<Clef>)> {
+    let mut tokens = value.split_whitespace();
+    let mut clef = None;
+    let mut fifths = match tokens.next() {
+        None => 0,
+        Some(tonic) if tonic.eq_ignore_ascii_case("none") => 0,
+        Some(tonic) => {
+            let mut chars = tonic.chars();
+            let base = match chars.next()?.to_ascii_uppercase() {
+                'F' => -1,
+                'C' => 0,
+                'G' => 1,
+                'D' => 2,
+                'A' => 3,
+                'E' => 4,
+                'B' => 5,
+                _ => return None,
+            };
+            let rest = chars.as_str();
+            let (sharp, mode) = match rest.chars().next() {
+                Some('#') => (7, &rest[1..]),
+                Some('b') => (-7, &rest[1..]),
+                _ => (0, rest),
+            };
+            base + sharp + mode_offset(mode)?
+        }
+    };
+    for token in tokens {
+        match token.trim_start_matches("clef=") {
+            "treble" => clef = Some(Clef::Treble),
+            "bass" => clef = Some(Clef::Bass),
+            mode => fifths += mode_offset(mode).unwrap_or_default(),
+        }
+    }
+    if (-7..=7).contains(&fifths) {
+        Some((fifths, clef))
+    } else {
+        None
+    }
+}
+
+// l'armure d'un mode, comparée à celle du majeur de même tonique
+fn mode_offset(mode: &str) -> Option<i32> {
+    let mode = mode.to_lowercase();
+    match mode.get(..3).unwrap_or(&mode) {
+        "" | "maj" | "ion" => Some(0),
+        "m" | "min" | "aeo" => Some(-3),
+        "mix" => Some(-1),
+        "dor" => Some(-2),
+        "phr" => Some(-4),
+        "lyd" => Some(1),
+        "loc" => Some(-5),
+        _ => None,
+    }
+}
+
+// M:3/4, M:C, M:C| ou M:none
+fn parse_meter(value: &str) -> Option<Option<Meter>> {
+    match value {
+        "" | "none" => Some(None),
+        "C" => Some(Some(Meter::Common)),
+        "C|" => Some(Some(Meter::Cut)),
+        _ => {
+            let (beats, beat_type) = value.split_once('/')?;
+            let beats = beats.trim().parse().ok()?;
+            let beat_type = beat_type.trim().parse().ok()?;
+            Some(Some(Meter::Fraction(beats, beat_type)))
+        }
+    }
+}
+
+fn parse_fraction(value: &str) -> Option<Length> {
+    let (num, den) = value.split_once('/')?;
+    let num = num.trim().parse::<u32>().ok()?;
+    let den = den.trim().parse::<u32>().ok()?;
+    if num == 0 || den == 0 {
+        return None;
+    }
+    Some(Length::new(num, den))
+}
+
+fn parse_body(body: &str, unit: Length) -> Result<Vec<Symbol>, AbcError> {
+    let chars = body.chars().collect::<Vec<_>>();
+    let mut symbols = vec![];
+    // rythme pointé : la durée de la note suivante
+    let mut broken: Option<Length> = None;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            // |, ||, |], :|, |:, ::, |1, :|2 ... : une seule barre
+            '|' | ':' => {
+                while i < chars.len() && "|:]".contains(chars[i]) {
+                    i += 1;
+                }
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                if !symbols.is_empty() && symbols.last() != Some(&Symbol::Bar) {
+                    symbols.push(Symbol::Bar);
+                }
+                continue;
+            }
+            '[' => {
+                let next = chars.get(i + 1).copied().unwrap_or(' ');
+                if next == '|' {
+                    i += 1;
+                    continue;
+                }
+                // [K:D] (champ dans la ligne) ou [1 (fin de reprise)
+                if next.is_ascii_digit() || chars.get(i + 2) == Some(&':') {
+                    i = skip_to(&chars, i + 1, ']');
+                    continue;
+                }
+                // un accord : les notes jusqu'au ]
+                i += 1;
+                let mut pitches = vec![];
+                let mut length = None;
+                while i < chars.len() && chars[i] != ']' {
+                    match parse_note(&chars, i)? {
+                        Some((pitch, multiplier, end)) => {
+                            pitches.push(pitch);
+                            length.get_or_insert(multiplier);
+                            i = end;
+                        }
+                        None => i += 1,
+                    }
+                }
+                let (multiplier, end) = parse_length(&chars, i + 1)?;
+                i = end;
+                if !pitches.is_empty() {
+                    let length = length
+                        .unwrap_or_else(|| Length::new(1, 1))
+                        .times(multiplier)?
+                        .times(unit)?;
+                    push_note(&mut symbols, pitches, length, &mut broken)?;
+                }
+                continue;
+            }
+            '"' => i = skip_to(&chars, i + 1, '"'),
+            '!' => i = skip_to(&chars, i + 1, '!'),
+            '+' => i = skip_to(&chars, i + 1, '+'),
+            '{' => i = skip_to(&chars, i + 1, '}'),
+            '-' => {
+                if let Some(Symbol::Note { tie, .. }) = symbols.last_mut() {
+                    *tie = true;
+                }
+                i += 1;
+            }
+            '>' | '<' => {
+                let (previous, next) = if c == '>' {
+                    (Length::new(3, 2), Length::new(1, 2))
+                } else {
+                    (Length::new(1, 2), Length::new(3, 2))
+                };
+                match symbols.last_mut() {
+                    Some(Symbol::Note { length, .. }) | Some(Symbol::Rest(length)) => {
+                        *length = length.times(previous)?;
+                        broken = Some(next);
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+            'z' | 'x' | 'Z' => {
+                let (multiplier, end) = parse_length(&chars, i + 1)?;
+                i = end;
+                // Z : des mesures entières de silence, dessinées comme une pause
+                let length = if c == 'Z' {
+                    Length::new(1, 1)
+                } else {
+                    multiplier.times(unit)?
+                };
+                let length = match broken.take() {
+                    Some(factor) => length.times(factor)?,
+                    None => length,
+                };
+                symbols.push(Symbol::Rest(length));
+            }
+            _ => match parse_note(&chars, i)? {
+                Some((pitch, multiplier, end)) => {
+                    i = end;
+                    push_note(
+                        &mut symbols,
+                        vec![pitch],
+                        multiplier.times(unit)?,
+                        &mut broken,
+                    )?;
+                }
+                // tout le reste (espaces, liaisons, ornements ...) est ignoré
+                None => i += 1,
+            },
+        }
+    }
+
+    Ok(symbols)
+}
+
+fn push_note(
+    symbols: &mut Vec<Symbol>,
+    pitches: Vec<Pitch>,
+    length: Length,
+    broken: &mut Option<Length>,
+) -> Result<(), AbcError> {
+    let length = match broken.take() {
+        Some(factor) => length.times(factor)?,
+        None => length,
+    };
+    symbols.push(Symbol::Note {
+        pitches,
+        length,
+        tie: false,
+    });
+    Ok(())
+}
+
+// l'indice qui suit le caractère `end` (ou la fin du texte)
+fn skip_to(chars: &[char], from: usize, end: char) -> usize {
+    chars[from.min(chars.len())..]
+        .iter()
+        .position(|&c| c == end)
+        .map_or(chars.len(), |p| from + p + 1)
+}
+
+// ^^, ^, =, _, __ puis la lettre, les marques d'octave et la durée ;
+// Ok(None) : pas une note
+fn parse_note(chars: &[char], start: usize) -> Result<Option<(Pitch, Length, usize)>, AbcError> {
+    let mut i = start;
+    let mut accidental = None;
+    while i < chars.len() {
+        match chars[i] {
+            '^' => accidental = Some(accidental.unwrap_or(0) + 1),
+            '_' => accidental = Some(accidental.unwrap_or(0) - 1),
+            '=' => accidental = Some(0),
+            _ => break,
+        }
+        i += 1;
+    }
+
+    let letter = match chars.get(i) {
+        Some(&letter) => letter,
+        None => return Ok(None),
+    };
+    let degree = match "CDEFGAB".find(letter.to_ascii_uppercase()) {
+        Some(degree) => degree as i32,
+        None => return Ok(None),
+    };
+    let mut step = if letter.is_ascii_lowercase() {
+        degree + 7
+    } else {
+        degree
+    };
+    i += 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\'' => step += 7,
+            ',' => step -= 7,
+            _ => break,
+        }
+        i += 1;
+    }
+
+    let (length, end) = parse_length(chars, i)?;
+    Ok(Some((Pitch { step, accidental }, length, end)))
+}
+
+// 2, 3/2, /, //, /4 ... : le multiple de la durée par défaut
+fn parse_length(chars: &[char], start: usize) -> Result<(Length, usize), AbcError> {
+    // Ok(None) : pas de chiffres ; trop de chiffres pour un u32 : hors bornes
+    fn number(chars: &[char], i: &mut usize) -> Result<Option<u32>, AbcError> {
+        let begin = *i;
+        while *i < chars.len() && chars[*i].is_ascii_digit() {
+            *i += 1;
+        }
+        if begin == *i {
+            return Ok(None);
+        }
+        let digits = chars[begin..*i].iter().collect::<String>();
+        digits.parse().map(Some).map_err(|_| AbcError::Length)
+    }
+
+    let mut i = start;
+    let num = number(chars, &mut i)?.unwrap_or(1);
+    let mut den: u32 = 1;
+    while i < chars.len() && chars[i] == '/' {
+        i += 1;
+        let value = number(chars, &mut i)?.unwrap_or(2);
+        den = den.checked_mul(value).ok_or(AbcError::Length)?;
+    }
+    Ok((Length::bounded(num.max(1), den.max(1))?, i))
+}
+
+// ***********************************************************************************************
+// Melodic search
+
+impl Incipit {
+    // les intervalles en demi-tons entre les notes successives : ",2,2,-4,".
+    // Pour un accord, la note du haut ; une note liée à la même ne compte
+    // qu'une fois. Vide s'il y a moins de deux notes.
+    pub fn melody_key(&self) -> String {
+        let mut pitches = vec![];
+        let mut tied = false;
+        let mut altered = HashMap::new();
+        for symbol in &self.symbols {
+            match symbol {
+                Symbol::Bar => altered.clear(),
+                Symbol::Rest(_) => tied = false,
+                Symbol::Note {
+                    pitches: notes,
+                    tie,
+                    ..
+                } => {
+                    let pitch = notes
+                        .iter()
+                        .map(|pitch| self.semitones(pitch, &mut altered))
+                        .max()
+                        .unwrap_or_default();
+                    if !(tied && pitches.last() == Some(&pitch)) {
+                        pitches.push(pitch);
+                    }
+                    tied = *tie;
+                }
+            }
+        }
+
+        if pitches.len() < 2 {
+            return String::new();
+        }
+        let mut key = ",".to_string();
+        for pair in pitches.windows(2) {
+            let _ = write!(key, "{},", pair[1] - pair[0]);
+        }
+        key
+    }
+
+    // la hauteur d'une note, avec l'armure et les altérations déjà écrites
+    // dans la mesure
+    fn semitones(&self, pitch: &Pitch, altered: &mut HashMap<i32, i32>) -> i32 {
+        let accidental = match pitch.accidental {
+            Some(accidental) => {
+                altered.insert(pitch.step, accidental);
+                accidental
+            }
+            None => altered
+                .get(&pitch.step)
+                .copied()
+                .unwrap_or_else(|| key_accidental(self.fifths, pitch.step.rem_euclid(7))),
+        };
+        12 * pitch.step.div_euclid(7) + SEMITONES[pitch.step.rem_euclid(7) as usize] + accidental
+    }
+}
+
+fn key_accidental(fifths: i32, degree: i32) -> i32 {
+    if fifths > 0 && SHARPS[..fifths as usize].contains(&degree) {
+        1
+    } else if fifths < 0 && SHARPS[(7 + fifths) as usize..].contains(&degree) {
+        -1
+    } else {
+        0
+    }
+}
+
+// ***********************************************************************************************
+// SVG
+
+impl Incipit {
+    // les premières mesures sur une portée ; le dessin ne contient que des
+    // nombres et des symboles fixes, il peut être inclus tel quel dans une page
+    pub fn render_svg(&self) -> String {
+        let clef = self.clef.unwrap_or_else(|| self.best_clef());
+        let bottom = clef.bottom_step();
+        let mut svg = String::new();
+        let mut top_y = STAFF_TOP - 2.0 * SPACE;
+        let mut bottom_y = STAFF_BOTTOM + 2.0 * SPACE;
+        let mut x = 4.0;
+
+        // la clé
+        let (glyph, size, y) = match clef {
+            Clef::Treble => ("&#x1D11E;", 4.6 * SPACE, STAFF_BOTTOM + 0.8 * SPACE),
+            Clef::Bass => ("&#x1D122;", 4.0 * SPACE, STAFF_TOP + 3.1 * SPACE),
+        };
+        let _ = write!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}" font-size="{:.1}">{}</text>"#,
+            x, y, size, glyph
+        );
+        x += 3.2 * SPACE;
+
+        // l'armure : les positions des dièses et des bémols en clé de sol
+        const SHARP_STEPS: [i32; 7] = [10, 7, 11, 8, 5, 9, 6];
+        const FLAT_STEPS: [i32; 7] = [6, 9, 5, 8, 4, 7, 3];
+        let (steps, glyph) = if self.fifths >= 0 {
+            (SHARP_STEPS, "&#x266F;")
+        } else {
+            (FLAT_STEPS, "&#x266D;")
+        };
+        let shift = if clef == Clef::Bass { -14 } else { 0 };
+        for step in steps.iter().take(self.fifths.unsigned_abs() as usize) {
+            let _ = write!(
+                svg,
+                r#"<text x="{:.1}" y="{:.1}" font-size="{:.1}">{}</text>"#,
+                x,
+                step_y(step + shift, bottom) + 0.5 * SPACE,
+                2.0 * SPACE,
+                glyph
+            );
+            x += SPACE;
+        }
+        x += 0.5 * SPACE;
+
+        // le chiffrage
+        match self.meter {
+            Some(Meter::Fraction(beats, beat_type)) => {
+                let _ = write!(
+                    svg,
+                    r#"<text x="{x:.1}" y="{:.1}" font-size="{s:.1}" font-weight="bold" text-anchor="middle">{}</text><text x="{x:.1}" y="{:.1}" font-size="{s:.1}" font-weight="bold" text-anchor="middle">{}</text>"#,
+                    STAFF_TOP + 2.0 * SPACE - 1.0,
+                    beats,
+                    STAFF_BOTTOM - 1.0,
+                    beat_type,
+                    x = x + SPACE,
+                    s = 2.6 * SPACE
+                );
+                x += 2.5 * SPACE;
+            }
+            Some(meter) => {
+                let glyph = if meter == Meter::Common {
+                    "C"
+                } else {
+                    "&#xA2;"
+                };
+                let _ = write!(
+                    svg,
+                    r#"<text x="{:.1}" y="{:.1}" font-size="{:.1}" font-weight="bold" text-anchor="middle">{}</text>"#,
+                    x + SPACE,
+                    STAFF_TOP + 3.0 * SPACE,
+                    3.0 * SPACE,
+                    glyph
+                );
+                x += 2.5 * SPACE;
+            }
+            None => {}
+        }
+
+        let mut bars = 0;
+        for symbol in &self.symbols {
+            match symbol {
+                Symbol::Bar => {
+                    x += 0.5 * SPACE;
+                    let _ = write!(
+                        svg,
+                        r#"<line x1="{x:.1}" y1="{:.1}" x2="{x:.1}" y2="{:.1}" stroke="black"/>"#,
+                        STAFF_TOP,
+                        STAFF_BOTTOM,
+                        x = x
+                    );
+                    x += SPACE;
+                    bars += 1;
+                    if bars == MAX_BARS {
+                        break;
+                    }
+                }
+                Symbol::Rest(length) => {
+                    draw_rest(&mut svg, x, *length);
+                    x += advance(*length);
+                }
+                Symbol::Note {
+                    pitches, length, ..
+                } => {
+                    let (low, high) = draw_note(&mut svg, x, pitches, *length, bottom);
+                    top_y = top_y.min(high);
+                    bottom_y = bottom_y.max(low);
+                    x += advance(*length) + if has_accidental(pitches) { SPACE } else { 0.0 };
+                }
+            }
+        }
+        let width = x + SPACE;
+
+        let mut staff = String::new();
+        for line in 0..5 {
+            let y = STAFF_TOP + f64::from(line) * SPACE;
+            let _ = write!(
+                staff,
+                r#"<line x1="0" y1="{y:.1}" x2="{:.1}" y2="{y:.1}" stroke="black" stroke-width="0.8"/>"#,
+                width,
+                y = y
+            );
+        }
+        let height = bottom_y - top_y;
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" class="incipit" width="{w:.0}" height="{h:.0}" viewBox="0 {:.1} {w:.1} {h:.1}" font-family="serif">{}{}</svg>"#,
+            top_y,
+            staff,
+            svg,
+            w = width,
+            h = height
+        )
+    }
+
+    // la clé de fa pour une mélodie surtout sous le do central
+    fn best_clef(&self) -> Clef {
+        let steps = self
+            .symbols
+            .iter()
+            .filter_map(|symbol| match symbol {
+                Symbol::Note { pitches, .. } => pitches.first().map(|pitch| pitch.step),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let mean = steps.iter().sum::<i32>() as f64 / steps.len().max(1) as f64;
+        if mean < -3.0 {
+            Clef::Bass
+        } else {
+            Clef::Treble
+        }
+    }
+}
+
+fn step_y(step: i32, bottom: i32) -> f64 {
+    STAFF_BOTTOM - f64::from(step - bottom) * SPACE / 2.0
+}
+
+// la place d'une note ou d'un silence, selon sa durée
+fn advance(length: Length) -> f64 {
+    1.5 * SPACE + 4.5 * SPACE * length.value().min(1.0)
+}
+
+fn has_accidental(pitches: &[Pitch]) -> bool {
+    pitches.iter().any(|pitch| pitch.accidental.is_some())
+}
+
+// crochets : 1 pour une croche, 2 pour une double croche ...
+fn flags(length: Length) -> usize {
+    let value = length.value();
+    if value >= 0.25 {
+        0
+    } else if value >= 0.125 {
+        1
+    } else if value >= 0.0625 {
+        2
+    } else {
+        3
+    }
+}
+
+// les têtes, lignes supplémentaires, altérations, point, hampe et crochets ;
+// renvoie le haut et le bas du dessin
+fn draw_note(
+    svg: &mut String,
+    x: f64,
+    pitches: &[Pitch],
+    length: Length,
+    bottom: i32,
+) -> (f64, f64) {
+    let x = if has_accidental(pitches) {
+        x + SPACE
+    } else {
+        x
+    };
+    let cx = x + 0.6 * SPACE;
+    let value = length.value();
+    let fill = if value < 0.5 { "black" } else { "white" };
+    let top = bottom + 8;
+
+    let low_step = pitches.iter().map(|p| p.step).min().unwrap_or(bottom);
+    let high_step = pitches.iter().map(|p| p.step).max().unwrap_or(bottom);
+    for pitch in pitches {
+        let y = step_y(pitch.step, bottom);
+        // lignes supplémentaires, sous et sur la portée
+        let ledgers = (pitch.step..=bottom - 2)
+            .chain(top + 2..=pitch.step)
+            .filter(|step| (step - bottom) % 2 == 0);
+        for step in ledgers {
+            let _ = write!(
+                svg,
+                r#"<line x1="{:.1}" y1="{y:.1}" x2="{:.1}" y2="{y:.1}" stroke="black"/>"#,
+                cx - 0.9 * SPACE,
+                cx + 0.9 * SPACE,
+                y = step_y(step, bottom)
+            );
+        }
+        if let Some(accidental) = pitch.accidental {
+            let glyph = match accidental {
+                0 => "&#x266E;",
+                1 => "&#x266F;",
+                2 => "&#x1D12A;",
+                -1 => "&#x266D;",
+                _ => "&#x1D12B;",
+            };
+            let _ = write!(
+                svg,
+                r#"<text x="{:.1}" y="{:.1}" font-size="{:.1}" text-anchor="end">{}</text>"#,
+                cx - 0.8 * SPACE,
+                y + 0.5 * SPACE,
+                2.0 * SPACE,
+                glyph
+            );
+        }
+        let _ = write!(
+            svg,
+            r#"<ellipse cx="{cx:.1}" cy="{cy:.1}" rx="{:.1}" ry="{:.1}" fill="{}" stroke="black" stroke-width="1.2" transform="rotate(-20 {cx:.1} {cy:.1})"/>"#,
+            0.58 * SPACE,
+            0.42 * SPACE,
+            fill,
+            cx = cx,
+            cy = y
+        );
+        // point : dans l'interligne, au-dessus d'une tête sur une ligne
+        if length.num == 3 {
+            let dot_y = if (pitch.step - bottom) % 2 == 0 {
+                y - SPACE / 2.0
+            } else {
+                y
+            };
+            let _ = write!(
+                svg,
+                r#"<circle cx="{:.1}" cy="{:.1}" r="1.5"/>"#,
+                cx + 1.2 * SPACE,
+                dot_y
+            );
+        }
+    }
+
+    let low_y = step_y(low_step, bottom);
+    let high_y = step_y(high_step, bottom);
+    if value >= 1.0 {
+        return (low_y + SPACE, high_y - SPACE);
+    }
+    // hampe vers le haut sous la ligne du milieu
+    let up = low_step + high_step < 2 * (bottom + 4);
+    let (stem_x, from_y, tip_y, direction) = if up {
+        (cx + 0.55 * SPACE, low_y, high_y - STEM, 1.0)
+    } else {
+        (cx - 0.55 * SPACE, high_y, low_y + STEM, -1.0)
+    };
+    let _ = write!(
+        svg,
+        r#"<line x1="{x:.1}" y1="{:.1}" x2="{x:.1}" y2="{:.1}" stroke="black" stroke-width="1.1"/>"#,
+        from_y,
+        tip_y,
+        x = stem_x
+    );
+    for flag in 0..flags(length) {
+        let y = tip_y + direction * 0.8 * SPACE * flag as f64;
+        let _ = write!(
+            svg,
+            r#"<path d="M{:.1} {:.1} q{:.1} {:.1} {:.1} {:.1}" fill="none" stroke="black" stroke-width="1.4"/>"#,
+            stem_x,
+            y,
+            SPACE,
+            direction * 0.8 * SPACE,
+            0.7 * SPACE,
+            direction * 1.8 * SPACE
+        );
+    }
+    (low_y.max(tip_y) + SPACE, high_y.min(tip_y) - SPACE)
+}
+
+fn draw_rest(svg: &mut String, x: f64, length: Length) {
+    let value = length.value();
+    let cx = x + 0.6 * SPACE;
+    if value >= 0.5 {
+        // pause sous la 4e ligne, demi-pause sur la 3e
+        let y = if value >= 1.0 {
+            STAFF_TOP + SPACE
+        } else {
+            STAFF_TOP + 2.0 * SPACE - 0.5 * SPACE
+        };
+        let _ = write!(
+            svg,
+            r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}"/>"#,
+            cx - 0.6 * SPACE,
+            y,
+            1.2 * SPACE,
+            0.5 * SPACE
+        );
+        return;
+    }
+    if value >= 0.25 {
+        // soupir
+        let _ = write!(
+            svg,
+            r#"<path d="M{:.1} {:.1} l{s6:.1} {s8:.1} l-{s6:.1} {s8:.1} l{s6:.1} {s8:.1} q-{s8:.1} 0 -{s2:.1} {s8:.1}" fill="none" stroke="black" stroke-width="1.6"/>"#,
+            cx - 0.3 * SPACE,
+            STAFF_TOP + 0.5 * SPACE,
+            s2 = 0.2 * SPACE,
+            s6 = 0.6 * SPACE,
+            s8 = 0.8 * SPACE
+        );
+        return;
+    }
+    // demi-soupir et plus court : un crochet par division
+    let _ = write!(
+        svg,
+        r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="black" stroke-width="1.1"/>"#,
+        cx + 0.5 * SPACE,
+        STAFF_TOP + 1.5 * SPACE,
+        cx - 0.2 * SPACE,
+        STAFF_TOP + 3.5 * SPACE
+    );
+    for flag in 0..flags(length) {
+        let _ = write!(
+            svg,
+            r#"<circle cx="{:.1}" cy="{:.1}" r="1.8"/>"#,
+            cx - 0.3 * SPACE,
+            STAFF_TOP + (1.6 + flag as f64) * SPACE
+        );
+    }
+}
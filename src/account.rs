@@ -0,0 +1,371 @@
+use fluent::FluentArgs;
+
+use rocket::http::CookieJar;
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket::serde::Serialize;
+use rocket::State;
+
+use rocket_dyn_templates::Template;
+
+use crate::api::new_api_token;
+use crate::auth::{
+    hash_password, hash_token, new_mail_token, normalize_email, password_problem, verify_password,
+    MIN_PASSWORD_LEN,
+};
+use crate::csrf::{CsrfChecked, CsrfForm, CsrfToken};
+use crate::ensemble::Member;
+use crate::i18n::{is_supported, remember_locale, supported_locales, Locale};
+use crate::mailer::{Mail, Outbox};
+use crate::models::{
+    ApiToken, ApiTokenForm, Ensemble, ForgotPasswordForm, PasswordForm, ProfileForm,
+    ResetPasswordForm, User, TOKEN_RESET_PASSWORD,
+};
+use crate::notification::Notification;
+use crate::{db, DBPool};
+
+// Account
+//
+// chaque membre gère son compte : nom affiché, langue préférée (appliquée
+// à la connexion) et mot de passe. Un mot de passe oublié se remplace avec
+// un lien envoyé par mail, valable RESET_TOKEN_HOURS et une seule fois ;
+// au plus MAX_RESET_REQUESTS liens par compte dans ce délai.
+//
+// le membre y crée aussi ses jetons d'API (api.rs) : un jeton n'est montré
+// qu'une fois, juste après sa création, la base n'en gardant que le hachage.
+// Il agit sur la bibliothèque choisie au moment de sa création.
+
+pub const RESET_TOKEN_HOURS: i32 = 1;
+pub const MAX_RESET_REQUESTS: i64 = 3;
+
+#[derive(Debug, Serialize)]
+struct AccountPage {
+    flash: Option<Notification>,
+    title: String,
+    lang: String,
+    csrf_token: String,
+    user: User,
+    locales: Vec<&'static str>,
+    min_password_len: usize,
+    api_tokens: Vec<ApiToken>,
+    // le jeton qui vient d'être créé, en clair
+    new_token: Option<String>,
+    // la bibliothèque des jetons créés ici
+    ensemble: Ensemble,
+}
+
+impl AccountPage {
+    async fn render(
+        conn: &DBPool,
+        user: User,
+        ensemble: Ensemble,
+        flash: Option<Notification>,
+        csrf: CsrfToken,
+        locale: Locale,
+        new_token: Option<String>,
+    ) -> Template {
+        let api_tokens = db::get_api_tokens(conn, user.id.unwrap_or_default())
+            .await
+            .unwrap_or_else(|e| {
+                error_!("DB get_api_tokens error: {}", e);
+                vec![]
+            });
+        let page = AccountPage {
+            flash,
+            title: locale.tr("title-account"),
+            lang: locale.lang().to_string(),
+            csrf_token: csrf.value().to_string(),
+            user,
+            locales: supported_locales(),
+            min_password_len: MIN_PASSWORD_LEN,
+            api_tokens,
+            new_token,
+            ensemble,
+        };
+        Template::render("account", &page)
+    }
+}
+
+#[get("/account")]
+pub async fn account_page(
+    user: User,
+    ensemble: Ensemble,
+    flash: Option<FlashMessage<'_>>,
+    conn: DBPool,
+    csrf: CsrfToken,
+    locale: Locale,
+) -> Template {
+    let flash = Notification::from_flash(flash);
+    AccountPage::render(&conn, user, ensemble, flash, csrf, locale, None).await
+}
+
+#[post("/account/profile", data = "<profile_form>")]
+pub async fn update_profile(
+    user: User,
+    profile_form: CsrfForm<ProfileForm>,
+    conn: DBPool,
+    cookies: &CookieJar<'_>,
+    locale: Locale,
+) -> Flash<Redirect> {
+    let form = profile_form.into_inner();
+    // une langue inconnue revient à suivre le navigateur
+    let preferred = if is_supported(&form.locale) {
+        form.locale
+    } else {
+        String::new()
+    };
+    let id = user.id.unwrap_or_default();
+    match db::update_profile(&conn, id, form.display_name.trim().to_string(), preferred).await {
+        Ok(user) => {
+            remember_locale(cookies, &user.locale);
+            Notification::success(locale.tr("msg-account-saved")).redirect("/account")
+        }
+        Err(e) => {
+            error_!("DB profile error: {}", e);
+            Notification::error(locale.tr("msg-account-failed")).redirect("/account")
+        }
+    }
+}
+
+#[post("/account/password", data = "<password_form>")]
+pub async fn change_password(
+    user: User,
+    password_form: CsrfForm<PasswordForm>,
+    conn: DBPool,
+    locale: Locale,
+) -> Flash<Redirect> {
+    let form = password_form.into_inner();
+    if !verify_password(&user.password_hash, &form.current) {
+        return Notification::warning(locale.tr("msg-account-password-wrong")).redirect("/account");
+    }
+    if let Some(problem) = password_problem(&locale, &form.password, &form.confirm) {
+        return Notification::warning(problem).redirect("/account");
+    }
+
+    let saved = match hash_password(&form.password) {
+        Ok(hash) => db::update_password(&conn, user.id.unwrap_or_default(), hash)
+            .await
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    match saved {
+        Ok(_) => {
+            Notification::success(locale.tr("msg-account-password-changed")).redirect("/account")
+        }
+        Err(e) => {
+            error_!("Password change error: {}", e);
+            Notification::error(locale.tr("msg-account-failed")).redirect("/account")
+        }
+    }
+}
+
+// ********************************************************************************************
+// API tokens
+//
+
+// la page du compte est rendue directement, pour montrer le jeton une seule fois
+#[post("/account/tokens", data = "<token_form>")]
+pub async fn create_api_token(
+    member: Member,
+    ensemble: Ensemble,
+    token_form: CsrfForm<ApiTokenForm>,
+    conn: DBPool,
+    csrf: CsrfToken,
+    locale: Locale,
+) -> Result<Template, Flash<Redirect>> {
+    // un jeton n'agit que sur un ensemble dont on est membre (voir api.rs)
+    let user = member.0;
+    let form = token_form.into_inner();
+    let name = form.name.trim().to_string();
+    if name.is_empty() {
+        return Err(Notification::warning(locale.tr("msg-token-name-missing")).redirect("/account"));
+    }
+    let valid_days = form.expires_in_days.filter(|days| *days > 0);
+
+    let (token, token_hash) = new_api_token();
+    let id = user.id.unwrap_or_default();
+    let ensemble_id = ensemble.id.unwrap_or_default();
+    let scope = form.scope.as_str();
+    match db::create_api_token(&conn, id, ensemble_id, name, scope, token_hash, valid_days).await {
+        Ok(_) => {
+            let flash = Some(Notification::success(locale.tr("msg-token-created")));
+            let page = AccountPage::render(&conn, user, ensemble, flash, csrf, locale, Some(token));
+            Ok(page.await)
+        }
+        Err(e) => {
+            error_!("DB API token error: {}", e);
+            Err(Notification::error(locale.tr("msg-token-failed")).redirect("/account"))
+        }
+    }
+}
+
+#[delete("/account/tokens/<id>")]
+pub async fn revoke_api_token(
+    _csrf: CsrfChecked,
+    id: i32,
+    user: User,
+    conn: DBPool,
+    locale: Locale,
+) -> Flash<Redirect> {
+    match db::delete_api_token(&conn, user.id.unwrap_or_default(), id).await {
+        Ok(0) => Notification::warning(locale.tr("msg-token-unknown")).redirect("/account"),
+        Ok(_) => Notification::success(locale.tr("msg-token-revoked")).redirect("/account"),
+        Err(e) => {
+            error_!("DB API token error: {}", e);
+            Notification::error(locale.tr("msg-token-failed")).redirect("/account")
+        }
+    }
+}
+
+// ********************************************************************************************
+// Forgotten password
+//
+
+#[derive(Debug, Serialize)]
+struct PasswordPage {
+    flash: Option<Notification>,
+    title: String,
+    lang: String,
+    csrf_token: String,
+    token: String,
+    min_password_len: usize,
+}
+
+impl PasswordPage {
+    fn new(
+        flash: Option<FlashMessage<'_>>,
+        csrf: CsrfToken,
+        locale: Locale,
+        token: String,
+    ) -> Self {
+        PasswordPage {
+            flash: Notification::from_flash(flash),
+            title: locale.tr("title-password"),
+            lang: locale.lang().to_string(),
+            csrf_token: csrf.value().to_string(),
+            token,
+            min_password_len: MIN_PASSWORD_LEN,
+        }
+    }
+}
+
+#[get("/password/forgot")]
+pub fn forgot_password_page(
+    flash: Option<FlashMessage<'_>>,
+    csrf: CsrfToken,
+    locale: Locale,
+) -> Template {
+    Template::render(
+        "password_forgot",
+        &PasswordPage::new(flash, csrf, locale, String::new()),
+    )
+}
+
+#[post("/password/forgot", data = "<forgot_form>")]
+pub async fn forgot_password(
+    forgot_form: CsrfForm<ForgotPasswordForm>,
+    conn: DBPool,
+    outbox: &State<Outbox>,
+    locale: Locale,
+) -> Flash<Redirect> {
+    let email = normalize_email(&forgot_form.email);
+    // même réponse que l'adresse soit inscrite ou non
+    let check_mail = Notification::info(locale.tr("msg-reset-check-mail")).redirect("/login");
+
+    let user = match db::get_user_by_email(&conn, email).await {
+        Ok(user) if user.is_active() => user,
+        _ => return check_mail,
+    };
+    let id = user.id.unwrap_or_default();
+    match db::count_recent_tokens(&conn, id, TOKEN_RESET_PASSWORD, RESET_TOKEN_HOURS).await {
+        Ok(count) if count < MAX_RESET_REQUESTS => (),
+        Ok(_) => {
+            warn_!("Too many password reset requests for {}", user.email);
+            return check_mail;
+        }
+        Err(e) => {
+            error_!("DB reset request error: {}", e);
+            return Notification::error(locale.tr("msg-reset-failed")).redirect("/password/forgot");
+        }
+    }
+
+    let (token, token_hash) = new_mail_token();
+    if let Err(e) = db::create_user_token(
+        &conn,
+        id,
+        TOKEN_RESET_PASSWORD,
+        token_hash,
+        RESET_TOKEN_HOURS,
+    )
+    .await
+    {
+        error_!("DB reset request error: {}", e);
+        return Notification::error(locale.tr("msg-reset-failed")).redirect("/password/forgot");
+    }
+    let mut args = FluentArgs::new();
+    args.set("link", outbox.link(&format!("/password/reset/{}", token)));
+    args.set("hours", RESET_TOKEN_HOURS);
+    let mail = Mail {
+        to: user.email.clone(),
+        subject: locale.tr("mail-reset-subject"),
+        body: locale.tr_args("mail-reset-body", &args),
+    };
+    if let Err(e) = outbox.send(mail).await {
+        error_!("Reset mail error: {}", e);
+        return Notification::error(locale.tr("msg-reset-failed")).redirect("/password/forgot");
+    }
+    check_mail
+}
+
+// la page ne consomme pas le jeton : seul l'envoi du nouveau mot de passe le fait
+#[get("/password/reset/<token>")]
+pub fn reset_password_page(
+    token: String,
+    flash: Option<FlashMessage<'_>>,
+    csrf: CsrfToken,
+    locale: Locale,
+) -> Template {
+    Template::render(
+        "password_reset",
+        &PasswordPage::new(flash, csrf, locale, token),
+    )
+}
+
+#[post("/password/reset", data = "<reset_form>")]
+pub async fn reset_password(
+    reset_form: CsrfForm<ResetPasswordForm>,
+    conn: DBPool,
+    locale: Locale,
+) -> Flash<Redirect> {
+    let form = reset_form.into_inner();
+    let invalid =
+        || Notification::warning(locale.tr("msg-reset-link-invalid")).redirect("/password/forgot");
+    // les jetons sont en base64 "URL safe" : on peut revenir à la page du lien
+    if form.token.is_empty()
+        || !form
+            .token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return invalid();
+    }
+    if let Some(problem) = password_problem(&locale, &form.password, &form.confirm) {
+        return Notification::warning(problem).redirect(format!("/password/reset/{}", form.token));
+    }
+
+    let password_hash = match hash_password(&form.password) {
+        Ok(hash) => hash,
+        Err(e) => {
+            error_!("Password reset error: {}", e);
+            return Notification::error(locale.tr("msg-reset-failed")).redirect("/password/forgot");
+        }
+    };
+    match db::reset_password(&conn, hash_token(&form.token), password_hash).await {
+        Ok(_) => Notification::success(locale.tr("msg-reset-done")).redirect("/login"),
+        Err(diesel::result::Error::NotFound) => invalid(),
+        Err(e) => {
+            error_!("DB password reset error: {}", e);
+            Notification::error(locale.tr("msg-reset-failed")).redirect("/password/forgot")
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use fluent::FluentArgs;
+
+use rocket::fs::TempFile;
+use rocket::http::{Header, Status};
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket::serde::Serialize;
+use rocket::tokio::task::spawn_blocking;
+use rocket::State;
+
+use rocket_dyn_templates::Template;
+
+use crate::auth::{normalize_email, AdminUser};
+use crate::backup::{self, FilesConfig};
+use crate::csrf::{CsrfChecked, CsrfForm, CsrfToken};
+use crate::i18n::Locale;
+use crate::jobs::{JobQueue, REINDEX_JOB};
+use crate::mailer::{Mail, Outbox};
+use crate::models::{Ensemble, EnsembleForm, MemberForm, User, STATUS_ACTIVE, STATUS_APPROVAL};
+use crate::notification::Notification;
+use crate::{db, repo, DBPool};
+
+// Administration
+//
+// pages réservées aux administrateurs : comptes inscrits en attente
+// d'acceptation (voir signup.rs), ensembles et leurs membres (voir ensemble.rs),
+// sauvegarde et restauration du catalogue (voir backup.rs pour le format
+// de l'archive), reconstruction des index de recherche (voir jobs.rs)
+
+#[derive(Debug, Serialize)]
+struct AdminPage {
+    flash: Option<Notification>,
+    title: String,
+    lang: String,
+    csrf_token: String,
+    user: User,
+    awaiting_users: Vec<User>,
+    ensembles: Vec<EnsembleMembers>,
+}
+
+#[derive(Debug, Serialize)]
+struct EnsembleMembers {
+    ensemble: Ensemble,
+    members: Vec<User>,
+}
+
+#[get("/admin")]
+pub async fn admin_page(
+    admin: AdminUser,
+    conn: DBPool,
+    flash: Option<FlashMessage<'_>>,
+    csrf: CsrfToken,
+    locale: Locale,
+) -> Template {
+    let awaiting_users = db::get_users_by_status(&conn, STATUS_APPROVAL)
+        .await
+        .unwrap_or_else(|e| {
+            error_!("DB awaiting users error: {}", e);
+            vec![]
+        });
+    let mut ensembles = vec![];
+    for ensemble in db::get_ensembles(&conn).await.unwrap_or_else(|e| {
+        error_!("DB get_ensembles error: {}", e);
+        vec![]
+    }) {
+        let members = db::get_ensemble_members(&conn, ensemble.id.unwrap_or_default())
+            .await
+            .unwrap_or_else(|e| {
+                error_!("DB get_ensemble_members error: {}", e);
+                vec![]
+            });
+        ensembles.push(EnsembleMembers { ensemble, members });
+    }
+    let page = AdminPage {
+        flash: Notification::from_flash(flash),
+        title: locale.tr("title-admin"),
+        lang: locale.lang().to_string(),
+        csrf_token: csrf.value().to_string(),
+        user: admin.0,
+        awaiting_users,
+        ensembles,
+    };
+    Template::render("admin", &page)
+}
+
+// ********************************************************************************************
+// Accounts awaiting approval
+//
+
+#[post("/admin/users/<id>/approve")]
+pub async fn approve_user(
+    _csrf: CsrfChecked,
+    _admin: AdminUser,
+    id: i32,
+    conn: DBPool,
+    outbox: &State<Outbox>,
+    locale: Locale,
+) -> Flash<Redirect> {
+    match db::get_user(&conn, id).await {
+        Ok(user) if user.status == STATUS_APPROVAL => (),
+        _ => return Notification::warning(locale.tr("msg-user-not-awaiting")).redirect("/admin"),
+    }
+    let user = match db::set_user_status(&conn, id, STATUS_ACTIVE).await {
+        Ok(user) => user,
+        Err(e) => {
+            error_!("DB approve error: {}", e);
+            return Notification::error(locale.tr("msg-user-approve-failed")).redirect("/admin");
+        }
+    };
+
+    let mut args = FluentArgs::new();
+    args.set("email", user.email.clone());
+    args.set("link", outbox.link("/login"));
+    let mail = Mail {
+        to: user.email.clone(),
+        subject: locale.tr("mail-approved-subject"),
+        body: locale.tr_args("mail-approved-body", &args),
+    };
+    // le compte est actif même si le mail ne part pas
+    if let Err(e) = outbox.send(mail).await {
+        error_!("Approval mail error: {}", e);
+    }
+    Notification::success(locale.tr_args("msg-user-approved", &args)).redirect("/admin")
+}
+
+#[delete("/admin/users/<id>")]
+pub async fn reject_user(
+    _csrf: CsrfChecked,
+    _admin: AdminUser,
+    id: i32,
+    conn: DBPool,
+    locale: Locale,
+) -> Flash<Redirect> {
+    // seul un compte en attente peut être refusé ici
+    let user = match db::get_user(&conn, id).await {
+        Ok(user) if user.status == STATUS_APPROVAL => user,
+        _ => return Notification::warning(locale.tr("msg-user-not-awaiting")).redirect("/admin"),
+    };
+    let mut args = FluentArgs::new();
+    args.set("email", user.email);
+    match db::delete_user(&conn, id).await {
+        Ok(_) => {
+            Notification::success(locale.tr_args("msg-user-rejected", &args)).redirect("/admin")
+        }
+        Err(e) => {
+            error_!("DB reject error: {}", e);
+            Notification::error(locale.tr("msg-user-approve-failed")).redirect("/admin")
+        }
+    }
+}
+
+// ********************************************************************************************
+// Ensembles and their members
+//
+
+#[post("/admin/ensembles", data = "<ensemble_form>")]
+pub async fn new_ensemble(
+    _admin: AdminUser,
+    ensemble_form: CsrfForm<EnsembleForm>,
+    conn: DBPool,
+    locale: Locale,
+) -> Flash<Redirect> {
+    let name = ensemble_form.into_inner().name;
+    if name.trim().is_empty() {
+        return Notification::warning(locale.tr("msg-ensemble-name-missing")).redirect("/admin");
+    }
+    let mut args = FluentArgs::new();
+    args.set("name", name.trim().to_string());
+    match db::create_ensemble(&conn, name).await {
+        Ok(_) => {
+            Notification::success(locale.tr_args("msg-ensemble-added", &args)).redirect("/admin")
+        }
+        Err(e) if repo::is_unique_violation(&e) => {
+            Notification::warning(locale.tr_args("msg-ensemble-exists", &args)).redirect("/admin")
+        }
+        Err(e) => {
+            error_!("DB create_ensemble error: {}", e);
+            Notification::error(locale.tr("msg-ensemble-add-failed")).redirect("/admin")
+        }
+    }
+}
+
+#[post("/admin/ensembles/<id>/members", data = "<member_form>")]
+pub async fn add_member(
+    _admin: AdminUser,
+    id: i32,
+    member_form: CsrfForm<MemberForm>,
+    conn: DBPool,
+    locale: Locale,
+) -> Flash<Redirect> {
+    let email = normalize_email(&member_form.email);
+    let mut args = FluentArgs::new();
+    args.set("email", email.clone());
+    let user = match db::get_user_by_email(&conn, email).await {
+        Ok(user) => user,
+        Err(diesel::result::Error::NotFound) => {
+            return Notification::warning(locale.tr_args("msg-member-unknown", &args))
+                .redirect("/admin")
+        }
+        Err(e) => {
+            error_!("DB get_user_by_email error: {}", e);
+            return Notification::error(locale.tr("msg-member-failed")).redirect("/admin");
+        }
+    };
+    match db::add_ensemble_member(&conn, id, user.id.unwrap_or_default()).await {
+        Ok(0) => Notification::info(locale.tr_args("msg-member-already", &args)).redirect("/admin"),
+        Ok(_) => {
+            Notification::success(locale.tr_args("msg-member-added", &args)).redirect("/admin")
+        }
+        Err(e) => {
+            error_!("DB add_ensemble_member error: {}", e);
+            Notification::error(locale.tr("msg-member-failed")).redirect("/admin")
+        }
+    }
+}
+
+#[delete("/admin/ensembles/<id>/members/<user_id>")]
+pub async fn remove_member(
+    _csrf: CsrfChecked,
+    _admin: AdminUser,
+    id: i32,
+    user_id: i32,
+    conn: DBPool,
+    locale: Locale,
+) -> Flash<Redirect> {
+    match db::remove_ensemble_member(&conn, id, user_id).await {
+        Ok(_) => Notification::success(locale.tr("msg-member-removed")).redirect("/admin"),
+        Err(e) => {
+            error_!("DB remove_ensemble_member error: {}", e);
+            Notification::error(locale.tr("msg-member-failed")).redirect("/admin")
+        }
+    }
+}
+
+// ********************************************************************************************
+// Backup
+//
+
+#[derive(Responder)]
+#[response(content_type = "application/zip")]
+pub struct ArchiveDownload(Vec<u8>, Header<'static>);
+
+#[get("/admin/backup")]
+pub async fn download_backup(
+    _admin: AdminUser,
+    conn: DBPool,
+    config: &State<FilesConfig>,
+) -> Result<ArchiveDownload, Status> {
+    let snapshot = db::load_snapshot(&conn).await.map_err(|e| {
+        error_!("DB backup error: {}", e);
+        Status::InternalServerError
+    })?;
+
+    let upload_dir = config.upload_dir.clone();
+    let archive = spawn_blocking(move || backup::write_archive(&snapshot, &upload_dir))
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .map_err(|e| {
+            error_!("Backup archive error: {}", e);
+            Status::InternalServerError
+        })?;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let disposition = format!(
+        "attachment; filename=\"{}-{}.zip\"",
+        backup::FORMAT,
+        timestamp
+    );
+    Ok(ArchiveDownload(
+        archive,
+        Header::new("Content-Disposition", disposition),
+    ))
+}
+
+// ********************************************************************************************
+// Restore
+//
+
+#[derive(FromForm)]
+pub struct RestoreForm<'r> {
+    archive: TempFile<'r>,
+}
+
+#[post("/admin/restore", data = "<restore_form>")]
+pub async fn restore_backup(
+    _admin: AdminUser,
+    mut restore_form: CsrfForm<RestoreForm<'_>>,
+    conn: DBPool,
+    config: &State<FilesConfig>,
+    locale: Locale,
+) -> Flash<Redirect> {
+    let failed = |e: &dyn std::fmt::Display| {
+        error_!("Restore error: {}", e);
+        Notification::error(locale.tr("msg-restore-failed")).redirect("/admin")
+    };
+
+    // le fichier envoyé peut être en mémoire ou sur disque : on le copie
+    // dans un fichier temporaire à nous pour le lire d'un bloc
+    let copy = std::env::temp_dir().join(format!("restore-{}.zip", rand::random::<u64>()));
+    if let Err(e) = restore_form.archive.copy_to(&copy).await {
+        return failed(&e);
+    }
+    let read = spawn_blocking(move || {
+        let bytes = std::fs::read(&copy);
+        let _ = std::fs::remove_file(&copy);
+        backup::read_archive(&bytes?)
+    })
+    .await;
+    let archive = match read {
+        Ok(Ok(archive)) => archive,
+        Ok(Err(e)) => return failed(&e),
+        Err(e) => return failed(&e),
+    };
+    info_!(
+        "Restoring backup v{} from hello-rocket {} ({} partitions, {} files)",
+        archive.manifest.version,
+        archive.manifest.app_version,
+        archive.manifest.partitions,
+        archive.manifest.files
+    );
+
+    let report = match db::restore_archive(&conn, archive, config.upload_dir.clone()).await {
+        Ok(report) => report,
+        Err(e) => return failed(&e),
+    };
+
+    let existing = report.persons_existing
+        + report.genres_existing
+        + report.partitions_existing
+        + report.users_existing;
+    let mut args = FluentArgs::new();
+    args.set("persons", report.persons_added);
+    args.set("genres", report.genres_added);
+    args.set("partitions", report.partitions_added);
+    args.set("users", report.users_added);
+    args.set("existing", existing);
+    args.set("files", report.files);
+    Notification::success(locale.tr_args("msg-restore-ok", &args)).redirect("/admin")
+}
+
+// ********************************************************************************************
+// Search indexes
+//
+
+// les clés de recherche et les index sont refaits par une tâche de fond
+#[post("/admin/reindex")]
+pub async fn reindex(
+    _csrf: CsrfChecked,
+    _admin: AdminUser,
+    conn: DBPool,
+    queue: &State<JobQueue>,
+    locale: Locale,
+) -> Flash<Redirect> {
+    match queue.push(&conn, REINDEX_JOB, None, &()).await {
+        Ok(job) => {
+            let mut args = FluentArgs::new();
+            args.set("id", job.id);
+            Notification::success(locale.tr_args("msg-reindex-queued", &args))
+                .redirect(format!("/jobs/{}", job.id))
+        }
+        Err(e) => {
+            error_!("Reindex job not queued: {}", e);
+            Notification::error(locale.tr("msg-reindex-failed")).redirect("/admin")
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use rocket::http::Status;
+use rocket::outcome::try_outcome;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::status::Created;
+use rocket::serde::json::Json;
+use rocket::serde::Serialize;
+use rocket::State;
+
+use crate::auth::hash_token;
+use crate::csrf::generate_token;
+use crate::jobs::JobQueue;
+use crate::models::{ApiScope, Genre, NewPartition, PartitionSearch, Person, ShowPartition, User};
+use crate::webhooks;
+use crate::{db, repo, DBPool};
+
+// API
+//
+// le catalogue en JSON pour les scripts (import de nuit ...), sans cookie
+// de session : chaque requête porte un jeton personnel, créé et révoqué
+// sur la page du compte (account.rs)
+//
+//   curl -H "Authorization: Bearer hr_..." http://localhost:8000/api/partitions
+//
+// un jeton "read" ne permet que les lectures, un jeton "write" aussi les
+// ajouts au catalogue, dans la bibliothèque de l'ensemble choisi à la
+// création du jeton. Ces requêtes ne passent pas par la vérification CSRF
+// (voir csrf.rs) : un navigateur n'envoie jamais cet en-tête de lui-même.
+
+pub const API_TOKEN_PREFIX: &str = "hr_";
+
+// un nouveau jeton et son hachage, le seul gardé en base ;
+// le préfixe aide à reconnaître un jeton égaré (dépôt, journal ...)
+pub fn new_api_token() -> (String, String) {
+    let token = format!("{}{}", API_TOKEN_PREFIX, generate_token());
+    let hash = hash_token(&token);
+    (token, hash)
+}
+
+fn bearer_token(header: &str) -> Option<&str> {
+    let token = header.strip_prefix("Bearer ")?.trim();
+    if token.starts_with(API_TOKEN_PREFIX) {
+        Some(token)
+    } else {
+        None
+    }
+}
+
+// la requête vient d'un script : l'en-tête Authorization porte un jeton
+pub fn has_bearer_token(req: &Request<'_>) -> bool {
+    req.headers()
+        .get_one("Authorization")
+        .and_then(bearer_token)
+        .is_some()
+}
+
+// ********************************************************************************************
+// Guards
+//
+
+// le propriétaire d'un jeton valide (ni expiré, ni révoqué) dont le compte est actif,
+// et toujours membre de l'ensemble du jeton
+pub struct ApiUser {
+    pub user: User,
+    pub scope: ApiScope,
+    pub ensemble_id: i32,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiUser {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let token = match req
+            .headers()
+            .get_one("Authorization")
+            .and_then(bearer_token)
+        {
+            Some(token) => token,
+            None => return Outcome::Failure((Status::Unauthorized, ())),
+        };
+        let conn = match req.guard::<DBPool>().await.succeeded() {
+            Some(conn) => conn,
+            None => return Outcome::Failure((Status::ServiceUnavailable, ())),
+        };
+
+        let (api_token, user) = match db::use_api_token(&conn, hash_token(token)).await {
+            Ok((api_token, user)) if user.is_active() => (api_token, user),
+            Ok(_) | Err(diesel::result::Error::NotFound) => {
+                return Outcome::Failure((Status::Unauthorized, ()))
+            }
+            Err(e) => {
+                error_!("DB API token error: {}", e);
+                return Outcome::Failure((Status::InternalServerError, ()));
+            }
+        };
+        // administrateur ou non, il faut être membre (voir ensemble::Member)
+        let ensemble_id = api_token.ensemble_id;
+        let user_id = user.id.unwrap_or_default();
+        let allowed = match db::is_ensemble_member(&conn, ensemble_id, user_id).await {
+            Ok(allowed) => allowed,
+            Err(e) => {
+                error_!("DB API token ensemble error: {}", e);
+                return Outcome::Failure((Status::InternalServerError, ()));
+            }
+        };
+        match ApiScope::parse(&api_token.scope) {
+            Some(scope) if allowed => Outcome::Success(ApiUser {
+                user,
+                scope,
+                ensemble_id,
+            }),
+            _ => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+// un jeton qui permet les ajouts au catalogue
+pub struct ApiWriter(pub ApiUser);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiWriter {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let api_user = try_outcome!(req.guard::<ApiUser>().await);
+        if api_user.scope == ApiScope::Write {
+            Outcome::Success(ApiWriter(api_user))
+        } else {
+            Outcome::Failure((Status::Forbidden, ()))
+        }
+    }
+}
+
+// ********************************************************************************************
+// Errors
+//
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ApiError {
+    error: String,
+}
+
+type ApiResult<T> = Result<T, (Status, Json<ApiError>)>;
+
+fn api_error(status: Status, message: &str) -> (Status, Json<ApiError>) {
+    (
+        status,
+        Json(ApiError {
+            error: message.to_string(),
+        }),
+    )
+}
+
+fn db_error(e: diesel::result::Error) -> (Status, Json<ApiError>) {
+    error_!("DB API error: {}", e);
+    api_error(Status::InternalServerError, "database error")
+}
+
+// les erreurs des guards, en JSON plutôt que la redirection vers /login
+#[catch(401)]
+pub fn api_unauthorized() -> Json<ApiError> {
+    Json(ApiError {
+        error: "missing, invalid or expired API token".to_string(),
+    })
+}
+
+#[catch(403)]
+pub fn api_forbidden() -> Json<ApiError> {
+    Json(ApiError {
+        error: "this API token is read-only".to_string(),
+    })
+}
+
+// ********************************************************************************************
+// Read
+//
+
+#[get("/api/persons")]
+pub async fn api_persons(_api: ApiUser, conn: DBPool) -> ApiResult<Json<Vec<Person>>> {
+    db::get_list_persons(&conn)
+        .await
+        .map(Json)
+        .map_err(db_error)
+}
+
+#[get("/api/genres")]
+pub async fn api_genres(api: ApiUser, conn: DBPool) -> ApiResult<Json<Vec<Genre>>> {
+    db::get_list_genres(&conn, api.ensemble_id)
+        .await
+        .map(Json)
+        .map_err(db_error)
+}
+
+// mêmes critères que la page : /api/partitions?q=...&author=...&genre=...
+#[get("/api/partitions?<search..>")]
+pub async fn api_partitions(
+    api: ApiUser,
+    search: PartitionSearch,
+    conn: DBPool,
+) -> ApiResult<Json<Vec<ShowPartition>>> {
+    let search = search.normalized();
+    let result = if search.is_empty() {
+        db::get_list_show_partitions(&conn, api.ensemble_id).await
+    } else {
+        db::search_partitions(&conn, api.ensemble_id, search).await
+    };
+    result.map(Json).map_err(db_error)
+}
+
+// ********************************************************************************************
+// Write
+//
+
+#[post("/api/persons", format = "json", data = "<person>")]
+pub async fn api_new_person(
+    api: ApiWriter,
+    person: Json<Person>,
+    conn: DBPool,
+    queue: &State<JobQueue>,
+) -> ApiResult<Created<Json<Person>>> {
+    let person = person.into_inner();
+    if person.full_name.trim().is_empty() {
+        return Err(api_error(Status::UnprocessableEntity, "full_name is empty"));
+    }
+    match db::create_person(&conn, person).await {
+        Ok(person) => {
+            webhooks::notify(&conn, queue, None, webhooks::PERSON_CREATED, &person).await;
+            let location = format!("/persons/{}", person.id.unwrap_or_default());
+            info_!("API: {} added by {}", location, api.0.user.email);
+            Ok(Created::new(location).body(Json(person)))
+        }
+        Err(e) if repo::is_unique_violation(&e) => {
+            Err(api_error(Status::Conflict, "this person already exists"))
+        }
+        Err(e) => Err(db_error(e)),
+    }
+}
+
+#[post("/api/genres", format = "json", data = "<genre>")]
+pub async fn api_new_genre(
+    api: ApiWriter,
+    genre: Json<Genre>,
+    conn: DBPool,
+    queue: &State<JobQueue>,
+) -> ApiResult<Created<Json<Genre>>> {
+    let mut genre = genre.into_inner();
+    if genre.name.trim().is_empty() {
+        return Err(api_error(Status::UnprocessableEntity, "name is empty"));
+    }
+    // un genre de l'ensemble du jeton : les genres communs restent aux
+    // administrateurs, sur le site (voir handlers::new_genre)
+    genre.ensemble_id = Some(api.0.ensemble_id);
+    match db::create_genre(&conn, genre).await {
+        Ok(genre) => {
+            let scope = genre.ensemble_id;
+            webhooks::notify(&conn, queue, scope, webhooks::GENRE_CREATED, &genre).await;
+            let location = format!("/genres/{}", genre.id.unwrap_or_default());
+            info_!("API: {} added by {}", location, api.0.user.email);
+            Ok(Created::new(location).body(Json(genre)))
+        }
+        Err(e) if repo::is_unique_violation(&e) => {
+            Err(api_error(Status::Conflict, "this genre already exists"))
+        }
+        Err(e) => Err(db_error(e)),
+    }
+}
+
+// { "title": ..., "full_name": compositeur, "name": genre, "create_missing": true }
+#[post("/api/partitions", format = "json", data = "<new_partition>")]
+pub async fn api_new_partition(
+    api: ApiWriter,
+    new_partition: Json<NewPartition>,
+    conn: DBPool,
+    queue: &State<JobQueue>,
+) -> ApiResult<Created<Json<ShowPartition>>> {
+    let new_partition = new_partition.into_inner();
+    if new_partition.title.trim().is_empty() {
+        return Err(api_error(Status::UnprocessableEntity, "title is empty"));
+    }
+    match db::create_partition(&conn, api.0.ensemble_id, new_partition).await {
+        Ok(partition) => {
+            let scope = Some(api.0.ensemble_id);
+            webhooks::notify(&conn, queue, scope, webhooks::PARTITION_CREATED, &partition).await;
+            let location = format!("/partitions/{}", partition.id.unwrap_or_default());
+            info_!("API: {} added by {}", location, api.0.user.email);
+            Ok(Created::new(location).body(Json(partition)))
+        }
+        Err(diesel::result::Error::NotFound) => Err(api_error(
+            Status::NotFound,
+            "unknown composer or genre (see create_missing)",
+        )),
+        Err(e) if repo::is_unique_violation(&e) => Err(api_error(
+            Status::Conflict,
+            "this composer already has a partition with this title",
+        )),
+        Err(e) => Err(db_error(e)),
+    }
+}
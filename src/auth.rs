@@ -0,0 +1,234 @@
+use argonautica::{Hasher, Verifier};
+use fluent::FluentArgs;
+use sha2::{Digest, Sha256};
+
+use rocket::fairing;
+use rocket::http::{Cookie, CookieJar, Status};
+use rocket::outcome::try_outcome;
+use rocket::request::{FlashMessage, FromRequest, Outcome, Request};
+use rocket::response::{Flash, Redirect};
+use rocket::serde::Serialize;
+use rocket::{Build, Rocket};
+
+use rocket_dyn_templates::Template;
+
+use crate::csrf::{generate_token, CsrfForm, CsrfToken};
+use crate::i18n::{remember_locale, Locale};
+use crate::models::{LoginForm, User, STATUS_ACTIVE, STATUS_APPROVAL};
+use crate::notification::Notification;
+use crate::{db, DBPool};
+
+// Authentification
+//
+// l'id de l'utilisateur connecté est gardé dans un cookie privé ;
+// les routes réservées demandent le guard `User` (connecté)
+// ou `AdminUser` (administrateur). Sans session, le catcher 401
+// renvoie vers la page de connexion.
+//
+// un compte inscrit en ligne (signup.rs) n'ouvre de session qu'une fois actif.
+//
+// le premier administrateur peut être créé au démarrage avec
+// `admin_email` et `admin_password` (Rocket.toml ou ROCKET_ADMIN_EMAIL, ...)
+
+pub const USER_COOKIE: &str = "user_id";
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+pub fn hash_password(password: &str) -> Result<String, argonautica::Error> {
+    Hasher::default()
+        .opt_out_of_secret_key(true)
+        .with_password(password)
+        .hash()
+}
+
+pub fn verify_password(hash: &str, password: &str) -> bool {
+    Verifier::default()
+        .with_hash(hash)
+        .with_password(password)
+        .verify()
+        .unwrap_or(false)
+}
+
+// un nouveau mot de passe (inscription, changement, réinitialisation) :
+// le message à afficher s'il ne convient pas
+pub fn password_problem(locale: &Locale, password: &str, confirm: &str) -> Option<String> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        let mut args = FluentArgs::new();
+        args.set("min", MIN_PASSWORD_LEN);
+        return Some(locale.tr_args("msg-password-short", &args));
+    }
+    if password != confirm {
+        return Some(locale.tr("msg-password-mismatch"));
+    }
+    None
+}
+
+// les adresses sont comparées en minuscules, sans espaces autour
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+// les liens envoyés par mail portent un jeton aléatoire ; la base n'en garde
+// que le hachage, inutilisable si elle fuit
+pub fn new_mail_token() -> (String, String) {
+    let token = generate_token();
+    let hash = hash_token(&token);
+    (token, hash)
+}
+
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for User {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        // une seule lecture en base par requête, même si plusieurs guards la demandent
+        let user = req
+            .local_cache_async(async {
+                let id = req
+                    .cookies()
+                    .get_private(USER_COOKIE)
+                    .and_then(|cookie| cookie.value().parse::<i32>().ok())?;
+                let conn = req.guard::<DBPool>().await.succeeded()?;
+                db::get_user(&conn, id).await.ok().filter(User::is_active)
+            })
+            .await;
+
+        match user {
+            Some(user) => Outcome::Success(user.clone()),
+            None => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+pub struct AdminUser(pub User);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminUser {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let user = try_outcome!(req.guard::<User>().await);
+        if user.is_admin {
+            Outcome::Success(AdminUser(user))
+        } else {
+            Outcome::Failure((Status::Forbidden, ()))
+        }
+    }
+}
+
+// ********************************************************************************************
+// Login / logout
+//
+
+#[derive(Debug, Serialize)]
+struct LoginPage {
+    flash: Option<Notification>,
+    title: String,
+    lang: String,
+    csrf_token: String,
+}
+
+#[get("/login")]
+pub fn login_page(flash: Option<FlashMessage<'_>>, csrf: CsrfToken, locale: Locale) -> Template {
+    let page = LoginPage {
+        flash: Notification::from_flash(flash),
+        title: locale.tr("title-login"),
+        lang: locale.lang().to_string(),
+        csrf_token: csrf.value().to_string(),
+    };
+    Template::render("login", &page)
+}
+
+#[post("/login", data = "<login_form>")]
+pub async fn login(
+    login_form: CsrfForm<LoginForm>,
+    conn: DBPool,
+    cookies: &CookieJar<'_>,
+    locale: Locale,
+) -> Flash<Redirect> {
+    let form = login_form.into_inner();
+
+    match db::get_user_by_email(&conn, normalize_email(&form.email)).await {
+        Ok(user) if verify_password(&user.password_hash, &form.password) => {
+            if !user.is_active() {
+                let key = if user.status == STATUS_APPROVAL {
+                    "msg-login-awaiting-approval"
+                } else {
+                    "msg-login-unverified"
+                };
+                return Notification::warning(locale.tr(key)).redirect("/login");
+            }
+            let id = user.id.unwrap_or_default();
+            cookies.add_private(Cookie::new(USER_COOKIE, id.to_string()));
+            remember_locale(cookies, &user.locale);
+            Notification::success(locale.tr("msg-login-ok")).redirect("/")
+        }
+        // même message que l'adresse soit inconnue ou le mot de passe faux
+        _ => Notification::error(locale.tr("msg-login-failed")).redirect("/login"),
+    }
+}
+
+#[get("/logout")]
+pub fn logout(cookies: &CookieJar<'_>, locale: Locale) -> Flash<Redirect> {
+    cookies.remove_private(Cookie::named(USER_COOKIE));
+    Notification::info(locale.tr("msg-logout-ok")).redirect("/")
+}
+
+#[catch(401)]
+pub fn unauthorized(req: &Request<'_>) -> Flash<Redirect> {
+    let locale = Locale::detect(req);
+    Notification::warning(locale.tr("msg-login-required")).redirect("/login")
+}
+
+// ********************************************************************************************
+// First administrator
+//
+
+pub async fn create_configured_admin(rocket: Rocket<Build>) -> fairing::Result {
+    let figment = rocket.figment();
+    let (email, password) = match (
+        figment.extract_inner::<String>("admin_email"),
+        figment.extract_inner::<String>("admin_password"),
+    ) {
+        (Ok(email), Ok(password)) => (normalize_email(&email), password),
+        _ => return Ok(rocket),
+    };
+
+    let conn = match DBPool::get_one(&rocket).await {
+        Some(conn) => conn,
+        None => return Err(rocket),
+    };
+    if db::get_user_by_email(&conn, email.clone()).await.is_ok() {
+        return Ok(rocket);
+    }
+
+    let password_hash = match hash_password(&password) {
+        Ok(hash) => hash,
+        Err(e) => {
+            error_!("Failed to hash the administrator password: {}", e);
+            return Err(rocket);
+        }
+    };
+    let admin = User {
+        id: None,
+        email,
+        password_hash,
+        is_admin: true,
+        status: STATUS_ACTIVE.to_string(),
+        display_name: String::new(),
+        locale: String::new(),
+    };
+    match db::create_user(&conn, admin).await {
+        Ok(admin) => {
+            info_!("Administrator {} created", admin.email);
+            Ok(rocket)
+        }
+        Err(e) => {
+            error_!("Failed to create the administrator: {}", e);
+            Err(rocket)
+        }
+    }
+}
@@ -0,0 +1,696 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Cursor, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use diesel::sql_types::{Array, Bool, Integer, Nullable, Text};
+use diesel::QueryableByName;
+
+use rocket::serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::models::{Ensemble, Genre, PartitionDetails, Person, Tag, User, STATUS_ACTIVE};
+
+// Backup and restore
+//
+// une archive zip portable :
+//   manifest.json          format, version, date, nombre de lignes
+//   tables/<table>.json    une liste d'objets par table de schema.rs
+//                          (tags et partition_tags depuis la version 2,
+//                          ensembles et ensemble_members depuis la version 3,
+//                          partitions.shared depuis la version 4,
+//                          partition_details, partition_creators et
+//                          partition_files depuis la version 5,
+//                          partition_details.incipit depuis la version 6,
+//                          loans depuis la version 7,
+//                          webhooks depuis la version 8)
+//   files/...              les fichiers envoyés (dossier `upload_dir`)
+//
+// le journal des envois (webhook_deliveries) n'est pas sauvegardé, comme la
+// file des travaux : il ne sert qu'à relancer et montrer les derniers envois.
+//
+// la restauration (db::restore_archive) garde les lignes déjà présentes
+// et renumérote les autres : les id de l'archive ne sont jamais réutilisés.
+// De même, un fichier déjà présent dans upload_dir n'est jamais remplacé.
+
+pub const FORMAT: &str = "hello-rocket-backup";
+pub const FORMAT_VERSION: u32 = 8;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const TABLES_DIR: &str = "tables/";
+const FILES_DIR: &str = "files/";
+
+// les tailles décompressées permises à la lecture, par fichier de l'archive
+// (un envoi est limité à 64 Mio, voir [default.limits]) et en tout : au-delà,
+// ce n'est pas une sauvegarde mais une bombe zip
+pub const MAX_ENTRY_BYTES: u64 = 64 * 1024 * 1024;
+pub const MAX_ARCHIVE_BYTES: u64 = 512 * 1024 * 1024;
+
+// réglages lus dans Rocket.toml (ou ROCKET_UPLOAD_DIR)
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct FilesConfig {
+    #[serde(default = "default_upload_dir")]
+    pub upload_dir: PathBuf,
+}
+
+fn default_upload_dir() -> PathBuf {
+    PathBuf::from("uploads")
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Manifest {
+    pub format: String,
+    pub version: u32,
+    pub app_version: String,
+    // secondes depuis le 1er janvier 1970 (UTC)
+    pub created_at: u64,
+    pub persons: usize,
+    pub genres: usize,
+    pub partitions: usize,
+    pub users: usize,
+    #[serde(default)]
+    pub tags: usize,
+    #[serde(default)]
+    pub ensembles: usize,
+    pub files: usize,
+}
+
+// ***********************************************************************************************
+// Records : une ligne de table telle qu'elle est écrite dans l'archive
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PersonRecord {
+    pub id: i32,
+    pub full_name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct GenreRecord {
+    pub id: i32,
+    pub name: String,
+    // None : genre commun (et toujours avant la version 3)
+    #[serde(default)]
+    pub ensemble_id: Option<i32>,
+}
+
+// created_at est lue en texte (ISO 8601) par sql_query
+#[derive(Debug, Clone, Serialize, Deserialize, QueryableByName)]
+#[serde(crate = "rocket::serde")]
+pub struct PartitionRecord {
+    #[sql_type = "Integer"]
+    pub id: i32,
+    #[sql_type = "Integer"]
+    pub person_id: i32,
+    #[sql_type = "Text"]
+    pub title: String,
+    #[sql_type = "Integer"]
+    pub genre_id: i32,
+    #[sql_type = "Nullable<Text>"]
+    pub created_at: Option<String>,
+    // absent avant la version 3 : l'ensemble par défaut
+    #[serde(default)]
+    #[sql_type = "Nullable<Integer>"]
+    pub ensemble_id: Option<i32>,
+    // montrée aux autres ensembles ; absent avant la version 4
+    #[serde(default)]
+    #[sql_type = "Bool"]
+    pub shared: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct UserRecord {
+    pub id: i32,
+    pub email: String,
+    pub password_hash: String,
+    pub is_admin: bool,
+    // absent des archives d'avant l'inscription en ligne : comptes actifs
+    #[serde(default = "default_user_status")]
+    pub status: String,
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub locale: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TagRecord {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PartitionTagRecord {
+    pub partition_id: i32,
+    pub tag_id: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct EnsembleRecord {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct EnsembleMemberRecord {
+    pub ensemble_id: i32,
+    pub user_id: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PartitionDetailsRecord {
+    pub partition_id: i32,
+    pub instrumentation: String,
+    pub key_signature: String,
+    pub time_signature: String,
+    // l'incipit ABC ; absent avant la version 6. Sa clé de recherche
+    // est recalculée à la restauration
+    #[serde(default)]
+    pub incipit: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PartitionCreatorRecord {
+    pub partition_id: i32,
+    pub person_id: i32,
+    pub role: String,
+}
+
+// le fichier lui-même est sous files/, à son chemin `path`
+#[derive(Debug, Clone, Serialize, Deserialize, QueryableByName)]
+#[serde(crate = "rocket::serde")]
+pub struct PartitionFileRecord {
+    #[sql_type = "Integer"]
+    pub partition_id: i32,
+    #[sql_type = "Text"]
+    pub name: String,
+    #[sql_type = "Text"]
+    pub path: String,
+    #[sql_type = "Text"]
+    pub content_type: String,
+    #[sql_type = "Nullable<Text>"]
+    pub created_at: Option<String>,
+}
+
+// une demande de prêt ; dates lues en texte comme pour les partitions
+#[derive(Debug, Clone, Serialize, Deserialize, QueryableByName)]
+#[serde(crate = "rocket::serde")]
+pub struct LoanRecord {
+    #[sql_type = "Integer"]
+    pub partition_id: i32,
+    // l'ensemble qui emprunte
+    #[sql_type = "Integer"]
+    pub ensemble_id: i32,
+    #[sql_type = "Nullable<Integer>"]
+    pub requested_by: Option<i32>,
+    #[sql_type = "Text"]
+    pub message: String,
+    #[sql_type = "Text"]
+    pub status: String,
+    #[sql_type = "Nullable<Text>"]
+    pub created_at: Option<String>,
+    #[sql_type = "Nullable<Text>"]
+    pub decided_at: Option<String>,
+}
+
+// un abonnement aux événements du catalogue, secret compris : les
+// destinataires vérifient la signature avec lui
+#[derive(Debug, Clone, Serialize, Deserialize, QueryableByName)]
+#[serde(crate = "rocket::serde")]
+pub struct WebhookRecord {
+    #[sql_type = "Integer"]
+    pub ensemble_id: i32,
+    #[sql_type = "Text"]
+    pub url: String,
+    #[sql_type = "Text"]
+    pub secret: String,
+    #[sql_type = "Array<Text>"]
+    pub events: Vec<String>,
+    #[sql_type = "Bool"]
+    pub active: bool,
+    #[sql_type = "Nullable<Text>"]
+    pub created_at: Option<String>,
+}
+
+impl From<Person> for PersonRecord {
+    fn from(person: Person) -> PersonRecord {
+        PersonRecord {
+            id: person.id.unwrap_or_default(),
+            full_name: person.full_name,
+        }
+    }
+}
+
+impl From<Genre> for GenreRecord {
+    fn from(genre: Genre) -> GenreRecord {
+        GenreRecord {
+            id: genre.id.unwrap_or_default(),
+            name: genre.name,
+            ensemble_id: genre.ensemble_id,
+        }
+    }
+}
+
+impl From<User> for UserRecord {
+    fn from(user: User) -> UserRecord {
+        UserRecord {
+            id: user.id.unwrap_or_default(),
+            email: user.email,
+            password_hash: user.password_hash,
+            is_admin: user.is_admin,
+            status: user.status,
+            display_name: user.display_name,
+            locale: user.locale,
+        }
+    }
+}
+
+fn default_user_status() -> String {
+    STATUS_ACTIVE.to_string()
+}
+
+impl From<Tag> for TagRecord {
+    fn from(tag: Tag) -> TagRecord {
+        TagRecord {
+            id: tag.id.unwrap_or_default(),
+            name: tag.name,
+        }
+    }
+}
+
+impl From<Ensemble> for EnsembleRecord {
+    fn from(ensemble: Ensemble) -> EnsembleRecord {
+        EnsembleRecord {
+            id: ensemble.id.unwrap_or_default(),
+            name: ensemble.name,
+        }
+    }
+}
+
+impl From<(i32, i32)> for EnsembleMemberRecord {
+    fn from((ensemble_id, user_id): (i32, i32)) -> EnsembleMemberRecord {
+        EnsembleMemberRecord {
+            ensemble_id,
+            user_id,
+        }
+    }
+}
+
+impl From<PartitionDetails> for PartitionDetailsRecord {
+    fn from(details: PartitionDetails) -> PartitionDetailsRecord {
+        PartitionDetailsRecord {
+            partition_id: details.partition_id,
+            instrumentation: details.instrumentation,
+            key_signature: details.key_signature,
+            time_signature: details.time_signature,
+            incipit: details.incipit,
+        }
+    }
+}
+
+impl From<(i32, i32, String)> for PartitionCreatorRecord {
+    fn from((partition_id, person_id, role): (i32, i32, String)) -> PartitionCreatorRecord {
+        PartitionCreatorRecord {
+            partition_id,
+            person_id,
+            role,
+        }
+    }
+}
+
+impl From<(i32, i32)> for PartitionTagRecord {
+    fn from((partition_id, tag_id): (i32, i32)) -> PartitionTagRecord {
+        PartitionTagRecord {
+            partition_id,
+            tag_id,
+        }
+    }
+}
+
+// le contenu de toutes les tables
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    pub persons: Vec<PersonRecord>,
+    pub genres: Vec<GenreRecord>,
+    pub partitions: Vec<PartitionRecord>,
+    pub users: Vec<UserRecord>,
+    pub tags: Vec<TagRecord>,
+    pub partition_tags: Vec<PartitionTagRecord>,
+    pub ensembles: Vec<EnsembleRecord>,
+    pub ensemble_members: Vec<EnsembleMemberRecord>,
+    pub partition_details: Vec<PartitionDetailsRecord>,
+    pub partition_creators: Vec<PartitionCreatorRecord>,
+    pub partition_files: Vec<PartitionFileRecord>,
+    pub loans: Vec<LoanRecord>,
+    pub webhooks: Vec<WebhookRecord>,
+}
+
+// ce que la restauration a ajouté ou retrouvé
+#[derive(Debug, Default)]
+pub struct RestoreReport {
+    pub persons_added: usize,
+    pub persons_existing: usize,
+    pub genres_added: usize,
+    pub genres_existing: usize,
+    pub partitions_added: usize,
+    pub partitions_existing: usize,
+    pub users_added: usize,
+    pub users_existing: usize,
+    pub files: usize,
+}
+
+// ***********************************************************************************************
+// Errors
+
+#[derive(Debug)]
+pub enum BackupError {
+    Io(io::Error),
+    Zip(zip::result::ZipError),
+    Json(serde_json::Error),
+    // l'archive n'est pas une sauvegarde lisible par cette version
+    Format(String),
+    Db(diesel::result::Error),
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupError::Io(e) => write!(f, "I/O error: {}", e),
+            BackupError::Zip(e) => write!(f, "zip error: {}", e),
+            BackupError::Json(e) => write!(f, "JSON error: {}", e),
+            BackupError::Format(msg) => write!(f, "invalid archive: {}", msg),
+            BackupError::Db(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BackupError {}
+
+impl From<io::Error> for BackupError {
+    fn from(e: io::Error) -> BackupError {
+        BackupError::Io(e)
+    }
+}
+
+impl From<diesel::result::Error> for BackupError {
+    fn from(e: diesel::result::Error) -> BackupError {
+        BackupError::Db(e)
+    }
+}
+
+impl From<zip::result::ZipError> for BackupError {
+    fn from(e: zip::result::ZipError) -> BackupError {
+        BackupError::Zip(e)
+    }
+}
+
+impl From<serde_json::Error> for BackupError {
+    fn from(e: serde_json::Error) -> BackupError {
+        BackupError::Json(e)
+    }
+}
+
+// ***********************************************************************************************
+// Write
+
+pub fn write_archive(snapshot: &Snapshot, upload_dir: &Path) -> Result<Vec<u8>, BackupError> {
+    let files = list_files(upload_dir)?;
+    let manifest = Manifest {
+        format: FORMAT.to_string(),
+        version: FORMAT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        persons: snapshot.persons.len(),
+        genres: snapshot.genres.len(),
+        partitions: snapshot.partitions.len(),
+        users: snapshot.users.len(),
+        tags: snapshot.tags.len(),
+        ensembles: snapshot.ensembles.len(),
+        files: files.len(),
+    };
+
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+
+    zip.start_file(MANIFEST_FILE, options)?;
+    zip.write_all(&serde_json::to_vec(&manifest)?)?;
+
+    let tables = [
+        ("persons", serde_json::to_vec(&snapshot.persons)?),
+        ("genres", serde_json::to_vec(&snapshot.genres)?),
+        ("partitions", serde_json::to_vec(&snapshot.partitions)?),
+        ("users", serde_json::to_vec(&snapshot.users)?),
+        ("tags", serde_json::to_vec(&snapshot.tags)?),
+        (
+            "partition_tags",
+            serde_json::to_vec(&snapshot.partition_tags)?,
+        ),
+        ("ensembles", serde_json::to_vec(&snapshot.ensembles)?),
+        (
+            "ensemble_members",
+            serde_json::to_vec(&snapshot.ensemble_members)?,
+        ),
+        (
+            "partition_details",
+            serde_json::to_vec(&snapshot.partition_details)?,
+        ),
+        (
+            "partition_creators",
+            serde_json::to_vec(&snapshot.partition_creators)?,
+        ),
+        (
+            "partition_files",
+            serde_json::to_vec(&snapshot.partition_files)?,
+        ),
+        ("loans", serde_json::to_vec(&snapshot.loans)?),
+        ("webhooks", serde_json::to_vec(&snapshot.webhooks)?),
+    ];
+    for (table, content) in tables.iter() {
+        zip.start_file(format!("{}{}.json", TABLES_DIR, table), options)?;
+        zip.write_all(content)?;
+    }
+
+    for relative in files {
+        let name = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        zip.start_file(format!("{}{}", FILES_DIR, name), options)?;
+        zip.write_all(&fs::read(upload_dir.join(&relative))?)?;
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
+
+// les fichiers du dossier, chemins relatifs à ce dossier ;
+// un dossier absent veut simplement dire qu'il n'y a pas de fichiers
+fn list_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    fn walk(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                walk(root, &path, files)?;
+            } else if let Ok(relative) = path.strip_prefix(root) {
+                files.push(relative.to_path_buf());
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = vec![];
+    if dir.is_dir() {
+        walk(dir, dir, &mut files)?;
+    }
+    files.sort();
+    Ok(files)
+}
+
+// ***********************************************************************************************
+// Read
+
+pub struct Archive {
+    pub manifest: Manifest,
+    pub snapshot: Snapshot,
+    // chemin relatif dans upload_dir, contenu
+    pub files: Vec<(PathBuf, Vec<u8>)>,
+}
+
+pub fn read_archive(bytes: &[u8]) -> Result<Archive, BackupError> {
+    let mut zip = ZipArchive::new(Cursor::new(bytes))?;
+    // ce qui reste à lire avant MAX_ARCHIVE_BYTES
+    let mut budget = MAX_ARCHIVE_BYTES;
+
+    let manifest: Manifest = read_json(&mut zip, MANIFEST_FILE, &mut budget)?;
+    if manifest.format != FORMAT {
+        return Err(BackupError::Format(format!(
+            "unknown format `{}`",
+            manifest.format
+        )));
+    }
+    if manifest.version > FORMAT_VERSION {
+        return Err(BackupError::Format(format!(
+            "version {} is newer than this application (version {})",
+            manifest.version, FORMAT_VERSION
+        )));
+    }
+
+    let mut snapshot = Snapshot {
+        persons: read_table(&mut zip, "persons", &mut budget)?,
+        genres: read_table(&mut zip, "genres", &mut budget)?,
+        partitions: read_table(&mut zip, "partitions", &mut budget)?,
+        users: read_table(&mut zip, "users", &mut budget)?,
+        ..Snapshot::default()
+    };
+    // pas d'étiquettes dans les archives de la version 1
+    if manifest.version >= 2 {
+        snapshot.tags = read_table(&mut zip, "tags", &mut budget)?;
+        snapshot.partition_tags = read_table(&mut zip, "partition_tags", &mut budget)?;
+    }
+    // un seul ensemble avant la version 3
+    if manifest.version >= 3 {
+        snapshot.ensembles = read_table(&mut zip, "ensembles", &mut budget)?;
+        snapshot.ensemble_members = read_table(&mut zip, "ensemble_members", &mut budget)?;
+    }
+    // pas d'import MusicXML avant la version 5
+    if manifest.version >= 5 {
+        snapshot.partition_details = read_table(&mut zip, "partition_details", &mut budget)?;
+        snapshot.partition_creators = read_table(&mut zip, "partition_creators", &mut budget)?;
+        snapshot.partition_files = read_table(&mut zip, "partition_files", &mut budget)?;
+    }
+    // pas de prêts avant la version 7 (partitions.shared existe depuis la 4)
+    if manifest.version >= 7 {
+        snapshot.loans = read_table(&mut zip, "loans", &mut budget)?;
+    }
+    // pas d'abonnements avant la version 8
+    if manifest.version >= 8 {
+        snapshot.webhooks = read_table(&mut zip, "webhooks", &mut budget)?;
+    }
+
+    let mut files = vec![];
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        if entry.is_dir() || !entry.name().starts_with(FILES_DIR) {
+            continue;
+        }
+        let relative = safe_path(&entry.name()[FILES_DIR.len()..])
+            .ok_or_else(|| BackupError::Format(format!("unsafe file name `{}`", entry.name())))?;
+        let name = entry.name().to_string();
+        files.push((relative, read_entry(&mut entry, &name, &mut budget)?));
+    }
+
+    Ok(Archive {
+        manifest,
+        snapshot,
+        files,
+    })
+}
+
+fn read_json<T: DeserializeOwned>(
+    zip: &mut ZipArchive<Cursor<&[u8]>>,
+    name: &str,
+    budget: &mut u64,
+) -> Result<T, BackupError> {
+    let entry = zip
+        .by_name(name)
+        .map_err(|_| BackupError::Format(format!("missing `{}`", name)))?;
+    Ok(serde_json::from_slice(&read_entry(entry, name, budget)?)?)
+}
+
+// tables/<table>.json
+fn read_table<T: DeserializeOwned>(
+    zip: &mut ZipArchive<Cursor<&[u8]>>,
+    table: &str,
+    budget: &mut u64,
+) -> Result<T, BackupError> {
+    read_json(zip, &format!("{}{}.json", TABLES_DIR, table), budget)
+}
+
+// la taille annoncée par l'archive ne compte pas : on lit un octet de plus
+// que permis, pour ce fichier et pour ce qui reste du total
+fn read_entry<R: Read>(entry: R, name: &str, budget: &mut u64) -> Result<Vec<u8>, BackupError> {
+    let mut content = vec![];
+    entry
+        .take(MAX_ENTRY_BYTES.min(*budget) + 1)
+        .read_to_end(&mut content)?;
+    let size = content.len() as u64;
+    if size > MAX_ENTRY_BYTES {
+        return Err(BackupError::Format(format!(
+            "`{}` is larger than {} bytes once uncompressed",
+            name, MAX_ENTRY_BYTES
+        )));
+    }
+    if size > *budget {
+        return Err(BackupError::Format(format!(
+            "the archive is larger than {} bytes once uncompressed",
+            MAX_ARCHIVE_BYTES
+        )));
+    }
+    *budget -= size;
+    Ok(content)
+}
+
+// refuse les chemins absolus et les `..` : rien ne doit sortir de upload_dir
+fn safe_path(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name);
+    if name.is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return None;
+    }
+    Some(path.to_path_buf())
+}
+
+// écrit les fichiers de l'archive qui manquent dans upload_dir ; ceux qui
+// existent déjà sont gardés, comme les lignes de partition_files
+// (ON CONFLICT (path) DO NOTHING). `written` reçoit les fichiers créés, pour
+// les retirer si la restauration est annulée.
+pub fn restore_files(
+    files: &[(PathBuf, Vec<u8>)],
+    upload_dir: &Path,
+    written: &mut Vec<PathBuf>,
+) -> io::Result<usize> {
+    let mut count = 0;
+    for (relative, content) in files {
+        let target = upload_dir.join(relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&target)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        written.push(target);
+        file.write_all(content)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+pub fn remove_files(written: &[PathBuf]) {
+    for path in written {
+        if let Err(e) = fs::remove_file(path) {
+            warn_!("{} not removed: {}", path.display(), e);
+        }
+    }
+}
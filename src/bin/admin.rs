@@ -0,0 +1,396 @@
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::process;
+
+use diesel::{Connection, PgConnection};
+use serde::{Deserialize, Serialize};
+use structopt::StructOpt;
+
+use hello_rocket::auth::{hash_password, normalize_email};
+use hello_rocket::models::{Duplicate, Genre, Partition, Person, User, STATUS_ACTIVE};
+use hello_rocket::repo;
+
+// Outil d'administration en ligne de commande
+//
+//   admin migrate
+//   admin create-admin --email admin@example.com [--password ...]
+//   admin import-csv partitions.csv [--ensemble "Chœur de chambre"]
+//   admin reindex
+//   admin dedupe [--apply]
+//
+// la base est celle de DATABASE_URL (ou d'un fichier .env), sinon celle
+// de Rocket.toml ; `--json` donne un résultat lisible par un script.
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "admin", about = "Maintenance of the partitions catalogue")]
+struct Opt {
+    #[structopt(long, help = "Database URL (default: DATABASE_URL, then Rocket.toml)")]
+    database_url: Option<String>,
+
+    #[structopt(long, help = "Print the result as JSON")]
+    json: bool,
+
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    #[structopt(about = "Apply the pending database migrations")]
+    Migrate,
+
+    #[structopt(about = "Create an administrator account")]
+    CreateAdmin {
+        #[structopt(long)]
+        email: String,
+        #[structopt(long, help = "Read from standard input when omitted")]
+        password: Option<String>,
+    },
+
+    #[structopt(about = "Import partitions from a CSV file (columns: title, composer, genre)")]
+    ImportCsv {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        #[structopt(long, help = "Library of the partitions (default: the first ensemble)")]
+        ensemble: Option<String>,
+    },
+
+    #[structopt(about = "Recompute the name search keys and rebuild the search indexes")]
+    Reindex,
+
+    #[structopt(about = "List the persons and genres whose names have the same search key")]
+    Dedupe {
+        #[structopt(long, help = "Merge them (default: only report what would change)")]
+        apply: bool,
+    },
+}
+
+// le résultat d'une commande, affiché en texte ou en JSON
+trait Report: Serialize {
+    fn human(&self) -> String;
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    if let Err(e) = run(&opt) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(opt: &Opt) -> Result<(), Box<dyn Error>> {
+    let conn = PgConnection::establish(&database_url(opt)?)?;
+
+    match &opt.command {
+        Command::Migrate => print(opt, &migrate(&conn)?),
+        Command::CreateAdmin { email, password } => {
+            print(opt, &create_admin(&conn, email, password.clone())?)
+        }
+        Command::ImportCsv { file, ensemble } => {
+            print(opt, &import_csv(&conn, file, ensemble.as_deref())?)
+        }
+        Command::Reindex => print(opt, &reindex(&conn)?),
+        Command::Dedupe { apply } => print(opt, &dedupe(&conn, *apply)?),
+    }
+}
+
+fn print<R: Report>(opt: &Opt, report: &R) -> Result<(), Box<dyn Error>> {
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(report)?);
+    } else {
+        println!("{}", report.human());
+    }
+    Ok(())
+}
+
+fn database_url(opt: &Opt) -> Result<String, Box<dyn Error>> {
+    if let Some(url) = &opt.database_url {
+        return Ok(url.clone());
+    }
+    dotenv::dotenv().ok();
+    if let Ok(url) = std::env::var("DATABASE_URL") {
+        return Ok(url);
+    }
+    // la même configuration que le serveur (Rocket.toml, ROCKET_DATABASES)
+    Ok(rocket::Config::figment().extract_inner::<String>("databases.persons.url")?)
+}
+
+// ***********************************************************************************************
+// migrate
+
+#[derive(Serialize)]
+struct MigrateReport {
+    migrated: bool,
+}
+
+impl Report for MigrateReport {
+    fn human(&self) -> String {
+        "Migrations applied.".to_string()
+    }
+}
+
+fn migrate(conn: &PgConnection) -> Result<MigrateReport, Box<dyn Error>> {
+    repo::run_migrations(conn)?;
+    Ok(MigrateReport { migrated: true })
+}
+
+// ***********************************************************************************************
+// create-admin
+
+#[derive(Serialize)]
+struct CreateAdminReport {
+    id: Option<i32>,
+    email: String,
+}
+
+impl Report for CreateAdminReport {
+    fn human(&self) -> String {
+        format!("Administrator {} created.", self.email)
+    }
+}
+
+fn create_admin(
+    conn: &PgConnection,
+    email: &str,
+    password: Option<String>,
+) -> Result<CreateAdminReport, Box<dyn Error>> {
+    let email = normalize_email(email);
+    if repo::get_user_by_email(conn, &email).is_ok() {
+        return Err(format!("a user with address {} already exists", email).into());
+    }
+
+    let password = match password {
+        Some(password) => password,
+        None => {
+            eprint!("Password: ");
+            io::stderr().flush()?;
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line)?;
+            line.trim_end_matches(&['\r', '\n'][..]).to_string()
+        }
+    };
+    if password.is_empty() {
+        return Err("the password cannot be empty".into());
+    }
+
+    let user = User {
+        id: None,
+        email,
+        password_hash: hash_password(&password)
+            .map_err(|e| format!("cannot hash the password: {}", e))?,
+        is_admin: true,
+        status: STATUS_ACTIVE.to_string(),
+        display_name: String::new(),
+        locale: String::new(),
+    };
+    let user = repo::create_user(conn, &user)?;
+    Ok(CreateAdminReport {
+        id: user.id,
+        email: user.email,
+    })
+}
+
+// ***********************************************************************************************
+// import-csv
+
+#[derive(Debug, Deserialize)]
+struct CsvRow {
+    title: String,
+    composer: String,
+    genre: String,
+}
+
+#[derive(Default, Serialize)]
+struct ImportReport {
+    partitions_added: usize,
+    partitions_existing: usize,
+    persons_created: usize,
+    genres_created: usize,
+    // lignes ignorées, avec la raison
+    rejected: Vec<String>,
+}
+
+impl Report for ImportReport {
+    fn human(&self) -> String {
+        let mut text = format!(
+            "{} partition(s) added, {} already present; {} composer(s) and {} genre(s) created.",
+            self.partitions_added,
+            self.partitions_existing,
+            self.persons_created,
+            self.genres_created
+        );
+        for line in &self.rejected {
+            text.push_str(&format!("\n  rejected: {}", line));
+        }
+        text
+    }
+}
+
+// tout le fichier est importé dans une seule transaction, dans la bibliothèque
+// d'un ensemble ; les compositeurs et genres inconnus sont créés (genres communs)
+fn import_csv(
+    conn: &PgConnection,
+    file: &PathBuf,
+    ensemble: Option<&str>,
+) -> Result<ImportReport, Box<dyn Error>> {
+    let ensemble = match ensemble {
+        Some(name) => match repo::get_ensemble_by_name(conn, name) {
+            Err(diesel::result::Error::NotFound) => {
+                return Err(format!("unknown ensemble `{}`", name).into())
+            }
+            ensemble => ensemble?,
+        },
+        None => repo::get_default_ensemble(conn)?,
+    };
+    let ensemble_id = ensemble.id.unwrap_or_default();
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_path(file)?;
+    let rows = reader.deserialize::<CsvRow>().collect::<Vec<_>>();
+
+    conn.transaction::<_, Box<dyn Error>, _>(|| {
+        let mut report = ImportReport::default();
+
+        for (i, row) in rows.into_iter().enumerate() {
+            // la ligne 1 est l'en-tête
+            let line = i + 2;
+            let row = match row {
+                Ok(row) => row,
+                Err(e) => {
+                    report.rejected.push(format!("line {}: {}", line, e));
+                    continue;
+                }
+            };
+            if row.title.is_empty() || row.composer.is_empty() || row.genre.is_empty() {
+                report.rejected.push(format!("line {}: empty field", line));
+                continue;
+            }
+
+            let person = match repo::get_person_by_name(conn, &row.composer) {
+                Ok(person) => person,
+                Err(diesel::result::Error::NotFound) => {
+                    report.persons_created += 1;
+                    repo::create_person(
+                        conn,
+                        Person {
+                            id: None,
+                            full_name: row.composer,
+                            full_name_key: String::new(),
+                        },
+                    )?
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let genre = match repo::get_genre_by_name(conn, ensemble_id, &row.genre) {
+                Ok(genre) => genre,
+                Err(diesel::result::Error::NotFound) => {
+                    report.genres_created += 1;
+                    repo::create_genre(
+                        conn,
+                        Genre {
+                            id: None,
+                            name: row.genre,
+                            name_key: String::new(),
+                            ensemble_id: None,
+                        },
+                    )?
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            let person_id = person.id.unwrap_or_default();
+            let genre_id = genre.id.unwrap_or_default();
+            if repo::partition_exists(conn, ensemble_id, &row.title, person_id)? {
+                report.partitions_existing += 1;
+                continue;
+            }
+            repo::insert_partition(
+                conn,
+                &Partition {
+                    id: None,
+                    person_id,
+                    title: row.title,
+                    genre_id,
+                    ensemble_id,
+                    shared: false,
+                },
+            )?;
+            report.partitions_added += 1;
+        }
+
+        Ok(report)
+    })
+}
+
+// ***********************************************************************************************
+// reindex
+
+// les doublons gardent leur ancienne clé : voir `admin dedupe`
+#[derive(Serialize)]
+struct ReindexReport {
+    keys_updated: usize,
+    duplicates: Vec<Duplicate>,
+}
+
+impl Report for ReindexReport {
+    fn human(&self) -> String {
+        let mut text = format!(
+            "{} search key(s) updated, search indexes rebuilt.",
+            self.keys_updated
+        );
+        if !self.duplicates.is_empty() {
+            text.push_str(&format!(
+                "\n{} duplicate name(s) left as is: see `admin dedupe`.",
+                self.duplicates.len()
+            ));
+        }
+        text
+    }
+}
+
+fn reindex(conn: &PgConnection) -> Result<ReindexReport, Box<dyn Error>> {
+    let keys = repo::update_name_keys(conn, false)?;
+    repo::reindex_search(conn)?;
+    Ok(ReindexReport {
+        keys_updated: keys.updated,
+        duplicates: keys.duplicates,
+    })
+}
+
+// ***********************************************************************************************
+// dedupe
+//
+// la fusion supprime des lignes (le doublon, et ses partitions dont le titre
+// existe déjà) : sans --apply, on dit seulement ce qui serait fait
+
+#[derive(Serialize)]
+struct DedupeReport {
+    applied: bool,
+    duplicates: Vec<Duplicate>,
+}
+
+impl Report for DedupeReport {
+    fn human(&self) -> String {
+        if self.duplicates.is_empty() {
+            return "No duplicate names.".to_string();
+        }
+        let mut lines = self
+            .duplicates
+            .iter()
+            .map(|duplicate| duplicate.to_string())
+            .collect::<Vec<_>>();
+        lines.push(if self.applied {
+            format!("{} duplicate(s) merged.", self.duplicates.len())
+        } else {
+            "Nothing changed: run again with --apply to merge them.".to_string()
+        });
+        lines.join("\n")
+    }
+}
+
+fn dedupe(conn: &PgConnection, apply: bool) -> Result<DedupeReport, Box<dyn Error>> {
+    let keys = repo::merge_duplicates(conn, apply)?;
+    Ok(DedupeReport {
+        applied: apply,
+        duplicates: keys.duplicates,
+    })
+}
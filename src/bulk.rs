@@ -0,0 +1,184 @@
+use fluent::FluentArgs;
+
+use rocket::http::Header;
+use rocket::response::{Flash, Redirect};
+use rocket::State;
+
+use crate::csrf::CsrfForm;
+use crate::db;
+use crate::ensemble::Member;
+use crate::i18n::Locale;
+use crate::jobs::JobQueue;
+use crate::models::{BulkAction, BulkKind, Ensemble, ShowPartition};
+use crate::notification::Notification;
+use crate::repo;
+use crate::webhooks::{self, Deleted, PARTITION_DELETED, PARTITION_UPDATED};
+use crate::DBPool;
+
+// Bulk operations
+//
+// les partitions cochées dans la liste reçoivent toutes la même action :
+// nouveau genre, nouveau compositeur, étiquette, suppression, partage avec
+// les autres ensembles (voir loans.rs), ou export en CSV (mêmes colonnes que
+// l'import de l'outil en ligne de commande)
+
+#[derive(Responder)]
+pub enum BulkResponse {
+    Done(Flash<Redirect>),
+    Export(CsvDownload),
+}
+
+#[derive(Responder)]
+#[response(content_type = "text/csv")]
+pub struct CsvDownload(Vec<u8>, Header<'static>);
+
+#[post("/partitions/bulk", data = "<bulk_form>")]
+pub async fn bulk_partitions(
+    bulk_form: CsrfForm<BulkAction>,
+    _member: Member,
+    conn: DBPool,
+    ensemble: Ensemble,
+    queue: &State<JobQueue>,
+    locale: Locale,
+) -> BulkResponse {
+    let ensemble_id = ensemble.id.unwrap_or_default();
+    let mut action = bulk_form.into_inner();
+    action.value = action.value.trim().to_string();
+
+    if action.ids.is_empty() {
+        return done(Notification::warning(locale.tr("msg-bulk-none")));
+    }
+    if action.action == BulkKind::Export {
+        return export(&conn, ensemble_id, &locale, action.ids).await;
+    }
+    let needs_value = !matches!(
+        action.action,
+        BulkKind::Delete | BulkKind::Share | BulkKind::Unshare
+    );
+    if needs_value && action.value.is_empty() {
+        return done(Notification::warning(locale.tr("msg-bulk-value-missing")));
+    }
+
+    let (kind, value, ids) = (action.action, action.value.clone(), action.ids.clone());
+    // avant la suppression : les partitions de la bibliothèque parmi celles cochées
+    let deleted = match kind {
+        BulkKind::Delete => db::get_partitions_with_tags(&conn, ensemble_id, ids.clone())
+            .await
+            .unwrap_or_default(),
+        _ => vec![],
+    };
+    let mut args = FluentArgs::new();
+    args.set("name", value.clone());
+    let notification = match db::bulk_update(&conn, ensemble_id, action).await {
+        Ok(count) => {
+            notify(&conn, queue, ensemble_id, kind, ids, deleted).await;
+            args.set("count", count);
+            let key = match kind {
+                BulkKind::ChangeGenre => "msg-bulk-genre",
+                BulkKind::ChangeComposer => "msg-bulk-composer",
+                BulkKind::AddTag => "msg-bulk-tag",
+                BulkKind::Share => "msg-bulk-shared",
+                BulkKind::Unshare => "msg-bulk-unshared",
+                _ => "msg-bulk-deleted",
+            };
+            Notification::success(locale.tr_args(key, &args))
+        }
+        Err(diesel::result::Error::NotFound) => {
+            let key = match kind {
+                BulkKind::ChangeGenre => "msg-unknown-genre",
+                _ => "msg-unknown-person",
+            };
+            Notification::warning(locale.tr_args(key, &args))
+        }
+        Err(e) if repo::is_unique_violation(&e) => {
+            Notification::warning(locale.tr_args("msg-bulk-conflict", &args))
+        }
+        Err(e) => {
+            error_!("DB bulk update error: {}", e);
+            Notification::error(locale.tr("msg-bulk-failed"))
+        }
+    };
+    done(notification)
+}
+
+// un événement par partition changée pour les abonnés (voir webhooks.rs) ;
+// les étiquettes et le partage ne sont pas dans les données envoyées
+async fn notify(
+    conn: &DBPool,
+    queue: &JobQueue,
+    ensemble_id: i32,
+    kind: BulkKind,
+    ids: Vec<i32>,
+    deleted: Vec<(ShowPartition, Vec<String>)>,
+) {
+    let scope = Some(ensemble_id);
+    match kind {
+        BulkKind::Delete => {
+            for (partition, _) in deleted {
+                let deleted = Deleted {
+                    id: partition.id.unwrap_or_default(),
+                };
+                webhooks::notify(conn, queue, scope, PARTITION_DELETED, &deleted).await;
+            }
+        }
+        BulkKind::ChangeGenre | BulkKind::ChangeComposer => {
+            match db::get_partitions_with_tags(conn, ensemble_id, ids).await {
+                Ok(partitions) => {
+                    for (partition, _) in partitions {
+                        webhooks::notify(conn, queue, scope, PARTITION_UPDATED, &partition).await;
+                    }
+                }
+                Err(e) => error_!("DB get_partitions_with_tags error: {}", e),
+            }
+        }
+        _ => {}
+    }
+}
+
+fn done(notification: Notification) -> BulkResponse {
+    BulkResponse::Done(notification.redirect("/partitions"))
+}
+
+async fn export(conn: &DBPool, ensemble_id: i32, locale: &Locale, ids: Vec<i32>) -> BulkResponse {
+    let partitions = match db::get_partitions_with_tags(conn, ensemble_id, ids).await {
+        Ok(partitions) => partitions,
+        Err(e) => {
+            error_!("DB export error: {}", e);
+            return done(Notification::error(locale.tr("msg-bulk-failed")));
+        }
+    };
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+    let mut rows = vec![vec![
+        "title".to_string(),
+        "composer".to_string(),
+        "genre".to_string(),
+        "tags".to_string(),
+    ]];
+    for (partition, tags) in partitions {
+        rows.push(vec![
+            partition.title,
+            partition.full_name,
+            partition.name,
+            tags.join(", "),
+        ]);
+    }
+    let written = rows
+        .iter()
+        .try_for_each(|row| writer.write_record(row))
+        .map_err(|e| e.to_string())
+        .and_then(|_| writer.into_inner().map_err(|e| e.to_string()));
+    match written {
+        Ok(csv) => BulkResponse::Export(CsvDownload(
+            csv,
+            Header::new(
+                "Content-Disposition",
+                "attachment; filename=\"partitions.csv\"",
+            ),
+        )),
+        Err(e) => {
+            error_!("CSV export error: {}", e);
+            done(Notification::error(locale.tr("msg-bulk-failed")))
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+
+use rand::RngCore;
+
+use rocket::data::{self, FromData};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::form::{self, DataField, Errors, FromForm, Options, ValueField};
+use rocket::http::uri::Origin;
+use rocket::http::{Cookie, Method, RawStr, Status};
+use rocket::outcome::try_outcome;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::Data;
+
+use rocket_dyn_templates::tera::{self, Value};
+
+use crate::api::has_bearer_token;
+
+// Protection CSRF
+//
+// un jeton par session est gardé dans un cookie privé (chiffré par Rocket) ;
+// chaque formulaire le renvoie dans un champ caché `_csrf` (ou dans l'en-tête
+// `X-CSRF-Token`). Le fairing vérifie toutes les requêtes POST/PUT/DELETE
+// et redirige celles sans jeton valide vers `/csrf`, qui répond 403.
+//
+// le fairing ne peut lire que le début du corps (512 octets, la limite de
+// Rocket) : quand le jeton d'un formulaire plus long n'y est pas, la
+// vérification est remise à la lecture du formulaire (CsrfForm), où que soit
+// le champ. Les routes sans formulaire refusent une vérification remise
+// (CsrfChecked).
+//
+// les requêtes de l'API avec un jeton `Authorization: Bearer` (api.rs) ne
+// sont pas vérifiées : elles ne dépendent pas des cookies du navigateur.
+
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_FIELD: &str = "_csrf";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+const FAILURE_PATH: &str = "/csrf";
+const PEEK_LEN: usize = 512;
+const TOKEN_BYTES: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    pub fn value(&self) -> &str {
+        &self.0
+    }
+
+    // le jeton de la session, créé s'il n'existe pas encore ;
+    // mis en cache pour que la requête n'en génère qu'un seul
+    fn from_session(req: &Request<'_>) -> CsrfToken {
+        req.local_cache(|| {
+            let cookies = req.cookies();
+            match cookies.get_private(CSRF_COOKIE) {
+                Some(cookie) => CsrfToken(cookie.value().to_string()),
+                None => {
+                    let token = generate_token();
+                    cookies.add_private(Cookie::new(CSRF_COOKIE, token.clone()));
+                    CsrfToken(token)
+                }
+            }
+        })
+        .clone()
+    }
+
+    fn verify(&self, submitted: &str) -> bool {
+        constant_time_eq(self.0.as_bytes(), submitted.as_bytes())
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CsrfToken {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(CsrfToken::from_session(req))
+    }
+}
+
+pub struct CsrfFairing;
+
+#[rocket::async_trait]
+impl Fairing for CsrfFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "CSRF protection",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, data: &mut Data<'_>) {
+        if !matches!(
+            req.method(),
+            Method::Post | Method::Put | Method::Delete | Method::Patch
+        ) {
+            return;
+        }
+        if req.uri().path().starts_with("/api/") && has_bearer_token(req) {
+            return;
+        }
+
+        let submitted = match req.headers().get_one(CSRF_HEADER) {
+            Some(header) => Some(header.to_string()),
+            None => {
+                let is_multipart = req.content_type().map_or(false, |ct| ct.is_form_data());
+                let is_form = is_multipart || req.content_type().map_or(false, |ct| ct.is_form());
+                // peek_complete() dit si tout le corps est en mémoire, pas
+                // s'il tient dans ce qui est rendu
+                let peeked = data.peek(PEEK_LEN).await;
+                let complete = peeked.len() < PEEK_LEN;
+                match token_from_body(peeked, is_multipart, complete) {
+                    // plus loin dans le formulaire : vu par CsrfForm
+                    None if is_form && !complete => {
+                        req.local_cache(|| Deferred(true));
+                        return;
+                    }
+                    token => token,
+                }
+            }
+        };
+
+        if !is_valid(req, submitted.as_deref()) {
+            warn_!("CSRF check failed for {} {}", req.method(), req.uri());
+            req.set_method(Method::Get);
+            req.set_uri(Origin::parse(FAILURE_PATH).unwrap());
+        }
+    }
+}
+
+// la vérification remise à la lecture du formulaire
+struct Deferred(bool);
+
+fn is_deferred(req: &Request<'_>) -> bool {
+    req.local_cache(|| Deferred(false)).0
+}
+
+fn is_valid(req: &Request<'_>, submitted: Option<&str>) -> bool {
+    let expected = req
+        .cookies()
+        .get_private(CSRF_COOKIE)
+        .map(|cookie| CsrfToken(cookie.value().to_string()));
+    match (expected, submitted) {
+        (Some(expected), Some(submitted)) => expected.verify(submitted),
+        _ => false,
+    }
+}
+
+// à la place de rocket::form::Form dans les routes qui modifient : vérifie le
+// jeton lu avec le formulaire quand le fairing n'a pas pu le faire
+pub struct CsrfForm<T>(T);
+
+impl<T> CsrfForm<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for CsrfForm<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for CsrfForm<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, T: FromForm<'r>> FromData<'r> for CsrfForm<T> {
+    type Error = Errors<'r>;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let form = try_outcome!(form::Form::<WithToken<T>>::from_data(req, data).await);
+        let WithToken { token, form } = form.into_inner();
+        if is_deferred(req) && !is_valid(req, token.as_deref()) {
+            warn_!("CSRF check failed for {} {}", req.method(), req.uri());
+            return data::Outcome::Failure((Status::Forbidden, Errors::new()));
+        }
+        data::Outcome::Success(CsrfForm(form))
+    }
+}
+
+// le formulaire T et le champ `_csrf`, mis de côté pendant la lecture
+struct WithToken<T> {
+    token: Option<String>,
+    form: T,
+}
+
+struct WithTokenContext<'r, T: FromForm<'r>> {
+    token: Option<String>,
+    form: T::Context,
+}
+
+#[rocket::async_trait]
+impl<'r, T: FromForm<'r>> FromForm<'r> for WithToken<T> {
+    type Context = WithTokenContext<'r, T>;
+
+    fn init(opts: Options) -> Self::Context {
+        WithTokenContext {
+            token: None,
+            form: T::init(opts),
+        }
+    }
+
+    fn push_value(ctxt: &mut Self::Context, field: ValueField<'r>) {
+        if field.name == CSRF_FIELD {
+            ctxt.token = Some(field.value.to_string());
+        } else {
+            T::push_value(&mut ctxt.form, field);
+        }
+    }
+
+    async fn push_data(ctxt: &mut Self::Context, field: DataField<'r, '_>) {
+        T::push_data(&mut ctxt.form, field).await;
+    }
+
+    fn push_error(ctxt: &mut Self::Context, error: form::Error<'r>) {
+        T::push_error(&mut ctxt.form, error);
+    }
+
+    fn finalize(ctxt: Self::Context) -> form::Result<'r, Self> {
+        Ok(WithToken {
+            token: ctxt.token,
+            form: T::finalize(ctxt.form)?,
+        })
+    }
+}
+
+// pour les routes sans formulaire : leur corps n'est pas lu, une
+// vérification remise est refusée
+pub struct CsrfChecked;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CsrfChecked {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if is_deferred(req) {
+            warn_!("CSRF check failed for {} {}", req.method(), req.uri());
+            return Outcome::Failure((Status::Forbidden, ()));
+        }
+        Outcome::Success(CsrfChecked)
+    }
+}
+
+#[get("/csrf")]
+pub fn csrf_failure() -> Status {
+    Status::Forbidden
+}
+
+// fonction Tera : {{ csrf_field(token=csrf_token) | safe }}
+pub fn tera_csrf_field(args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let token = args
+        .get("token")
+        .and_then(Value::as_str)
+        .ok_or_else(|| tera::Error::msg("csrf_field: missing `token` argument"))?;
+    Ok(Value::String(format!(
+        r#"<input type="hidden" name="{}" value="{}" />"#,
+        CSRF_FIELD, token
+    )))
+}
+
+pub(crate) fn generate_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// `complete` : tout le corps a été lu ; sinon le dernier champ peut être
+// coupé et n'est pas pris
+fn token_from_body(peeked: &[u8], is_multipart: bool, complete: bool) -> Option<String> {
+    let body = String::from_utf8_lossy(peeked);
+    if is_multipart {
+        // ... name="_csrf"\r\n\r\n<jeton>\r\n--boundary
+        let marker = format!("name=\"{}\"", CSRF_FIELD);
+        let start = body.find(&marker)? + marker.len();
+        let value = body[start..].splitn(2, "\r\n\r\n").nth(1)?;
+        let end = value.find("\r\n")?;
+        Some(value[..end].to_string())
+    } else {
+        let fields = match body.rfind('&') {
+            Some(end) if !complete => &body[..end],
+            None if !complete => "",
+            _ => &body[..],
+        };
+        fields.split('&').find_map(|field| {
+            let mut parts = field.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) if name == CSRF_FIELD => {
+                    RawStr::new(value).url_decode().ok().map(|v| v.into_owned())
+                }
+                _ => None,
+            }
+        })
+    }
+}
@@ -1,292 +1,875 @@
+use std::path::PathBuf;
+
 use self::diesel::prelude::*;
 use rocket_sync_db_pools::diesel;
 
-use crate::models::{Genre, Partition, Person, ShowPartition};
-
-use crate::schema::genres::columns::name;
-use crate::schema::persons::columns::full_name;
-use crate::schema::partitions::columns::title;
-use crate::schema::{genres, partitions, persons};
+use crate::backup::{Archive, BackupError, RestoreReport, Snapshot};
+use crate::models::{
+    ApiToken, BulkAction, Ensemble, Genre, Job, LabelCount, Loan, LoanDecision, NameKeys,
+    NewPartition, Partition, PartitionCreator, PartitionDetails, PartitionFile, PartitionSearch,
+    Person, QueuedDelivery, SharedLibrary, SharedPartition, ShowPartition, Suggestion, Tag, User,
+    Webhook, WebhookDelivery,
+};
+use crate::musicxml::Score;
 
+use crate::repo::{self, SuggestField};
 use crate::DBPool;
 
+// les fonctions utilisées par les routes : chacune passe sa requête
+// (voir repo.rs) à une connexion du pool de Rocket ;
+// `ensemble_id` est celui de la bibliothèque choisie (voir ensemble.rs)
 
 // ***********************************************************************************************
 // LISTS
 
-pub async fn get_list_raw_partitions(conn: &DBPool) -> QueryResult<Vec<Partition>> {
-    conn.run(|c| {
-        partitions::table
-            .order(partitions::title)
-            .load::<Partition>(c)
-    })
-    .await
+pub async fn get_list_raw_partitions(
+    conn: &DBPool,
+    ensemble_id: i32,
+) -> QueryResult<Vec<Partition>> {
+    conn.run(move |c| repo::get_list_raw_partitions(c, ensemble_id))
+        .await
 }
 
-pub async fn get_list_show_partitions(conn: &DBPool) -> QueryResult<Vec<ShowPartition>> {
-    /*
-    let rep: QueryResult<Vec<ShowPartition>>  = conn.run(|c| {
-        sql_query("
-    SELECT partitions.id, partitions.title, persons.last_name, genres.name
-    FROM partitions
-    INNER JOIN persons
-    ON partitions.person_id = persons.id
-    INNER JOIN genres
-    ON partitions.genre_id = genres.id"
-        ).load::<ShowPartition>(c)
-    }).await;
-    rep
-
-     */
-
-    let data = conn
-        .run(|c| {
-            partitions::table
-                .inner_join(persons::table)
-                .inner_join(genres::table)
-                .select((
-                    partitions::id,
-                    partitions::title,
-                    persons::full_name,
-                    genres::name,
-                ))
-                .order(partitions::title)
-                .load(c)
-                .expect("error")
-        })
-        .await;
-    Ok(data)
-}
-
-pub async fn get_list_genres(conn: &DBPool) -> QueryResult<Vec<Genre>> {
-    conn.run(|c| genres::table.order(name.asc()).load::<Genre>(c))
+pub async fn get_list_show_partitions(
+    conn: &DBPool,
+    ensemble_id: i32,
+) -> QueryResult<Vec<ShowPartition>> {
+    conn.run(move |c| repo::get_list_show_partitions(c, ensemble_id))
         .await
 }
 
-pub async fn get_list_persons(conn: &DBPool) -> QueryResult<Vec<Person>> {
-    conn.run(|c| persons::table.order(full_name.asc()).load::<Person>(c))
+pub async fn get_list_genres(conn: &DBPool, ensemble_id: i32) -> QueryResult<Vec<Genre>> {
+    conn.run(move |c| repo::get_list_genres(c, ensemble_id))
         .await
 }
 
+pub async fn get_list_persons(conn: &DBPool) -> QueryResult<Vec<Person>> {
+    conn.run(|c| repo::get_list_persons(c)).await
+}
+
 // ************************************************************************************************
-// Get vector with all occurences with same name or title
+// Lookup by name
 
 pub async fn get_person_by_name(conn: &DBPool, person_full_name: String) -> QueryResult<Person> {
-    conn.run(move |c|
-        persons::table
-            .filter(full_name.eq(person_full_name))
-            .first(c)
-    ).await
+    conn.run(move |c| repo::get_person_by_name(c, &person_full_name))
+        .await
 }
 
-pub async fn get_genre_by_name(conn: &DBPool, genre_name: String) -> QueryResult<Genre> {
-    conn.run(move |c|
-            genres::table
-                .filter(name.eq(genre_name))
-                .first(c)
-            ).await
+pub async fn get_genre_by_name(
+    conn: &DBPool,
+    ensemble_id: i32,
+    genre_name: String,
+) -> QueryResult<Genre> {
+    conn.run(move |c| repo::get_genre_by_name(c, ensemble_id, &genre_name))
+        .await
 }
 
-pub async fn get_raw_partition_by_title(conn: &DBPool, partition_title: String) -> QueryResult<Partition> {
-    conn.run(move |c|
-        partitions::table
-            .filter(title.eq(partition_title))
-            .first(c)
-    ).await
+pub async fn get_genre_by_key(
+    conn: &DBPool,
+    ensemble_id: Option<i32>,
+    genre_name: String,
+) -> QueryResult<Genre> {
+    conn.run(move |c| repo::get_genre_by_key(c, ensemble_id, &genre_name))
+        .await
 }
 
-pub async fn get_partition_by_title(
+pub async fn get_similar_persons(
     conn: &DBPool,
-    partition_title: String,
-) -> QueryResult<ShowPartition> {
+    person_full_name: String,
+) -> QueryResult<Vec<Person>> {
+    conn.run(move |c| repo::get_similar_persons(c, &person_full_name))
+        .await
+}
 
-    let raw_partition = get_raw_partition_by_title(conn, partition_title.clone()).await;
-    match raw_partition {
-        Ok(..) => {
-           let data = conn.run(|c| {
-                partitions::table
-                    .inner_join(persons::table)
-                    .inner_join(genres::table)
-                    .select((
-                        partitions::id,
-                        partitions::title,
-                        persons::full_name,
-                        genres::name,
-                    ))
-                    .filter(partitions::title.eq(partition_title))
-                    .first(c)
-                    .expect("error")
-            }).await;
-            Ok(data)
-        }
-        Err(e) => Err(e)
-    }
-    /*
-    let data = conn.run(|c| {
-        partitions::table
-            .inner_join(persons::table)
-            .inner_join(genres::table)
-            .select((
-                partitions::id,
-                partitions::title,
-                persons::full_name,
-                genres::name,
-            ))
-            .filter(partitions::title.eq(partition_title))
-            .first(c)
-            .expect("error")
-    }).await;
-    Ok(data)
-     */
-}
-
-pub async fn get_partition_by_author(
-    conn: &DBPool,
-    partition_author: String,
-) -> QueryResult<Vec<ShowPartition>> {
-    let pers = get_person_by_name(conn, partition_author).await.unwrap();
-
-    let data = conn
-        .run(move |c| {
-            partitions::table
-                .inner_join(persons::table)
-                .inner_join(genres::table)
-                .select((
-                    partitions::id,
-                    partitions::title,
-                    persons::full_name,
-                    genres::name,
-                ))
-                .filter(partitions::person_id.eq(pers.id.unwrap()))
-                .load(c)
-                .expect("error in finding partition by author")
-        })
-        .await;
-    Ok(data)
-}
-
-pub async fn get_partition_by_genre(
-    conn: &DBPool,
-    partition_genre: String,
+pub async fn get_similar_genres(
+    conn: &DBPool,
+    ensemble_id: i32,
+    genre_name: String,
+) -> QueryResult<Vec<Genre>> {
+    conn.run(move |c| repo::get_similar_genres(c, ensemble_id, &genre_name))
+        .await
+}
+
+// remplit les clés des lignes qui n'en ont pas encore (voir repo::update_name_keys)
+pub async fn fill_missing_name_keys(conn: &DBPool) -> QueryResult<NameKeys> {
+    conn.run(|c| repo::update_name_keys(c, true)).await
+}
+
+// ************************************************************************************************
+// Search
+
+pub async fn search_partitions(
+    conn: &DBPool,
+    ensemble_id: i32,
+    search: PartitionSearch,
 ) -> QueryResult<Vec<ShowPartition>> {
-    let genre = get_genre_by_name(conn, partition_genre).await.unwrap();
-
-    let data = conn
-        .run(move |c| {
-            partitions::table
-                .inner_join(persons::table)
-                .inner_join(genres::table)
-                .select((
-                    partitions::id,
-                    partitions::title,
-                    persons::full_name,
-                    genres::name,
-                ))
-                .filter(partitions::genre_id.eq(genre.id.unwrap()))
-                .load(c)
-                .expect("error in finding partition by genre")
-        })
-        .await;
-    Ok(data)
+    conn.run(move |c| repo::search_partitions(c, ensemble_id, &search))
+        .await
 }
 
-//*************************************************************************************************
-// DELETE
+pub async fn suggest(
+    conn: &DBPool,
+    ensemble_id: i32,
+    field: SuggestField,
+    text: String,
+    limit: i64,
+) -> QueryResult<Vec<Suggestion>> {
+    conn.run(move |c| repo::suggest(c, ensemble_id, field, &text, limit))
+        .await
+}
 
-pub async fn delete_one_person(conn: &DBPool, person_id: i32) -> QueryResult<usize> {
-    conn.run(move |c| diesel::delete(persons::table.find(person_id)).execute(c))
+// ************************************************************************************************
+// Get one item with its relations, for the detail pages
+
+pub async fn get_partition_detail(
+    conn: &DBPool,
+    ensemble_id: i32,
+    partition_id: i32,
+) -> QueryResult<(Partition, Person, Genre)> {
+    conn.run(move |c| repo::get_partition_detail(c, ensemble_id, partition_id))
         .await
 }
 
-pub async fn delete_one_genre(conn: &DBPool, genre_id: i32) -> QueryResult<usize> {
-    conn.run(move |c| diesel::delete(genres::table.find(genre_id)).execute(c))
+pub async fn get_related_partitions(
+    conn: &DBPool,
+    partition: &Partition,
+) -> QueryResult<Vec<Partition>> {
+    let partition = partition.clone();
+    conn.run(move |c| repo::get_related_partitions(c, &partition))
         .await
 }
 
-pub async fn delete_one_partition(conn: &DBPool, partition_id: i32) -> QueryResult<usize> {
-    conn.run(move |c| diesel::delete(partitions::table.find(partition_id)).execute(c))
+pub async fn get_person(conn: &DBPool, person_id: i32) -> QueryResult<Person> {
+    conn.run(move |c| repo::get_person(c, person_id)).await
+}
+
+pub async fn get_genre(conn: &DBPool, ensemble_id: i32, genre_id: i32) -> QueryResult<Genre> {
+    conn.run(move |c| repo::get_genre(c, ensemble_id, genre_id))
         .await
 }
 
-//*************************************************************************************************
-// CREATE
+pub async fn get_partitions_by_person_id(
+    conn: &DBPool,
+    ensemble_id: i32,
+    person_id: i32,
+) -> QueryResult<Vec<ShowPartition>> {
+    conn.run(move |c| repo::get_partitions_by_person_id(c, ensemble_id, person_id))
+        .await
+}
 
-pub async fn create_person(conn: &DBPool, person: Person) -> QueryResult<Person> {
+pub async fn get_partitions_by_genre_id(
+    conn: &DBPool,
+    ensemble_id: i32,
+    genre_id: i32,
+) -> QueryResult<Vec<ShowPartition>> {
+    conn.run(move |c| repo::get_partitions_by_genre_id(c, ensemble_id, genre_id))
+        .await
+}
+
+// la partition de ce titre pour le compositeur nommé
+pub async fn find_partition(
+    conn: &DBPool,
+    ensemble_id: i32,
+    title: String,
+    person_full_name: String,
+) -> QueryResult<Partition> {
     conn.run(move |c| {
-        diesel::insert_into(persons::table)
-            .values(&person)
-            .get_result(c)
+        let person = repo::get_person_by_name(c, &person_full_name)?;
+        repo::find_partition(c, ensemble_id, &title, person.id.unwrap_or_default())
     })
     .await
 }
 
-pub async fn create_genre(conn: &DBPool, genre: Genre) -> QueryResult<Genre> {
+// ************************************************************************************************
+// Tags and bulk operations
+
+pub async fn get_partition_tags(conn: &DBPool, partition_id: i32) -> QueryResult<Vec<Tag>> {
+    conn.run(move |c| repo::get_partition_tags(c, partition_id))
+        .await
+}
+
+pub async fn get_partitions_with_tags(
+    conn: &DBPool,
+    ensemble_id: i32,
+    ids: Vec<i32>,
+) -> QueryResult<Vec<(ShowPartition, Vec<String>)>> {
+    conn.run(move |c| repo::get_partitions_with_tags(c, ensemble_id, &ids))
+        .await
+}
+
+pub async fn bulk_update(
+    conn: &DBPool,
+    ensemble_id: i32,
+    action: BulkAction,
+) -> QueryResult<usize> {
+    conn.run(move |c| repo::bulk_update(c, ensemble_id, &action))
+        .await
+}
+
+// ************************************************************************************************
+// Statistics, for the dashboard
+
+pub async fn count_catalogue(conn: &DBPool, ensemble_id: i32) -> QueryResult<(i64, i64, i64)> {
+    conn.run(move |c| repo::count_catalogue(c, ensemble_id))
+        .await
+}
+
+pub async fn count_partitions_by_genre(
+    conn: &DBPool,
+    ensemble_id: i32,
+) -> QueryResult<Vec<LabelCount>> {
+    conn.run(move |c| repo::count_partitions_by_genre(c, ensemble_id))
+        .await
+}
+
+pub async fn count_partitions_by_person(
+    conn: &DBPool,
+    ensemble_id: i32,
+    limit: i64,
+) -> QueryResult<Vec<LabelCount>> {
+    conn.run(move |c| repo::count_partitions_by_person(c, ensemble_id, limit))
+        .await
+}
+
+pub async fn count_loans_by_partition(
+    conn: &DBPool,
+    ensemble_id: i32,
+    limit: i64,
+) -> QueryResult<Vec<LabelCount>> {
+    conn.run(move |c| repo::count_loans_by_partition(c, ensemble_id, limit))
+        .await
+}
+
+pub async fn get_recent_partitions(
+    conn: &DBPool,
+    ensemble_id: i32,
+    limit: i64,
+) -> QueryResult<Vec<ShowPartition>> {
+    conn.run(move |c| repo::get_recent_partitions(c, ensemble_id, limit))
+        .await
+}
+
+pub async fn count_partitions_by_month(
+    conn: &DBPool,
+    ensemble_id: i32,
+) -> QueryResult<Vec<LabelCount>> {
+    conn.run(move |c| repo::count_partitions_by_month(c, ensemble_id))
+        .await
+}
+
+// ************************************************************************************************
+// Users
+
+pub async fn get_user(conn: &DBPool, user_id: i32) -> QueryResult<User> {
+    conn.run(move |c| repo::get_user(c, user_id)).await
+}
+
+pub async fn get_user_by_email(conn: &DBPool, email: String) -> QueryResult<User> {
+    conn.run(move |c| repo::get_user_by_email(c, &email)).await
+}
+
+pub async fn create_user(conn: &DBPool, user: User) -> QueryResult<User> {
+    conn.run(move |c| repo::create_user(c, &user)).await
+}
+
+pub async fn get_users_by_status(conn: &DBPool, status: &'static str) -> QueryResult<Vec<User>> {
+    conn.run(move |c| repo::get_users_by_status(c, status))
+        .await
+}
+
+pub async fn set_user_status(
+    conn: &DBPool,
+    user_id: i32,
+    status: &'static str,
+) -> QueryResult<User> {
+    conn.run(move |c| repo::set_user_status(c, user_id, status))
+        .await
+}
+
+pub async fn update_profile(
+    conn: &DBPool,
+    user_id: i32,
+    display_name: String,
+    locale: String,
+) -> QueryResult<User> {
+    conn.run(move |c| repo::update_profile(c, user_id, &display_name, &locale))
+        .await
+}
+
+pub async fn update_password(
+    conn: &DBPool,
+    user_id: i32,
+    password_hash: String,
+) -> QueryResult<User> {
+    conn.run(move |c| repo::update_password(c, user_id, &password_hash))
+        .await
+}
+
+pub async fn reset_password(
+    conn: &DBPool,
+    token_hash: String,
+    password_hash: String,
+) -> QueryResult<User> {
+    conn.run(move |c| repo::reset_password(c, &token_hash, &password_hash))
+        .await
+}
+
+pub async fn delete_user(conn: &DBPool, user_id: i32) -> QueryResult<usize> {
+    conn.run(move |c| repo::delete_user(c, user_id)).await
+}
+
+pub async fn create_pending_user(
+    conn: &DBPool,
+    user: User,
+    token_hash: String,
+    valid_hours: i32,
+) -> QueryResult<User> {
+    conn.run(move |c| repo::create_pending_user(c, &user, &token_hash, valid_hours))
+        .await
+}
+
+pub async fn create_user_token(
+    conn: &DBPool,
+    user_id: i32,
+    purpose: &'static str,
+    token_hash: String,
+    valid_hours: i32,
+) -> QueryResult<()> {
+    conn.run(move |c| repo::create_user_token(c, user_id, purpose, &token_hash, valid_hours))
+        .await
+}
+
+pub async fn count_recent_tokens(
+    conn: &DBPool,
+    user_id: i32,
+    purpose: &'static str,
+    hours: i32,
+) -> QueryResult<i64> {
+    conn.run(move |c| repo::count_recent_tokens(c, user_id, purpose, hours))
+        .await
+}
+
+pub async fn take_user_token(
+    conn: &DBPool,
+    purpose: &'static str,
+    token_hash: String,
+) -> QueryResult<User> {
+    conn.run(move |c| repo::take_user_token(c, purpose, &token_hash))
+        .await
+}
+
+// ************************************************************************************************
+// API tokens
+
+pub async fn get_api_tokens(conn: &DBPool, user_id: i32) -> QueryResult<Vec<ApiToken>> {
+    conn.run(move |c| repo::get_api_tokens(c, user_id)).await
+}
+
+pub async fn create_api_token(
+    conn: &DBPool,
+    user_id: i32,
+    ensemble_id: i32,
+    name: String,
+    scope: &'static str,
+    token_hash: String,
+    valid_days: Option<i32>,
+) -> QueryResult<ApiToken> {
     conn.run(move |c| {
-        diesel::insert_into(genres::table)
-            .values(&genre)
-            .get_result(c)
+        repo::create_api_token(
+            c,
+            user_id,
+            ensemble_id,
+            &name,
+            scope,
+            &token_hash,
+            valid_days,
+        )
     })
     .await
 }
 
-pub async fn create_partition(
+pub async fn delete_api_token(conn: &DBPool, user_id: i32, token_id: i32) -> QueryResult<usize> {
+    conn.run(move |c| repo::delete_api_token(c, user_id, token_id))
+        .await
+}
+
+pub async fn use_api_token(conn: &DBPool, token_hash: String) -> QueryResult<(ApiToken, User)> {
+    conn.run(move |c| repo::use_api_token(c, &token_hash)).await
+}
+
+// ************************************************************************************************
+// Ensembles
+
+pub async fn get_ensembles(conn: &DBPool) -> QueryResult<Vec<Ensemble>> {
+    conn.run(|c| repo::get_ensembles(c)).await
+}
+
+pub async fn get_ensemble(conn: &DBPool, ensemble_id: i32) -> QueryResult<Ensemble> {
+    conn.run(move |c| repo::get_ensemble(c, ensemble_id)).await
+}
+
+pub async fn get_available_ensembles(
     conn: &DBPool,
-    show_partition: ShowPartition,
-) -> QueryResult<Partition> {
-    let nom = show_partition.full_name.trim();
-
-    let pers = get_person_by_name(conn, nom.to_string()).await?;
-    println!("{:?}", pers);
-    let g = get_genre_by_name(conn, show_partition.name).await?;
-    println!("{:?}", g);
-    let person_id = pers.id.unwrap();
-    let genre_id = g.id.unwrap();
-
-    let partition = Partition {
-        id: None,
-        person_id,
-        title: show_partition.title,
-        genre_id,
-    };
+    user: Option<User>,
+) -> QueryResult<Vec<Ensemble>> {
+    conn.run(move |c| repo::get_available_ensembles(c, user.as_ref()))
+        .await
+}
+
+pub async fn choose_ensemble(
+    conn: &DBPool,
+    user: Option<User>,
+    chosen: Option<i32>,
+) -> QueryResult<Ensemble> {
+    conn.run(move |c| repo::choose_ensemble(c, user.as_ref(), chosen))
+        .await
+}
+
+pub async fn create_ensemble(conn: &DBPool, name: String) -> QueryResult<Ensemble> {
+    conn.run(move |c| repo::create_ensemble(c, &name)).await
+}
+
+pub async fn is_ensemble_member(
+    conn: &DBPool,
+    ensemble_id: i32,
+    user_id: i32,
+) -> QueryResult<bool> {
+    conn.run(move |c| repo::is_ensemble_member(c, ensemble_id, user_id))
+        .await
+}
+
+pub async fn get_ensemble_members(conn: &DBPool, ensemble_id: i32) -> QueryResult<Vec<User>> {
+    conn.run(move |c| repo::get_ensemble_members(c, ensemble_id))
+        .await
+}
 
+pub async fn add_ensemble_member(
+    conn: &DBPool,
+    ensemble_id: i32,
+    user_id: i32,
+) -> QueryResult<usize> {
+    conn.run(move |c| repo::add_ensemble_member(c, ensemble_id, user_id))
+        .await
+}
+
+pub async fn remove_ensemble_member(
+    conn: &DBPool,
+    ensemble_id: i32,
+    user_id: i32,
+) -> QueryResult<usize> {
+    conn.run(move |c| repo::remove_ensemble_member(c, ensemble_id, user_id))
+        .await
+}
+
+// ************************************************************************************************
+// Files and details, for the imported partitions, the thumbnails and the incipits
+
+// `score` : ce que le fichier MusicXML décrit (voir musicxml.rs)
+pub async fn import_partition(
+    conn: &DBPool,
+    ensemble_id: i32,
+    new_partition: NewPartition,
+    score: Score,
+    file_name: String,
+    path: String,
+    content_type: String,
+) -> QueryResult<(ShowPartition, PartitionFile)> {
     conn.run(move |c| {
-        diesel::insert_into(partitions::table)
-            .values(&partition)
-            .get_result(c)
+        repo::import_partition(
+            c,
+            ensemble_id,
+            &new_partition,
+            &score,
+            &file_name,
+            &path,
+            &content_type,
+        )
     })
     .await
 }
 
-//******************************************************************************************
-// UPDATE
+pub async fn get_partition_files(
+    conn: &DBPool,
+    partition_id: i32,
+) -> QueryResult<Vec<PartitionFile>> {
+    conn.run(move |c| repo::get_partition_files(c, partition_id))
+        .await
+}
 
-pub async fn update_person(pers_id: i32, person: Person, conn: &DBPool) -> QueryResult<Person> {
+pub async fn get_partition_file(
+    conn: &DBPool,
+    ensemble_id: i32,
+    partition_id: i32,
+    file_id: i32,
+) -> QueryResult<PartitionFile> {
+    conn.run(move |c| repo::get_partition_file(c, ensemble_id, partition_id, file_id))
+        .await
+}
+
+pub async fn attach_partition_file(
+    conn: &DBPool,
+    ensemble_id: i32,
+    partition_id: i32,
+    name: String,
+    path: String,
+    content_type: String,
+) -> QueryResult<PartitionFile> {
     conn.run(move |c| {
-        diesel::update(persons::table.find(pers_id))
-            .set(&person)
-            .get_result(c)
+        repo::attach_partition_file(c, ensemble_id, partition_id, &name, &path, &content_type)
     })
     .await
 }
 
-pub async fn update_genre(genre_id: i32, genre: Genre, conn: &DBPool) -> QueryResult<Genre> {
+pub async fn set_file_thumbnail(
+    conn: &DBPool,
+    file_id: i32,
+    thumbnail: bool,
+) -> QueryResult<usize> {
+    conn.run(move |c| repo::set_file_thumbnail(c, file_id, thumbnail))
+        .await
+}
+
+pub async fn get_partition_thumbnail(
+    conn: &DBPool,
+    ensemble_id: i32,
+    partition_id: i32,
+) -> QueryResult<PartitionFile> {
+    conn.run(move |c| repo::get_partition_thumbnail(c, ensemble_id, partition_id))
+        .await
+}
+
+pub async fn get_thumbnail_ids(conn: &DBPool, ensemble_id: i32) -> QueryResult<Vec<i32>> {
+    conn.run(move |c| repo::get_thumbnail_ids(c, ensemble_id))
+        .await
+}
+
+pub async fn get_partition_details(
+    conn: &DBPool,
+    partition_id: i32,
+) -> QueryResult<Option<PartitionDetails>> {
+    conn.run(move |c| repo::get_partition_details(c, partition_id))
+        .await
+}
+
+pub async fn get_partition_incipit(
+    conn: &DBPool,
+    ensemble_id: i32,
+    partition_id: i32,
+) -> QueryResult<String> {
+    conn.run(move |c| repo::get_partition_incipit(c, ensemble_id, partition_id))
+        .await
+}
+
+pub async fn get_incipit_ids(conn: &DBPool, ensemble_id: i32) -> QueryResult<Vec<i32>> {
+    conn.run(move |c| repo::get_incipit_ids(c, ensemble_id))
+        .await
+}
+
+pub async fn set_partition_incipit(
+    conn: &DBPool,
+    ensemble_id: i32,
+    partition_id: i32,
+    incipit: String,
+) -> QueryResult<usize> {
+    conn.run(move |c| repo::set_partition_incipit(c, ensemble_id, partition_id, &incipit))
+        .await
+}
+
+pub async fn get_partition_creators(
+    conn: &DBPool,
+    partition_id: i32,
+) -> QueryResult<Vec<PartitionCreator>> {
+    conn.run(move |c| repo::get_partition_creators(c, partition_id))
+        .await
+}
+
+// ************************************************************************************************
+// Sharing and loans
+
+pub async fn is_partition_shared(conn: &DBPool, partition_id: i32) -> QueryResult<bool> {
+    conn.run(move |c| repo::is_partition_shared(c, partition_id))
+        .await
+}
+
+pub async fn get_shared_libraries(
+    conn: &DBPool,
+    ensemble_id: i32,
+) -> QueryResult<Vec<SharedLibrary>> {
+    conn.run(move |c| repo::get_shared_libraries(c, ensemble_id))
+        .await
+}
+
+pub async fn get_shared_partitions(
+    conn: &DBPool,
+    owner_id: i32,
+    ensemble_id: i32,
+) -> QueryResult<Vec<SharedPartition>> {
+    conn.run(move |c| repo::get_shared_partitions(c, owner_id, ensemble_id))
+        .await
+}
+
+pub async fn request_loan(
+    conn: &DBPool,
+    ensemble_id: i32,
+    user_id: i32,
+    partition_id: i32,
+    message: String,
+) -> QueryResult<Loan> {
+    conn.run(move |c| repo::request_loan(c, ensemble_id, user_id, partition_id, &message))
+        .await
+}
+
+pub async fn get_loans_received(conn: &DBPool, ensemble_id: i32) -> QueryResult<Vec<Loan>> {
+    conn.run(move |c| repo::get_loans_received(c, ensemble_id))
+        .await
+}
+
+pub async fn get_loans_sent(conn: &DBPool, ensemble_id: i32) -> QueryResult<Vec<Loan>> {
+    conn.run(move |c| repo::get_loans_sent(c, ensemble_id))
+        .await
+}
+
+pub async fn count_pending_loans(conn: &DBPool, ensemble_id: i32) -> QueryResult<i64> {
+    conn.run(move |c| repo::count_pending_loans(c, ensemble_id))
+        .await
+}
+
+pub async fn decide_loan(
+    conn: &DBPool,
+    ensemble_id: i32,
+    loan_id: i32,
+    decision: LoanDecision,
+) -> QueryResult<Loan> {
+    conn.run(move |c| repo::decide_loan(c, ensemble_id, loan_id, decision))
+        .await
+}
+
+// ************************************************************************************************
+// Background jobs, for the pages and the worker (see jobs.rs)
+
+pub async fn enqueue_job(
+    conn: &DBPool,
+    kind: String,
+    ensemble_id: Option<i32>,
+    payload: String,
+    max_attempts: i32,
+) -> QueryResult<Job> {
+    conn.run(move |c| repo::enqueue_job(c, &kind, ensemble_id, &payload, max_attempts))
+        .await
+}
+
+pub async fn claim_job(conn: &DBPool) -> QueryResult<Option<Job>> {
+    conn.run(|c| repo::claim_job(c)).await
+}
+
+pub async fn finish_job(conn: &DBPool, job_id: i32) -> QueryResult<usize> {
+    conn.run(move |c| repo::finish_job(c, job_id)).await
+}
+
+pub async fn fail_job(
+    conn: &DBPool,
+    job_id: i32,
+    error: String,
+    retry_delay: i32,
+    give_up: bool,
+) -> QueryResult<usize> {
+    conn.run(move |c| repo::fail_job(c, job_id, &error, retry_delay, give_up))
+        .await
+}
+
+pub async fn requeue_running_jobs(conn: &DBPool) -> QueryResult<usize> {
+    conn.run(|c| repo::requeue_running_jobs(c)).await
+}
+
+pub async fn cancel_job(
+    conn: &DBPool,
+    ensemble_id: Option<i32>,
+    with_admin: bool,
+    job_id: i32,
+) -> QueryResult<Job> {
+    conn.run(move |c| repo::cancel_job(c, ensemble_id, with_admin, job_id))
+        .await
+}
+
+pub async fn get_jobs(
+    conn: &DBPool,
+    ensemble_id: Option<i32>,
+    with_admin: bool,
+) -> QueryResult<Vec<Job>> {
+    conn.run(move |c| repo::get_jobs(c, ensemble_id, with_admin))
+        .await
+}
+
+pub async fn get_job(
+    conn: &DBPool,
+    ensemble_id: Option<i32>,
+    with_admin: bool,
+    job_id: i32,
+) -> QueryResult<Job> {
+    conn.run(move |c| repo::get_job(c, ensemble_id, with_admin, job_id))
+        .await
+}
+
+// ************************************************************************************************
+// Webhooks, the subscriptions and their deliveries (see webhooks.rs)
+
+pub async fn create_webhook(
+    conn: &DBPool,
+    ensemble_id: i32,
+    url: String,
+    secret: String,
+    events: Vec<String>,
+    active: bool,
+) -> QueryResult<Webhook> {
+    conn.run(move |c| repo::create_webhook(c, ensemble_id, &url, &secret, &events, active))
+        .await
+}
+
+pub async fn update_webhook(
+    conn: &DBPool,
+    ensemble_id: i32,
+    webhook_id: i32,
+    url: String,
+    secret: String,
+    events: Vec<String>,
+    active: bool,
+) -> QueryResult<Webhook> {
     conn.run(move |c| {
-        diesel::update(genres::table.find(genre_id))
-            .set(&genre)
-            .get_result(c)
+        repo::update_webhook(c, ensemble_id, webhook_id, &url, &secret, &events, active)
     })
     .await
 }
 
+pub async fn delete_webhook(
+    conn: &DBPool,
+    ensemble_id: i32,
+    webhook_id: i32,
+) -> QueryResult<usize> {
+    conn.run(move |c| repo::delete_webhook(c, ensemble_id, webhook_id))
+        .await
+}
+
+pub async fn get_webhooks(conn: &DBPool, ensemble_id: i32) -> QueryResult<Vec<Webhook>> {
+    conn.run(move |c| repo::get_webhooks(c, ensemble_id)).await
+}
+
+pub async fn get_webhook(conn: &DBPool, ensemble_id: i32, webhook_id: i32) -> QueryResult<Webhook> {
+    conn.run(move |c| repo::get_webhook(c, ensemble_id, webhook_id))
+        .await
+}
+
+pub async fn queue_deliveries(
+    conn: &DBPool,
+    ensemble_id: Option<i32>,
+    event: String,
+    data: String,
+) -> QueryResult<Vec<QueuedDelivery>> {
+    conn.run(move |c| repo::queue_deliveries(c, ensemble_id, &event, &data))
+        .await
+}
+
+pub async fn queue_test_delivery(
+    conn: &DBPool,
+    ensemble_id: i32,
+    webhook_id: i32,
+    event: String,
+    data: String,
+) -> QueryResult<QueuedDelivery> {
+    conn.run(move |c| repo::queue_test_delivery(c, ensemble_id, webhook_id, &event, &data))
+        .await
+}
+
+pub async fn get_deliveries(conn: &DBPool, webhook_id: i32) -> QueryResult<Vec<WebhookDelivery>> {
+    conn.run(move |c| repo::get_deliveries(c, webhook_id)).await
+}
+
+// ************************************************************************************************
+// Backup and restore
+
+pub async fn load_snapshot(conn: &DBPool) -> QueryResult<Snapshot> {
+    conn.run(|c| repo::load_snapshot(c)).await
+}
+
+pub async fn restore_archive(
+    conn: &DBPool,
+    archive: Archive,
+    upload_dir: PathBuf,
+) -> Result<RestoreReport, BackupError> {
+    conn.run(move |c| repo::restore_archive(c, archive, &upload_dir))
+        .await
+}
+
+//*************************************************************************************************
+// DELETE
+
+pub async fn delete_one_person(conn: &DBPool, person_id: i32) -> QueryResult<usize> {
+    conn.run(move |c| repo::delete_person(c, person_id)).await
+}
+
+pub async fn delete_one_genre(
+    conn: &DBPool,
+    ensemble_id: i32,
+    admin: bool,
+    genre_id: i32,
+) -> QueryResult<usize> {
+    conn.run(move |c| repo::delete_genre(c, ensemble_id, admin, genre_id))
+        .await
+}
+
+pub async fn delete_one_partition(
+    conn: &DBPool,
+    ensemble_id: i32,
+    partition_id: i32,
+) -> QueryResult<usize> {
+    conn.run(move |c| repo::delete_partition(c, ensemble_id, partition_id))
+        .await
+}
+
+//*************************************************************************************************
+// CREATE
+
+pub async fn create_person(conn: &DBPool, person: Person) -> QueryResult<Person> {
+    conn.run(move |c| repo::create_person(c, person)).await
+}
+
+pub async fn create_genre(conn: &DBPool, genre: Genre) -> QueryResult<Genre> {
+    conn.run(move |c| repo::create_genre(c, genre)).await
+}
+
+pub async fn create_partition(
+    conn: &DBPool,
+    ensemble_id: i32,
+    new_partition: NewPartition,
+) -> QueryResult<ShowPartition> {
+    conn.run(move |c| repo::create_partition(c, ensemble_id, &new_partition))
+        .await
+}
+
+//******************************************************************************************
+// UPDATE
+
+pub async fn update_person(pers_id: i32, person: Person, conn: &DBPool) -> QueryResult<Person> {
+    conn.run(move |c| repo::update_person(c, pers_id, person))
+        .await
+}
+
+pub async fn update_genre(
+    ensemble_id: i32,
+    admin: bool,
+    genre_id: i32,
+    genre: Genre,
+    conn: &DBPool,
+) -> QueryResult<Genre> {
+    conn.run(move |c| repo::update_genre(c, ensemble_id, admin, genre_id, genre))
+        .await
+}
+
 pub async fn update_partition(
+    ensemble_id: i32,
     part_id: i32,
     partition: Partition,
     conn: &DBPool,
 ) -> QueryResult<Partition> {
-    conn.run(move |c| {
-        diesel::update(partitions::table.find(part_id))
-            .set(&partition)
-            .get_result(c)
-    })
-    .await
+    conn.run(move |c| repo::update_partition(c, ensemble_id, part_id, partition))
+        .await
 }
@@ -0,0 +1,150 @@
+use fluent::FluentArgs;
+
+use rocket::http::{Cookie, CookieJar, Status};
+use rocket::outcome::try_outcome;
+use rocket::request::{FlashMessage, FromRequest, Outcome, Request};
+use rocket::response::{Flash, Redirect};
+use rocket::serde::Serialize;
+
+use rocket_dyn_templates::Template;
+
+use crate::i18n::Locale;
+use crate::models::{Ensemble, User};
+use crate::notification::Notification;
+use crate::{db, DBPool};
+
+// Ensembles
+//
+// une instance sert plusieurs chœurs ou orchestres : chacun a sa bibliothèque
+// (partitions, genres qui lui sont réservés), les compositeurs et les genres
+// communs sont partagés. La bibliothèque choisie est gardée dans un cookie
+// privé permanent ; elle doit rester permise (voir repo::choose_ensemble),
+// sinon c'est la première permise qui est utilisée.
+//
+// sans compte, ou sans être membre, une bibliothèque ne se consulte qu'en
+// lecture : les routes qui la modifient demandent le guard `Member`. Un
+// visiteur ou un compte sans ensemble ne choisit que l'ensemble par défaut
+// (voir repo::get_available_ensembles).
+
+pub const ENSEMBLE_COOKIE: &str = "ensemble";
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Ensemble {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let ensemble = req
+            .local_cache_async(async {
+                let user = req.guard::<User>().await.succeeded();
+                let chosen = req
+                    .cookies()
+                    .get_private(ENSEMBLE_COOKIE)
+                    .and_then(|cookie| cookie.value().parse::<i32>().ok());
+                let conn = req.guard::<DBPool>().await.succeeded()?;
+                match db::choose_ensemble(&conn, user, chosen).await {
+                    Ok(ensemble) => Some(ensemble),
+                    Err(e) => {
+                        error_!("DB choose_ensemble error: {}", e);
+                        None
+                    }
+                }
+            })
+            .await;
+
+        match ensemble {
+            Some(ensemble) => Outcome::Success(ensemble.clone()),
+            None => Outcome::Failure((Status::InternalServerError, ())),
+        }
+    }
+}
+
+// un compte connecté, membre de l'ensemble choisi : administrateur ou non,
+// il faut être membre pour modifier une bibliothèque
+pub struct Member(pub User);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Member {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let user = try_outcome!(req.guard::<User>().await);
+        let ensemble = try_outcome!(req.guard::<Ensemble>().await);
+        let conn = match req.guard::<DBPool>().await.succeeded() {
+            Some(conn) => conn,
+            None => return Outcome::Failure((Status::ServiceUnavailable, ())),
+        };
+        let (ensemble_id, user_id) = (ensemble.id.unwrap_or_default(), user.id.unwrap_or_default());
+        match db::is_ensemble_member(&conn, ensemble_id, user_id).await {
+            Ok(true) => Outcome::Success(Member(user)),
+            Ok(false) => Outcome::Failure((Status::Forbidden, ())),
+            Err(e) => {
+                error_!("DB is_ensemble_member error: {}", e);
+                Outcome::Failure((Status::InternalServerError, ()))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct EnsemblesPage {
+    flash: Option<Notification>,
+    title: String,
+    lang: String,
+    current: Ensemble,
+    ensembles: Vec<Ensemble>,
+}
+
+#[get("/ensembles")]
+pub async fn ensembles_page(
+    current: Ensemble,
+    user: Option<User>,
+    conn: DBPool,
+    flash: Option<FlashMessage<'_>>,
+    locale: Locale,
+) -> Template {
+    let ensembles = db::get_available_ensembles(&conn, user)
+        .await
+        .unwrap_or_else(|e| {
+            error_!("DB get_available_ensembles error: {}", e);
+            vec![]
+        });
+    let page = EnsemblesPage {
+        flash: Notification::from_flash(flash),
+        title: locale.tr("title-ensembles"),
+        lang: locale.lang().to_string(),
+        current,
+        ensembles,
+    };
+    Template::render("ensembles", &page)
+}
+
+// comme le changement de langue : un simple lien, qui ne change qu'un cookie
+#[get("/ensembles/<id>/choose")]
+pub async fn choose_ensemble(
+    id: i32,
+    user: Option<User>,
+    conn: DBPool,
+    cookies: &CookieJar<'_>,
+    locale: Locale,
+) -> Flash<Redirect> {
+    let ensembles = match db::get_available_ensembles(&conn, user).await {
+        Ok(ensembles) => ensembles,
+        Err(e) => {
+            error_!("DB get_available_ensembles error: {}", e);
+            return Notification::error(locale.tr("msg-db-access-failed")).redirect("/ensembles");
+        }
+    };
+    match ensembles.into_iter().find(|e| e.id == Some(id)) {
+        Some(ensemble) => {
+            let mut cookie = Cookie::new(ENSEMBLE_COOKIE, id.to_string());
+            cookie.set_path("/");
+            cookie.make_permanent();
+            cookies.add_private(cookie);
+            let mut args = FluentArgs::new();
+            args.set("name", ensemble.name);
+            Notification::success(locale.tr_args("msg-ensemble-chosen", &args))
+                .redirect("/partitions")
+        }
+        None => Notification::warning(locale.tr("msg-ensemble-not-allowed")).redirect("/ensembles"),
+    }
+}
@@ -1,102 +1,233 @@
 use std::collections::HashMap;
 
-use rocket::form::Form;
+use fluent::FluentArgs;
+
+use rocket::request::FlashMessage;
 use rocket::response::{Flash, Redirect};
 use rocket::serde::Serialize;
-use rocket::Request;
+use rocket::{Request, State};
 
 use rocket_dyn_templates::Template;
 
-use crate::db::{get_list_genres, get_list_persons};
-use crate::models::{Genre, Person, ShowPartition, Partition};
-use crate::{db, DBPool};
+use crate::auth::AdminUser;
+use crate::csrf::{CsrfChecked, CsrfForm, CsrfToken};
+use crate::ensemble::Member;
+use crate::i18n::Locale;
+use crate::jobs::JobQueue;
+use crate::models::{
+    Ensemble, Genre, NewPartition, Partition, PartitionCreator, PartitionDetails, PartitionFile,
+    PartitionSearch, Person, ShowPartition, Tag, User,
+};
+use crate::notification::Notification;
+use crate::stats::Dashboard;
+use crate::webhooks::{self, Deleted};
+use crate::{db, repo, DBPool};
 
 // Context : pour affichage général
 //
 #[derive(Debug, Serialize)]
 struct Context {
-    flash: Option<(String, String)>,
+    flash: Option<Notification>,
     title: String,
     partitions: Vec<ShowPartition>,
     persons: Vec<Person>,
     genres: Vec<Genre>,
+    search: PartitionSearch,
+    csrf_token: String,
+    lang: String,
+    // la bibliothèque affichée (voir ensemble.rs)
+    ensemble: Option<Ensemble>,
+    // les partitions de la liste qui ont un incipit (voir incipits.rs)
+    incipits: Vec<i32>,
+    // ... ou une vignette (voir thumbnails.rs)
+    thumbnails: Vec<i32>,
 }
 
 impl Context {
-    pub async fn err<M: std::fmt::Display>(conn: &DBPool, msg: M) -> Context {
+    pub async fn err<M: std::fmt::Display>(
+        conn: &DBPool,
+        csrf: &CsrfToken,
+        locale: &Locale,
+        msg: M,
+    ) -> Context {
         Context {
-            flash: Some(("error".into(), msg.to_string())),
+            flash: Some(Notification::error(msg.to_string())),
             persons: vec![],
             genres: vec![],
-            title: "Erreur !".to_string(),
+            title: locale.tr("title-error"),
             partitions: vec![],
+            search: PartitionSearch::default(),
+            csrf_token: csrf.value().to_string(),
+            lang: locale.lang().to_string(),
+            ensemble: None,
+            incipits: vec![],
+            thumbnails: vec![],
         }
     }
 
-    pub async fn raw_pers(conn: &DBPool, flash: Option<(String, String)>) -> Context {
+    pub async fn raw_pers(
+        conn: &DBPool,
+        ensemble: &Ensemble,
+        csrf: &CsrfToken,
+        locale: &Locale,
+        flash: Option<FlashMessage<'_>>,
+    ) -> Context {
         match db::get_list_persons(conn).await {
             Ok(persons) => Context {
-                flash,
+                flash: Notification::from_flash(flash),
                 persons,
                 genres: vec![],
-                title: "Liste des Personnes".to_string(),
+                title: locale.tr("title-persons"),
                 partitions: vec![],
+                search: PartitionSearch::default(),
+                csrf_token: csrf.value().to_string(),
+                lang: locale.lang().to_string(),
+                ensemble: Some(ensemble.clone()),
+                incipits: vec![],
+                thumbnails: vec![],
             },
             Err(e) => {
                 error_!("DB get_list_persons error: {}", e);
                 Context {
-                    flash: Some(("error".into(), "Fail to access database.".into())),
+                    flash: Some(Notification::error(locale.tr("msg-db-access-failed"))),
                     persons: vec![],
                     genres: vec![],
-                    title: "".to_string(),
+                    title: locale.tr("title-error"),
                     partitions: vec![],
+                    search: PartitionSearch::default(),
+                    csrf_token: csrf.value().to_string(),
+                    lang: locale.lang().to_string(),
+                    ensemble: Some(ensemble.clone()),
+                    incipits: vec![],
+                    thumbnails: vec![],
                 }
             }
         }
     }
 
-    pub async fn raw_genres(conn: &DBPool, flash: Option<(String, String)>) -> Context {
-        match db::get_list_genres(conn).await {
+    pub async fn raw_genres(
+        conn: &DBPool,
+        ensemble: &Ensemble,
+        csrf: &CsrfToken,
+        locale: &Locale,
+        flash: Option<FlashMessage<'_>>,
+    ) -> Context {
+        let ensemble_id = ensemble.id.unwrap_or_default();
+        match db::get_list_genres(conn, ensemble_id).await {
             Ok(genres) => Context {
-                flash,
+                flash: Notification::from_flash(flash),
                 persons: vec![],
                 genres,
-                title: "Liste des Genres".to_string(),
+                title: locale.tr("title-genres"),
                 partitions: vec![],
+                search: PartitionSearch::default(),
+                csrf_token: csrf.value().to_string(),
+                lang: locale.lang().to_string(),
+                ensemble: Some(ensemble.clone()),
+                incipits: vec![],
+                thumbnails: vec![],
             },
             Err(e) => {
                 error_!("DB get_list_genres error: {}", e);
                 Context {
-                    flash: Some(("error".into(), "Fail to access database.".into())),
+                    flash: Some(Notification::error(locale.tr("msg-db-access-failed"))),
                     persons: vec![],
                     genres: vec![],
-                    title: "".to_string(),
+                    title: locale.tr("title-error"),
                     partitions: vec![],
+                    search: PartitionSearch::default(),
+                    csrf_token: csrf.value().to_string(),
+                    lang: locale.lang().to_string(),
+                    ensemble: Some(ensemble.clone()),
+                    incipits: vec![],
+                    thumbnails: vec![],
                 }
             }
         }
     }
 
-    pub async fn raw_partitions(conn: &DBPool, flash: Option<(String, String)>) -> Context {
-        let persons = db::get_list_persons(conn).await.unwrap();
-        let genres = db::get_list_genres(conn).await.unwrap();
-
-        match db::get_list_show_partitions(conn).await {
-            Ok(show_part) => Context {
-                flash,
-                persons,
-                genres,
-                title: "Liste des Partitions".to_string(),
-                partitions: show_part,
-            },
+    pub async fn raw_partitions(
+        conn: &DBPool,
+        ensemble: &Ensemble,
+        csrf: &CsrfToken,
+        locale: &Locale,
+        flash: Option<FlashMessage<'_>>,
+        search: PartitionSearch,
+    ) -> Context {
+        let ensemble_id = ensemble.id.unwrap_or_default();
+        // les listes des formulaires de la page : elle s'affiche aussi sans elles
+        let persons = db::get_list_persons(conn).await.unwrap_or_else(|e| {
+            error_!("DB get_list_persons error: {}", e);
+            vec![]
+        });
+        let genres = db::get_list_genres(conn, ensemble_id).await.unwrap_or_else(|e| {
+            error_!("DB get_list_genres error: {}", e);
+            vec![]
+        });
+
+        let result = if search.is_empty() {
+            db::get_list_show_partitions(conn, ensemble_id).await
+        } else {
+            db::search_partitions(conn, ensemble_id, search.clone()).await
+        };
+
+        match result {
+            Ok(show_part) => {
+                // sans message de la page précédente, on indique le nombre de résultats
+                let flash = Notification::from_flash(flash).or_else(|| {
+                    if search.is_empty() {
+                        None
+                    } else {
+                        let mut args = FluentArgs::new();
+                        args.set("count", show_part.len());
+                        Some(Notification::info(locale.tr_args("msg-search-results", &args)))
+                    }
+                });
+                let title = if search.is_empty() {
+                    locale.tr("title-partitions")
+                } else {
+                    locale.tr("title-search")
+                };
+                let incipits = db::get_incipit_ids(conn, ensemble_id)
+                    .await
+                    .unwrap_or_else(|e| {
+                        error_!("DB get_incipit_ids error: {}", e);
+                        vec![]
+                    });
+                let thumbnails = db::get_thumbnail_ids(conn, ensemble_id)
+                    .await
+                    .unwrap_or_else(|e| {
+                        error_!("DB get_thumbnail_ids error: {}", e);
+                        vec![]
+                    });
+                Context {
+                    flash,
+                    persons,
+                    genres,
+                    title,
+                    partitions: show_part,
+                    search,
+                    csrf_token: csrf.value().to_string(),
+                    lang: locale.lang().to_string(),
+                    ensemble: Some(ensemble.clone()),
+                    incipits,
+                    thumbnails,
+                }
+            }
             Err(e) => {
                 error_!("DB get_list_show_partitions error: {}", e);
                 Context {
-                    flash: Some(("error".into(), "Fail to access database.".into())),
+                    flash: Some(Notification::error(locale.tr("msg-db-access-failed"))),
                     persons,
                     genres,
-                    title: "".to_string(),
+                    title: locale.tr("title-error"),
                     partitions: vec![],
+                    search,
+                    csrf_token: csrf.value().to_string(),
+                    lang: locale.lang().to_string(),
+                    ensemble: Some(ensemble.clone()),
+                    incipits: vec![],
+                    thumbnails: vec![],
                 }
             }
         }
@@ -107,33 +238,250 @@ impl Context {
 // GET all pages
 
 #[get("/")]
-pub fn start() -> Template {
+pub async fn start(
+    conn: DBPool,
+    ensemble: Ensemble,
+    user: Option<User>,
+    locale: Locale,
+) -> Template {
     #[derive(serde::Serialize)]
     struct StartContext {
         title: String,
+        lang: String,
+        dashboard: Option<Dashboard>,
+        ensemble: Ensemble,
+        // les demandes de prêt qui attendent une réponse (membres connectés)
+        pending_loans: i64,
     }
+    let dashboard = match Dashboard::load(&conn, ensemble.id.unwrap_or_default()).await {
+        Ok(dashboard) => Some(dashboard),
+        Err(e) => {
+            error_!("DB statistics error: {}", e);
+            None
+        }
+    };
+    let pending_loans = match user {
+        Some(_) => db::count_pending_loans(&conn, ensemble.id.unwrap_or_default())
+            .await
+            .unwrap_or_else(|e| {
+                error_!("DB count_pending_loans error: {}", e);
+                0
+            }),
+        None => 0,
+    };
     let context = StartContext {
-        title: "Start".to_string(),
+        title: locale.tr("title-start"),
+        lang: locale.lang().to_string(),
+        dashboard,
+        ensemble,
+        pending_loans,
     };
     Template::render("start", &context)
 }
 
 #[get("/genres")]
-pub async fn all_genres(conn: DBPool) -> Template {
-    let flash = Some(("pas d'erreur".into(), "liste des genres trouvée".into()));
-    Template::render("genres", Context::raw_genres(&conn, flash).await)
+pub async fn all_genres(
+    flash: Option<FlashMessage<'_>>,
+    conn: DBPool,
+    ensemble: Ensemble,
+    csrf: CsrfToken,
+    locale: Locale,
+) -> Template {
+    Template::render(
+        "genres",
+        Context::raw_genres(&conn, &ensemble, &csrf, &locale, flash).await,
+    )
 }
 
 #[get("/persons")]
-pub async fn all_persons(conn: DBPool) -> Template {
-    let flash = Some(("pas d'erreur".into(), "liste des personnes trouvée".into()));
-    Template::render("persons", Context::raw_pers(&conn, flash).await)
+pub async fn all_persons(
+    flash: Option<FlashMessage<'_>>,
+    conn: DBPool,
+    ensemble: Ensemble,
+    csrf: CsrfToken,
+    locale: Locale,
+) -> Template {
+    Template::render(
+        "persons",
+        Context::raw_pers(&conn, &ensemble, &csrf, &locale, flash).await,
+    )
 }
 
-#[get("/partitions")]
-pub async fn all_partitions(conn: DBPool) -> Template {
-    let flash = Some(("pas d'erreur".into(), "liste des partitions trouvée".into()));
-    Template::render("partitions", Context::raw_partitions(&conn, flash).await)
+// la liste et la recherche : /partitions?q=...&author=...&genre=...
+#[get("/partitions?<search..>")]
+pub async fn all_partitions(
+    search: PartitionSearch,
+    flash: Option<FlashMessage<'_>>,
+    conn: DBPool,
+    ensemble: Ensemble,
+    csrf: CsrfToken,
+    locale: Locale,
+) -> Template {
+    Template::render(
+        "partitions",
+        Context::raw_partitions(&conn, &ensemble, &csrf, &locale, flash, search.normalized())
+            .await,
+    )
+}
+
+// ********************************************************************************************
+// Detail pages
+//
+
+#[derive(Debug, Serialize)]
+struct PartitionPage {
+    flash: Option<Notification>,
+    title: String,
+    lang: String,
+    csrf_token: String,
+    partition: Partition,
+    // montrée aux autres ensembles (voir loans.rs)
+    shared: bool,
+    person: Person,
+    genre: Genre,
+    tags: Vec<Tag>,
+    // lus dans un fichier MusicXML importé (voir scores.rs)
+    details: Option<PartitionDetails>,
+    creators: Vec<PartitionCreator>,
+    files: Vec<PartitionFile>,
+    related: Vec<Partition>,
+}
+
+// une personne ou un genre, avec ses partitions
+#[derive(Debug, Serialize)]
+struct OwnerPage<T: Serialize> {
+    flash: Option<Notification>,
+    title: String,
+    lang: String,
+    item: T,
+    partitions: Vec<ShowPartition>,
+}
+
+#[get("/partitions/<id>")]
+pub async fn show_partition(
+    id: i32,
+    flash: Option<FlashMessage<'_>>,
+    conn: DBPool,
+    ensemble: Ensemble,
+    csrf: CsrfToken,
+    locale: Locale,
+) -> Option<Template> {
+    let ensemble_id = ensemble.id.unwrap_or_default();
+    let (partition, person, genre) = match db::get_partition_detail(&conn, ensemble_id, id).await {
+        Ok(detail) => detail,
+        Err(e) => {
+            if e != diesel::result::Error::NotFound {
+                error_!("DB get_partition_detail({}) error: {}", id, e);
+            }
+            return None;
+        }
+    };
+    let related = db::get_related_partitions(&conn, &partition)
+        .await
+        .unwrap_or_else(|e| {
+            error_!("DB get_related_partitions({}) error: {}", id, e);
+            vec![]
+        });
+    let tags = db::get_partition_tags(&conn, id)
+        .await
+        .unwrap_or_else(|e| {
+            error_!("DB get_partition_tags({}) error: {}", id, e);
+            vec![]
+        });
+    let shared = db::is_partition_shared(&conn, id)
+        .await
+        .unwrap_or_else(|e| {
+            error_!("DB is_partition_shared({}) error: {}", id, e);
+            false
+        });
+    let details = db::get_partition_details(&conn, id)
+        .await
+        .unwrap_or_else(|e| {
+            error_!("DB get_partition_details({}) error: {}", id, e);
+            None
+        });
+    let creators = db::get_partition_creators(&conn, id)
+        .await
+        .unwrap_or_else(|e| {
+            error_!("DB get_partition_creators({}) error: {}", id, e);
+            vec![]
+        });
+    let files = db::get_partition_files(&conn, id)
+        .await
+        .unwrap_or_else(|e| {
+            error_!("DB get_partition_files({}) error: {}", id, e);
+            vec![]
+        });
+
+    let page = PartitionPage {
+        flash: Notification::from_flash(flash),
+        title: partition.title.clone(),
+        lang: locale.lang().to_string(),
+        csrf_token: csrf.value().to_string(),
+        partition,
+        shared,
+        person,
+        genre,
+        tags,
+        details,
+        creators,
+        files,
+        related,
+    };
+    Some(Template::render("partition", &page))
+}
+
+#[get("/persons/<id>")]
+pub async fn show_person(
+    id: i32,
+    flash: Option<FlashMessage<'_>>,
+    conn: DBPool,
+    ensemble: Ensemble,
+    locale: Locale,
+) -> Option<Template> {
+    let person = db::get_person(&conn, id).await.ok()?;
+    let partitions = db::get_partitions_by_person_id(&conn, ensemble.id.unwrap_or_default(), id)
+        .await
+        .unwrap_or_else(|e| {
+            error_!("DB get_partitions_by_person_id({}) error: {}", id, e);
+            vec![]
+        });
+
+    let page = OwnerPage {
+        flash: Notification::from_flash(flash),
+        title: person.full_name.clone(),
+        lang: locale.lang().to_string(),
+        item: person,
+        partitions,
+    };
+    Some(Template::render("person", &page))
+}
+
+#[get("/genres/<id>")]
+pub async fn show_genre(
+    id: i32,
+    flash: Option<FlashMessage<'_>>,
+    conn: DBPool,
+    ensemble: Ensemble,
+    locale: Locale,
+) -> Option<Template> {
+    let ensemble_id = ensemble.id.unwrap_or_default();
+    let genre = db::get_genre(&conn, ensemble_id, id).await.ok()?;
+    let partitions = db::get_partitions_by_genre_id(&conn, ensemble_id, id)
+        .await
+        .unwrap_or_else(|e| {
+            error_!("DB get_partitions_by_genre_id({}) error: {}", id, e);
+            vec![]
+        });
+
+    let page = OwnerPage {
+        flash: Notification::from_flash(flash),
+        title: genre.name.clone(),
+        lang: locale.lang().to_string(),
+        item: genre,
+        partitions,
+    };
+    Some(Template::render("genre", &page))
 }
 
 // ********************************************************************************************
@@ -141,51 +489,94 @@ pub async fn all_partitions(conn: DBPool) -> Template {
 //
 
 #[delete("/persons/<id>")]
-pub async fn delete_person(id: i32, conn: DBPool) -> Result<Flash<Redirect>, Template> {
+pub async fn delete_person(
+    _csrf: CsrfChecked,
+    id: i32,
+    _admin: AdminUser,
+    conn: DBPool,
+    queue: &State<JobQueue>,
+    csrf: CsrfToken,
+    locale: Locale,
+) -> Result<Flash<Redirect>, Template> {
     match db::delete_one_person(&conn, id).await {
-        Ok(_) => Ok(Flash::success(
-            Redirect::to("/persons"),
-            "Person successfully deleted",
-        )),
+        Ok(count) => {
+            if count > 0 {
+                webhooks::notify(&conn, queue, None, webhooks::PERSON_DELETED, &Deleted { id }).await;
+            }
+            Ok(Notification::success(locale.tr("msg-person-deleted")).redirect("/persons"))
+        }
         Err(e) => {
             error_!("DB deletion({}) error: {}", id, e);
             Err(Template::render(
                 "persons",
-                Context::err(&conn, "Failed to delete person.").await,
+                Context::err(&conn, &csrf, &locale, locale.tr("msg-person-delete-failed"))
+                    .await,
             ))
         }
     }
 }
 
 #[delete("/genres/<id>")]
-pub async fn delete_genre(id: i32, conn: DBPool) -> Result<Flash<Redirect>, Template> {
-   match db::delete_one_genre(&conn, id).await{
-       Ok(_) => Ok(Flash::success(
-           Redirect::to("/genres"),
-           "Genre successfully deleted",
-       )),
+pub async fn delete_genre(
+    _csrf: CsrfChecked,
+    id: i32,
+    member: Member,
+    conn: DBPool,
+    ensemble: Ensemble,
+    queue: &State<JobQueue>,
+    csrf: CsrfToken,
+    locale: Locale,
+) -> Result<Flash<Redirect>, Template> {
+   let admin = member.0.is_admin;
+   // un genre commun concerne les abonnés de tous les ensembles
+   let scope = match db::get_genre(&conn, ensemble.id.unwrap_or_default(), id).await {
+       Ok(genre) if genre.ensemble_id.is_none() && !admin => return Ok(common_genre(&locale, id)),
+       Ok(genre) => genre.ensemble_id,
+       Err(_) => ensemble.id,
+   };
+   match db::delete_one_genre(&conn, ensemble.id.unwrap_or_default(), admin, id).await{
+       Ok(count) => {
+           if count > 0 {
+               webhooks::notify(&conn, queue, scope, webhooks::GENRE_DELETED, &Deleted { id }).await;
+           }
+           Ok(Notification::success(locale.tr("msg-genre-deleted")).redirect("/genres"))
+       }
        Err(e) => {
            error_!("DB deletion({}) error: {}", id, e);
            Err(Template::render(
                "genres",
-               Context::err(&conn, "Failed to delete genre.").await,
+               Context::err(&conn, &csrf, &locale, locale.tr("msg-genre-delete-failed"))
+                   .await,
            ))
        }
    }
 }
 
 #[delete("/partitions/<id>")]
-pub async fn delete_partition(id: i32, conn: DBPool) -> Result<Flash<Redirect>, Template> {
-    match db::delete_one_partition(&conn, id).await {
-        Ok(_) => Ok(Flash::success(
-            Redirect::to("/partitions"),
-            "Partition successfully deleted",
-        )),
+pub async fn delete_partition(
+    _csrf: CsrfChecked,
+    id: i32,
+    _member: Member,
+    conn: DBPool,
+    ensemble: Ensemble,
+    queue: &State<JobQueue>,
+    csrf: CsrfToken,
+    locale: Locale,
+) -> Result<Flash<Redirect>, Template> {
+    match db::delete_one_partition(&conn, ensemble.id.unwrap_or_default(), id).await {
+        Ok(count) => {
+            if count > 0 {
+                let event = webhooks::PARTITION_DELETED;
+                webhooks::notify(&conn, queue, ensemble.id, event, &Deleted { id }).await;
+            }
+            Ok(Notification::success(locale.tr("msg-partition-deleted")).redirect("/partitions"))
+        }
         Err(e) => {
             error_!("DB deletion({}) error: {}", id, e);
             Err(Template::render(
                 "partitions",
-                Context::err(&conn, "Failed to delete partition.").await,
+                Context::err(&conn, &csrf, &locale, locale.tr("msg-partition-delete-failed"))
+                    .await,
             ))
         }
     }
@@ -196,7 +587,13 @@ pub async fn delete_partition(id: i32, conn: DBPool) -> Result<Flash<Redirect>,
 //
 
 #[post("/persons/add", data = "<person_form>")]
-pub async fn new_person(person_form: Form<Person>, conn: DBPool) -> Flash<Redirect> {
+pub async fn new_person(
+    person_form: CsrfForm<Person>,
+    _member: Member,
+    conn: DBPool,
+    queue: &State<JobQueue>,
+    locale: Locale,
+) -> Flash<Redirect> {
     let person = person_form.into_inner();
     /*
     let comp = db::get_person_by_name(&conn, person.full_name.clone()).await.unwrap();
@@ -221,15 +618,49 @@ pub async fn new_person(person_form: Form<Person>, conn: DBPool) -> Flash<Redire
     }
 
      */
-    db::create_person(&conn, person).await.unwrap();
-    Flash::success(Redirect::to("/persons"), "Person successfully added.")
+    let full_name = person.full_name.clone();
+    match db::create_person(&conn, person).await {
+        Ok(person) => {
+            webhooks::notify(&conn, queue, None, webhooks::PERSON_CREATED, &person).await;
+            Notification::success(locale.tr("msg-person-added")).redirect("/persons")
+        }
+        Err(e) if repo::is_unique_violation(&e) => existing_person(&conn, &locale, full_name).await,
+        Err(e) => {
+            error_!("DB insertion error: {}", e);
+            Notification::error(locale.tr("msg-person-add-failed")).redirect("/persons")
+        }
+    }
 }
 
 #[post("/genres/add", data = "<genre_form>")]
-pub async fn new_genre(genre_form: Form<Genre>, conn: DBPool) -> Flash<Redirect> {
-    let genre = genre_form.into_inner();
-    db::create_genre(&conn, genre).await.unwrap();
-    Flash::success(Redirect::to("/genres"), "Genre successfully added.")
+pub async fn new_genre(
+    genre_form: CsrfForm<Genre>,
+    _member: Member,
+    conn: DBPool,
+    ensemble: Ensemble,
+    queue: &State<JobQueue>,
+    locale: Locale,
+) -> Flash<Redirect> {
+    let mut genre = genre_form.into_inner();
+    // case cochée : le genre est réservé à la bibliothèque affichée
+    if genre.ensemble_id != ensemble.id {
+        genre.ensemble_id = None;
+    }
+    let (genre_name, scope) = (genre.name.clone(), genre.ensemble_id);
+    match db::create_genre(&conn, genre).await {
+        Ok(genre) => {
+            let scope = genre.ensemble_id;
+            webhooks::notify(&conn, queue, scope, webhooks::GENRE_CREATED, &genre).await;
+            Notification::success(locale.tr("msg-genre-added")).redirect("/genres")
+        }
+        Err(e) if repo::is_unique_violation(&e) => {
+            existing_genre(&conn, scope, &locale, genre_name).await
+        }
+        Err(e) => {
+            error_!("DB insertion error: {}", e);
+            Notification::error(locale.tr("msg-genre-add-failed")).redirect("/genres")
+        }
+    }
 
     /*
     let comp = db::get_person_by_name(&conn, person.last_name.clone()).await.unwrap();
@@ -256,12 +687,38 @@ pub async fn new_genre(genre_form: Form<Genre>, conn: DBPool) -> Flash<Redirect>
 }
 
 #[post("/partitions/add", data = "<partition_form>")]
-pub async fn new_partition(partition_form: Form<ShowPartition>, conn: DBPool) -> Flash<Redirect> {
+pub async fn new_partition(
+    partition_form: CsrfForm<NewPartition>,
+    _member: Member,
+    conn: DBPool,
+    ensemble: Ensemble,
+    queue: &State<JobQueue>,
+    locale: Locale,
+) -> Flash<Redirect> {
     let data = partition_form.into_inner();
-    println!("ShowPartition from partitions/add : {:?}", data);
-
-    db::create_partition(&conn, data).await.unwrap();
-    Flash::success(Redirect::to("/partitions"), "Partition successfully added.")
+    let (title, musician_name, genre_name) =
+        (data.title.clone(), data.full_name.clone(), data.name.clone());
+
+    match db::create_partition(&conn, ensemble.id.unwrap_or_default(), data).await {
+        Ok(partition) => {
+            let event = webhooks::PARTITION_CREATED;
+            webhooks::notify(&conn, queue, ensemble.id, event, &partition).await;
+            Notification::success(locale.tr("msg-partition-added"))
+                .redirect(format!("/partitions/{}", partition.id.unwrap_or_default()))
+        }
+        Err(diesel::result::Error::NotFound) => {
+            unknown_reference(&conn, &ensemble, &locale, musician_name, genre_name)
+                .await
+                .redirect("/partitions")
+        }
+        Err(e) if repo::is_unique_violation(&e) => {
+            existing_partition(&conn, &ensemble, &locale, title, musician_name).await
+        }
+        Err(e) => {
+            error_!("DB insertion error: {}", e);
+            Notification::error(locale.tr("msg-partition-add-failed")).redirect("/partitions")
+        }
+    }
 }
 
 // ********************************************************************************************
@@ -269,201 +726,225 @@ pub async fn new_partition(partition_form: Form<ShowPartition>, conn: DBPool) ->
 //
 
 #[put("/persons/<id>", data = "<person_form>")]
-pub async fn update_person(id: i32, person_form: Form<Person>, conn: DBPool) -> Flash<Redirect> {
+pub async fn update_person(
+    id: i32,
+    person_form: CsrfForm<Person>,
+    _admin: AdminUser,
+    conn: DBPool,
+    queue: &State<JobQueue>,
+    locale: Locale,
+) -> Flash<Redirect> {
     let person = person_form.into_inner();
-    println!("{:?}", person);
+    let full_name = person.full_name.clone();
 
-    db::update_person(id, person, &conn).await.unwrap();
-    Flash::success(Redirect::to("/persons"), "Person successfully modified.")
+    match db::update_person(id, person, &conn).await {
+        Ok(person) => {
+            webhooks::notify(&conn, queue, None, webhooks::PERSON_UPDATED, &person).await;
+            Notification::success(locale.tr("msg-person-modified")).redirect("/persons")
+        }
+        Err(e) if repo::is_unique_violation(&e) => existing_person(&conn, &locale, full_name).await,
+        Err(e) => {
+            error_!("DB update({}) error: {}", id, e);
+            Notification::error(locale.tr("msg-person-modify-failed")).redirect("/persons")
+        }
+    }
 }
 
 #[put("/genres/<id>", data = "<genre_form>")]
-pub async fn update_genre(id: i32, genre_form: Form<Genre>, conn: DBPool) -> Flash<Redirect> {
+pub async fn update_genre(
+    id: i32,
+    genre_form: CsrfForm<Genre>,
+    member: Member,
+    conn: DBPool,
+    ensemble: Ensemble,
+    queue: &State<JobQueue>,
+    locale: Locale,
+) -> Flash<Redirect> {
     let genre = genre_form.into_inner();
-    println!("{:?}", genre);
+    let genre_name = genre.name.clone();
+    let (ensemble_id, admin) = (ensemble.id.unwrap_or_default(), member.0.is_admin);
+    // le nom doit rester libre parmi les genres de même portée
+    let scope = match db::get_genre(&conn, ensemble_id, id).await {
+        Ok(genre) if genre.ensemble_id.is_none() && !admin => return common_genre(&locale, id),
+        Ok(genre) => genre.ensemble_id,
+        Err(_) => ensemble.id,
+    };
 
-    db::update_genre(id, genre, &conn).await.unwrap();
-    Flash::success(Redirect::to("/genres"), "Genre successfully modified.")
+    match db::update_genre(ensemble_id, admin, id, genre, &conn).await {
+        Ok(genre) => {
+            let scope = genre.ensemble_id;
+            webhooks::notify(&conn, queue, scope, webhooks::GENRE_UPDATED, &genre).await;
+            Notification::success(locale.tr("msg-genre-modified")).redirect("/genres")
+        }
+        Err(e) if repo::is_unique_violation(&e) => {
+            existing_genre(&conn, scope, &locale, genre_name).await
+        }
+        Err(e) => {
+            error_!("DB update({}) error: {}", id, e);
+            Notification::error(locale.tr("msg-genre-modify-failed")).redirect("/genres")
+        }
+    }
 }
 
 #[put("/partitions/<id>", data = "<show_partition_form>")]
 pub async fn update_partition(
     id: i32,
-    show_partition_form: Form<ShowPartition>,
+    show_partition_form: CsrfForm<ShowPartition>,
+    _member: Member,
     conn: DBPool,
+    ensemble: Ensemble,
+    queue: &State<JobQueue>,
+    locale: Locale,
 ) -> Flash<Redirect> {
     let show_partition = show_partition_form.into_inner();
+    let ensemble_id = ensemble.id.unwrap_or_default();
 
     let partition_id = id;
-    let musician = db::get_person_by_name(&conn, show_partition.full_name).await.unwrap();
-    let musician_id = musician.id.unwrap();
-    let genre = db::get_genre_by_name(&conn, show_partition.name).await.unwrap();
-    let genre_id = genre.id.unwrap();
+    let musician = db::get_person_by_name(&conn, show_partition.full_name.clone()).await;
+    let genre = db::get_genre_by_name(&conn, ensemble_id, show_partition.name.clone()).await;
+    let (musician_id, genre_id) = match (musician, genre) {
+        (Ok(musician), Ok(genre)) => (musician.id.unwrap(), genre.id.unwrap()),
+        _ => {
+            return unknown_reference(
+                &conn,
+                &ensemble,
+                &locale,
+                show_partition.full_name,
+                show_partition.name,
+            )
+            .await
+            .redirect("/partitions")
+        }
+    };
 
     let partition = Partition {
         id: Some(partition_id),
         person_id: musician_id,
-        title: show_partition.title,
+        title: show_partition.title.clone(),
         genre_id,
+        ensemble_id,
+        shared: false,
     };
-    println!("{:?}", partition);
-    db::update_partition(id, partition, &conn).await.unwrap();
-    Flash::success(Redirect::to("/partitions"), "Partition successfully modified.")
-}
-
-//*************************************************************************************************
-// Handles RESEARCH operations
-//
-
-#[post("/persons/find", data = "<name>")]
-pub async fn get_person_by_name(name: Form<String>, conn: DBPool) -> Template {
-
-    let n = name.into_inner();
-    match db::get_person_by_name(&conn, n).await {
-        Ok(p) => {
-            println!("{:?}", p);
-            let mut vec_pers: Vec<Person> = Vec::new();
-            vec_pers.push(p);
-            let flash = Some(("pas d'erreur".into(), "Personne trouvée".into()));
-
-            let context = Context {
-                flash,
-                persons: vec_pers,
-                genres: vec![],
-                title: "Personne trouvée".to_string(),
-                partitions: vec![],
+    match db::update_partition(ensemble_id, id, partition, &conn).await {
+        Ok(_) => {
+            let partition = ShowPartition {
+                id: Some(id),
+                ..show_partition
             };
-            Template::render("persons", &context)
+            let event = webhooks::PARTITION_UPDATED;
+            webhooks::notify(&conn, queue, ensemble.id, event, &partition).await;
+            Notification::success(locale.tr("msg-partition-modified")).redirect("/partitions")
+        }
+        Err(e) if repo::is_unique_violation(&e) => {
+            existing_partition(
+                &conn,
+                &ensemble,
+                &locale,
+                show_partition.title,
+                show_partition.full_name,
+            )
+            .await
         }
         Err(e) => {
-            let flash = Some(("Erreur".into(), e.to_string()));
-            let context = Context {
-                flash,
-                persons: vec![],
-                genres: vec![],
-                title: "Erreur !".to_string(),
-                partitions: vec![],
-            };
-            Template::render("persons", &context)
+            error_!("DB update({}) error: {}", id, e);
+            Notification::error(locale.tr("msg-partition-modify-failed")).redirect("/partitions")
         }
     }
 }
 
-#[post("/genres/find", data = "<name>")]
-pub async fn get_genre_by_type(name: Form<String>, conn: DBPool) -> Template {
-
-    let name =name.into_inner();
-    match db::get_genre_by_name(&conn, name).await {
-        Ok(g) => {
-            println!("{:?}", g);
-            let mut vec_genres: Vec<Genre> = Vec::new();
-            vec_genres.push(g);
-            let flash = Some(("pas d'erreur".into(), "Genre trouvé".into()));
-
-            let context = Context {
-                flash,
-                persons: vec![],
-                genres: vec_genres,
-                title: "Genre trouvé".to_string(),
-                partitions: vec![],
-            };
-            Template::render("genres", &context)
-        }
-        Err(e) => {
-            let flash = Some(("Erreur".into(), e.to_string()));
-            let context = Context {
-                flash,
-                persons: vec![],
-                genres: vec![],
-                title: "Erreur !".to_string(),
-                partitions: vec![],
-            };
-            Template::render("persons", &context)
-        }
+// le nom ou le titre est déjà pris : on va sur la page de l'élément existant
+async fn existing_person(conn: &DBPool, locale: &Locale, full_name: String) -> Flash<Redirect> {
+    let notification = already_exists(locale, "msg-person-exists", &full_name);
+    match db::get_person_by_name(conn, full_name).await {
+        Ok(person) => notification.redirect(format!("/persons/{}", person.id.unwrap_or_default())),
+        Err(_) => notification.redirect("/persons"),
     }
 }
 
-#[post("/partitions/find/title", data = "<title>")]
-pub async fn get_partition_by_title(title: Form<String>, conn: DBPool) -> Template {
+// `ensemble_id` : celui du genre refusé, None pour un genre commun
+async fn existing_genre(
+    conn: &DBPool,
+    ensemble_id: Option<i32>,
+    locale: &Locale,
+    genre_name: String,
+) -> Flash<Redirect> {
+    let notification = already_exists(locale, "msg-genre-exists", &genre_name);
+    match db::get_genre_by_key(conn, ensemble_id, genre_name).await {
+        Ok(genre) => notification.redirect(format!("/genres/{}", genre.id.unwrap_or_default())),
+        Err(_) => notification.redirect("/genres"),
+    }
+}
 
-    let title = title.into_inner();
-    let persons = get_list_persons(&conn).await.unwrap();
-    let genres = get_list_genres(&conn).await.unwrap();
+// seuls les administrateurs changent les genres communs à tous les ensembles
+fn common_genre(locale: &Locale, id: i32) -> Flash<Redirect> {
+    Notification::warning(locale.tr("msg-genre-common")).redirect(format!("/genres/{}", id))
+}
 
-    match db::get_partition_by_title(&conn, title).await {
-        Ok(p) => {
-            let mut vec_p: Vec<ShowPartition> = Vec::new();
-            vec_p.push(p);
-            if vec_p.len() != 0 {
-                let flash = Some(("pas d'erreur".into(), "Partition trouvée".into()));
-                let context = Context {
-                    flash,
-                    persons,
-                    genres,
-                    title: "Partition trouvée".to_string(),
-                    partitions: vec_p,
-                };
-                Template::render("partitions", &context)
-            } else {
-                let flash = Some(("pas d'erreur".into(), "Aucune partition trouvée".into()));
-                let context = Context {
-                    flash,
-                    persons: vec![],
-                    genres: vec![],
-                    title: "Aucune Partition trouvée".to_string(),
-                    partitions: vec![],
-                };
-                Template::render("partitions", &context)
-            }
-        }
-        Err(e) => {
-            let flash = Some(("Erreur".into(), e.to_string()));
-            let context = Context {
-                flash,
-                persons: vec![],
-                genres: vec![],
-                title: "Erreur !".to_string(),
-                partitions: vec![],
-            };
-            Template::render("persons", &context)
+pub async fn existing_partition(
+    conn: &DBPool,
+    ensemble: &Ensemble,
+    locale: &Locale,
+    title: String,
+    musician_name: String,
+) -> Flash<Redirect> {
+    let notification = already_exists(locale, "msg-partition-exists", &title);
+    match db::find_partition(conn, ensemble.id.unwrap_or_default(), title, musician_name).await {
+        Ok(partition) => {
+            notification.redirect(format!("/partitions/{}", partition.id.unwrap_or_default()))
         }
+        Err(_) => notification.redirect("/partitions"),
     }
 }
 
-#[post("/partitions/find/author", data = "<author>")]
-pub async fn get_partition_by_author(author: Form<String>, conn: DBPool) -> Template {
-
-    let persons = get_list_persons(&conn).await.unwrap();
-    let genres = get_list_genres(&conn).await.unwrap();
-    let author = author.into_inner();
-    let partitions = db::get_partition_by_author(&conn, author).await.unwrap();
-    let flash = Some(("pas d'erreur".into(), "partition trouvée".into()));
-    let context = Context {
-        flash,
-        persons,
-        genres,
-        title: "Aucune Partition trouvée".to_string(),
-        partitions,
-    };
-    Template::render("partitions", &context)
+fn already_exists(locale: &Locale, key: &str, name: &str) -> Notification {
+    let mut args = FluentArgs::new();
+    args.set("name", name.trim().to_string());
+    Notification::warning(locale.tr_args(key, &args))
 }
 
-#[post("/partitions/find/genre", data = "<genre>")]
-pub async fn get_partition_by_genre(genre: Form<String>, conn: DBPool) -> Template {
-
-    let persons = get_list_persons(&conn).await.unwrap();
-    let genres = get_list_genres(&conn).await.unwrap();
-    let genre = genre.into_inner();
-    let partitions = db::get_partition_by_genre(&conn, genre).await.unwrap();
-    let flash = Some(("pas d'erreur".into(), "partition trouvée".into()));
-    let context = Context {
-        flash,
-        persons,
-        genres,
-        title: "Aucune Partition trouvée".to_string(),
-        partitions,
-    };
-    Template::render("partitions", &context)
+// le musicien ou le genre d'une partition n'existe pas :
+// on le dit, avec les noms proches s'il y en a
+pub async fn unknown_reference(
+    conn: &DBPool,
+    ensemble: &Ensemble,
+    locale: &Locale,
+    musician_name: String,
+    genre_name: String,
+) -> Notification {
+    let ensemble_id = ensemble.id.unwrap_or_default();
+    let mut messages = vec![];
+
+    if db::get_person_by_name(conn, musician_name.clone()).await.is_err() {
+        let similar = db::get_similar_persons(conn, musician_name.clone())
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|p| p.full_name)
+            .collect::<Vec<_>>();
+        messages.push(did_you_mean(locale, "msg-unknown-person", musician_name, similar));
+    }
+    if db::get_genre_by_name(conn, ensemble_id, genre_name.clone()).await.is_err() {
+        let similar = db::get_similar_genres(conn, ensemble_id, genre_name.clone())
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|g| g.name)
+            .collect::<Vec<_>>();
+        messages.push(did_you_mean(locale, "msg-unknown-genre", genre_name, similar));
+    }
+
+    Notification::warning(messages.join(" "))
+}
+
+fn did_you_mean(locale: &Locale, key: &str, name: String, similar: Vec<String>) -> String {
+    let mut args = FluentArgs::new();
+    args.set("name", name.trim().to_string());
+    if similar.is_empty() {
+        locale.tr_args(key, &args)
+    } else {
+        args.set("suggestions", similar.join(", "));
+        locale.tr_args(&format!("{}-suggest", key), &args)
+    }
 }
 
 //*************************************************************************************************
@@ -471,14 +952,17 @@ pub async fn get_partition_by_genre(genre: Form<String>, conn: DBPool) -> Templa
 //
 #[catch(404)]
 pub fn not_found(req: &Request<'_>) -> Template {
+    let locale = Locale::detect(req);
     let mut map = HashMap::new();
-    map.insert("path", req.uri().path().raw());
+    map.insert("path", req.uri().path().raw().as_str());
+    map.insert("lang", locale.lang());
     Template::render("error/404", &map)
 }
 
 #[get("/about")]
-pub fn about() -> Template {
+pub fn about(locale: Locale) -> Template {
     let mut map = HashMap::new();
-    map.insert("title", "A propos de ...");
+    map.insert("title", locale.tr("title-about"));
+    map.insert("lang", locale.lang().to_string());
     Template::render("about", &map)
 }
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+
+use fluent::bundle::FluentBundle;
+use fluent::{FluentArgs, FluentResource, FluentValue};
+use intl_memoizer::concurrent::IntlLangMemoizer;
+use once_cell::sync::Lazy;
+use unic_langid::LanguageIdentifier;
+
+use rocket::http::uri::Absolute;
+use rocket::http::{Cookie, CookieJar};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::Redirect;
+
+use rocket_dyn_templates::tera::{self, Value};
+
+// Traductions de l'interface
+//
+// les messages sont dans des fichiers Fluent (locales/<langue>/main.ftl)
+// compilés dans l'exécutable. La langue est choisie dans cet ordre :
+// cookie `lang`, puis en-tête Accept-Language, puis le français par défaut.
+// Les handlers traduisent avec `Locale::tr`, les templates avec la
+// fonction Tera `t(key="...", lang=lang)`.
+
+pub const DEFAULT_LOCALE: &str = "fr";
+pub const LOCALE_COOKIE: &str = "lang";
+
+const RESOURCES: [(&str, &str); 2] = [
+    ("fr", include_str!("../locales/fr/main.ftl")),
+    ("en", include_str!("../locales/en/main.ftl")),
+];
+
+type Bundle = FluentBundle<FluentResource, IntlLangMemoizer>;
+
+static CATALOG: Lazy<HashMap<&'static str, Bundle>> = Lazy::new(|| {
+    RESOURCES
+        .iter()
+        .map(|(lang, source)| {
+            let langid: LanguageIdentifier = lang.parse().expect("invalid locale identifier");
+            let resource = FluentResource::try_new(source.to_string())
+                .unwrap_or_else(|_| panic!("invalid Fluent resource for locale {}", lang));
+            let mut bundle = Bundle::new_concurrent(vec![langid]);
+            // pas de caractères d'isolation Unicode dans le HTML
+            bundle.set_use_isolating(false);
+            bundle
+                .add_resource(resource)
+                .unwrap_or_else(|_| panic!("duplicate Fluent messages for locale {}", lang));
+            (*lang, bundle)
+        })
+        .collect()
+});
+
+pub fn is_supported(lang: &str) -> bool {
+    RESOURCES.iter().any(|(l, _)| *l == lang)
+}
+
+pub fn supported_locales() -> Vec<&'static str> {
+    RESOURCES.iter().map(|(l, _)| *l).collect()
+}
+
+// cherche le message dans la langue demandée, puis en français ;
+// renvoie la clé elle-même si elle n'existe nulle part
+pub fn translate(lang: &str, key: &str, args: Option<&FluentArgs>) -> String {
+    for candidate in [lang, DEFAULT_LOCALE].iter() {
+        if let Some(bundle) = CATALOG.get(*candidate) {
+            if let Some(pattern) = bundle.get_message(key).and_then(|m| m.value()) {
+                let mut errors = vec![];
+                let text = bundle.format_pattern(pattern, args, &mut errors);
+                if !errors.is_empty() {
+                    warn_!("Fluent errors for `{}` ({}): {:?}", key, candidate, errors);
+                }
+                return text.into_owned();
+            }
+        }
+    }
+    warn_!("missing translation `{}` for locale {}", key, lang);
+    key.to_string()
+}
+
+#[derive(Debug, Clone)]
+pub struct Locale(String);
+
+impl Locale {
+    pub fn detect(req: &Request<'_>) -> Locale {
+        if let Some(cookie) = req.cookies().get(LOCALE_COOKIE) {
+            if is_supported(cookie.value()) {
+                return Locale(cookie.value().to_string());
+            }
+        }
+        req.headers()
+            .get_one("Accept-Language")
+            .and_then(negotiate)
+            .map(|lang| Locale(lang.to_string()))
+            .unwrap_or_else(|| Locale(DEFAULT_LOCALE.to_string()))
+    }
+
+    pub fn lang(&self) -> &str {
+        &self.0
+    }
+
+    pub fn tr(&self, key: &str) -> String {
+        translate(&self.0, key, None)
+    }
+
+    pub fn tr_args(&self, key: &str, args: &FluentArgs) -> String {
+        translate(&self.0, key, Some(args))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Locale {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Locale::detect(req))
+    }
+}
+
+// Accept-Language: en-US,en;q=0.9,fr;q=0.8
+fn negotiate(header: &str) -> Option<&'static str> {
+    let mut ranges: Vec<(String, f32)> = header
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.trim().split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let primary = tag.split('-').next()?.to_lowercase();
+            Some((primary, quality))
+        })
+        .collect();
+    ranges.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    ranges
+        .iter()
+        .filter(|(_, q)| *q > 0.0)
+        .find_map(|(tag, _)| RESOURCES.iter().map(|(l, _)| *l).find(|l| l == tag))
+}
+
+// fonction Tera : {{ t(key="nav-start", lang=lang) }}
+// les autres arguments sont passés au message Fluent
+pub fn tera_translate(args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let key = args
+        .get("key")
+        .and_then(Value::as_str)
+        .ok_or_else(|| tera::Error::msg("t: missing `key` argument"))?;
+    let lang = args
+        .get("lang")
+        .and_then(Value::as_str)
+        .unwrap_or(DEFAULT_LOCALE);
+
+    let mut fluent_args = FluentArgs::new();
+    for (name, value) in args.iter() {
+        if name == "key" || name == "lang" {
+            continue;
+        }
+        let value = match value {
+            Value::Number(n) => match n.as_f64() {
+                Some(f) => FluentValue::from(f),
+                None => FluentValue::from(n.to_string()),
+            },
+            Value::String(s) => FluentValue::from(s.clone()),
+            other => FluentValue::from(other.to_string()),
+        };
+        fluent_args.set(name.clone(), value);
+    }
+
+    Ok(Value::String(translate(lang, key, Some(&fluent_args))))
+}
+
+// Change the language
+//
+#[get("/lang/<code>")]
+pub fn set_locale(code: String, cookies: &CookieJar<'_>, referer: Referer) -> Redirect {
+    remember_locale(cookies, &code);
+    Redirect::to(referer.0)
+}
+
+// la langue choisie est gardée dans un cookie permanent ; aussi à la connexion,
+// pour la langue préférée du compte
+pub fn remember_locale(cookies: &CookieJar<'_>, code: &str) {
+    if is_supported(code) {
+        let mut cookie = Cookie::new(LOCALE_COOKIE, code.to_string());
+        cookie.set_path("/");
+        cookie.make_permanent();
+        cookies.add(cookie);
+    }
+}
+
+// le chemin de la page précédente, pour y revenir après un changement de langue ;
+// seul le chemin est gardé pour ne jamais rediriger vers un autre site. Un
+// chemin en `//` ou `/\` serait lu par le navigateur comme un autre site.
+pub struct Referer(String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Referer {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let path = req
+            .headers()
+            .get_one("Referer")
+            .and_then(|r| Absolute::parse(r).ok())
+            .map(|uri| match uri.query() {
+                Some(q) => format!("{}?{}", uri.path(), q),
+                None => uri.path().to_string(),
+            })
+            .filter(|p| p.starts_with('/') && !p.starts_with("//") && !p.starts_with("/\\"))
+            .unwrap_or_else(|| "/".to_string());
+        Outcome::Success(Referer(path))
+    }
+}
@@ -0,0 +1,74 @@
+use fluent::FluentArgs;
+
+use rocket::http::ContentType;
+use rocket::response::{Flash, Redirect};
+
+use crate::abc;
+use crate::csrf::CsrfForm;
+use crate::ensemble::Member;
+use crate::i18n::Locale;
+use crate::models::{Ensemble, IncipitForm};
+use crate::notification::Notification;
+use crate::{db, DBPool};
+
+// Incipits
+//
+// les premières mesures d'une partition, saisies en notation ABC sur sa page
+// (voir abc.rs) : elles sont dessinées en SVG sur cette page et dans la
+// liste, et servent à la recherche mélodique (voir repo::search_partitions).
+
+// le dessin de l'incipit, pour <img src="...">
+#[get("/partitions/<id>/incipit.svg")]
+pub async fn incipit_svg(
+    id: i32,
+    conn: DBPool,
+    ensemble: Ensemble,
+) -> Option<(ContentType, String)> {
+    let incipit = match db::get_partition_incipit(&conn, ensemble.id.unwrap_or_default(), id).await
+    {
+        Ok(incipit) => incipit,
+        Err(e) => {
+            error_!("DB get_partition_incipit({}) error: {}", id, e);
+            return None;
+        }
+    };
+    // un incipit enregistré a été lu sans erreur (voir update_incipit)
+    let incipit = abc::parse(&incipit).ok()?;
+    Some((ContentType::SVG, incipit.render_svg()))
+}
+
+#[put("/partitions/<id>/incipit", data = "<incipit_form>")]
+pub async fn update_incipit(
+    id: i32,
+    incipit_form: CsrfForm<IncipitForm>,
+    _member: Member,
+    conn: DBPool,
+    ensemble: Ensemble,
+    locale: Locale,
+) -> Option<Flash<Redirect>> {
+    let back = format!("/partitions/{}", id);
+    let incipit = incipit_form.into_inner().incipit.trim().to_string();
+    if !incipit.is_empty() {
+        if let Err(e) = abc::parse(&incipit) {
+            let mut args = FluentArgs::new();
+            args.set("error", e.to_string());
+            return Some(
+                Notification::warning(locale.tr_args("msg-incipit-invalid", &args)).redirect(back),
+            );
+        }
+    }
+    let key = if incipit.is_empty() {
+        "msg-incipit-removed"
+    } else {
+        "msg-incipit-saved"
+    };
+
+    match db::set_partition_incipit(&conn, ensemble.id.unwrap_or_default(), id, incipit).await {
+        Ok(0) => None,
+        Ok(_) => Some(Notification::success(locale.tr(key)).redirect(back)),
+        Err(e) => {
+            error_!("DB set_partition_incipit({}) error: {}", id, e);
+            Some(Notification::error(locale.tr("msg-incipit-failed")).redirect(back))
+        }
+    }
+}
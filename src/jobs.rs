@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use diesel::PgConnection;
+
+use fluent::FluentArgs;
+
+use rocket::fairing;
+use rocket::http::Status;
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::sync::Notify;
+use rocket::{Build, Orbit, Rocket, Shutdown};
+
+use rocket_dyn_templates::Template;
+
+use serde::de::DeserializeOwned;
+
+use crate::csrf::{CsrfChecked, CsrfToken};
+use crate::ensemble::Member;
+use crate::i18n::Locale;
+use crate::models::{Ensemble, Job, User};
+use crate::notification::Notification;
+use crate::{db, repo, DBPool};
+
+// Background jobs
+//
+// les tâches longues (vignettes, reconstruction des index ...) ne se font
+// pas pendant la requête : la route les met dans la table `jobs`
+// (JobQueue::push) et un worker, lancé au démarrage de Rocket, les fait une
+// par une. Chaque module enregistre la fonction qui fait ses tâches
+// (JobQueue::register, depuis sa fairing) sous un nom, le `kind` de la tâche.
+//
+// une tâche en erreur est reprise plus tard, avec un délai qui double à
+// chaque essai ; les tâches se suivent sur /jobs, où elles s'annulent :
+// celles d'une bibliothèque pour ses membres, celles de l'administration
+// (sans ensemble) pour un administrateur.
+// Réglages dans Rocket.toml ([default.jobs]).
+
+// les clés de recherche des noms et les index du catalogue, refaits depuis
+// la page d'administration (comme `admin reindex` en ligne de commande)
+pub const REINDEX_JOB: &str = "reindex";
+
+// ce que rend la fonction d'une tâche : l'erreur est gardée avec la tâche
+pub type JobResult = Result<(), String>;
+
+type Handler = dyn Fn(&PgConnection, &Job) -> JobResult + Send + Sync;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct JobsConfig {
+    // secondes entre deux recherches de tâches, quand personne ne réveille le worker
+    pub poll_interval: u64,
+    pub max_attempts: i32,
+    // secondes avant le deuxième essai, doublées ensuite
+    pub retry_delay: i32,
+}
+
+impl Default for JobsConfig {
+    fn default() -> JobsConfig {
+        JobsConfig {
+            poll_interval: 10,
+            max_attempts: 3,
+            retry_delay: 30,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct JobQueue {
+    config: JobsConfig,
+    handlers: Arc<RwLock<HashMap<String, Arc<Handler>>>>,
+    wake: Arc<Notify>,
+}
+
+impl JobQueue {
+    pub fn new(config: JobsConfig) -> JobQueue {
+        JobQueue {
+            config,
+            handlers: Arc::new(RwLock::new(HashMap::new())),
+            wake: Arc::new(Notify::new()),
+        }
+    }
+
+    pub fn register<F>(&self, kind: &str, handler: F)
+    where
+        F: Fn(&PgConnection, &Job) -> JobResult + Send + Sync + 'static,
+    {
+        self.handlers
+            .write()
+            .expect("job handlers")
+            .insert(kind.to_string(), Arc::new(handler));
+    }
+
+    fn handler(&self, kind: &str) -> Option<Arc<Handler>> {
+        self.handlers
+            .read()
+            .expect("job handlers")
+            .get(kind)
+            .cloned()
+    }
+
+    // ajoute une tâche et réveille le worker
+    pub async fn push<T: Serialize>(
+        &self,
+        conn: &DBPool,
+        kind: &str,
+        ensemble_id: Option<i32>,
+        payload: &T,
+    ) -> diesel::QueryResult<Job> {
+        let payload = serde_json::to_string(payload)
+            .map_err(|e| diesel::result::Error::SerializationError(Box::new(e)))?;
+        let job = db::enqueue_job(
+            conn,
+            kind.to_string(),
+            ensemble_id,
+            payload,
+            self.config.max_attempts,
+        )
+        .await?;
+        self.wake.notify_one();
+        Ok(job)
+    }
+}
+
+// les données d'une tâche, telles que JobQueue::push les a reçues
+pub fn payload_of<T: DeserializeOwned>(job: &Job) -> Result<T, String> {
+    serde_json::from_str(&job.payload).map_err(|e| format!("invalid payload: {}", e))
+}
+
+pub async fn configure_jobs(rocket: Rocket<Build>) -> fairing::Result {
+    let figment = rocket.figment();
+    let config = if figment.contains("jobs") {
+        match figment.extract_inner::<JobsConfig>("jobs") {
+            Ok(config) => config,
+            Err(e) => {
+                error_!("Invalid jobs configuration: {}", e);
+                return Err(rocket);
+            }
+        }
+    } else {
+        JobsConfig::default()
+    };
+    let queue = JobQueue::new(config);
+    queue.register(REINDEX_JOB, |c, _| {
+        let keys = repo::update_name_keys(c, false).map_err(|e| e.to_string())?;
+        repo::reindex_search(c).map_err(|e| e.to_string())?;
+        info_!(
+            "{} search key(s) updated, search indexes rebuilt",
+            keys.updated
+        );
+        for duplicate in keys.duplicates {
+            warn_!("{}: left as is, see `admin dedupe`", duplicate);
+        }
+        Ok(())
+    });
+    Ok(rocket.manage(queue))
+}
+
+// ********************************************************************************************
+// Worker
+//
+
+// lancé quand Rocket a démarré ; il garde une connexion du pool pour lui
+pub async fn start_worker(rocket: &Rocket<Orbit>) {
+    let queue = match rocket.state::<JobQueue>() {
+        Some(queue) => queue.clone(),
+        None => {
+            error_!("No job queue: the jobs will not run");
+            return;
+        }
+    };
+    let conn = match DBPool::get_one(rocket).await {
+        Some(conn) => conn,
+        None => {
+            error_!("No database connection: the jobs will not run");
+            return;
+        }
+    };
+    rocket::tokio::spawn(work(queue, conn, rocket.shutdown()));
+}
+
+async fn work(queue: JobQueue, conn: DBPool, shutdown: Shutdown) {
+    match db::requeue_running_jobs(&conn).await {
+        Ok(0) => {}
+        Ok(count) => info_!("{} interrupted job(s) will run again", count),
+        Err(e) => error_!("DB requeue_running_jobs error: {}", e),
+    }
+    let poll_interval = Duration::from_secs(queue.config.poll_interval.max(1));
+    loop {
+        let idle = match db::claim_job(&conn).await {
+            Ok(Some(job)) => {
+                run(&queue, &conn, job).await;
+                false
+            }
+            Ok(None) => true,
+            Err(e) => {
+                error_!("DB claim_job error: {}", e);
+                true
+            }
+        };
+        if idle {
+            rocket::tokio::select! {
+                _ = shutdown.clone() => break,
+                _ = queue.wake.notified() => {}
+                _ = rocket::tokio::time::sleep(poll_interval) => {}
+            }
+        }
+    }
+}
+
+async fn run(queue: &JobQueue, conn: &DBPool, job: Job) {
+    let (job_id, kind) = (job.id, job.kind.clone());
+    let handler = match queue.handler(&kind) {
+        Some(handler) => handler,
+        None => {
+            error_!("Job {}: no handler for {:?}", job_id, kind);
+            let error = format!("unknown job kind {:?}", kind);
+            if let Err(e) = db::fail_job(conn, job_id, error, 0, true).await {
+                error_!("DB fail_job({}) error: {}", job_id, e);
+            }
+            return;
+        }
+    };
+
+    // une fonction qui panique ne doit pas arrêter le worker
+    let result = conn
+        .run(move |c| {
+            catch_unwind(AssertUnwindSafe(|| handler(c, &job)))
+                .unwrap_or_else(|_| Err("the job panicked".to_string()))
+        })
+        .await;
+    let saved = match result {
+        Ok(()) => db::finish_job(conn, job_id).await,
+        Err(error) => {
+            warn_!("Job {} ({}) failed: {}", job_id, kind, error);
+            db::fail_job(conn, job_id, error, queue.config.retry_delay, false).await
+        }
+    };
+    if let Err(e) = saved {
+        error_!("DB job {} status error: {}", job_id, e);
+    }
+}
+
+// ********************************************************************************************
+// Pages
+//
+
+#[derive(Debug, Serialize)]
+struct JobsPage {
+    flash: Option<Notification>,
+    title: String,
+    lang: String,
+    csrf_token: String,
+    ensemble: Ensemble,
+    jobs: Vec<Job>,
+}
+
+#[derive(Debug, Serialize)]
+struct JobPage {
+    flash: Option<Notification>,
+    title: String,
+    lang: String,
+    csrf_token: String,
+    ensemble: Ensemble,
+    job: Job,
+}
+
+// les tâches de la bibliothèque choisie pour ses membres (garde Member),
+// celles de l'administration pour un administrateur ; les autres comptes
+// n'ont rien à voir ici
+fn job_scope(
+    user: &User,
+    member: &Option<Member>,
+    ensemble: &Ensemble,
+) -> Result<Option<i32>, Status> {
+    match member {
+        Some(_) => Ok(ensemble.id),
+        None if user.is_admin => Ok(None),
+        None => Err(Status::Forbidden),
+    }
+}
+
+#[get("/jobs")]
+pub async fn jobs_page(
+    user: User,
+    member: Option<Member>,
+    ensemble: Ensemble,
+    conn: DBPool,
+    flash: Option<FlashMessage<'_>>,
+    csrf: CsrfToken,
+    locale: Locale,
+) -> Result<Template, Status> {
+    let scope = job_scope(&user, &member, &ensemble)?;
+    let jobs = db::get_jobs(&conn, scope, user.is_admin)
+        .await
+        .unwrap_or_else(|e| {
+            error_!("DB get_jobs error: {}", e);
+            vec![]
+        });
+    let page = JobsPage {
+        flash: Notification::from_flash(flash),
+        title: locale.tr("title-jobs"),
+        lang: locale.lang().to_string(),
+        csrf_token: csrf.value().to_string(),
+        ensemble,
+        jobs,
+    };
+    Ok(Template::render("jobs", &page))
+}
+
+#[allow(clippy::too_many_arguments)]
+#[get("/jobs/<id>")]
+pub async fn job_page(
+    id: i32,
+    user: User,
+    member: Option<Member>,
+    ensemble: Ensemble,
+    conn: DBPool,
+    flash: Option<FlashMessage<'_>>,
+    csrf: CsrfToken,
+    locale: Locale,
+) -> Result<Option<Template>, Status> {
+    let scope = job_scope(&user, &member, &ensemble)?;
+    let job = match db::get_job(&conn, scope, user.is_admin, id).await {
+        Ok(job) => job,
+        Err(e) => {
+            if e != diesel::result::Error::NotFound {
+                error_!("DB get_job({}) error: {}", id, e);
+            }
+            return Ok(None);
+        }
+    };
+    let mut args = FluentArgs::new();
+    args.set("id", id);
+    let page = JobPage {
+        flash: Notification::from_flash(flash),
+        title: locale.tr_args("title-job", &args),
+        lang: locale.lang().to_string(),
+        csrf_token: csrf.value().to_string(),
+        ensemble,
+        job,
+    };
+    Ok(Some(Template::render("job", &page)))
+}
+
+#[post("/jobs/<id>/cancel")]
+pub async fn cancel_job(
+    _csrf: CsrfChecked,
+    id: i32,
+    user: User,
+    member: Option<Member>,
+    ensemble: Ensemble,
+    conn: DBPool,
+    locale: Locale,
+) -> Result<Flash<Redirect>, Status> {
+    let scope = job_scope(&user, &member, &ensemble)?;
+    let back = format!("/jobs/{}", id);
+    let mut args = FluentArgs::new();
+    args.set("id", id);
+    Ok(
+        match db::cancel_job(&conn, scope, user.is_admin, id).await {
+            Ok(_) => {
+                Notification::success(locale.tr_args("msg-job-cancelled", &args)).redirect(back)
+            }
+            Err(diesel::result::Error::NotFound) => {
+                Notification::warning(locale.tr_args("msg-job-not-cancelled", &args)).redirect(back)
+            }
+            Err(e) => {
+                error_!("DB cancel_job({}) error: {}", id, e);
+                Notification::error(locale.tr("msg-db-access-failed")).redirect(back)
+            }
+        },
+    )
+}
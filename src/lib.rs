@@ -0,0 +1,221 @@
+#[macro_use]
+extern crate rocket;
+#[macro_use]
+extern crate diesel;
+#[macro_use]
+extern crate diesel_migrations;
+extern crate rocket_sync_db_pools;
+
+use rocket_dyn_templates::Template;
+use rocket_sync_db_pools::database;
+
+use rocket::fairing::AdHoc;
+use rocket::fs::{relative, FileServer};
+use rocket::{Build, Rocket};
+
+// l'application est une bibliothèque : le serveur (main.rs) et l'outil
+// d'administration en ligne de commande (bin/admin.rs) la partagent
+
+pub mod abc;
+mod account;
+mod admin;
+pub mod api;
+pub mod auth;
+pub mod backup;
+mod bulk;
+mod csrf;
+pub mod db;
+mod ensemble;
+mod handlers;
+mod i18n;
+mod incipits;
+mod jobs;
+mod loans;
+pub mod mailer;
+pub mod models;
+pub mod musicxml;
+mod notification;
+pub mod repo;
+pub mod schema;
+mod scores;
+mod signup;
+mod stats;
+mod suggest;
+mod thumbnails;
+pub mod webhooks;
+
+use crate::account::{
+    account_page, change_password, create_api_token, forgot_password, forgot_password_page,
+    reset_password, reset_password_page, revoke_api_token, update_profile,
+};
+use crate::admin::{
+    add_member, admin_page, approve_user, download_backup, new_ensemble, reindex, reject_user,
+    remove_member, restore_backup,
+};
+use crate::api::{
+    api_forbidden, api_genres, api_new_genre, api_new_partition, api_new_person, api_partitions,
+    api_persons, api_unauthorized,
+};
+use crate::auth::{create_configured_admin, login, login_page, logout, unauthorized};
+use crate::backup::FilesConfig;
+use crate::bulk::bulk_partitions;
+use crate::csrf::{csrf_failure, tera_csrf_field, CsrfFairing};
+use crate::ensemble::{choose_ensemble, ensembles_page};
+use crate::handlers::*;
+use crate::i18n::{set_locale, tera_translate};
+use crate::incipits::{incipit_svg, update_incipit};
+use crate::jobs::{cancel_job, configure_jobs, job_page, jobs_page, start_worker};
+use crate::loans::{decide_loan, loans_page, request_loan, shared_catalogue};
+use crate::mailer::configure_mailer;
+use crate::scores::{add_file, download_file, import_score};
+use crate::signup::{sign_up, signup_page, verify_email, SignupConfig};
+use crate::suggest::{suggest_genres, suggest_persons, suggest_titles};
+use crate::thumbnails::{configure_thumbnails, file_thumbnail, partition_thumbnail};
+use crate::webhooks::{
+    configure_webhooks, create_webhook, delete_webhook, test_webhook, update_webhook, webhook_page,
+    webhooks_page,
+};
+
+#[database("persons")]
+pub struct DBPool(diesel::PgConnection);
+
+// les migrations sont appliquées au démarrage
+async fn run_migrations(rocket: Rocket<Build>) -> Result<Rocket<Build>, Rocket<Build>> {
+    let conn = match DBPool::get_one(&rocket).await {
+        Some(conn) => conn,
+        None => {
+            error_!("No database connection available for migrations");
+            return Err(rocket);
+        }
+    };
+    if let Err(e) = conn.run(|c| repo::run_migrations(c)).await {
+        error_!("Failed to run database migrations: {}", e);
+        return Err(rocket);
+    }
+    // les clés de recherche des noms (voir models::name_key) ; un doublon
+    // n'est jamais fusionné au démarrage, seulement signalé
+    match db::fill_missing_name_keys(&conn).await {
+        Ok(keys) => {
+            for duplicate in keys.duplicates {
+                warn_!("{}: left as is, see `admin dedupe`", duplicate);
+            }
+            Ok(rocket)
+        }
+        Err(e) => {
+            error_!("Failed to fill the name keys: {}", e);
+            Err(rocket)
+        }
+    }
+}
+
+pub fn rocket() -> Rocket<Build> {
+    rocket::build()
+        .mount("/", FileServer::from(relative!("/static")))
+        .mount(
+            "/",
+            routes![
+                start,
+                all_persons,
+                new_person,
+                delete_person,
+                update_person,
+                all_genres,
+                new_genre,
+                delete_genre,
+                update_genre,
+                all_partitions,
+                show_partition,
+                show_person,
+                show_genre,
+                new_partition,
+                update_partition,
+                delete_partition,
+                bulk_partitions,
+                import_score,
+                download_file,
+                add_file,
+                file_thumbnail,
+                partition_thumbnail,
+                incipit_svg,
+                update_incipit,
+                ensembles_page,
+                choose_ensemble,
+                loans_page,
+                shared_catalogue,
+                request_loan,
+                decide_loan,
+                jobs_page,
+                job_page,
+                cancel_job,
+                webhooks_page,
+                webhook_page,
+                create_webhook,
+                update_webhook,
+                delete_webhook,
+                test_webhook,
+                about,
+                csrf_failure,
+                set_locale,
+                suggest_persons,
+                suggest_genres,
+                suggest_titles,
+                login_page,
+                login,
+                logout,
+                signup_page,
+                sign_up,
+                verify_email,
+                account_page,
+                update_profile,
+                change_password,
+                create_api_token,
+                revoke_api_token,
+                forgot_password_page,
+                forgot_password,
+                reset_password_page,
+                reset_password,
+                admin_page,
+                approve_user,
+                reject_user,
+                new_ensemble,
+                add_member,
+                remove_member,
+                download_backup,
+                restore_backup,
+                reindex
+            ],
+        )
+        .mount(
+            "/",
+            routes![
+                api_persons,
+                api_genres,
+                api_partitions,
+                api_new_person,
+                api_new_genre,
+                api_new_partition
+            ],
+        )
+        .attach(DBPool::fairing())
+        .attach(AdHoc::try_on_ignite("Database migrations", run_migrations))
+        .attach(AdHoc::try_on_ignite("Administrator", create_configured_admin))
+        .attach(AdHoc::config::<FilesConfig>())
+        .attach(AdHoc::config::<SignupConfig>())
+        .attach(AdHoc::try_on_ignite("Mailer", configure_mailer))
+        .attach(AdHoc::try_on_ignite("Jobs", configure_jobs))
+        .attach(AdHoc::try_on_ignite("Thumbnails", configure_thumbnails))
+        .attach(AdHoc::try_on_ignite("Webhooks", configure_webhooks))
+        .attach(AdHoc::on_liftoff("Job worker", |rocket| {
+            Box::pin(start_worker(rocket))
+        }))
+        .attach(CsrfFairing)
+        .attach(Template::custom(|engines| {
+            engines
+                .tera
+                .register_function("csrf_field", tera_csrf_field);
+            engines.tera.register_function("t", tera_translate);
+        }))
+        .register("/", catchers![not_found, unauthorized])
+        // l'API répond en JSON, sans renvoyer vers la page de connexion
+        .register("/api", catchers![api_unauthorized, api_forbidden])
+}
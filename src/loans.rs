@@ -0,0 +1,201 @@
+use fluent::FluentArgs;
+
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket::serde::Serialize;
+
+use rocket_dyn_templates::Template;
+
+use crate::csrf::{CsrfForm, CsrfToken};
+use crate::ensemble::Member;
+use crate::i18n::Locale;
+use crate::models::{
+    Ensemble, Loan, LoanDecision, LoanDecisionForm, LoanForm, SharedLibrary, SharedPartition,
+};
+use crate::notification::Notification;
+use crate::{db, repo, DBPool};
+
+// Sharing and loans
+//
+// une bibliothèque marque des partitions comme partagées (actions groupées
+// de la liste, voir bulk.rs) : les autres ensembles voient alors ce catalogue
+// partagé et peuvent demander à emprunter une partition. La bibliothèque qui
+// la possède accepte ou refuse, puis note le retour ; chaque ensemble suit
+// sur /loans les demandes reçues et envoyées.
+//
+// tout se fait au nom de la bibliothèque choisie (voir ensemble.rs), par un
+// de ses membres (garde Member) : on ne demande ni ne prête pour un ensemble
+// dont on n'est pas membre ; parcourir un catalogue partagé ne demande pas de
+// compte. Hors de ce catalogue, une partition non partagée ne se lit que dans
+// sa bibliothèque (repo::get_available_ensembles) ; seule la bibliothèque par
+// défaut est publique.
+
+#[derive(Debug, Serialize)]
+struct LoansPage {
+    flash: Option<Notification>,
+    title: String,
+    lang: String,
+    csrf_token: String,
+    ensemble: Ensemble,
+    libraries: Vec<SharedLibrary>,
+    received: Vec<Loan>,
+    sent: Vec<Loan>,
+}
+
+#[get("/loans")]
+pub async fn loans_page(
+    _member: Member,
+    ensemble: Ensemble,
+    conn: DBPool,
+    flash: Option<FlashMessage<'_>>,
+    csrf: CsrfToken,
+    locale: Locale,
+) -> Template {
+    let ensemble_id = ensemble.id.unwrap_or_default();
+    let libraries = db::get_shared_libraries(&conn, ensemble_id)
+        .await
+        .unwrap_or_else(|e| {
+            error_!("DB get_shared_libraries error: {}", e);
+            vec![]
+        });
+    let received = db::get_loans_received(&conn, ensemble_id)
+        .await
+        .unwrap_or_else(|e| {
+            error_!("DB get_loans_received error: {}", e);
+            vec![]
+        });
+    let sent = db::get_loans_sent(&conn, ensemble_id)
+        .await
+        .unwrap_or_else(|e| {
+            error_!("DB get_loans_sent error: {}", e);
+            vec![]
+        });
+
+    let page = LoansPage {
+        flash: Notification::from_flash(flash),
+        title: locale.tr("title-loans"),
+        lang: locale.lang().to_string(),
+        csrf_token: csrf.value().to_string(),
+        ensemble,
+        libraries,
+        received,
+        sent,
+    };
+    Template::render("loans", &page)
+}
+
+#[derive(Debug, Serialize)]
+struct SharedPage {
+    flash: Option<Notification>,
+    title: String,
+    lang: String,
+    csrf_token: String,
+    ensemble: Ensemble,
+    owner: Ensemble,
+    partitions: Vec<SharedPartition>,
+}
+
+// le catalogue partagé d'un autre ensemble
+#[get("/ensembles/<id>/shared")]
+pub async fn shared_catalogue(
+    id: i32,
+    ensemble: Ensemble,
+    conn: DBPool,
+    flash: Option<FlashMessage<'_>>,
+    csrf: CsrfToken,
+    locale: Locale,
+) -> Option<Template> {
+    let owner = db::get_ensemble(&conn, id).await.ok()?;
+    let partitions = db::get_shared_partitions(&conn, id, ensemble.id.unwrap_or_default())
+        .await
+        .unwrap_or_else(|e| {
+            error_!("DB get_shared_partitions({}) error: {}", id, e);
+            vec![]
+        });
+
+    let mut args = FluentArgs::new();
+    args.set("name", owner.name.clone());
+    let page = SharedPage {
+        flash: Notification::from_flash(flash),
+        title: locale.tr_args("title-shared", &args),
+        lang: locale.lang().to_string(),
+        csrf_token: csrf.value().to_string(),
+        ensemble,
+        owner,
+        partitions,
+    };
+    Some(Template::render("shared", &page))
+}
+
+#[post("/loans", data = "<loan_form>")]
+pub async fn request_loan(
+    loan_form: CsrfForm<LoanForm>,
+    member: Member,
+    ensemble: Ensemble,
+    conn: DBPool,
+    locale: Locale,
+) -> Flash<Redirect> {
+    let loan_form = loan_form.into_inner();
+    let message = loan_form.message.trim().to_string();
+
+    match db::request_loan(
+        &conn,
+        ensemble.id.unwrap_or_default(),
+        member.0.id.unwrap_or_default(),
+        loan_form.partition_id,
+        message,
+    )
+    .await
+    {
+        Ok(loan) => {
+            let mut args = FluentArgs::new();
+            args.set("title", loan.title);
+            args.set("name", loan.owner);
+            Notification::success(locale.tr_args("msg-loan-requested", &args)).redirect("/loans")
+        }
+        Err(diesel::result::Error::NotFound) => {
+            Notification::warning(locale.tr("msg-loan-not-shared")).redirect("/loans")
+        }
+        Err(e) if repo::is_unique_violation(&e) => {
+            Notification::warning(locale.tr("msg-loan-already")).redirect("/loans")
+        }
+        Err(e) => {
+            error_!("DB request_loan({}) error: {}", loan_form.partition_id, e);
+            Notification::error(locale.tr("msg-loan-failed")).redirect("/loans")
+        }
+    }
+}
+
+// accepter, refuser ou noter le retour d'une demande reçue
+#[post("/loans/<id>", data = "<decision_form>")]
+pub async fn decide_loan(
+    id: i32,
+    decision_form: CsrfForm<LoanDecisionForm>,
+    _member: Member,
+    ensemble: Ensemble,
+    conn: DBPool,
+    locale: Locale,
+) -> Flash<Redirect> {
+    let decision = decision_form.decision;
+
+    match db::decide_loan(&conn, ensemble.id.unwrap_or_default(), id, decision).await {
+        Ok(loan) => {
+            let mut args = FluentArgs::new();
+            args.set("title", loan.title);
+            args.set("name", loan.ensemble);
+            let key = match decision {
+                LoanDecision::Accept => "msg-loan-accepted",
+                LoanDecision::Decline => "msg-loan-declined",
+                LoanDecision::Return => "msg-loan-returned",
+            };
+            Notification::success(locale.tr_args(key, &args)).redirect("/loans")
+        }
+        Err(diesel::result::Error::NotFound) => {
+            Notification::warning(locale.tr("msg-loan-unknown")).redirect("/loans")
+        }
+        Err(e) => {
+            error_!("DB decide_loan({}) error: {}", id, e);
+            Notification::error(locale.tr("msg-loan-failed")).redirect("/loans")
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rocket::fairing;
+use rocket::serde::Deserialize;
+use rocket::tokio::task::spawn_blocking;
+use rocket::{Build, Rocket};
+
+// Mail
+//
+// les messages aux utilisateurs (lien de confirmation de l'inscription ...)
+// passent par un `Mailer`, choisi dans Rocket.toml ([default.mail]) :
+//   transport = "log"   le message est écrit dans le journal (développement)
+//   transport = "file"  un fichier .eml par message dans `dir`
+//   transport = "smtp"  envoi SMTP simple, sans TLS ni authentification,
+//                       vers un relais local (postfix, MailHog ...)
+//
+// les routes reçoivent l'`Outbox` (State), qui envoie sans bloquer Rocket
+
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+pub trait Mailer: Send + Sync {
+    fn send(&self, from: &str, mail: &Mail) -> io::Result<()>;
+}
+
+// ********************************************************************************************
+// Configuration
+//
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum Transport {
+    Log,
+    File,
+    Smtp,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct MailConfig {
+    pub transport: Transport,
+    pub from: String,
+    // adresse du site dans les liens envoyés
+    pub base_url: String,
+    pub dir: PathBuf,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+}
+
+impl Default for MailConfig {
+    fn default() -> MailConfig {
+        MailConfig {
+            transport: Transport::Log,
+            from: "hello-rocket <noreply@localhost>".to_string(),
+            base_url: "http://localhost:8000".to_string(),
+            dir: PathBuf::from("mail"),
+            smtp_host: "localhost".to_string(),
+            smtp_port: 25,
+        }
+    }
+}
+
+pub struct Outbox {
+    mailer: Arc<dyn Mailer>,
+    from: String,
+    base_url: String,
+}
+
+impl Outbox {
+    pub fn new(mailer: Arc<dyn Mailer>, config: &MailConfig) -> Outbox {
+        Outbox {
+            mailer,
+            from: config.from.clone(),
+            base_url: config.base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    // l'adresse complète d'une page du site, pour un lien dans un message
+    pub fn link(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    pub async fn send(&self, mail: Mail) -> io::Result<()> {
+        let mailer = self.mailer.clone();
+        let from = self.from.clone();
+        spawn_blocking(move || mailer.send(&from, &mail))
+            .await
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+    }
+}
+
+pub async fn configure_mailer(rocket: Rocket<Build>) -> fairing::Result {
+    let figment = rocket.figment();
+    let config = if figment.contains("mail") {
+        match figment.extract_inner::<MailConfig>("mail") {
+            Ok(config) => config,
+            Err(e) => {
+                error_!("Invalid mail configuration: {}", e);
+                return Err(rocket);
+            }
+        }
+    } else {
+        MailConfig::default()
+    };
+
+    let mailer: Arc<dyn Mailer> = match config.transport {
+        Transport::Log => Arc::new(LogMailer),
+        Transport::File => Arc::new(FileMailer::new(config.dir.clone())),
+        Transport::Smtp => Arc::new(SmtpMailer::new(&config.smtp_host, config.smtp_port)),
+    };
+    let outbox = Outbox::new(mailer, &config);
+    Ok(rocket.manage(outbox))
+}
+
+// ********************************************************************************************
+// Message
+//
+
+// le message complet (en-têtes et texte) tel qu'il part en SMTP ou dans un .eml
+pub fn format_message(from: &str, mail: &Mail) -> String {
+    let mut message = format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nMIME-Version: 1.0\r\n\
+         Content-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n",
+        header_value(from),
+        header_value(&mail.to),
+        encode_header(&header_value(&mail.subject))
+    );
+    for line in mail.body.lines() {
+        message.push_str(line);
+        message.push_str("\r\n");
+    }
+    message
+}
+
+// un en-tête tient sur une ligne : pas de retour à la ligne venu des données
+fn header_value(value: &str) -> String {
+    value.replace(&['\r', '\n'][..], " ")
+}
+
+// les accents d'un en-tête sont encodés (RFC 2047)
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        value.to_string()
+    } else {
+        format!("=?utf-8?B?{}?=", base64::encode(value))
+    }
+}
+
+// l'adresse seule, sans le nom : "Nom <a@b.c>" -> "a@b.c"
+fn address(mailbox: &str) -> &str {
+    match (mailbox.find('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &mailbox[start + 1..end],
+        _ => mailbox.trim(),
+    }
+}
+
+// ********************************************************************************************
+// Log and file mailers
+//
+
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send(&self, _from: &str, mail: &Mail) -> io::Result<()> {
+        info_!("Mail to {}: {}\n{}", mail.to, mail.subject, mail.body);
+        Ok(())
+    }
+}
+
+pub struct FileMailer {
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(dir: PathBuf) -> FileMailer {
+        FileMailer { dir }
+    }
+}
+
+impl Mailer for FileMailer {
+    fn send(&self, from: &str, mail: &Mail) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        // les noms se suivent dans l'ordre d'envoi
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_micros())
+            .unwrap_or_default();
+        let path = self
+            .dir
+            .join(format!("{}-{:08x}.eml", timestamp, rand::random::<u32>()));
+        fs::write(&path, format_message(from, mail))?;
+        info_!("Mail to {} written to {}", mail.to, path.display());
+        Ok(())
+    }
+}
+
+// ********************************************************************************************
+// SMTP mailer
+//
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct SmtpMailer {
+    host: String,
+    port: u16,
+}
+
+impl SmtpMailer {
+    pub fn new(host: &str, port: u16) -> SmtpMailer {
+        SmtpMailer {
+            host: host.to_string(),
+            port,
+        }
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, from: &str, mail: &Mail) -> io::Result<()> {
+        let stream = TcpStream::connect((self.host.as_str(), self.port))?;
+        stream.set_read_timeout(Some(SMTP_TIMEOUT))?;
+        stream.set_write_timeout(Some(SMTP_TIMEOUT))?;
+        let mut session = SmtpSession {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        };
+
+        session.reply(2)?;
+        session.command("EHLO localhost", 2)?;
+        session.command(&format!("MAIL FROM:<{}>", address(from)), 2)?;
+        session.command(&format!("RCPT TO:<{}>", address(&mail.to)), 2)?;
+        session.command("DATA", 3)?;
+        // une ligne qui commence par un point est doublée (RFC 5321, 4.5.2)
+        let mut data = String::new();
+        for line in format_message(from, mail).lines() {
+            if line.starts_with('.') {
+                data.push('.');
+            }
+            data.push_str(line);
+            data.push_str("\r\n");
+        }
+        data.push_str(".\r\n");
+        session.writer.write_all(data.as_bytes())?;
+        session.reply(2)?;
+        session.command("QUIT", 2)?;
+        Ok(())
+    }
+}
+
+struct SmtpSession {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl SmtpSession {
+    fn command(&mut self, command: &str, class: u8) -> io::Result<()> {
+        self.writer.write_all(command.as_bytes())?;
+        self.writer.write_all(b"\r\n")?;
+        self.reply(class)
+    }
+
+    // lit une réponse (éventuellement sur plusieurs lignes "250-...")
+    // et vérifie sa classe : 2 pour 2xx, 3 pour 3xx
+    fn reply(&mut self, class: u8) -> io::Result<()> {
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "SMTP server closed the connection",
+                ));
+            }
+            let line = line.trim_end();
+            let bytes = line.as_bytes();
+            if bytes.len() < 3 || bytes[0] != b'0' + class {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("SMTP server replied: {}", line),
+                ));
+            }
+            if bytes.get(3) != Some(&b'-') {
+                return Ok(());
+            }
+        }
+    }
+}
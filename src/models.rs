@@ -1,10 +1,13 @@
 use super::schema::*;
 
-use diesel::sql_types::{Integer, Nullable, Text};
+use diesel::sql_types::{Array, BigInt, Bool, Integer, Nullable, Text};
 use diesel::{AsChangeset, Associations, Insertable, Queryable, QueryableByName};
 
 use rocket::serde::{Deserialize, Serialize};
 
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
 #[derive(
     Debug, Clone, Deserialize, Serialize, Queryable, Identifiable, AsChangeset, Insertable, FromForm,
 )]
@@ -14,6 +17,10 @@ pub struct Person {
     #[serde(skip_deserializing)]
     pub id: Option<i32>,
     pub full_name: String,
+    // calculée par normalized(), jamais lue dans les formulaires
+    #[serde(skip)]
+    #[field(default = String::new())]
+    pub full_name_key: String,
 }
 
 impl Person {
@@ -24,6 +31,13 @@ impl Person {
             false
         }
     }
+
+    // nom nettoyé et clé de recherche à jour, avant d'écrire en base
+    pub fn normalized(mut self) -> Person {
+        self.full_name = collapse_whitespace(&self.full_name);
+        self.full_name_key = name_key(&self.full_name);
+        self
+    }
 }
 
 #[derive(
@@ -44,6 +58,98 @@ pub struct Genre {
     #[serde(skip_deserializing)]
     pub id: Option<i32>,
     pub name: String,
+    #[serde(skip)]
+    #[field(default = String::new())]
+    pub name_key: String,
+    // l'ensemble à qui le genre est réservé, None s'il est commun à tous
+    #[serde(skip_deserializing)]
+    pub ensemble_id: Option<i32>,
+}
+
+impl Genre {
+    pub fn normalized(mut self) -> Genre {
+        self.name = collapse_whitespace(&self.name);
+        self.name_key = name_key(&self.name);
+        self
+    }
+}
+
+// deux musiciens ou deux genres dont les noms ont la même clé (voir
+// repo::update_name_keys) : `id` n'a pas reçu la sienne, `admin dedupe` le
+// fusionne dans `into_id`
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Duplicate {
+    // "person" ou "genre"
+    pub kind: &'static str,
+    pub id: i32,
+    pub name: String,
+    pub into_id: i32,
+    pub into_name: String,
+    // les partitions rattachées à `into_id`, et celles supprimées parce que
+    // `into_id` a déjà le même titre
+    pub partitions_moved: i64,
+    pub partitions_deleted: i64,
+}
+
+impl std::fmt::Display for Duplicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} \"{}\" has the name key of {} \"{}\" ({} partition(s) to move, {} to delete)",
+            self.kind,
+            self.id,
+            self.name,
+            self.into_id,
+            self.into_name,
+            self.partitions_moved,
+            self.partitions_deleted
+        )
+    }
+}
+
+// les clés recalculées, et les doublons laissés tels quels (ou fusionnés)
+#[derive(Debug, Default, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct NameKeys {
+    pub updated: usize,
+    pub duplicates: Vec<Duplicate>,
+}
+
+// une étiquette libre posée sur des partitions
+#[derive(Debug, Clone, Serialize, Queryable, Insertable)]
+#[serde(crate = "rocket::serde")]
+#[table_name = "tags"]
+pub struct Tag {
+    pub id: Option<i32>,
+    pub name: String,
+    #[serde(skip)]
+    pub name_key: String,
+}
+
+impl Tag {
+    pub fn new(name: &str) -> Tag {
+        let name = collapse_whitespace(name);
+        Tag {
+            id: None,
+            name_key: name_key(&name),
+            name,
+        }
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// clé de comparaison des noms : "  Gabriel   FAURÉ " -> "gabriel faure"
+// (espaces réduits, minuscules, accents enlevés)
+pub fn name_key(text: &str) -> String {
+    collapse_whitespace(text)
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect::<String>()
+        .to_lowercase()
 }
 
 #[derive(
@@ -59,6 +165,14 @@ pub struct Partition {
     pub person_id: i32,
     pub title: String,
     pub genre_id: i32,
+    // la bibliothèque qui possède la partition, jamais lue dans les formulaires
+    #[serde(skip_deserializing)]
+    #[field(default = 0)]
+    pub ensemble_id: i32,
+    // montrée aux autres ensembles (voir loans.rs), pas non plus
+    #[serde(skip_deserializing)]
+    #[field(default = false)]
+    pub shared: bool,
 }
 
 // une struct pour présenter les partitions avec les
@@ -87,3 +201,553 @@ pub struct ShowPartition {
     #[sql_type = "Text"]
     pub name: String,
 }
+
+// le formulaire d'ajout d'une partition (ou le JSON envoyé à l'API) :
+// le compositeur et le genre sont donnés par leur nom ; `create_missing`
+// (case à cocher) crée ceux qui n'existent pas encore au lieu de refuser
+// la partition
+//
+#[derive(Debug, Deserialize, FromForm)]
+#[serde(crate = "rocket::serde")]
+pub struct NewPartition {
+    pub title: String,
+    pub full_name: String,
+    pub name: String,
+    #[serde(default)]
+    pub create_missing: bool,
+}
+
+// une action sur les partitions cochées dans la liste ;
+// `value` est le nom du genre, du compositeur ou de l'étiquette
+//
+#[derive(Debug, Clone, Copy, PartialEq, FromFormField)]
+pub enum BulkKind {
+    #[field(value = "change-genre")]
+    ChangeGenre,
+    #[field(value = "change-composer")]
+    ChangeComposer,
+    #[field(value = "delete")]
+    Delete,
+    #[field(value = "add-tag")]
+    AddTag,
+    #[field(value = "export")]
+    Export,
+    #[field(value = "share")]
+    Share,
+    #[field(value = "unshare")]
+    Unshare,
+}
+
+#[derive(Debug, FromForm)]
+pub struct BulkAction {
+    pub action: BulkKind,
+    pub ids: Vec<i32>,
+    #[field(default = String::new())]
+    pub value: String,
+}
+
+// critères de recherche des partitions, lus dans la query string :
+// /partitions?q=...&author=...&genre=...&melody=...
+//
+#[derive(Debug, Default, Clone, Serialize, FromForm)]
+#[serde(crate = "rocket::serde")]
+pub struct PartitionSearch {
+    pub q: Option<String>,
+    pub author: Option<String>,
+    pub genre: Option<String>,
+    // quelques notes en ABC, cherchées dans les incipits (voir abc.rs)
+    pub melody: Option<String>,
+}
+
+impl PartitionSearch {
+    // enlève les espaces et oublie les champs vides
+    pub fn normalized(self) -> PartitionSearch {
+        fn clean(field: Option<String>) -> Option<String> {
+            field
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+        }
+        PartitionSearch {
+            q: clean(self.q),
+            author: clean(self.author),
+            genre: clean(self.genre),
+            melody: clean(self.melody),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.q.is_none()
+            && self.author.is_none()
+            && self.genre.is_none()
+            && self.melody.is_none()
+    }
+}
+
+// une suggestion d'autocomplétion, renvoyée en JSON
+// par les routes /suggest/...
+//
+#[derive(Debug, Serialize, QueryableByName)]
+#[serde(crate = "rocket::serde")]
+pub struct Suggestion {
+    #[sql_type = "Nullable<Integer>"]
+    pub id: Option<i32>,
+    #[sql_type = "Text"]
+    pub label: String,
+}
+
+// Ensembles
+//
+// un chœur ou un orchestre, avec sa bibliothèque de partitions
+//
+#[derive(Debug, Clone, PartialEq, Serialize, Queryable, Identifiable, Insertable)]
+#[serde(crate = "rocket::serde")]
+#[table_name = "ensembles"]
+pub struct Ensemble {
+    pub id: Option<i32>,
+    pub name: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct EnsembleForm {
+    pub name: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct MemberForm {
+    pub email: String,
+}
+
+// Files and details
+//
+// effectif, armure et mesure d'une partition, lus dans son fichier MusicXML
+// (voir musicxml.rs) ; l'incipit en notation ABC (voir abc.rs)
+//
+#[derive(Debug, Clone, Default, Serialize, Queryable, Insertable)]
+#[serde(crate = "rocket::serde")]
+#[table_name = "partition_details"]
+pub struct PartitionDetails {
+    pub partition_id: i32,
+    pub instrumentation: String,
+    pub key_signature: String,
+    pub time_signature: String,
+    pub incipit: String,
+    // calculée par abc::Incipit::melody_key, jamais montrée
+    #[serde(skip)]
+    pub incipit_key: String,
+}
+
+// un créateur de la partition autre que son compositeur, avec son rôle
+// ("lyricist", "arranger" ...)
+//
+#[derive(Debug, Clone, Serialize, Queryable)]
+#[serde(crate = "rocket::serde")]
+pub struct PartitionCreator {
+    pub role: String,
+    pub person_id: Option<i32>,
+    pub full_name: String,
+}
+
+// un fichier gardé avec la partition ; `path` est relatif au dossier
+// `upload_dir` et n'est jamais montré
+//
+#[derive(Debug, Clone, Serialize, QueryableByName)]
+#[serde(crate = "rocket::serde")]
+pub struct PartitionFile {
+    #[sql_type = "Integer"]
+    pub id: i32,
+    #[sql_type = "Integer"]
+    pub partition_id: i32,
+    #[sql_type = "Text"]
+    pub name: String,
+    #[serde(skip)]
+    #[sql_type = "Text"]
+    pub path: String,
+    #[sql_type = "Text"]
+    pub content_type: String,
+    #[sql_type = "Text"]
+    pub created_at: String,
+    // la vignette de la première page est prête (voir thumbnails.rs)
+    #[sql_type = "Bool"]
+    pub thumbnail: bool,
+}
+
+// l'incipit saisi sur la page de la partition ; vide, il est effacé
+//
+#[derive(Debug, FromForm)]
+pub struct IncipitForm {
+    #[field(default = String::new())]
+    pub incipit: String,
+}
+
+// Sharing and loans
+//
+// un autre ensemble qui montre des partitions de sa bibliothèque
+//
+#[derive(Debug, Clone, Serialize, QueryableByName)]
+#[serde(crate = "rocket::serde")]
+pub struct SharedLibrary {
+    #[sql_type = "Integer"]
+    pub id: i32,
+    #[sql_type = "Text"]
+    pub name: String,
+    #[sql_type = "BigInt"]
+    pub count: i64,
+}
+
+// une partition partagée, vue depuis un autre ensemble ; `loan` est l'état
+// de la demande en cours de cet ensemble, s'il en a une
+//
+#[derive(Debug, Clone, Serialize, QueryableByName)]
+#[serde(crate = "rocket::serde")]
+pub struct SharedPartition {
+    #[sql_type = "Integer"]
+    pub id: i32,
+    #[sql_type = "Text"]
+    pub title: String,
+    #[sql_type = "Text"]
+    pub full_name: String,
+    #[sql_type = "Text"]
+    pub name: String,
+    #[sql_type = "Nullable<Text>"]
+    pub loan: Option<String>,
+}
+
+// une demande de prêt, avec les noms de la partition et des deux ensembles :
+// `owner` possède la partition, `ensemble` l'emprunte.
+// decided_at est la date du dernier changement d'état
+//
+#[derive(Debug, Clone, Serialize, QueryableByName)]
+#[serde(crate = "rocket::serde")]
+pub struct Loan {
+    #[sql_type = "Integer"]
+    pub id: i32,
+    #[sql_type = "Integer"]
+    pub partition_id: i32,
+    #[sql_type = "Text"]
+    pub title: String,
+    #[sql_type = "Text"]
+    pub full_name: String,
+    #[sql_type = "Integer"]
+    pub owner_id: i32,
+    #[sql_type = "Text"]
+    pub owner: String,
+    #[sql_type = "Integer"]
+    pub ensemble_id: i32,
+    #[sql_type = "Text"]
+    pub ensemble: String,
+    // l'adresse du membre qui a fait la demande, None si son compte est supprimé
+    #[sql_type = "Nullable<Text>"]
+    pub requested_by: Option<String>,
+    #[sql_type = "Text"]
+    pub message: String,
+    #[sql_type = "Text"]
+    pub status: String,
+    #[sql_type = "Text"]
+    pub created_at: String,
+    #[sql_type = "Nullable<Text>"]
+    pub decided_at: Option<String>,
+}
+
+// états d'une demande de prêt (loans.status)
+pub const LOAN_PENDING: &str = "pending";
+pub const LOAN_ACCEPTED: &str = "accepted";
+pub const LOAN_DECLINED: &str = "declined";
+pub const LOAN_RETURNED: &str = "returned";
+
+#[derive(Debug, FromForm)]
+pub struct LoanForm {
+    pub partition_id: i32,
+    #[field(default = String::new())]
+    pub message: String,
+}
+
+// ce que la bibliothèque qui possède la partition décide
+#[derive(Debug, Clone, Copy, PartialEq, FromFormField)]
+pub enum LoanDecision {
+    #[field(value = "accept")]
+    Accept,
+    #[field(value = "decline")]
+    Decline,
+    #[field(value = "return")]
+    Return,
+}
+
+impl LoanDecision {
+    // l'état de départ et l'état d'arrivée
+    pub fn transition(self) -> (&'static str, &'static str) {
+        match self {
+            LoanDecision::Accept => (LOAN_PENDING, LOAN_ACCEPTED),
+            LoanDecision::Decline => (LOAN_PENDING, LOAN_DECLINED),
+            LoanDecision::Return => (LOAN_ACCEPTED, LOAN_RETURNED),
+        }
+    }
+}
+
+#[derive(Debug, FromForm)]
+pub struct LoanDecisionForm {
+    pub decision: LoanDecision,
+}
+
+// un compte utilisateur ; le mot de passe n'est jamais gardé en clair
+// ni renvoyé dans les pages
+//
+#[derive(Debug, Clone, Serialize, Queryable, Identifiable, Insertable)]
+#[serde(crate = "rocket::serde")]
+#[table_name = "users"]
+pub struct User {
+    pub id: Option<i32>,
+    pub email: String,
+    #[serde(skip)]
+    pub password_hash: String,
+    pub is_admin: bool,
+    pub status: String,
+    pub display_name: String,
+    // code de langue préféré, vide pour suivre le navigateur
+    pub locale: String,
+}
+
+// états d'un compte (users.status) : inscrit mais adresse pas encore
+// confirmée, confirmé mais en attente d'un administrateur, actif
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_APPROVAL: &str = "approval";
+pub const STATUS_ACTIVE: &str = "active";
+
+impl User {
+    pub fn is_active(&self) -> bool {
+        self.status == STATUS_ACTIVE
+    }
+}
+
+// usage des jetons envoyés par mail (user_tokens.purpose)
+pub const TOKEN_VERIFY_EMAIL: &str = "verify-email";
+pub const TOKEN_RESET_PASSWORD: &str = "reset-password";
+
+#[derive(Debug, FromForm)]
+pub struct LoginForm {
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct SignupForm {
+    pub email: String,
+    pub password: String,
+    pub confirm: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct ProfileForm {
+    pub display_name: String,
+    pub locale: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct PasswordForm {
+    pub current: String,
+    pub password: String,
+    pub confirm: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct ForgotPasswordForm {
+    pub email: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct ResetPasswordForm {
+    pub token: String,
+    pub password: String,
+    pub confirm: String,
+}
+
+// un jeton personnel pour l'API (voir api.rs) ; comme partitions.created_at,
+// les dates sont lues en texte (UTC) avec sql_query
+//
+#[derive(Debug, Clone, Serialize, QueryableByName)]
+#[serde(crate = "rocket::serde")]
+pub struct ApiToken {
+    #[sql_type = "Integer"]
+    pub id: i32,
+    #[sql_type = "Integer"]
+    pub user_id: i32,
+    #[sql_type = "Text"]
+    pub name: String,
+    #[sql_type = "Text"]
+    pub scope: String,
+    // l'ensemble sur lequel le jeton agit, et son nom
+    #[sql_type = "Integer"]
+    pub ensemble_id: i32,
+    #[sql_type = "Text"]
+    pub ensemble: String,
+    #[sql_type = "Text"]
+    pub created_at: String,
+    #[sql_type = "Nullable<Text>"]
+    pub expires_at: Option<String>,
+    #[sql_type = "Nullable<Text>"]
+    pub last_used_at: Option<String>,
+}
+
+// lecture seule, ou lecture et ajouts au catalogue
+#[derive(Debug, Clone, Copy, PartialEq, FromFormField)]
+pub enum ApiScope {
+    #[field(value = "read")]
+    Read,
+    #[field(value = "write")]
+    Write,
+}
+
+impl ApiScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::Read => "read",
+            ApiScope::Write => "write",
+        }
+    }
+
+    pub fn parse(scope: &str) -> Option<ApiScope> {
+        match scope {
+            "read" => Some(ApiScope::Read),
+            "write" => Some(ApiScope::Write),
+            _ => None,
+        }
+    }
+}
+
+// sans durée (ou 0), le jeton n'expire pas
+#[derive(Debug, FromForm)]
+pub struct ApiTokenForm {
+    pub name: String,
+    pub scope: ApiScope,
+    pub expires_in_days: Option<i32>,
+}
+
+// Statistics
+//
+// un nombre par étiquette (genre, compositeur, mois ...)
+//
+#[derive(Debug, Clone, Serialize, Queryable, QueryableByName)]
+#[serde(crate = "rocket::serde")]
+pub struct LabelCount {
+    #[sql_type = "Text"]
+    pub label: String,
+    #[sql_type = "BigInt"]
+    pub count: i64,
+}
+
+// Background jobs
+//
+// une tâche de fond (voir jobs.rs) ; `payload` est le JSON donné à la
+// fonction qui la fait, finished_at la date de la fin (faite, échouée ou annulée)
+//
+#[derive(Debug, Clone, Serialize, QueryableByName)]
+#[serde(crate = "rocket::serde")]
+pub struct Job {
+    #[sql_type = "Integer"]
+    pub id: i32,
+    #[sql_type = "Text"]
+    pub kind: String,
+    #[sql_type = "Nullable<Integer>"]
+    pub ensemble_id: Option<i32>,
+    #[sql_type = "Text"]
+    pub payload: String,
+    #[sql_type = "Text"]
+    pub status: String,
+    #[sql_type = "Integer"]
+    pub attempts: i32,
+    #[sql_type = "Integer"]
+    pub max_attempts: i32,
+    #[sql_type = "Text"]
+    pub error: String,
+    #[sql_type = "Text"]
+    pub run_at: String,
+    #[sql_type = "Text"]
+    pub created_at: String,
+    #[sql_type = "Nullable<Text>"]
+    pub finished_at: Option<String>,
+}
+
+// états d'une tâche (jobs.status)
+pub const JOB_PENDING: &str = "pending";
+pub const JOB_RUNNING: &str = "running";
+pub const JOB_DONE: &str = "done";
+pub const JOB_FAILED: &str = "failed";
+pub const JOB_CANCELLED: &str = "cancelled";
+
+// Webhooks
+//
+// un abonnement d'un site aux changements du catalogue (voir webhooks.rs)
+// et le journal de ses envois ; `data` est le JSON de l'objet changé
+//
+#[derive(Debug, Clone, Serialize, QueryableByName)]
+#[serde(crate = "rocket::serde")]
+pub struct Webhook {
+    #[sql_type = "Integer"]
+    pub id: i32,
+    #[sql_type = "Integer"]
+    pub ensemble_id: i32,
+    #[sql_type = "Text"]
+    pub url: String,
+    #[sql_type = "Text"]
+    pub secret: String,
+    #[sql_type = "Array<Text>"]
+    pub events: Vec<String>,
+    #[sql_type = "Bool"]
+    pub active: bool,
+    #[sql_type = "Text"]
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, QueryableByName)]
+#[serde(crate = "rocket::serde")]
+pub struct WebhookDelivery {
+    #[sql_type = "Integer"]
+    pub id: i32,
+    #[sql_type = "Integer"]
+    pub webhook_id: i32,
+    #[sql_type = "Text"]
+    pub event: String,
+    #[sql_type = "Text"]
+    pub data: String,
+    #[sql_type = "Text"]
+    pub status: String,
+    #[sql_type = "Integer"]
+    pub attempts: i32,
+    #[sql_type = "Nullable<Integer>"]
+    pub response_status: Option<i32>,
+    #[sql_type = "Text"]
+    pub response_body: String,
+    #[sql_type = "Text"]
+    pub error: String,
+    #[sql_type = "Nullable<Integer>"]
+    pub job_id: Option<i32>,
+    #[sql_type = "Text"]
+    pub created_at: String,
+    #[sql_type = "Nullable<Text>"]
+    pub delivered_at: Option<String>,
+}
+
+// un envoi à faire, et l'ensemble de son abonnement (celui de la tâche)
+#[derive(Debug, Clone, QueryableByName)]
+pub struct QueuedDelivery {
+    #[sql_type = "Integer"]
+    pub id: i32,
+    #[sql_type = "Integer"]
+    pub ensemble_id: i32,
+}
+
+// états d'un envoi (webhook_deliveries.status)
+pub const DELIVERY_PENDING: &str = "pending";
+pub const DELIVERY_DELIVERED: &str = "delivered";
+pub const DELIVERY_FAILED: &str = "failed";
+
+// sans secret, un secret est tiré au hasard ; à la modification, un secret
+// vide garde l'ancien
+#[derive(Debug, FromForm)]
+pub struct WebhookForm {
+    pub url: String,
+    #[field(default = String::new())]
+    pub secret: String,
+    pub events: Vec<String>,
+    // case à cocher : absente, l'abonnement est suspendu
+    pub active: bool,
+}
@@ -0,0 +1,264 @@
+use std::fmt;
+use std::io::{Cursor, Read};
+
+use roxmltree::{Document, Node};
+use zip::ZipArchive;
+
+// MusicXML
+//
+// lecture des fichiers exportés par les logiciels de notation : `.musicxml`
+// (ou `.xml`, non compressé) et `.mxl` (une archive zip dont le fichier
+// META-INF/container.xml donne la partition). On n'en garde que ce qui
+// décrit l'œuvre : titre, créateurs avec leur rôle (compositeur, parolier,
+// arrangeur ...), noms des parties (l'effectif), première armure et première
+// mesure. Les partitions `score-partwise` et `score-timewise` sont lues de la
+// même façon.
+
+const CONTAINER_FILE: &str = "META-INF/container.xml";
+
+// la taille décompressée d'un fichier de l'archive : au-delà, ce n'est pas
+// une partition mais une bombe zip
+pub const MAX_XML_BYTES: u64 = 32 * 1024 * 1024;
+
+// l'œuvre décrite par un fichier MusicXML ; les champs absents du fichier
+// restent vides
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Score {
+    pub title: Option<String>,
+    // (rôle, nom) : le rôle est l'attribut `type` de <creator>, "composer" ...
+    pub creators: Vec<(String, String)>,
+    pub parts: Vec<String>,
+    pub key: Option<String>,
+    pub time: Option<String>,
+}
+
+impl Score {
+    // le premier créateur de ce rôle
+    pub fn creator(&self, role: &str) -> Option<&str> {
+        self.creators
+            .iter()
+            .find(|(r, _)| r == role)
+            .map(|(_, name)| name.as_str())
+    }
+
+    pub fn composer(&self) -> Option<&str> {
+        self.creator("composer")
+    }
+
+    // l'effectif, tel qu'on l'affiche : "Soprano, Alto, Piano"
+    pub fn instrumentation(&self) -> String {
+        self.parts.join(", ")
+    }
+}
+
+#[derive(Debug)]
+pub enum MusicXmlError {
+    Io(std::io::Error),
+    Zip(zip::result::ZipError),
+    Xml(roxmltree::Error),
+    // lisible, mais ce n'est pas une partition MusicXML
+    Format(String),
+}
+
+impl fmt::Display for MusicXmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MusicXmlError::Io(e) => write!(f, "I/O error: {}", e),
+            MusicXmlError::Zip(e) => write!(f, "zip error: {}", e),
+            MusicXmlError::Xml(e) => write!(f, "XML error: {}", e),
+            MusicXmlError::Format(msg) => write!(f, "invalid MusicXML: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for MusicXmlError {}
+
+impl From<zip::result::ZipError> for MusicXmlError {
+    fn from(e: zip::result::ZipError) -> MusicXmlError {
+        MusicXmlError::Zip(e)
+    }
+}
+
+impl From<roxmltree::Error> for MusicXmlError {
+    fn from(e: roxmltree::Error) -> MusicXmlError {
+        MusicXmlError::Xml(e)
+    }
+}
+
+impl From<std::io::Error> for MusicXmlError {
+    fn from(e: std::io::Error) -> MusicXmlError {
+        MusicXmlError::Io(e)
+    }
+}
+
+// un fichier envoyé, compressé ou non : on le reconnaît à son contenu,
+// pas à son nom
+pub fn read_score(bytes: &[u8]) -> Result<Score, MusicXmlError> {
+    if bytes.starts_with(b"PK") {
+        let xml = read_compressed(bytes)?;
+        parse_score(&xml)
+    } else {
+        parse_score(&text_of(bytes.to_vec())?)
+    }
+}
+
+// .mxl : la partition est le premier <rootfile> du container, sinon le
+// premier fichier .xml ou .musicxml hors de META-INF
+fn read_compressed(bytes: &[u8]) -> Result<String, MusicXmlError> {
+    let mut zip = ZipArchive::new(Cursor::new(bytes))?;
+
+    let root = match read_entry(&mut zip, CONTAINER_FILE) {
+        Ok(container) => {
+            let container = text_of(container)?;
+            let doc = Document::parse(&container)?;
+            doc.descendants()
+                .find(|n| n.has_tag_name("rootfile"))
+                .and_then(|n| n.attribute("full-path"))
+                .map(|path| path.to_string())
+        }
+        Err(_) => None,
+    };
+    let root = match root {
+        Some(root) => root,
+        None => zip
+            .file_names()
+            .filter(|name| !name.starts_with("META-INF/"))
+            .find(|name| name.ends_with(".xml") || name.ends_with(".musicxml"))
+            .map(|name| name.to_string())
+            .ok_or_else(|| MusicXmlError::Format("no score in the archive".to_string()))?,
+    };
+    text_of(read_entry(&mut zip, &root)?)
+}
+
+fn read_entry(zip: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Result<Vec<u8>, MusicXmlError> {
+    let entry = zip.by_name(name)?;
+    // la taille annoncée par l'archive ne compte pas : on lit un octet de plus
+    let mut content = vec![];
+    entry.take(MAX_XML_BYTES + 1).read_to_end(&mut content)?;
+    if content.len() as u64 > MAX_XML_BYTES {
+        return Err(MusicXmlError::Format(format!(
+            "`{}` is larger than {} bytes once uncompressed",
+            name, MAX_XML_BYTES
+        )));
+    }
+    Ok(content)
+}
+
+// les fichiers MusicXML sont en UTF-8, parfois avec une marque d'ordre
+fn text_of(bytes: Vec<u8>) -> Result<String, MusicXmlError> {
+    let text = String::from_utf8(bytes)
+        .map_err(|_| MusicXmlError::Format("the file is not UTF-8 text".to_string()))?;
+    Ok(text.trim_start_matches('\u{feff}').to_string())
+}
+
+pub fn parse_score(xml: &str) -> Result<Score, MusicXmlError> {
+    let doc = Document::parse(xml)?;
+    let root = doc.root_element();
+    if !root.has_tag_name("score-partwise") && !root.has_tag_name("score-timewise") {
+        return Err(MusicXmlError::Format(format!(
+            "unexpected root element <{}>",
+            root.tag_name().name()
+        )));
+    }
+
+    // <work><work-title>, sinon <movement-title>
+    let title = child(root, "work")
+        .and_then(|work| child_text(work, "work-title"))
+        .or_else(|| child_text(root, "movement-title"));
+
+    let creators = child(root, "identification")
+        .map(|identification| {
+            identification
+                .children()
+                .filter(|n| n.has_tag_name("creator"))
+                .filter_map(|n| {
+                    let name = clean(n.text()?)?;
+                    let role = n.attribute("type").unwrap_or("composer").trim();
+                    Some((role.to_lowercase(), name))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let parts = child(root, "part-list")
+        .map(|part_list| {
+            part_list
+                .children()
+                .filter(|n| n.has_tag_name("score-part"))
+                .filter_map(|n| child_text(n, "part-name"))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let key = root
+        .descendants()
+        .find(|n| n.has_tag_name("key"))
+        .and_then(key_name);
+    let time = root
+        .descendants()
+        .find(|n| n.has_tag_name("time"))
+        .and_then(time_signature);
+
+    Ok(Score {
+        title,
+        creators,
+        parts,
+        key,
+        time,
+    })
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn child_text(node: Node, name: &str) -> Option<String> {
+    child(node, name).and_then(|n| n.text()).and_then(clean)
+}
+
+// espaces réduits ; un texte vide ne compte pas
+fn clean(text: &str) -> Option<String> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+// <key><fifths>-3</fifths><mode>minor</mode></key> -> "C minor" ;
+// sans mode, la tonalité majeure
+fn key_name(key: Node) -> Option<String> {
+    const MAJOR: [&str; 15] = [
+        "C♭", "G♭", "D♭", "A♭", "E♭", "B♭", "F", "C", "G", "D", "A", "E", "B", "F♯", "C♯",
+    ];
+    const MINOR: [&str; 15] = [
+        "A♭", "E♭", "B♭", "F", "C", "G", "D", "A", "E", "B", "F♯", "C♯", "G♯", "D♯", "A♯",
+    ];
+
+    let fifths = child_text(key, "fifths")?.parse::<i32>().ok()?;
+    if !(-7..=7).contains(&fifths) {
+        return None;
+    }
+    let index = (fifths + 7) as usize;
+    match child_text(key, "mode").as_deref() {
+        None | Some("major") => Some(format!("{} major", MAJOR[index])),
+        Some("minor") => Some(format!("{} minor", MINOR[index])),
+        // modes anciens (dorian ...) : on les nomme sur la tonique majeure
+        Some(mode) => Some(format!("{} {}", MAJOR[index], mode)),
+    }
+}
+
+// <time><beats>3</beats><beat-type>4</beat-type></time> -> "3/4"
+fn time_signature(time: Node) -> Option<String> {
+    if time.attribute("symbol") == Some("common") {
+        return Some("C".to_string());
+    }
+    if time.attribute("symbol") == Some("cut") {
+        return Some("¢".to_string());
+    }
+    match (child_text(time, "beats"), child_text(time, "beat-type")) {
+        (Some(beats), Some(beat_type)) => Some(format!("{}/{}", beats, beat_type)),
+        _ => child(time, "senza-misura").map(|_| "senza misura".to_string()),
+    }
+}
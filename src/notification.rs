@@ -0,0 +1,86 @@
+use std::convert::TryInto;
+
+use rocket::http::uri::Reference;
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket::serde::Serialize;
+
+// Notifications
+//
+// un message affiché en haut de page par base.html.tera ;
+// il vient soit du cookie flash posé par la redirection précédente,
+// soit directement du handler qui rend le template
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum Level {
+    Success,
+    Info,
+    Warning,
+    Error,
+}
+
+impl Level {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Success => "success",
+            Level::Info => "info",
+            Level::Warning => "warning",
+            Level::Error => "error",
+        }
+    }
+
+    // les types inconnus sont traités comme de l'information
+    pub fn from_kind(kind: &str) -> Level {
+        match kind {
+            "success" => Level::Success,
+            "warning" => Level::Warning,
+            "error" => Level::Error,
+            _ => Level::Info,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Notification {
+    pub level: Level,
+    pub message: String,
+}
+
+impl Notification {
+    pub fn new<M: Into<String>>(level: Level, message: M) -> Notification {
+        Notification {
+            level,
+            message: message.into(),
+        }
+    }
+
+    pub fn success<M: Into<String>>(message: M) -> Notification {
+        Notification::new(Level::Success, message)
+    }
+
+    pub fn info<M: Into<String>>(message: M) -> Notification {
+        Notification::new(Level::Info, message)
+    }
+
+    pub fn warning<M: Into<String>>(message: M) -> Notification {
+        Notification::new(Level::Warning, message)
+    }
+
+    pub fn error<M: Into<String>>(message: M) -> Notification {
+        Notification::new(Level::Error, message)
+    }
+
+    pub fn from_flash(flash: Option<FlashMessage<'_>>) -> Option<Notification> {
+        flash.map(|f| {
+            let (kind, message) = f.into_inner();
+            Notification::new(Level::from_kind(&kind), message)
+        })
+    }
+
+    // redirection qui porte la notification jusqu'à la page suivante
+    pub fn redirect<U: TryInto<Reference<'static>>>(self, uri: U) -> Flash<Redirect> {
+        Flash::new(Redirect::to(uri), self.level.as_str(), self.message)
+    }
+}
@@ -0,0 +1,2589 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::result::DatabaseErrorKind;
+use diesel::sql_query;
+use diesel::sql_types::{Array, BigInt, Bool, Float, Integer, Nullable, Text};
+use diesel::PgConnection;
+
+use crate::abc;
+use crate::backup::{
+    self, Archive, BackupError, LoanRecord, PartitionFileRecord, PartitionRecord, RestoreReport,
+    Snapshot, WebhookRecord,
+};
+use crate::models::{
+    name_key, ApiToken, BulkAction, BulkKind, Duplicate, Ensemble, Genre, Job, LabelCount, Loan,
+    LoanDecision, NameKeys, NewPartition, Partition, PartitionCreator, PartitionDetails,
+    PartitionFile, PartitionSearch, Person, QueuedDelivery, SharedLibrary, SharedPartition,
+    ShowPartition, Suggestion, Tag, User, Webhook, WebhookDelivery, DELIVERY_DELIVERED,
+    JOB_CANCELLED, JOB_DONE, JOB_FAILED, JOB_PENDING, JOB_RUNNING, TOKEN_RESET_PASSWORD,
+    TOKEN_VERIFY_EMAIL,
+};
+use crate::musicxml::Score;
+use crate::schema::genres::columns::name_key as genre_key;
+use crate::schema::persons::columns::full_name_key;
+use crate::schema::{
+    ensemble_members, ensembles, genres, partition_creators, partition_details, partition_tags,
+    partitions, persons, tags, users,
+};
+
+// Repository
+//
+// toutes les requêtes, sur une connexion Diesel ordinaire : utilisables sans
+// Rocket (outil en ligne de commande src/bin/admin.rs, tests) ; les fonctions
+// de db.rs les appellent à travers le pool de Rocket.
+// Une opération en plusieurs requêtes se fait dans une transaction.
+//
+// les partitions et les genres sont lus dans la bibliothèque d'un ensemble
+// (paramètre `ensemble_id`) : ses partitions, ses genres et les genres communs.
+// Les compositeurs sont communs à tous les ensembles.
+
+// les migrations du dossier migrations/ sont compilées dans l'exécutable
+embed_migrations!();
+
+pub fn run_migrations(c: &PgConnection) -> Result<(), diesel_migrations::RunMigrationsError> {
+    embedded_migrations::run(c)
+}
+
+// ************************************************************************************************
+// Lists
+
+pub fn get_list_raw_partitions(c: &PgConnection, ensemble_id: i32) -> QueryResult<Vec<Partition>> {
+    partitions::table
+        .filter(partitions::ensemble_id.eq(ensemble_id))
+        .order(partitions::title)
+        .load::<Partition>(c)
+}
+
+pub fn get_list_show_partitions(
+    c: &PgConnection,
+    ensemble_id: i32,
+) -> QueryResult<Vec<ShowPartition>> {
+    partitions::table
+        .inner_join(persons::table)
+        .inner_join(genres::table)
+        .select((
+            partitions::id,
+            partitions::title,
+            persons::full_name,
+            genres::name,
+        ))
+        .filter(partitions::ensemble_id.eq(ensemble_id))
+        .order(partitions::title)
+        .load(c)
+}
+
+// les genres communs et ceux de l'ensemble
+pub fn get_list_genres(c: &PgConnection, ensemble_id: i32) -> QueryResult<Vec<Genre>> {
+    genres::table
+        .filter(
+            genres::ensemble_id
+                .is_null()
+                .or(genres::ensemble_id.eq(ensemble_id)),
+        )
+        .order(genres::name.asc())
+        .load::<Genre>(c)
+}
+
+pub fn get_list_persons(c: &PgConnection) -> QueryResult<Vec<Person>> {
+    persons::table
+        .order(persons::full_name.asc())
+        .load::<Person>(c)
+}
+
+// ************************************************************************************************
+// Persons and genres
+//
+// la comparaison des noms se fait sur les colonnes "clé" (models::name_key) :
+// "Fauré", "faure" ou " Faure  " désignent la même personne
+
+// seuil de similarité pg_trgm pour les suggestions (celui de l'opérateur %)
+const SIMILARITY_THRESHOLD: f32 = 0.3;
+const MAX_SIMILAR: i64 = 3;
+
+sql_function!(fn similarity(x: Text, y: Text) -> Float);
+sql_function!(fn lower(x: Text) -> Text);
+
+pub fn get_person(c: &PgConnection, person_id: i32) -> QueryResult<Person> {
+    persons::table.find(person_id).first(c)
+}
+
+pub fn get_genre(c: &PgConnection, ensemble_id: i32, genre_id: i32) -> QueryResult<Genre> {
+    genres::table
+        .find(genre_id)
+        .filter(
+            genres::ensemble_id
+                .is_null()
+                .or(genres::ensemble_id.eq(ensemble_id)),
+        )
+        .first(c)
+}
+
+pub fn get_person_by_name(c: &PgConnection, person_full_name: &str) -> QueryResult<Person> {
+    persons::table
+        .filter(full_name_key.eq(name_key(person_full_name)))
+        .first(c)
+}
+
+// un genre réservé à l'ensemble passe avant le genre commun du même nom
+pub fn get_genre_by_name(
+    c: &PgConnection,
+    ensemble_id: i32,
+    genre_name: &str,
+) -> QueryResult<Genre> {
+    genres::table
+        .filter(genre_key.eq(name_key(genre_name)))
+        .filter(
+            genres::ensemble_id
+                .is_null()
+                .or(genres::ensemble_id.eq(ensemble_id)),
+        )
+        .order(genres::ensemble_id.asc().nulls_last())
+        .first(c)
+}
+
+// le genre qui porte ce nom parmi les communs (None) ou ceux d'un ensemble :
+// les noms sont uniques pour chacun (index genres_name_key_unique)
+pub fn get_genre_by_key(
+    c: &PgConnection,
+    ensemble_id: Option<i32>,
+    genre_name: &str,
+) -> QueryResult<Genre> {
+    genres::table
+        .filter(genre_key.eq(name_key(genre_name)))
+        .filter(genres::ensemble_id.is_not_distinct_from(ensemble_id))
+        .first(c)
+}
+
+// "vouliez-vous dire ..." quand le nom n'existe pas tel quel
+pub fn get_similar_persons(c: &PgConnection, person_full_name: &str) -> QueryResult<Vec<Person>> {
+    let key = name_key(person_full_name);
+    persons::table
+        .filter(similarity(full_name_key, &key).gt(SIMILARITY_THRESHOLD))
+        .order(similarity(full_name_key, &key).desc())
+        .limit(MAX_SIMILAR)
+        .load(c)
+}
+
+pub fn get_similar_genres(
+    c: &PgConnection,
+    ensemble_id: i32,
+    genre_name: &str,
+) -> QueryResult<Vec<Genre>> {
+    let key = name_key(genre_name);
+    genres::table
+        .filter(similarity(genre_key, &key).gt(SIMILARITY_THRESHOLD))
+        .filter(
+            genres::ensemble_id
+                .is_null()
+                .or(genres::ensemble_id.eq(ensemble_id)),
+        )
+        .order(similarity(genre_key, &key).desc())
+        .limit(MAX_SIMILAR)
+        .load(c)
+}
+
+pub fn create_person(c: &PgConnection, person: Person) -> QueryResult<Person> {
+    diesel::insert_into(persons::table)
+        .values(&person.normalized())
+        .get_result(c)
+}
+
+pub fn create_genre(c: &PgConnection, genre: Genre) -> QueryResult<Genre> {
+    diesel::insert_into(genres::table)
+        .values(&genre.normalized())
+        .get_result(c)
+}
+
+pub fn update_person(c: &PgConnection, person_id: i32, person: Person) -> QueryResult<Person> {
+    diesel::update(persons::table.find(person_id))
+        .set(&person.normalized())
+        .get_result(c)
+}
+
+// un genre garde l'ensemble à qui il est réservé (None n'est pas écrit) ;
+// les genres communs ne changent que par un administrateur (`admin`),
+// sinon NotFound
+pub fn update_genre(
+    c: &PgConnection,
+    ensemble_id: i32,
+    admin: bool,
+    genre_id: i32,
+    genre: Genre,
+) -> QueryResult<Genre> {
+    let genre = Genre {
+        ensemble_id: None,
+        ..genre
+    };
+    diesel::update(
+        genres::table.find(genre_id).filter(
+            genres::ensemble_id
+                .eq(ensemble_id)
+                .or(genres::ensemble_id.is_null().and(admin.into_sql::<Bool>())),
+        ),
+    )
+    .set(&genre.normalized())
+    .get_result(c)
+}
+
+pub fn delete_person(c: &PgConnection, person_id: i32) -> QueryResult<usize> {
+    diesel::delete(persons::table.find(person_id)).execute(c)
+}
+
+// comme update_genre : 0 pour un genre commun sans être administrateur
+pub fn delete_genre(
+    c: &PgConnection,
+    ensemble_id: i32,
+    admin: bool,
+    genre_id: i32,
+) -> QueryResult<usize> {
+    diesel::delete(
+        genres::table.find(genre_id).filter(
+            genres::ensemble_id
+                .eq(ensemble_id)
+                .or(genres::ensemble_id.is_null().and(admin.into_sql::<Bool>())),
+        ),
+    )
+    .execute(c)
+}
+
+// ************************************************************************************************
+// Partitions
+
+pub fn get_partition_detail(
+    c: &PgConnection,
+    ensemble_id: i32,
+    partition_id: i32,
+) -> QueryResult<(Partition, Person, Genre)> {
+    partitions::table
+        .inner_join(persons::table)
+        .inner_join(genres::table)
+        .filter(partitions::id.eq(partition_id))
+        .filter(partitions::ensemble_id.eq(ensemble_id))
+        .first::<(Partition, Person, Genre)>(c)
+}
+
+// les autres partitions du même compositeur, dans la même bibliothèque
+pub fn get_related_partitions(
+    c: &PgConnection,
+    partition: &Partition,
+) -> QueryResult<Vec<Partition>> {
+    partitions::table
+        .filter(partitions::person_id.eq(partition.person_id))
+        .filter(partitions::ensemble_id.eq(partition.ensemble_id))
+        .filter(partitions::id.ne(partition.id))
+        .order(partitions::title)
+        .load::<Partition>(c)
+}
+
+pub fn get_partitions_by_person_id(
+    c: &PgConnection,
+    ensemble_id: i32,
+    person_id: i32,
+) -> QueryResult<Vec<ShowPartition>> {
+    partitions::table
+        .inner_join(persons::table)
+        .inner_join(genres::table)
+        .select((
+            partitions::id,
+            partitions::title,
+            persons::full_name,
+            genres::name,
+        ))
+        .filter(partitions::person_id.eq(person_id))
+        .filter(partitions::ensemble_id.eq(ensemble_id))
+        .order(partitions::title)
+        .load(c)
+}
+
+pub fn get_partitions_by_genre_id(
+    c: &PgConnection,
+    ensemble_id: i32,
+    genre_id: i32,
+) -> QueryResult<Vec<ShowPartition>> {
+    partitions::table
+        .inner_join(persons::table)
+        .inner_join(genres::table)
+        .select((
+            partitions::id,
+            partitions::title,
+            persons::full_name,
+            genres::name,
+        ))
+        .filter(partitions::genre_id.eq(genre_id))
+        .filter(partitions::ensemble_id.eq(ensemble_id))
+        .order(partitions::title)
+        .load(c)
+}
+
+pub fn insert_partition(c: &PgConnection, partition: &Partition) -> QueryResult<Partition> {
+    diesel::insert_into(partitions::table)
+        .values(partition)
+        .get_result(c)
+}
+
+// ajoute une partition à partir des noms du compositeur et du genre,
+// en créant ceux qui manquent si `create_missing` est coché ;
+// tout se fait dans la même transaction. Un nom inconnu (sans création)
+// donne NotFound et rien n'est ajouté. Un genre créé ainsi est propre à
+// l'ensemble ; un genre commun du même nom sert tel quel (get_genre_by_name).
+pub fn create_partition(
+    c: &PgConnection,
+    ensemble_id: i32,
+    new_partition: &NewPartition,
+) -> QueryResult<ShowPartition> {
+    c.transaction(|| {
+        let person = match get_person_by_name(c, &new_partition.full_name) {
+            Err(diesel::result::Error::NotFound) if new_partition.create_missing => create_person(
+                c,
+                Person {
+                    id: None,
+                    full_name: new_partition.full_name.clone(),
+                    full_name_key: String::new(),
+                },
+            )?,
+            person => person?,
+        };
+        let genre = match get_genre_by_name(c, ensemble_id, &new_partition.name) {
+            Err(diesel::result::Error::NotFound) if new_partition.create_missing => create_genre(
+                c,
+                Genre {
+                    id: None,
+                    name: new_partition.name.clone(),
+                    name_key: String::new(),
+                    ensemble_id: Some(ensemble_id),
+                },
+            )?,
+            genre => genre?,
+        };
+        let partition = insert_partition(
+            c,
+            &Partition {
+                id: None,
+                person_id: person.id.unwrap_or_default(),
+                title: new_partition.title.clone(),
+                genre_id: genre.id.unwrap_or_default(),
+                ensemble_id,
+                shared: false,
+            },
+        )?;
+        Ok(ShowPartition {
+            id: partition.id,
+            title: partition.title,
+            full_name: person.full_name,
+            name: genre.name,
+        })
+    })
+}
+
+// la bibliothèque et le partage ne changent pas ici
+pub fn update_partition(
+    c: &PgConnection,
+    ensemble_id: i32,
+    partition_id: i32,
+    partition: Partition,
+) -> QueryResult<Partition> {
+    diesel::update(
+        partitions::table
+            .find(partition_id)
+            .filter(partitions::ensemble_id.eq(ensemble_id)),
+    )
+    .set((
+        partitions::person_id.eq(partition.person_id),
+        partitions::title.eq(partition.title),
+        partitions::genre_id.eq(partition.genre_id),
+    ))
+    .get_result(c)
+}
+
+pub fn delete_partition(
+    c: &PgConnection,
+    ensemble_id: i32,
+    partition_id: i32,
+) -> QueryResult<usize> {
+    diesel::delete(
+        partitions::table
+            .find(partition_id)
+            .filter(partitions::ensemble_id.eq(ensemble_id)),
+    )
+    .execute(c)
+}
+
+// la partition d'un compositeur qui porte ce titre (sans tenir compte de la casse) :
+// il ne peut y en avoir qu'une par ensemble (migrations 2021-07-15-000000_unique_names
+// et 2021-08-05-000000_ensembles)
+pub fn find_partition(
+    c: &PgConnection,
+    ensemble_id: i32,
+    title: &str,
+    person_id: i32,
+) -> QueryResult<Partition> {
+    partitions::table
+        .filter(lower(partitions::title).eq(title.to_lowercase()))
+        .filter(partitions::person_id.eq(person_id))
+        .filter(partitions::ensemble_id.eq(ensemble_id))
+        .first(c)
+}
+
+pub fn partition_exists(
+    c: &PgConnection,
+    ensemble_id: i32,
+    title: &str,
+    person_id: i32,
+) -> QueryResult<bool> {
+    find_partition(c, ensemble_id, title, person_id)
+        .optional()
+        .map(|p| p.is_some())
+}
+
+// ************************************************************************************************
+// Conflicts
+//
+// les noms des musiciens et des genres, et les titres d'un même compositeur
+// dans un ensemble, sont uniques : un ajout ou une modification qui ferait un doublon échoue
+// avec une erreur UniqueViolation
+
+pub fn is_unique_violation(e: &diesel::result::Error) -> bool {
+    matches!(
+        e,
+        diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)
+    )
+}
+
+// rattache les partitions de `from_id` à `into_id` puis supprime `from_id` ;
+// les partitions que `into_id` a déjà (même titre) sont supprimées
+fn merge_person(c: &PgConnection, from_id: i32, into_id: i32) -> QueryResult<()> {
+    sql_query(
+        "DELETE FROM partitions a USING partitions b
+         WHERE a.person_id = $1 AND b.person_id = $2 AND lower(a.title) = lower(b.title)
+           AND a.ensemble_id = b.ensemble_id",
+    )
+    .bind::<Integer, _>(from_id)
+    .bind::<Integer, _>(into_id)
+    .execute(c)?;
+    diesel::update(partitions::table.filter(partitions::person_id.eq(from_id)))
+        .set(partitions::person_id.eq(into_id))
+        .execute(c)?;
+    sql_query(
+        "INSERT INTO partition_creators (partition_id, person_id, role)
+         SELECT partition_id, $2, role FROM partition_creators WHERE person_id = $1
+         ON CONFLICT DO NOTHING",
+    )
+    .bind::<Integer, _>(from_id)
+    .bind::<Integer, _>(into_id)
+    .execute(c)?;
+    diesel::delete(persons::table.find(Some(from_id))).execute(c)?;
+    Ok(())
+}
+
+// un genre qui reçoit les partitions d'un autre ensemble devient commun
+fn merge_genre(c: &PgConnection, from_id: i32, into_id: i32) -> QueryResult<()> {
+    sql_query(
+        "UPDATE genres SET ensemble_id = NULL
+         WHERE id = $2
+           AND ensemble_id IS DISTINCT FROM (SELECT ensemble_id FROM genres WHERE id = $1)",
+    )
+    .bind::<Integer, _>(from_id)
+    .bind::<Integer, _>(into_id)
+    .execute(c)?;
+    diesel::update(partitions::table.filter(partitions::genre_id.eq(from_id)))
+        .set(partitions::genre_id.eq(into_id))
+        .execute(c)?;
+    diesel::delete(genres::table.find(Some(from_id))).execute(c)?;
+    Ok(())
+}
+
+// ************************************************************************************************
+// Tags
+
+// l'étiquette de ce nom (comparé sur sa clé), créée si besoin
+pub fn get_or_create_tag(c: &PgConnection, tag_name: &str) -> QueryResult<Tag> {
+    let tag = Tag::new(tag_name);
+    match tags::table
+        .filter(tags::name_key.eq(&tag.name_key))
+        .first(c)
+    {
+        Err(diesel::result::Error::NotFound) => {
+            diesel::insert_into(tags::table).values(&tag).get_result(c)
+        }
+        found => found,
+    }
+}
+
+pub fn get_partition_tags(c: &PgConnection, partition_id: i32) -> QueryResult<Vec<Tag>> {
+    tags::table
+        .inner_join(partition_tags::table)
+        .filter(partition_tags::partition_id.eq(partition_id))
+        .select((tags::id, tags::name, tags::name_key))
+        .order(tags::name)
+        .load(c)
+}
+
+// pose l'étiquette sur les partitions (celles qui existent) ;
+// renvoie le nombre de partitions qui ne l'avaient pas encore
+pub fn tag_partitions(c: &PgConnection, tag_id: i32, partition_ids: &[i32]) -> QueryResult<usize> {
+    sql_query(
+        "INSERT INTO partition_tags (partition_id, tag_id)
+         SELECT id, $1 FROM partitions WHERE id = ANY($2)
+         ON CONFLICT DO NOTHING",
+    )
+    .bind::<Integer, _>(tag_id)
+    .bind::<Array<Integer>, _>(partition_ids)
+    .execute(c)
+}
+
+// ************************************************************************************************
+// Files and details
+//
+// les fichiers d'une partition sont dans le dossier `upload_dir` : la base
+// n'en garde que le chemin, l'écriture du fichier revient à l'appelant.
+
+const PARTITION_FILE_COLUMNS: &str = "id, partition_id, name, path, content_type,
+    to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI') AS created_at, thumbnail";
+
+pub fn get_partition_files(c: &PgConnection, partition_id: i32) -> QueryResult<Vec<PartitionFile>> {
+    sql_query(format!(
+        "SELECT {} FROM partition_files WHERE partition_id = $1 ORDER BY id",
+        PARTITION_FILE_COLUMNS
+    ))
+    .bind::<Integer, _>(partition_id)
+    .load::<PartitionFile>(c)
+}
+
+// un fichier d'une partition de la bibliothèque
+pub fn get_partition_file(
+    c: &PgConnection,
+    ensemble_id: i32,
+    partition_id: i32,
+    file_id: i32,
+) -> QueryResult<PartitionFile> {
+    sql_query(format!(
+        "SELECT {} FROM partition_files
+         WHERE id = $1 AND partition_id = $2
+           AND partition_id IN (SELECT id FROM partitions WHERE ensemble_id = $3)",
+        PARTITION_FILE_COLUMNS
+    ))
+    .bind::<Integer, _>(file_id)
+    .bind::<Integer, _>(partition_id)
+    .bind::<Integer, _>(ensemble_id)
+    .get_result::<PartitionFile>(c)
+}
+
+pub fn add_partition_file(
+    c: &PgConnection,
+    partition_id: i32,
+    name: &str,
+    path: &str,
+    content_type: &str,
+) -> QueryResult<PartitionFile> {
+    sql_query(format!(
+        "INSERT INTO partition_files (partition_id, name, path, content_type)
+         VALUES ($1, $2, $3, $4)
+         RETURNING {}",
+        PARTITION_FILE_COLUMNS
+    ))
+    .bind::<Integer, _>(partition_id)
+    .bind::<Text, _>(name)
+    .bind::<Text, _>(path)
+    .bind::<Text, _>(content_type)
+    .get_result::<PartitionFile>(c)
+}
+
+// un fichier envoyé depuis la page d'une partition de la bibliothèque ;
+// NotFound si la partition n'en fait pas partie
+pub fn attach_partition_file(
+    c: &PgConnection,
+    ensemble_id: i32,
+    partition_id: i32,
+    name: &str,
+    path: &str,
+    content_type: &str,
+) -> QueryResult<PartitionFile> {
+    sql_query(format!(
+        "INSERT INTO partition_files (partition_id, name, path, content_type)
+         SELECT id, $3, $4, $5 FROM partitions WHERE id = $1 AND ensemble_id = $2
+         RETURNING {}",
+        PARTITION_FILE_COLUMNS
+    ))
+    .bind::<Integer, _>(partition_id)
+    .bind::<Integer, _>(ensemble_id)
+    .bind::<Text, _>(name)
+    .bind::<Text, _>(path)
+    .bind::<Text, _>(content_type)
+    .get_result::<PartitionFile>(c)
+}
+
+// un fichier, sans vérifier la bibliothèque (tâches de fond)
+pub fn get_file(c: &PgConnection, file_id: i32) -> QueryResult<PartitionFile> {
+    sql_query(format!(
+        "SELECT {} FROM partition_files WHERE id = $1",
+        PARTITION_FILE_COLUMNS
+    ))
+    .bind::<Integer, _>(file_id)
+    .get_result::<PartitionFile>(c)
+}
+
+pub fn set_file_thumbnail(c: &PgConnection, file_id: i32, thumbnail: bool) -> QueryResult<usize> {
+    sql_query("UPDATE partition_files SET thumbnail = $2 WHERE id = $1")
+        .bind::<Integer, _>(file_id)
+        .bind::<Bool, _>(thumbnail)
+        .execute(c)
+}
+
+// le premier fichier de la partition qui a une vignette
+pub fn get_partition_thumbnail(
+    c: &PgConnection,
+    ensemble_id: i32,
+    partition_id: i32,
+) -> QueryResult<PartitionFile> {
+    sql_query(format!(
+        "SELECT {} FROM partition_files
+         WHERE partition_id = $1 AND thumbnail
+           AND partition_id IN (SELECT id FROM partitions WHERE ensemble_id = $2)
+         ORDER BY id LIMIT 1",
+        PARTITION_FILE_COLUMNS
+    ))
+    .bind::<Integer, _>(partition_id)
+    .bind::<Integer, _>(ensemble_id)
+    .get_result::<PartitionFile>(c)
+}
+
+#[derive(QueryableByName)]
+struct ThumbnailOwner {
+    #[sql_type = "Integer"]
+    partition_id: i32,
+}
+
+// les partitions de la bibliothèque qui ont une vignette, pour la liste
+pub fn get_thumbnail_ids(c: &PgConnection, ensemble_id: i32) -> QueryResult<Vec<i32>> {
+    let owners = sql_query(
+        "SELECT DISTINCT partition_id FROM partition_files
+         WHERE thumbnail
+           AND partition_id IN (SELECT id FROM partitions WHERE ensemble_id = $1)",
+    )
+    .bind::<Integer, _>(ensemble_id)
+    .load::<ThumbnailOwner>(c)?;
+    Ok(owners.into_iter().map(|owner| owner.partition_id).collect())
+}
+
+pub fn get_partition_details(
+    c: &PgConnection,
+    partition_id: i32,
+) -> QueryResult<Option<PartitionDetails>> {
+    partition_details::table
+        .find(partition_id)
+        .first(c)
+        .optional()
+}
+
+// les détails lus dans un nouveau fichier remplacent les anciens ;
+// l'incipit reste celui qui a été saisi
+pub fn set_partition_details(c: &PgConnection, details: &PartitionDetails) -> QueryResult<usize> {
+    diesel::insert_into(partition_details::table)
+        .values(details)
+        .on_conflict(partition_details::partition_id)
+        .do_update()
+        .set((
+            partition_details::instrumentation.eq(&details.instrumentation),
+            partition_details::key_signature.eq(&details.key_signature),
+            partition_details::time_signature.eq(&details.time_signature),
+        ))
+        .execute(c)
+}
+
+// l'incipit ABC d'une partition de la bibliothèque ; vide s'il n'y en a pas
+pub fn get_partition_incipit(
+    c: &PgConnection,
+    ensemble_id: i32,
+    partition_id: i32,
+) -> QueryResult<String> {
+    partition_details::table
+        .inner_join(partitions::table)
+        .filter(partition_details::partition_id.eq(partition_id))
+        .filter(partitions::ensemble_id.eq(ensemble_id))
+        .select(partition_details::incipit)
+        .first(c)
+        .optional()
+        .map(Option::unwrap_or_default)
+}
+
+// les partitions de la bibliothèque qui ont un incipit
+pub fn get_incipit_ids(c: &PgConnection, ensemble_id: i32) -> QueryResult<Vec<i32>> {
+    partition_details::table
+        .inner_join(partitions::table)
+        .filter(partitions::ensemble_id.eq(ensemble_id))
+        .filter(partition_details::incipit.ne(""))
+        .select(partition_details::partition_id)
+        .load(c)
+}
+
+// `incipit` vide : l'incipit est effacé. Renvoie 0 pour une partition
+// d'une autre bibliothèque.
+pub fn set_partition_incipit(
+    c: &PgConnection,
+    ensemble_id: i32,
+    partition_id: i32,
+    incipit: &str,
+) -> QueryResult<usize> {
+    sql_query(
+        "INSERT INTO partition_details (partition_id, incipit, incipit_key)
+         SELECT id, $3, $4 FROM partitions WHERE id = $1 AND ensemble_id = $2
+         ON CONFLICT (partition_id) DO UPDATE
+         SET incipit = EXCLUDED.incipit, incipit_key = EXCLUDED.incipit_key",
+    )
+    .bind::<Integer, _>(partition_id)
+    .bind::<Integer, _>(ensemble_id)
+    .bind::<Text, _>(incipit)
+    .bind::<Text, _>(melody_key(incipit))
+    .execute(c)
+}
+
+// la clé de recherche mélodique d'un incipit (voir abc.rs)
+fn melody_key(incipit: &str) -> String {
+    abc::parse(incipit)
+        .map(|incipit| incipit.melody_key())
+        .unwrap_or_default()
+}
+
+pub fn get_partition_creators(
+    c: &PgConnection,
+    partition_id: i32,
+) -> QueryResult<Vec<PartitionCreator>> {
+    partition_creators::table
+        .inner_join(persons::table)
+        .filter(partition_creators::partition_id.eq(partition_id))
+        .select((partition_creators::role, persons::id, persons::full_name))
+        .order((partition_creators::role, persons::full_name))
+        .load(c)
+}
+
+pub fn add_partition_creator(
+    c: &PgConnection,
+    partition_id: i32,
+    person_id: i32,
+    role: &str,
+) -> QueryResult<usize> {
+    diesel::insert_into(partition_creators::table)
+        .values((
+            partition_creators::partition_id.eq(partition_id),
+            partition_creators::person_id.eq(person_id),
+            partition_creators::role.eq(role),
+        ))
+        .on_conflict_do_nothing()
+        .execute(c)
+}
+
+// une partition lue dans un fichier MusicXML, dans la même transaction :
+// la partition (comme create_partition, le compositeur et le genre donnés
+// par leur nom), ses détails, ses autres créateurs et le fichier.
+// Un autre créateur inconnu est ajouté aux compositeurs si `create_missing`
+// est coché, sinon il est laissé de côté.
+pub fn import_partition(
+    c: &PgConnection,
+    ensemble_id: i32,
+    new_partition: &NewPartition,
+    score: &Score,
+    file_name: &str,
+    path: &str,
+    content_type: &str,
+) -> QueryResult<(ShowPartition, PartitionFile)> {
+    c.transaction(|| {
+        let partition = create_partition(c, ensemble_id, new_partition)?;
+        let partition_id = partition.id.unwrap_or_default();
+
+        set_partition_details(
+            c,
+            &PartitionDetails {
+                partition_id,
+                instrumentation: score.instrumentation(),
+                key_signature: score.key.clone().unwrap_or_default(),
+                time_signature: score.time.clone().unwrap_or_default(),
+                ..PartitionDetails::default()
+            },
+        )?;
+
+        let composer_key = name_key(&new_partition.full_name);
+        for (role, name) in &score.creators {
+            if role == "composer" && name_key(name) == composer_key {
+                continue;
+            }
+            let person = match get_person_by_name(c, name) {
+                Err(diesel::result::Error::NotFound) if new_partition.create_missing => {
+                    create_person(
+                        c,
+                        Person {
+                            id: None,
+                            full_name: name.clone(),
+                            full_name_key: String::new(),
+                        },
+                    )?
+                }
+                Err(diesel::result::Error::NotFound) => continue,
+                person => person?,
+            };
+            add_partition_creator(c, partition_id, person.id.unwrap_or_default(), role)?;
+        }
+
+        let file = add_partition_file(c, partition_id, file_name, path, content_type)?;
+        Ok((partition, file))
+    })
+}
+
+// ************************************************************************************************
+// Bulk operations
+
+// les partitions choisies, dans l'ordre de la liste
+pub fn get_partitions_by_ids(
+    c: &PgConnection,
+    ensemble_id: i32,
+    ids: &[i32],
+) -> QueryResult<Vec<ShowPartition>> {
+    let ids = ids.iter().map(|&id| Some(id)).collect::<Vec<_>>();
+    partitions::table
+        .inner_join(persons::table)
+        .inner_join(genres::table)
+        .select((
+            partitions::id,
+            partitions::title,
+            persons::full_name,
+            genres::name,
+        ))
+        .filter(partitions::id.eq_any(ids))
+        .filter(partitions::ensemble_id.eq(ensemble_id))
+        .order(partitions::title)
+        .load(c)
+}
+
+// les noms des étiquettes de ces partitions : (partition, étiquette)
+fn get_tags_by_partition(c: &PgConnection, ids: &[i32]) -> QueryResult<Vec<(i32, String)>> {
+    partition_tags::table
+        .inner_join(tags::table)
+        .filter(partition_tags::partition_id.eq_any(ids))
+        .select((partition_tags::partition_id, tags::name))
+        .order(tags::name)
+        .load(c)
+}
+
+// les partitions choisies avec les noms de leurs étiquettes, pour l'export
+pub fn get_partitions_with_tags(
+    c: &PgConnection,
+    ensemble_id: i32,
+    ids: &[i32],
+) -> QueryResult<Vec<(ShowPartition, Vec<String>)>> {
+    let tags = get_tags_by_partition(c, ids)?;
+    let partitions = get_partitions_by_ids(c, ensemble_id, ids)?
+        .into_iter()
+        .map(|partition| {
+            let names = tags
+                .iter()
+                .filter(|(id, _)| Some(*id) == partition.id)
+                .map(|(_, name)| name.clone())
+                .collect();
+            (partition, names)
+        })
+        .collect();
+    Ok(partitions)
+}
+
+// applique une action aux partitions choisies, dans une seule transaction ;
+// renvoie le nombre de partitions touchées.
+// Un genre ou un compositeur inconnu donne NotFound, un doublon (même titre
+// chez le nouveau compositeur) UniqueViolation : rien n'est alors modifié.
+// L'export ne modifie rien (voir get_partitions_by_ids).
+// Seules les partitions de l'ensemble sont touchées.
+pub fn bulk_update(c: &PgConnection, ensemble_id: i32, action: &BulkAction) -> QueryResult<usize> {
+    let ids = action.ids.iter().map(|&id| Some(id)).collect::<Vec<_>>();
+    let selected = partitions::table
+        .filter(partitions::id.eq_any(ids))
+        .filter(partitions::ensemble_id.eq(ensemble_id));
+
+    c.transaction(|| match action.action {
+        BulkKind::ChangeGenre => {
+            let genre = get_genre_by_name(c, ensemble_id, &action.value)?;
+            diesel::update(selected)
+                .set(partitions::genre_id.eq(genre.id.unwrap_or_default()))
+                .execute(c)
+        }
+        BulkKind::ChangeComposer => {
+            let person = get_person_by_name(c, &action.value)?;
+            diesel::update(selected)
+                .set(partitions::person_id.eq(person.id.unwrap_or_default()))
+                .execute(c)
+        }
+        BulkKind::Delete => diesel::delete(selected).execute(c),
+        BulkKind::AddTag => {
+            let ids = selected
+                .select(partitions::id)
+                .load::<Option<i32>>(c)?
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>();
+            let tag = get_or_create_tag(c, &action.value)?;
+            tag_partitions(c, tag.id.unwrap_or_default(), &ids)
+        }
+        BulkKind::Share => share_partitions(c, ensemble_id, &action.ids, true),
+        BulkKind::Unshare => share_partitions(c, ensemble_id, &action.ids, false),
+        BulkKind::Export => Ok(0),
+    })
+}
+
+// ************************************************************************************************
+// Search
+
+// échappe les caractères spéciaux de LIKE
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+// motif ILIKE "contient"
+fn contains_pattern(text: &str) -> String {
+    format!("%{}%", escape_like(text))
+}
+
+// motif ILIKE "commence par"
+fn prefix_pattern(text: &str) -> String {
+    format!("{}%", escape_like(text))
+}
+
+pub fn search_partitions(
+    c: &PgConnection,
+    ensemble_id: i32,
+    search: &PartitionSearch,
+) -> QueryResult<Vec<ShowPartition>> {
+    let mut query = partitions::table
+        .inner_join(persons::table)
+        .inner_join(genres::table)
+        .select((
+            partitions::id,
+            partitions::title,
+            persons::full_name,
+            genres::name,
+        ))
+        .filter(partitions::ensemble_id.eq(ensemble_id))
+        .into_boxed();
+
+    if let Some(q) = &search.q {
+        query = query.filter(partitions::title.ilike(contains_pattern(q)));
+    }
+    if let Some(author) = &search.author {
+        query = query.filter(persons::full_name.ilike(contains_pattern(author)));
+    }
+    if let Some(genre) = &search.genre {
+        query = query.filter(genres::name.ilike(contains_pattern(genre)));
+    }
+    // les intervalles de la mélodie dans ceux d'un incipit, quelle que soit
+    // la tonalité ; une seule note : toutes les partitions avec un incipit
+    if let Some(melody) = &search.melody {
+        let with_melody = partition_details::table
+            .filter(partition_details::incipit_key.ne(""))
+            .filter(partition_details::incipit_key.like(format!("%{}%", melody_key(melody))))
+            .select(partition_details::partition_id.nullable());
+        query = query.filter(partitions::id.eq_any(with_melody));
+    }
+
+    query.order(partitions::title).load(c)
+}
+
+// ************************************************************************************************
+// Autocomplete
+
+// les colonnes sur lesquelles on propose des suggestions
+#[derive(Debug, Clone, Copy)]
+pub enum SuggestField {
+    PersonName,
+    GenreName,
+    PartitionTitle,
+}
+
+impl SuggestField {
+    // la table, la colonne, et les lignes visibles depuis l'ensemble $4
+    fn source(&self) -> (&'static str, &'static str, Option<&'static str>) {
+        match self {
+            SuggestField::PersonName => ("persons", "full_name", None),
+            SuggestField::GenreName => (
+                "genres",
+                "name",
+                Some("(ensemble_id IS NULL OR ensemble_id = $4)"),
+            ),
+            SuggestField::PartitionTitle => ("partitions", "title", Some("ensemble_id = $4")),
+        }
+    }
+}
+
+// les valeurs qui commencent par le texte tapé viennent d'abord,
+// puis celles qui lui ressemblent (similarité pg_trgm)
+pub fn suggest(
+    c: &PgConnection,
+    ensemble_id: i32,
+    field: SuggestField,
+    text: &str,
+    limit: i64,
+) -> QueryResult<Vec<Suggestion>> {
+    let (table, column, visible) = field.source();
+    let query = format!(
+        "SELECT id, {col} AS label FROM {table}
+         WHERE ({col} ILIKE $1 OR {col} % $2) {visible}
+         ORDER BY ({col} ILIKE $1) DESC, similarity({col}, $2) DESC, {col}
+         LIMIT $3",
+        col = column,
+        table = table,
+        visible = visible.map(|v| format!("AND {}", v)).unwrap_or_default(),
+    );
+    let query = sql_query(query)
+        .bind::<Text, _>(prefix_pattern(text))
+        .bind::<Text, _>(text)
+        .bind::<BigInt, _>(limit);
+    // PostgreSQL refuse un paramètre qui n'apparaît pas dans la requête
+    match visible {
+        Some(_) => query.bind::<Integer, _>(ensemble_id).load::<Suggestion>(c),
+        None => query.load::<Suggestion>(c),
+    }
+}
+
+// ************************************************************************************************
+// Statistics
+
+// les compositeurs sont communs, les partitions et les genres ceux de l'ensemble
+pub fn count_catalogue(c: &PgConnection, ensemble_id: i32) -> QueryResult<(i64, i64, i64)> {
+    let nb_partitions = partitions::table
+        .filter(partitions::ensemble_id.eq(ensemble_id))
+        .count()
+        .get_result(c)?;
+    let nb_persons = persons::table.count().get_result(c)?;
+    let nb_genres = genres::table
+        .filter(
+            genres::ensemble_id
+                .is_null()
+                .or(genres::ensemble_id.eq(ensemble_id)),
+        )
+        .count()
+        .get_result(c)?;
+    Ok((nb_partitions, nb_persons, nb_genres))
+}
+
+pub fn count_partitions_by_genre(
+    c: &PgConnection,
+    ensemble_id: i32,
+) -> QueryResult<Vec<LabelCount>> {
+    sql_query(
+        "SELECT genres.name AS label, count(partitions.id) AS count
+         FROM genres
+         LEFT JOIN partitions
+           ON partitions.genre_id = genres.id AND partitions.ensemble_id = $1
+         WHERE genres.ensemble_id IS NULL OR genres.ensemble_id = $1
+         GROUP BY genres.id, genres.name
+         ORDER BY 2 DESC, 1",
+    )
+    .bind::<Integer, _>(ensemble_id)
+    .load::<LabelCount>(c)
+}
+
+// les compositeurs les plus représentés
+pub fn count_partitions_by_person(
+    c: &PgConnection,
+    ensemble_id: i32,
+    limit: i64,
+) -> QueryResult<Vec<LabelCount>> {
+    persons::table
+        .inner_join(partitions::table)
+        .filter(partitions::ensemble_id.eq(ensemble_id))
+        .group_by((persons::id, persons::full_name))
+        .select((persons::full_name, sql::<BigInt>("count(partitions.id)")))
+        .order((
+            sql::<BigInt>("count(partitions.id)").desc(),
+            persons::full_name,
+        ))
+        .limit(limit)
+        .load::<LabelCount>(c)
+}
+
+// les partitions de l'ensemble les plus prêtées (prêts acceptés, rendus ou non)
+pub fn count_loans_by_partition(
+    c: &PgConnection,
+    ensemble_id: i32,
+    limit: i64,
+) -> QueryResult<Vec<LabelCount>> {
+    sql_query(
+        "SELECT partitions.title AS label, count(*) AS count
+         FROM loans
+         INNER JOIN partitions ON partitions.id = loans.partition_id
+         WHERE partitions.ensemble_id = $1 AND loans.status IN ('accepted', 'returned')
+         GROUP BY partitions.id, partitions.title
+         ORDER BY 2 DESC, 1
+         LIMIT $2",
+    )
+    .bind::<Integer, _>(ensemble_id)
+    .bind::<BigInt, _>(limit)
+    .load::<LabelCount>(c)
+}
+
+pub fn get_recent_partitions(
+    c: &PgConnection,
+    ensemble_id: i32,
+    limit: i64,
+) -> QueryResult<Vec<ShowPartition>> {
+    sql_query(
+        "SELECT partitions.id, partitions.title, persons.full_name, genres.name
+         FROM partitions
+         INNER JOIN persons ON partitions.person_id = persons.id
+         INNER JOIN genres ON partitions.genre_id = genres.id
+         WHERE partitions.ensemble_id = $2
+         ORDER BY partitions.created_at DESC, partitions.id DESC
+         LIMIT $1",
+    )
+    .bind::<BigInt, _>(limit)
+    .bind::<Integer, _>(ensemble_id)
+    .load::<ShowPartition>(c)
+}
+
+// nombre de partitions ajoutées chaque mois ("2021-06"), du premier ajout
+// à ce mois-ci : les mois sans ajout comptent zéro
+pub fn count_partitions_by_month(
+    c: &PgConnection,
+    ensemble_id: i32,
+) -> QueryResult<Vec<LabelCount>> {
+    sql_query(
+        "SELECT to_char(month, 'YYYY-MM') AS label, count(partitions.id) AS count
+         FROM (SELECT date_trunc('month', min(created_at)) AS first,
+                      greatest(date_trunc('month', max(created_at)),
+                               date_trunc('month', now())) AS last
+               FROM partitions
+               WHERE ensemble_id = $1) AS bounds
+         CROSS JOIN generate_series(bounds.first, bounds.last, interval '1 month') AS month
+         LEFT JOIN partitions
+                ON partitions.ensemble_id = $1
+               AND date_trunc('month', partitions.created_at) = month
+         GROUP BY month
+         ORDER BY month",
+    )
+    .bind::<Integer, _>(ensemble_id)
+    .load::<LabelCount>(c)
+}
+
+// ************************************************************************************************
+// Users
+
+pub fn get_user(c: &PgConnection, user_id: i32) -> QueryResult<User> {
+    users::table.find(user_id).first(c)
+}
+
+pub fn get_user_by_email(c: &PgConnection, email: &str) -> QueryResult<User> {
+    users::table.filter(users::email.eq(email)).first(c)
+}
+
+pub fn create_user(c: &PgConnection, user: &User) -> QueryResult<User> {
+    diesel::insert_into(users::table).values(user).get_result(c)
+}
+
+pub fn get_users_by_status(c: &PgConnection, status: &str) -> QueryResult<Vec<User>> {
+    users::table
+        .filter(users::status.eq(status))
+        .order(users::id)
+        .load(c)
+}
+
+pub fn set_user_status(c: &PgConnection, user_id: i32, status: &str) -> QueryResult<User> {
+    diesel::update(users::table.find(user_id))
+        .set(users::status.eq(status))
+        .get_result(c)
+}
+
+pub fn update_profile(
+    c: &PgConnection,
+    user_id: i32,
+    display_name: &str,
+    locale: &str,
+) -> QueryResult<User> {
+    diesel::update(users::table.find(user_id))
+        .set((
+            users::display_name.eq(display_name),
+            users::locale.eq(locale),
+        ))
+        .get_result(c)
+}
+
+// un nouveau mot de passe rend inutiles les liens de réinitialisation en cours
+pub fn update_password(c: &PgConnection, user_id: i32, password_hash: &str) -> QueryResult<User> {
+    c.transaction(|| {
+        sql_query("DELETE FROM user_tokens WHERE user_id = $1 AND purpose = $2")
+            .bind::<Integer, _>(user_id)
+            .bind::<Text, _>(TOKEN_RESET_PASSWORD)
+            .execute(c)?;
+        diesel::update(users::table.find(user_id))
+            .set(users::password_hash.eq(password_hash))
+            .get_result(c)
+    })
+}
+
+// le jeton du lien reçu par mail est consommé avec le changement de mot de passe
+pub fn reset_password(
+    c: &PgConnection,
+    token_hash: &str,
+    password_hash: &str,
+) -> QueryResult<User> {
+    c.transaction(|| {
+        let user = take_user_token(c, TOKEN_RESET_PASSWORD, token_hash)?;
+        update_password(c, user.id.unwrap_or_default(), password_hash)
+    })
+}
+
+pub fn delete_user(c: &PgConnection, user_id: i32) -> QueryResult<usize> {
+    diesel::delete(users::table.find(user_id)).execute(c)
+}
+
+// un compte inscrit en ligne et le jeton du lien de confirmation envoyé par mail
+pub fn create_pending_user(
+    c: &PgConnection,
+    user: &User,
+    token_hash: &str,
+    valid_hours: i32,
+) -> QueryResult<User> {
+    c.transaction(|| {
+        let user = create_user(c, user)?;
+        create_user_token(
+            c,
+            user.id.unwrap_or_default(),
+            TOKEN_VERIFY_EMAIL,
+            token_hash,
+            valid_hours,
+        )?;
+        Ok(user)
+    })
+}
+
+// ************************************************************************************************
+// User tokens
+//
+// les jetons des liens envoyés par mail ; la base n'en garde que le hachage
+
+#[derive(QueryableByName)]
+struct TokenOwner {
+    #[sql_type = "Integer"]
+    user_id: i32,
+}
+
+#[derive(QueryableByName)]
+struct TokenCount {
+    #[sql_type = "BigInt"]
+    count: i64,
+}
+
+pub fn create_user_token(
+    c: &PgConnection,
+    user_id: i32,
+    purpose: &str,
+    token_hash: &str,
+    valid_hours: i32,
+) -> QueryResult<()> {
+    sql_query(
+        "INSERT INTO user_tokens (token_hash, user_id, purpose, expires_at)
+         VALUES ($1, $2, $3, now() + make_interval(hours => $4))",
+    )
+    .bind::<Text, _>(token_hash)
+    .bind::<Integer, _>(user_id)
+    .bind::<Text, _>(purpose)
+    .bind::<Integer, _>(valid_hours)
+    .execute(c)
+    .map(|_| ())
+}
+
+// les jetons de ce compte créés depuis moins de `hours` heures
+// et pas encore utilisés
+pub fn count_recent_tokens(
+    c: &PgConnection,
+    user_id: i32,
+    purpose: &str,
+    hours: i32,
+) -> QueryResult<i64> {
+    sql_query(
+        "SELECT count(*) AS count FROM user_tokens
+         WHERE user_id = $1 AND purpose = $2
+           AND created_at > now() - make_interval(hours => $3)",
+    )
+    .bind::<Integer, _>(user_id)
+    .bind::<Text, _>(purpose)
+    .bind::<Integer, _>(hours)
+    .get_result::<TokenCount>(c)
+    .map(|row| row.count)
+}
+
+// un jeton ne sert qu'une fois : il est supprimé quand il est utilisé.
+// NotFound s'il est inconnu, expiré ou prévu pour autre chose
+pub fn take_user_token(c: &PgConnection, purpose: &str, token_hash: &str) -> QueryResult<User> {
+    c.transaction(|| {
+        sql_query("DELETE FROM user_tokens WHERE expires_at <= now()").execute(c)?;
+        let owner = sql_query(
+            "DELETE FROM user_tokens
+             WHERE token_hash = $1 AND purpose = $2
+             RETURNING user_id",
+        )
+        .bind::<Text, _>(token_hash)
+        .bind::<Text, _>(purpose)
+        .get_result::<TokenOwner>(c)?;
+        get_user(c, owner.user_id)
+    })
+}
+
+// ************************************************************************************************
+// API tokens
+//
+// les jetons personnels de l'API, gardés hachés comme ceux des mails ;
+// ils servent jusqu'à leur expiration (jamais sans date) ou leur révocation,
+// dans l'ensemble choisi à leur création
+
+const API_TOKEN_COLUMNS: &str = "id, user_id, name, scope, ensemble_id,
+    (SELECT name FROM ensembles WHERE ensembles.id = api_tokens.ensemble_id) AS ensemble,
+    to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI') AS created_at,
+    to_char(expires_at AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI') AS expires_at,
+    to_char(last_used_at AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI') AS last_used_at";
+
+pub fn get_api_tokens(c: &PgConnection, user_id: i32) -> QueryResult<Vec<ApiToken>> {
+    sql_query(format!(
+        "SELECT {} FROM api_tokens WHERE user_id = $1 ORDER BY id",
+        API_TOKEN_COLUMNS
+    ))
+    .bind::<Integer, _>(user_id)
+    .load::<ApiToken>(c)
+}
+
+pub fn create_api_token(
+    c: &PgConnection,
+    user_id: i32,
+    ensemble_id: i32,
+    name: &str,
+    scope: &str,
+    token_hash: &str,
+    valid_days: Option<i32>,
+) -> QueryResult<ApiToken> {
+    sql_query(format!(
+        "INSERT INTO api_tokens (user_id, name, scope, token_hash, expires_at, ensemble_id)
+         VALUES ($1, $2, $3, $4, now() + make_interval(days => $5), $6)
+         RETURNING {}",
+        API_TOKEN_COLUMNS
+    ))
+    .bind::<Integer, _>(user_id)
+    .bind::<Text, _>(name)
+    .bind::<Text, _>(scope)
+    .bind::<Text, _>(token_hash)
+    .bind::<Nullable<Integer>, _>(valid_days)
+    .bind::<Integer, _>(ensemble_id)
+    .get_result::<ApiToken>(c)
+}
+
+// un membre ne révoque que ses propres jetons
+pub fn delete_api_token(c: &PgConnection, user_id: i32, token_id: i32) -> QueryResult<usize> {
+    sql_query("DELETE FROM api_tokens WHERE id = $1 AND user_id = $2")
+        .bind::<Integer, _>(token_id)
+        .bind::<Integer, _>(user_id)
+        .execute(c)
+}
+
+// le jeton présenté à l'API et son propriétaire ; la date de dernière
+// utilisation est mise à jour. NotFound s'il est inconnu ou expiré
+pub fn use_api_token(c: &PgConnection, token_hash: &str) -> QueryResult<(ApiToken, User)> {
+    c.transaction(|| {
+        let token = sql_query(format!(
+            "UPDATE api_tokens SET last_used_at = now()
+             WHERE token_hash = $1 AND (expires_at IS NULL OR expires_at > now())
+             RETURNING {}",
+            API_TOKEN_COLUMNS
+        ))
+        .bind::<Text, _>(token_hash)
+        .get_result::<ApiToken>(c)?;
+        let user = get_user(c, token.user_id)?;
+        Ok((token, user))
+    })
+}
+
+// ************************************************************************************************
+// Ensembles
+//
+// un membre travaille dans la bibliothèque d'un des ensembles dont il fait
+// partie ; un administrateur, un visiteur ou un membre qui n'est dans aucun
+// ensemble peut les parcourir tous
+
+pub fn get_ensembles(c: &PgConnection) -> QueryResult<Vec<Ensemble>> {
+    ensembles::table.order(ensembles::name).load(c)
+}
+
+pub fn get_ensemble(c: &PgConnection, ensemble_id: i32) -> QueryResult<Ensemble> {
+    ensembles::table.find(ensemble_id).first(c)
+}
+
+pub fn get_ensemble_by_name(c: &PgConnection, name: &str) -> QueryResult<Ensemble> {
+    ensembles::table
+        .filter(lower(ensembles::name).eq(name.trim().to_lowercase()))
+        .first(c)
+}
+
+// le premier ensemble créé (celui de la migration) : l'outil en ligne de
+// commande et la restauration d'une ancienne sauvegarde y mettent les partitions
+pub fn get_default_ensemble(c: &PgConnection) -> QueryResult<Ensemble> {
+    ensembles::table.order(ensembles::id).first(c)
+}
+
+pub fn create_ensemble(c: &PgConnection, name: &str) -> QueryResult<Ensemble> {
+    diesel::insert_into(ensembles::table)
+        .values(&Ensemble {
+            id: None,
+            name: name.split_whitespace().collect::<Vec<_>>().join(" "),
+        })
+        .get_result(c)
+}
+
+pub fn get_user_ensembles(c: &PgConnection, user_id: i32) -> QueryResult<Vec<Ensemble>> {
+    ensembles::table
+        .inner_join(ensemble_members::table)
+        .filter(ensemble_members::user_id.eq(user_id))
+        .select((ensembles::id, ensembles::name))
+        .order(ensembles::name)
+        .load(c)
+}
+
+// les ensembles qu'on peut choisir : les siens, tous pour un administrateur
+// (en lecture seule sans en être membre, voir ensemble::Member). Sans compte
+// ou sans ensemble, seulement l'ensemble par défaut, la bibliothèque publique :
+// les autres ne montrent que leurs partitions partagées (loans.rs).
+pub fn get_available_ensembles(
+    c: &PgConnection,
+    user: Option<&User>,
+) -> QueryResult<Vec<Ensemble>> {
+    let ensembles = match user {
+        Some(user) if user.is_admin => return get_ensembles(c),
+        Some(user) => get_user_ensembles(c, user.id.unwrap_or_default())?,
+        None => vec![],
+    };
+    if ensembles.is_empty() {
+        Ok(vec![get_default_ensemble(c)?])
+    } else {
+        Ok(ensembles)
+    }
+}
+
+// l'ensemble choisi s'il est permis, sinon le plus ancien des ensembles permis
+pub fn choose_ensemble(
+    c: &PgConnection,
+    user: Option<&User>,
+    chosen: Option<i32>,
+) -> QueryResult<Ensemble> {
+    let mut ensembles = get_available_ensembles(c, user)?;
+    if let Some(ensemble) = ensembles
+        .iter()
+        .find(|e| chosen.is_some() && e.id == chosen)
+    {
+        return Ok(ensemble.clone());
+    }
+    ensembles.sort_by_key(|e| e.id);
+    ensembles
+        .into_iter()
+        .next()
+        .ok_or(diesel::result::Error::NotFound)
+}
+
+// seuls les membres modifient la bibliothèque d'un ensemble (voir ensemble.rs),
+// les administrateurs compris
+pub fn is_ensemble_member(c: &PgConnection, ensemble_id: i32, user_id: i32) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(
+        ensemble_members::table
+            .filter(ensemble_members::ensemble_id.eq(ensemble_id))
+            .filter(ensemble_members::user_id.eq(user_id)),
+    ))
+    .get_result(c)
+}
+
+pub fn get_ensemble_members(c: &PgConnection, ensemble_id: i32) -> QueryResult<Vec<User>> {
+    users::table
+        .inner_join(ensemble_members::table)
+        .filter(ensemble_members::ensemble_id.eq(ensemble_id))
+        .select(users::all_columns)
+        .order(users::email)
+        .load(c)
+}
+
+// renvoie 0 si le compte était déjà membre
+pub fn add_ensemble_member(c: &PgConnection, ensemble_id: i32, user_id: i32) -> QueryResult<usize> {
+    diesel::insert_into(ensemble_members::table)
+        .values((
+            ensemble_members::ensemble_id.eq(ensemble_id),
+            ensemble_members::user_id.eq(user_id),
+        ))
+        .on_conflict_do_nothing()
+        .execute(c)
+}
+
+pub fn remove_ensemble_member(
+    c: &PgConnection,
+    ensemble_id: i32,
+    user_id: i32,
+) -> QueryResult<usize> {
+    diesel::delete(
+        ensemble_members::table
+            .filter(ensemble_members::ensemble_id.eq(ensemble_id))
+            .filter(ensemble_members::user_id.eq(user_id)),
+    )
+    .execute(c)
+}
+
+// ************************************************************************************************
+// Sharing and loans
+//
+// une bibliothèque montre aux autres ensembles ses partitions marquées
+// `shared` (colonne lue ici seulement, voir schema.rs) ; un autre ensemble
+// demande à en emprunter une, la bibliothèque qui la possède accepte ou
+// refuse la demande, puis note le retour de la partition.
+
+pub fn share_partitions(
+    c: &PgConnection,
+    ensemble_id: i32,
+    ids: &[i32],
+    shared: bool,
+) -> QueryResult<usize> {
+    diesel::update(
+        partitions::table
+            .filter(partitions::ensemble_id.eq(ensemble_id))
+            .filter(partitions::id.eq_any(ids)),
+    )
+    .set(partitions::shared.eq(shared))
+    .execute(c)
+}
+
+pub fn is_partition_shared(c: &PgConnection, partition_id: i32) -> QueryResult<bool> {
+    partitions::table
+        .find(partition_id)
+        .select(partitions::shared)
+        .first(c)
+}
+
+// les autres ensembles qui partagent au moins une partition
+pub fn get_shared_libraries(c: &PgConnection, ensemble_id: i32) -> QueryResult<Vec<SharedLibrary>> {
+    sql_query(
+        "SELECT ensembles.id, ensembles.name, count(partitions.id) AS count
+         FROM ensembles
+         JOIN partitions ON partitions.ensemble_id = ensembles.id AND partitions.shared
+         WHERE ensembles.id <> $1
+         GROUP BY ensembles.id, ensembles.name
+         ORDER BY ensembles.name",
+    )
+    .bind::<Integer, _>(ensemble_id)
+    .load::<SharedLibrary>(c)
+}
+
+// les partitions que `owner_id` partage, vues depuis la bibliothèque `ensemble_id`
+pub fn get_shared_partitions(
+    c: &PgConnection,
+    owner_id: i32,
+    ensemble_id: i32,
+) -> QueryResult<Vec<SharedPartition>> {
+    sql_query(
+        "SELECT partitions.id, partitions.title, persons.full_name, genres.name,
+                (SELECT loans.status FROM loans
+                 WHERE loans.partition_id = partitions.id AND loans.ensemble_id = $2
+                   AND loans.status IN ('pending', 'accepted')) AS loan
+         FROM partitions
+         JOIN persons ON persons.id = partitions.person_id
+         JOIN genres ON genres.id = partitions.genre_id
+         WHERE partitions.ensemble_id = $1 AND partitions.ensemble_id <> $2
+           AND partitions.shared
+         ORDER BY partitions.title",
+    )
+    .bind::<Integer, _>(owner_id)
+    .bind::<Integer, _>(ensemble_id)
+    .load::<SharedPartition>(c)
+}
+
+// les colonnes de models::Loan, pour une requête sur `loans l`
+const LOAN_COLUMNS: &str = "l.id, l.partition_id, p.title, pe.full_name,
+    p.ensemble_id AS owner_id, o.name AS owner, l.ensemble_id, e.name AS ensemble,
+    u.email AS requested_by, l.message, l.status,
+    to_char(l.created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI') AS created_at,
+    to_char(l.decided_at AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI') AS decided_at";
+
+const LOAN_JOINS: &str = "JOIN partitions p ON p.id = l.partition_id
+    JOIN persons pe ON pe.id = p.person_id
+    JOIN ensembles o ON o.id = p.ensemble_id
+    JOIN ensembles e ON e.id = l.ensemble_id
+    LEFT JOIN users u ON u.id = l.requested_by";
+
+#[derive(QueryableByName)]
+struct LoanCount {
+    #[sql_type = "BigInt"]
+    count: i64,
+}
+
+// la demande de la bibliothèque `ensemble_id` ; NotFound si la partition
+// n'est pas partagée ou si elle est déjà dans cette bibliothèque, une
+// violation d'unicité s'il y a déjà une demande en cours
+pub fn request_loan(
+    c: &PgConnection,
+    ensemble_id: i32,
+    user_id: i32,
+    partition_id: i32,
+    message: &str,
+) -> QueryResult<Loan> {
+    sql_query(format!(
+        "WITH l AS (
+             INSERT INTO loans (partition_id, ensemble_id, requested_by, message)
+             SELECT id, $2, $3, $4 FROM partitions
+             WHERE id = $1 AND shared AND ensemble_id <> $2
+             RETURNING *)
+         SELECT {} FROM l {}",
+        LOAN_COLUMNS, LOAN_JOINS
+    ))
+    .bind::<Integer, _>(partition_id)
+    .bind::<Integer, _>(ensemble_id)
+    .bind::<Integer, _>(user_id)
+    .bind::<Text, _>(message)
+    .get_result::<Loan>(c)
+}
+
+// les demandes reçues pour les partitions de la bibliothèque, celles en
+// attente d'abord
+pub fn get_loans_received(c: &PgConnection, ensemble_id: i32) -> QueryResult<Vec<Loan>> {
+    sql_query(format!(
+        "SELECT {} FROM loans l {}
+         WHERE p.ensemble_id = $1
+         ORDER BY l.status <> 'pending', l.id DESC",
+        LOAN_COLUMNS, LOAN_JOINS
+    ))
+    .bind::<Integer, _>(ensemble_id)
+    .load::<Loan>(c)
+}
+
+pub fn get_loans_sent(c: &PgConnection, ensemble_id: i32) -> QueryResult<Vec<Loan>> {
+    sql_query(format!(
+        "SELECT {} FROM loans l {}
+         WHERE l.ensemble_id = $1
+         ORDER BY l.id DESC",
+        LOAN_COLUMNS, LOAN_JOINS
+    ))
+    .bind::<Integer, _>(ensemble_id)
+    .load::<Loan>(c)
+}
+
+// les demandes qui attendent une réponse de la bibliothèque
+pub fn count_pending_loans(c: &PgConnection, ensemble_id: i32) -> QueryResult<i64> {
+    sql_query(
+        "SELECT count(*) AS count FROM loans
+         JOIN partitions ON partitions.id = loans.partition_id
+         WHERE partitions.ensemble_id = $1 AND loans.status = 'pending'",
+    )
+    .bind::<Integer, _>(ensemble_id)
+    .get_result::<LoanCount>(c)
+    .map(|row| row.count)
+}
+
+// seule la bibliothèque qui possède la partition décide ; NotFound si la
+// demande n'est pas la sienne ou n'est pas dans l'état de départ
+pub fn decide_loan(
+    c: &PgConnection,
+    ensemble_id: i32,
+    loan_id: i32,
+    decision: LoanDecision,
+) -> QueryResult<Loan> {
+    let (from, to) = decision.transition();
+    sql_query(format!(
+        "WITH l AS (
+             UPDATE loans SET status = $3, decided_at = now()
+             WHERE id = $1 AND status = $4
+               AND partition_id IN (SELECT id FROM partitions WHERE ensemble_id = $2)
+             RETURNING *)
+         SELECT {} FROM l {}",
+        LOAN_COLUMNS, LOAN_JOINS
+    ))
+    .bind::<Integer, _>(loan_id)
+    .bind::<Integer, _>(ensemble_id)
+    .bind::<Text, _>(to)
+    .bind::<Text, _>(from)
+    .get_result::<Loan>(c)
+}
+
+// ************************************************************************************************
+// Backup and restore
+
+pub fn load_snapshot(c: &PgConnection) -> QueryResult<Snapshot> {
+    c.transaction(|| {
+        let persons = persons::table.order(persons::id).load::<Person>(c)?;
+        let genres = genres::table.order(genres::id).load::<Genre>(c)?;
+        let partitions = sql_query(
+            "SELECT id, person_id, title, genre_id, ensemble_id, shared,
+                    to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"')
+                        AS created_at
+             FROM partitions
+             ORDER BY id",
+        )
+        .load::<PartitionRecord>(c)?;
+        let users = users::table.order(users::id).load::<User>(c)?;
+        let tags = tags::table.order(tags::id).load::<Tag>(c)?;
+        let partition_tags = partition_tags::table
+            .order((partition_tags::partition_id, partition_tags::tag_id))
+            .load::<(i32, i32)>(c)?;
+        let ensembles = ensembles::table.order(ensembles::id).load::<Ensemble>(c)?;
+        let ensemble_members = ensemble_members::table
+            .order((ensemble_members::ensemble_id, ensemble_members::user_id))
+            .load::<(i32, i32)>(c)?;
+        let partition_details = partition_details::table
+            .order(partition_details::partition_id)
+            .load::<PartitionDetails>(c)?;
+        let partition_creators = partition_creators::table
+            .order((
+                partition_creators::partition_id,
+                partition_creators::person_id,
+                partition_creators::role,
+            ))
+            .load::<(i32, i32, String)>(c)?;
+        let partition_files = sql_query(
+            "SELECT partition_id, name, path, content_type,
+                    to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"')
+                        AS created_at
+             FROM partition_files
+             ORDER BY id",
+        )
+        .load::<PartitionFileRecord>(c)?;
+        let loans = sql_query(
+            "SELECT partition_id, ensemble_id, requested_by, message, status,
+                    to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"')
+                        AS created_at,
+                    to_char(decided_at AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"')
+                        AS decided_at
+             FROM loans
+             ORDER BY id",
+        )
+        .load::<LoanRecord>(c)?;
+        let webhooks = sql_query(
+            "SELECT ensemble_id, url, secret, events::text[] AS events, active,
+                    to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"')
+                        AS created_at
+             FROM webhooks
+             ORDER BY id",
+        )
+        .load::<WebhookRecord>(c)?;
+
+        Ok(Snapshot {
+            persons: persons.into_iter().map(Into::into).collect(),
+            genres: genres.into_iter().map(Into::into).collect(),
+            partitions,
+            users: users.into_iter().map(Into::into).collect(),
+            tags: tags.into_iter().map(Into::into).collect(),
+            partition_tags: partition_tags.into_iter().map(Into::into).collect(),
+            ensembles: ensembles.into_iter().map(Into::into).collect(),
+            ensemble_members: ensemble_members.into_iter().map(Into::into).collect(),
+            partition_details: partition_details.into_iter().map(Into::into).collect(),
+            partition_creators: partition_creators.into_iter().map(Into::into).collect(),
+            partition_files,
+            loans,
+            webhooks,
+        })
+    })
+}
+
+// restaure une archive : les lignes et les fichiers dans la même transaction.
+// Si un fichier ne peut être écrit, rien n'est restauré et les fichiers déjà
+// écrits (tous nouveaux : backup::restore_files ne remplace rien) sont retirés.
+pub fn restore_archive(
+    c: &PgConnection,
+    archive: Archive,
+    upload_dir: &Path,
+) -> Result<RestoreReport, BackupError> {
+    let mut written = vec![];
+    let restored = c.transaction::<_, BackupError, _>(|| {
+        let mut report = restore_snapshot(c, archive.snapshot)?;
+        report.files = backup::restore_files(&archive.files, upload_dir, &mut written)?;
+        Ok(report)
+    });
+    if restored.is_err() {
+        backup::remove_files(&written);
+    }
+    restored
+}
+
+// restaure une sauvegarde dans une seule transaction.
+// Les personnes et genres déjà présents (même clé de nom), les partitions
+// identiques (même titre, compositeur et genre) et les utilisateurs de même
+// adresse sont gardés ; les autres lignes sont ajoutées avec de nouveaux id
+// et les références de l'archive sont traduites vers ces id.
+// Les ensembles sont retrouvés par leur nom ; les partitions d'une archive
+// sans ensembles vont dans l'ensemble par défaut.
+pub fn restore_snapshot(c: &PgConnection, snapshot: Snapshot) -> QueryResult<RestoreReport> {
+    c.transaction(|| {
+        let mut report = RestoreReport::default();
+
+        let mut ensemble_ids = HashMap::new();
+        for record in snapshot.ensembles {
+            let ensemble = match get_ensemble_by_name(c, &record.name) {
+                Err(diesel::result::Error::NotFound) => create_ensemble(c, &record.name)?,
+                ensemble => ensemble?,
+            };
+            ensemble_ids.insert(record.id, ensemble.id.unwrap_or_default());
+        }
+        let default_ensemble_id = get_default_ensemble(c)?.id.unwrap_or_default();
+        // None : pas d'ensemble dans l'archive ; Err : une référence absente
+        let ensemble_of = |record_id: Option<i32>| match record_id {
+            None => Ok(None),
+            Some(id) => ensemble_ids
+                .get(&id)
+                .map(|&id| Some(id))
+                .ok_or(diesel::result::Error::RollbackTransaction),
+        };
+
+        let mut person_ids = HashMap::new();
+        for record in snapshot.persons {
+            let person = Person {
+                id: None,
+                full_name: record.full_name,
+                full_name_key: String::new(),
+            }
+            .normalized();
+            let existing = persons::table
+                .filter(full_name_key.eq(&person.full_name_key))
+                .select(persons::id)
+                .first::<Option<i32>>(c)
+                .optional()?;
+            let id = match existing {
+                Some(id) => {
+                    report.persons_existing += 1;
+                    id
+                }
+                None => {
+                    report.persons_added += 1;
+                    diesel::insert_into(persons::table)
+                        .values(&person)
+                        .returning(persons::id)
+                        .get_result::<Option<i32>>(c)?
+                }
+            };
+            person_ids.insert(record.id, id);
+        }
+
+        let mut genre_ids = HashMap::new();
+        for record in snapshot.genres {
+            let genre = Genre {
+                id: None,
+                name: record.name,
+                name_key: String::new(),
+                ensemble_id: ensemble_of(record.ensemble_id)?,
+            }
+            .normalized();
+            let existing = genres::table
+                .filter(genre_key.eq(&genre.name_key))
+                .filter(genres::ensemble_id.is_not_distinct_from(genre.ensemble_id))
+                .select(genres::id)
+                .first::<Option<i32>>(c)
+                .optional()?;
+            let id = match existing {
+                Some(id) => {
+                    report.genres_existing += 1;
+                    id
+                }
+                None => {
+                    report.genres_added += 1;
+                    diesel::insert_into(genres::table)
+                        .values(&genre)
+                        .returning(genres::id)
+                        .get_result::<Option<i32>>(c)?
+                }
+            };
+            genre_ids.insert(record.id, id);
+        }
+
+        let mut partition_ids = HashMap::new();
+        for record in snapshot.partitions {
+            // une référence absente : l'archive est incohérente, on annule tout
+            let (person_id, genre_id) = match (
+                person_ids.get(&record.person_id).copied().flatten(),
+                genre_ids.get(&record.genre_id).copied().flatten(),
+            ) {
+                (Some(person_id), Some(genre_id)) => (person_id, genre_id),
+                _ => return Err(diesel::result::Error::RollbackTransaction),
+            };
+            let ensemble_id = ensemble_of(record.ensemble_id)?.unwrap_or(default_ensemble_id);
+            if partition_exists(c, ensemble_id, &record.title, person_id)? {
+                report.partitions_existing += 1;
+            } else {
+                sql_query(
+                    "INSERT INTO partitions
+                         (person_id, title, genre_id, created_at, ensemble_id, shared)
+                     VALUES ($1, $2, $3, COALESCE($4::timestamptz, now()), $5, $6)",
+                )
+                .bind::<Integer, _>(person_id)
+                .bind::<Text, _>(&record.title)
+                .bind::<Integer, _>(genre_id)
+                .bind::<Nullable<Text>, _>(record.created_at)
+                .bind::<Integer, _>(ensemble_id)
+                .bind::<Bool, _>(record.shared)
+                .execute(c)?;
+                report.partitions_added += 1;
+            }
+            let partition = find_partition(c, ensemble_id, &record.title, person_id)?;
+            partition_ids.insert(record.id, partition.id);
+        }
+
+        // les étiquettes sont reprises par nom, puis reposées sur les partitions
+        let mut tag_ids = HashMap::new();
+        for record in snapshot.tags {
+            let tag = get_or_create_tag(c, &record.name)?;
+            tag_ids.insert(record.id, tag.id);
+        }
+        for record in snapshot.partition_tags {
+            match (
+                partition_ids.get(&record.partition_id).copied().flatten(),
+                tag_ids.get(&record.tag_id).copied().flatten(),
+            ) {
+                (Some(partition_id), Some(tag_id)) => {
+                    tag_partitions(c, tag_id, &[partition_id])?;
+                }
+                _ => return Err(diesel::result::Error::RollbackTransaction),
+            }
+        }
+
+        // ce qu'apporte un fichier MusicXML, et l'incipit : déjà là sur une
+        // partition existante, on le garde
+        for record in snapshot.partition_details {
+            let partition_id = partition_ids
+                .get(&record.partition_id)
+                .copied()
+                .flatten()
+                .ok_or(diesel::result::Error::RollbackTransaction)?;
+            diesel::insert_into(partition_details::table)
+                .values(&PartitionDetails {
+                    partition_id,
+                    instrumentation: record.instrumentation,
+                    key_signature: record.key_signature,
+                    time_signature: record.time_signature,
+                    incipit_key: melody_key(&record.incipit),
+                    incipit: record.incipit,
+                })
+                .on_conflict_do_nothing()
+                .execute(c)?;
+        }
+        for record in snapshot.partition_creators {
+            match (
+                partition_ids.get(&record.partition_id).copied().flatten(),
+                person_ids.get(&record.person_id).copied().flatten(),
+            ) {
+                (Some(partition_id), Some(person_id)) => {
+                    add_partition_creator(c, partition_id, person_id, &record.role)?;
+                }
+                _ => return Err(diesel::result::Error::RollbackTransaction),
+            }
+        }
+        for record in snapshot.partition_files {
+            let partition_id = partition_ids
+                .get(&record.partition_id)
+                .copied()
+                .flatten()
+                .ok_or(diesel::result::Error::RollbackTransaction)?;
+            sql_query(
+                "INSERT INTO partition_files (partition_id, name, path, content_type, created_at)
+                 VALUES ($1, $2, $3, $4, COALESCE($5::timestamptz, now()))
+                 ON CONFLICT (path) DO NOTHING",
+            )
+            .bind::<Integer, _>(partition_id)
+            .bind::<Text, _>(&record.name)
+            .bind::<Text, _>(&record.path)
+            .bind::<Text, _>(&record.content_type)
+            .bind::<Nullable<Text>, _>(record.created_at)
+            .execute(c)?;
+        }
+
+        let mut user_ids = HashMap::new();
+        for record in snapshot.users {
+            let existing = users::table
+                .filter(users::email.eq(&record.email))
+                .select(users::id)
+                .first::<Option<i32>>(c)
+                .optional()?;
+            if let Some(id) = existing {
+                report.users_existing += 1;
+                user_ids.insert(record.id, id);
+                continue;
+            }
+            let record_id = record.id;
+            let user = User {
+                id: None,
+                email: record.email,
+                password_hash: record.password_hash,
+                is_admin: record.is_admin,
+                status: record.status,
+                display_name: record.display_name,
+                locale: record.locale,
+            };
+            let id = diesel::insert_into(users::table)
+                .values(&user)
+                .returning(users::id)
+                .get_result::<Option<i32>>(c)?;
+            user_ids.insert(record_id, id);
+            report.users_added += 1;
+        }
+
+        for record in snapshot.ensemble_members {
+            match (
+                ensemble_ids.get(&record.ensemble_id),
+                user_ids.get(&record.user_id).copied().flatten(),
+            ) {
+                (Some(&ensemble_id), Some(user_id)) => {
+                    add_ensemble_member(c, ensemble_id, user_id)?;
+                }
+                _ => return Err(diesel::result::Error::RollbackTransaction),
+            }
+        }
+
+        // une demande déjà là (même partition, même ensemble, même date à la
+        // seconde près, la précision de l'archive) est
+        // gardée ; une demande en cours en double n'est pas reprise
+        // (loans_open_unique)
+        for record in snapshot.loans {
+            let (partition_id, ensemble_id) = match (
+                partition_ids.get(&record.partition_id).copied().flatten(),
+                ensemble_ids.get(&record.ensemble_id),
+            ) {
+                (Some(partition_id), Some(&ensemble_id)) => (partition_id, ensemble_id),
+                _ => return Err(diesel::result::Error::RollbackTransaction),
+            };
+            // le demandeur peut avoir été supprimé depuis
+            let requested_by = match record.requested_by {
+                None => None,
+                Some(id) => Some(
+                    user_ids
+                        .get(&id)
+                        .copied()
+                        .flatten()
+                        .ok_or(diesel::result::Error::RollbackTransaction)?,
+                ),
+            };
+            sql_query(
+                "INSERT INTO loans
+                     (partition_id, ensemble_id, requested_by, message, status,
+                      created_at, decided_at)
+                 SELECT $1, $2, $3, $4, $5, COALESCE($6::timestamptz, now()), $7::timestamptz
+                 WHERE NOT EXISTS (
+                     SELECT 1 FROM loans
+                     WHERE partition_id = $1 AND ensemble_id = $2
+                       AND date_trunc('second', created_at) = $6::timestamptz)
+                 ON CONFLICT DO NOTHING",
+            )
+            .bind::<Integer, _>(partition_id)
+            .bind::<Integer, _>(ensemble_id)
+            .bind::<Nullable<Integer>, _>(requested_by)
+            .bind::<Text, _>(&record.message)
+            .bind::<Text, _>(&record.status)
+            .bind::<Nullable<Text>, _>(record.created_at)
+            .bind::<Nullable<Text>, _>(record.decided_at)
+            .execute(c)?;
+        }
+
+        // un abonnement de même adresse dans le même ensemble est gardé
+        for record in snapshot.webhooks {
+            let ensemble_id = *ensemble_ids
+                .get(&record.ensemble_id)
+                .ok_or(diesel::result::Error::RollbackTransaction)?;
+            sql_query(
+                "INSERT INTO webhooks (ensemble_id, url, secret, events, active, created_at)
+                 SELECT $1, $2, $3, $4, $5, COALESCE($6::timestamptz, now())
+                 WHERE NOT EXISTS (SELECT 1 FROM webhooks WHERE ensemble_id = $1 AND url = $2)",
+            )
+            .bind::<Integer, _>(ensemble_id)
+            .bind::<Text, _>(&record.url)
+            .bind::<Text, _>(&record.secret)
+            .bind::<Array<Text>, _>(&record.events)
+            .bind::<Bool, _>(record.active)
+            .bind::<Nullable<Text>, _>(record.created_at)
+            .execute(c)?;
+        }
+
+        Ok(report)
+    })
+}
+
+// ************************************************************************************************
+// Search keys and indexes
+
+// recalcule les clés de recherche des noms (models::name_key) ;
+// `only_missing` : seulement les lignes qui n'en ont pas encore
+// (lignes d'avant la migration, ou ajoutées hors de l'application).
+// Une ligne dont la nouvelle clé est déjà prise garde l'ancienne : elle est
+// rendue dans `duplicates`, rien n'est fusionné ni supprimé ici (voir
+// merge_duplicates).
+pub fn update_name_keys(c: &PgConnection, only_missing: bool) -> QueryResult<NameKeys> {
+    c.transaction(|| name_keys(c, only_missing, false))
+}
+
+// fusionne chaque doublon dans la ligne qui porte sa clé (merge_person,
+// merge_genre) ; sans `apply`, tout est annulé et le rapport dit seulement
+// ce qui serait fait
+pub fn merge_duplicates(c: &PgConnection, apply: bool) -> QueryResult<NameKeys> {
+    let mut report = NameKeys::default();
+    let result = c.transaction(|| {
+        report = name_keys(c, false, true)?;
+        if apply {
+            Ok(())
+        } else {
+            Err(diesel::result::Error::RollbackTransaction)
+        }
+    });
+    match result {
+        Ok(()) | Err(diesel::result::Error::RollbackTransaction) => Ok(report),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(QueryableByName)]
+struct MergeCount {
+    #[sql_type = "BigInt"]
+    moved: i64,
+    #[sql_type = "BigInt"]
+    deleted: i64,
+}
+
+fn name_keys(c: &PgConnection, only_missing: bool, merge: bool) -> QueryResult<NameKeys> {
+    let mut report = NameKeys::default();
+
+    let mut persons_query = persons::table.order(persons::id).into_boxed();
+    if only_missing {
+        persons_query = persons_query.filter(full_name_key.eq(""));
+    }
+    for person in persons_query.load::<Person>(c)? {
+        let key = name_key(&person.full_name);
+        if key == person.full_name_key {
+            continue;
+        }
+        let taken = persons::table
+            .filter(full_name_key.eq(&key))
+            .filter(persons::id.ne(person.id))
+            .first::<Person>(c)
+            .optional()?;
+        let (from_id, other) = match taken {
+            Some(other) => (person.id.unwrap_or_default(), other),
+            None => {
+                diesel::update(persons::table.find(person.id))
+                    .set(full_name_key.eq(key))
+                    .execute(c)?;
+                report.updated += 1;
+                continue;
+            }
+        };
+        let into_id = other.id.unwrap_or_default();
+        // les partitions de même titre dans le même ensemble sont supprimées
+        let count = sql_query(
+            "SELECT count(*) FILTER (WHERE NOT d.found) AS moved,
+                    count(*) FILTER (WHERE d.found) AS deleted
+             FROM (SELECT EXISTS (
+                       SELECT 1 FROM partitions b
+                       WHERE b.person_id = $2 AND lower(a.title) = lower(b.title)
+                         AND a.ensemble_id = b.ensemble_id) AS found
+                   FROM partitions a WHERE a.person_id = $1) d",
+        )
+        .bind::<Integer, _>(from_id)
+        .bind::<Integer, _>(into_id)
+        .get_result::<MergeCount>(c)?;
+        report.duplicates.push(Duplicate {
+            kind: "person",
+            id: from_id,
+            name: person.full_name,
+            into_id,
+            into_name: other.full_name,
+            partitions_moved: count.moved,
+            partitions_deleted: count.deleted,
+        });
+        if merge {
+            merge_person(c, from_id, into_id)?;
+        }
+    }
+
+    let mut genres_query = genres::table.order(genres::id).into_boxed();
+    if only_missing {
+        genres_query = genres_query.filter(genre_key.eq(""));
+    }
+    for genre in genres_query.load::<Genre>(c)? {
+        let key = name_key(&genre.name);
+        if key == genre.name_key {
+            continue;
+        }
+        let taken = genres::table
+            .filter(genre_key.eq(&key))
+            .filter(genres::ensemble_id.is_not_distinct_from(genre.ensemble_id))
+            .filter(genres::id.ne(genre.id))
+            .first::<Genre>(c)
+            .optional()?;
+        let (from_id, other) = match taken {
+            Some(other) => (genre.id.unwrap_or_default(), other),
+            None => {
+                diesel::update(genres::table.find(genre.id))
+                    .set(genre_key.eq(key))
+                    .execute(c)?;
+                report.updated += 1;
+                continue;
+            }
+        };
+        let partitions_moved = partitions::table
+            .filter(partitions::genre_id.eq(from_id))
+            .count()
+            .get_result::<i64>(c)?;
+        let into_id = other.id.unwrap_or_default();
+        report.duplicates.push(Duplicate {
+            kind: "genre",
+            id: from_id,
+            name: genre.name,
+            into_id,
+            into_name: other.name,
+            partitions_moved,
+            partitions_deleted: 0,
+        });
+        if merge {
+            merge_genre(c, from_id, into_id)?;
+        }
+    }
+
+    Ok(report)
+}
+
+// reconstruit les index des tables du catalogue (trigrammes compris)
+pub fn reindex_search(c: &PgConnection) -> QueryResult<()> {
+    for table in ["persons", "genres", "partitions"].iter() {
+        sql_query(format!("REINDEX TABLE {}", table)).execute(c)?;
+    }
+    Ok(())
+}
+
+// ************************************************************************************************
+// Background jobs
+//
+// la file des tâches de fond (voir jobs.rs) ; un ensemble voit ses tâches,
+// les administrateurs aussi celles de l'administration (ensemble_id NULL).
+
+const JOB_COLUMNS: &str = "id, kind, ensemble_id, payload::text AS payload, status, attempts,
+    max_attempts, error,
+    to_char(run_at AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS') AS run_at,
+    to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS') AS created_at,
+    to_char(finished_at AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS') AS finished_at";
+
+pub fn enqueue_job(
+    c: &PgConnection,
+    kind: &str,
+    ensemble_id: Option<i32>,
+    payload: &str,
+    max_attempts: i32,
+) -> QueryResult<Job> {
+    sql_query(format!(
+        "INSERT INTO jobs (kind, ensemble_id, payload, max_attempts)
+         VALUES ($1, $2, $3::jsonb, $4)
+         RETURNING {}",
+        JOB_COLUMNS
+    ))
+    .bind::<Text, _>(kind)
+    .bind::<Nullable<Integer>, _>(ensemble_id)
+    .bind::<Text, _>(payload)
+    .bind::<Integer, _>(max_attempts)
+    .get_result::<Job>(c)
+}
+
+// la prochaine tâche à faire, marquée en cours ; SKIP LOCKED : plusieurs
+// instances de l'application ne prennent jamais la même
+pub fn claim_job(c: &PgConnection) -> QueryResult<Option<Job>> {
+    sql_query(format!(
+        "UPDATE jobs SET status = '{}', attempts = attempts + 1
+         WHERE id = (SELECT id FROM jobs
+                     WHERE status = '{}' AND run_at <= now()
+                     ORDER BY run_at, id
+                     LIMIT 1 FOR UPDATE SKIP LOCKED)
+         RETURNING {}",
+        JOB_RUNNING, JOB_PENDING, JOB_COLUMNS
+    ))
+    .get_result::<Job>(c)
+    .optional()
+}
+
+// une tâche annulée pendant qu'elle tournait le reste
+pub fn finish_job(c: &PgConnection, job_id: i32) -> QueryResult<usize> {
+    sql_query(format!(
+        "UPDATE jobs SET status = '{}', error = '', finished_at = now()
+         WHERE id = $1 AND status = '{}'",
+        JOB_DONE, JOB_RUNNING
+    ))
+    .bind::<Integer, _>(job_id)
+    .execute(c)
+}
+
+// après une erreur, la tâche est reprise `retry_delay` secondes plus tard,
+// un délai qui double à chaque essai ; au dernier essai (ou si `give_up`)
+// elle échoue pour de bon
+pub fn fail_job(
+    c: &PgConnection,
+    job_id: i32,
+    error: &str,
+    retry_delay: i32,
+    give_up: bool,
+) -> QueryResult<usize> {
+    sql_query(format!(
+        "UPDATE jobs SET error = $2,
+             status = CASE WHEN $4 OR attempts >= max_attempts THEN '{failed}' ELSE '{pending}' END,
+             finished_at = CASE WHEN $4 OR attempts >= max_attempts THEN now() END,
+             run_at = now() + make_interval(secs => $3 * power(2, attempts - 1))
+         WHERE id = $1 AND status = '{running}'",
+        failed = JOB_FAILED,
+        pending = JOB_PENDING,
+        running = JOB_RUNNING
+    ))
+    .bind::<Integer, _>(job_id)
+    .bind::<Text, _>(error)
+    .bind::<Integer, _>(retry_delay)
+    .bind::<Bool, _>(give_up)
+    .execute(c)
+}
+
+// au démarrage : les tâches interrompues par un arrêt sont reprises
+pub fn requeue_running_jobs(c: &PgConnection) -> QueryResult<usize> {
+    sql_query(format!(
+        "UPDATE jobs SET status = '{}' WHERE status = '{}'",
+        JOB_PENDING, JOB_RUNNING
+    ))
+    .execute(c)
+}
+
+// une tâche en attente n'est plus reprise ; une tâche en cours va jusqu'au
+// bout, mais son résultat n'est pas gardé.
+// `ensemble_id` : les tâches de cet ensemble (None : aucune), `with_admin` :
+// celles de l'administration, sans ensemble (de même pour get_jobs et get_job)
+pub fn cancel_job(
+    c: &PgConnection,
+    ensemble_id: Option<i32>,
+    with_admin: bool,
+    job_id: i32,
+) -> QueryResult<Job> {
+    sql_query(format!(
+        "UPDATE jobs SET status = '{}', finished_at = now()
+         WHERE id = $1 AND status IN ('{}', '{}')
+           AND (ensemble_id = $2 OR ($3 AND ensemble_id IS NULL))
+         RETURNING {}",
+        JOB_CANCELLED, JOB_PENDING, JOB_RUNNING, JOB_COLUMNS
+    ))
+    .bind::<Integer, _>(job_id)
+    .bind::<Nullable<Integer>, _>(ensemble_id)
+    .bind::<Bool, _>(with_admin)
+    .get_result::<Job>(c)
+}
+
+// les dernières tâches, les plus récentes d'abord
+pub fn get_jobs(
+    c: &PgConnection,
+    ensemble_id: Option<i32>,
+    with_admin: bool,
+) -> QueryResult<Vec<Job>> {
+    sql_query(format!(
+        "SELECT {} FROM jobs
+         WHERE ensemble_id = $1 OR ($2 AND ensemble_id IS NULL)
+         ORDER BY id DESC LIMIT 100",
+        JOB_COLUMNS
+    ))
+    .bind::<Nullable<Integer>, _>(ensemble_id)
+    .bind::<Bool, _>(with_admin)
+    .load::<Job>(c)
+}
+
+pub fn get_job(
+    c: &PgConnection,
+    ensemble_id: Option<i32>,
+    with_admin: bool,
+    job_id: i32,
+) -> QueryResult<Job> {
+    sql_query(format!(
+        "SELECT {} FROM jobs
+         WHERE id = $1 AND (ensemble_id = $2 OR ($3 AND ensemble_id IS NULL))",
+        JOB_COLUMNS
+    ))
+    .bind::<Integer, _>(job_id)
+    .bind::<Nullable<Integer>, _>(ensemble_id)
+    .bind::<Bool, _>(with_admin)
+    .get_result::<Job>(c)
+}
+
+// ************************************************************************************************
+// Webhooks
+//
+// les abonnements d'un ensemble aux changements du catalogue, et leurs envois
+// (voir webhooks.rs). Un événement sans ensemble (une personne, un genre
+// commun) est envoyé aux abonnés de tous les ensembles.
+
+const WEBHOOK_COLUMNS: &str = "id, ensemble_id, url, secret, events::text[] AS events, active,
+    to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS') AS created_at";
+
+const DELIVERY_COLUMNS: &str = "id, webhook_id, event, data::text AS data, status, attempts,
+    response_status, response_body, error, job_id,
+    to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS') AS created_at,
+    to_char(delivered_at AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS') AS delivered_at";
+
+pub fn create_webhook(
+    c: &PgConnection,
+    ensemble_id: i32,
+    url: &str,
+    secret: &str,
+    events: &[String],
+    active: bool,
+) -> QueryResult<Webhook> {
+    sql_query(format!(
+        "INSERT INTO webhooks (ensemble_id, url, secret, events, active)
+         VALUES ($1, $2, $3, $4, $5)
+         RETURNING {}",
+        WEBHOOK_COLUMNS
+    ))
+    .bind::<Integer, _>(ensemble_id)
+    .bind::<Text, _>(url)
+    .bind::<Text, _>(secret)
+    .bind::<Array<Text>, _>(events)
+    .bind::<Bool, _>(active)
+    .get_result::<Webhook>(c)
+}
+
+// un secret vide garde l'ancien
+pub fn update_webhook(
+    c: &PgConnection,
+    ensemble_id: i32,
+    webhook_id: i32,
+    url: &str,
+    secret: &str,
+    events: &[String],
+    active: bool,
+) -> QueryResult<Webhook> {
+    sql_query(format!(
+        "UPDATE webhooks SET url = $3, secret = COALESCE(NULLIF($4, ''), secret),
+             events = $5, active = $6
+         WHERE id = $1 AND ensemble_id = $2
+         RETURNING {}",
+        WEBHOOK_COLUMNS
+    ))
+    .bind::<Integer, _>(webhook_id)
+    .bind::<Integer, _>(ensemble_id)
+    .bind::<Text, _>(url)
+    .bind::<Text, _>(secret)
+    .bind::<Array<Text>, _>(events)
+    .bind::<Bool, _>(active)
+    .get_result::<Webhook>(c)
+}
+
+pub fn delete_webhook(c: &PgConnection, ensemble_id: i32, webhook_id: i32) -> QueryResult<usize> {
+    sql_query("DELETE FROM webhooks WHERE id = $1 AND ensemble_id = $2")
+        .bind::<Integer, _>(webhook_id)
+        .bind::<Integer, _>(ensemble_id)
+        .execute(c)
+}
+
+pub fn get_webhooks(c: &PgConnection, ensemble_id: i32) -> QueryResult<Vec<Webhook>> {
+    sql_query(format!(
+        "SELECT {} FROM webhooks WHERE ensemble_id = $1 ORDER BY id",
+        WEBHOOK_COLUMNS
+    ))
+    .bind::<Integer, _>(ensemble_id)
+    .load::<Webhook>(c)
+}
+
+pub fn get_webhook(c: &PgConnection, ensemble_id: i32, webhook_id: i32) -> QueryResult<Webhook> {
+    sql_query(format!(
+        "SELECT {} FROM webhooks WHERE id = $1 AND ensemble_id = $2",
+        WEBHOOK_COLUMNS
+    ))
+    .bind::<Integer, _>(webhook_id)
+    .bind::<Integer, _>(ensemble_id)
+    .get_result::<Webhook>(c)
+}
+
+// un envoi par abonnement actif à l'événement, à confier ensuite aux tâches de fond
+pub fn queue_deliveries(
+    c: &PgConnection,
+    ensemble_id: Option<i32>,
+    event: &str,
+    data: &str,
+) -> QueryResult<Vec<QueuedDelivery>> {
+    sql_query(
+        "WITH queued AS (
+             INSERT INTO webhook_deliveries (webhook_id, event, data)
+             SELECT id, $2, $3::jsonb FROM webhooks
+             WHERE active AND $2 = ANY(events) AND ($1::integer IS NULL OR ensemble_id = $1)
+             RETURNING id, webhook_id)
+         SELECT queued.id, webhooks.ensemble_id
+         FROM queued INNER JOIN webhooks ON webhooks.id = queued.webhook_id
+         ORDER BY queued.id",
+    )
+    .bind::<Nullable<Integer>, _>(ensemble_id)
+    .bind::<Text, _>(event)
+    .bind::<Text, _>(data)
+    .load::<QueuedDelivery>(c)
+}
+
+// l'envoi du bouton de test, quels que soient les événements choisis
+pub fn queue_test_delivery(
+    c: &PgConnection,
+    ensemble_id: i32,
+    webhook_id: i32,
+    event: &str,
+    data: &str,
+) -> QueryResult<QueuedDelivery> {
+    sql_query(
+        "INSERT INTO webhook_deliveries (webhook_id, event, data)
+         SELECT id, $3, $4::jsonb FROM webhooks WHERE id = $1 AND ensemble_id = $2
+         RETURNING id, $2 AS ensemble_id",
+    )
+    .bind::<Integer, _>(webhook_id)
+    .bind::<Integer, _>(ensemble_id)
+    .bind::<Text, _>(event)
+    .bind::<Text, _>(data)
+    .get_result::<QueuedDelivery>(c)
+}
+
+// l'envoi et son abonnement, pour la tâche qui le fait
+pub fn get_delivery_target(
+    c: &PgConnection,
+    delivery_id: i32,
+) -> QueryResult<(WebhookDelivery, Webhook)> {
+    let delivery = sql_query(format!(
+        "SELECT {} FROM webhook_deliveries WHERE id = $1",
+        DELIVERY_COLUMNS
+    ))
+    .bind::<Integer, _>(delivery_id)
+    .get_result::<WebhookDelivery>(c)?;
+    let webhook = sql_query(format!(
+        "SELECT {} FROM webhooks WHERE id = $1",
+        WEBHOOK_COLUMNS
+    ))
+    .bind::<Integer, _>(delivery.webhook_id)
+    .get_result::<Webhook>(c)?;
+    Ok((delivery, webhook))
+}
+
+// le résultat d'un essai
+pub fn record_delivery(
+    c: &PgConnection,
+    delivery_id: i32,
+    job_id: i32,
+    status: &str,
+    response_status: Option<i32>,
+    response_body: &str,
+    error: &str,
+) -> QueryResult<usize> {
+    sql_query(format!(
+        "UPDATE webhook_deliveries SET job_id = $2, status = $3, attempts = attempts + 1,
+             response_status = $4, response_body = $5, error = $6,
+             delivered_at = CASE WHEN $3 = '{}' THEN now() END
+         WHERE id = $1",
+        DELIVERY_DELIVERED
+    ))
+    .bind::<Integer, _>(delivery_id)
+    .bind::<Integer, _>(job_id)
+    .bind::<Text, _>(status)
+    .bind::<Nullable<Integer>, _>(response_status)
+    .bind::<Text, _>(response_body)
+    .bind::<Text, _>(error)
+    .execute(c)
+}
+
+// les derniers envois, les plus récents d'abord
+pub fn get_deliveries(c: &PgConnection, webhook_id: i32) -> QueryResult<Vec<WebhookDelivery>> {
+    sql_query(format!(
+        "SELECT {} FROM webhook_deliveries WHERE webhook_id = $1 ORDER BY id DESC LIMIT 50",
+        DELIVERY_COLUMNS
+    ))
+    .bind::<Integer, _>(webhook_id)
+    .load::<WebhookDelivery>(c)
+}
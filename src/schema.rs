@@ -2,6 +2,7 @@ table! {
     persons (id) {
         id -> Nullable<Integer>,
         full_name -> Varchar,
+        full_name_key -> Varchar,
     }
 }
 
@@ -9,18 +10,107 @@ table! {
     genres (id) {
         id -> Nullable<Integer>,
         name -> Varchar,
+        name_key -> Varchar,
+        ensemble_id -> Nullable<Integer>,
     }
 }
 
+// partitions.created_at (rempli par la base) n'est pas déclarée ici :
+// elle ne sert qu'aux statistiques, lues avec sql_query dans db.rs
 table! {
     partitions (id) {
         id -> Nullable<Integer>,
         person_id -> Integer,
         title -> Varchar,
         genre_id -> Integer,
+        ensemble_id -> Integer,
+        shared -> Bool,
     }
 }
 
-allow_tables_to_appear_in_same_query!(partitions, genres, persons);
+table! {
+    ensembles (id) {
+        id -> Nullable<Integer>,
+        name -> Varchar,
+    }
+}
+
+table! {
+    ensemble_members (ensemble_id, user_id) {
+        ensemble_id -> Integer,
+        user_id -> Integer,
+    }
+}
+
+table! {
+    users (id) {
+        id -> Nullable<Integer>,
+        email -> Varchar,
+        password_hash -> Varchar,
+        is_admin -> Bool,
+        status -> Varchar,
+        display_name -> Varchar,
+        locale -> Varchar,
+    }
+}
+
+// user_tokens (jetons envoyés par mail), api_tokens (jetons de l'API),
+// loans (demandes de prêt) et partition_files (fichiers des partitions)
+// n'ont que des requêtes sql_query, à cause de leurs dates : voir repo.rs
+
+table! {
+    tags (id) {
+        id -> Nullable<Integer>,
+        name -> Varchar,
+        name_key -> Varchar,
+    }
+}
+
+table! {
+    partition_tags (partition_id, tag_id) {
+        partition_id -> Integer,
+        tag_id -> Integer,
+    }
+}
+
+table! {
+    partition_details (partition_id) {
+        partition_id -> Integer,
+        instrumentation -> Varchar,
+        key_signature -> Varchar,
+        time_signature -> Varchar,
+        incipit -> Varchar,
+        incipit_key -> Varchar,
+    }
+}
+
+table! {
+    partition_creators (partition_id, person_id, role) {
+        partition_id -> Integer,
+        person_id -> Integer,
+        role -> Varchar,
+    }
+}
+
+allow_tables_to_appear_in_same_query!(
+    partitions,
+    genres,
+    persons,
+    tags,
+    partition_tags,
+    partition_details,
+    partition_creators,
+    ensembles,
+    ensemble_members,
+    users
+);
 joinable!(partitions -> genres(genre_id));
 joinable!(partitions -> persons(person_id));
+joinable!(partitions -> ensembles(ensemble_id));
+joinable!(ensemble_members -> ensembles(ensemble_id));
+joinable!(ensemble_members -> users(user_id));
+joinable!(partition_tags -> partitions(partition_id));
+joinable!(partition_tags -> tags(tag_id));
+joinable!(partition_details -> partitions(partition_id));
+joinable!(partition_creators -> partitions(partition_id));
+joinable!(partition_creators -> persons(person_id));
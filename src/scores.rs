@@ -0,0 +1,382 @@
+use std::path::Path;
+
+use fluent::FluentArgs;
+
+use rocket::fs::{NamedFile, TempFile};
+use rocket::http::{ContentType, Header};
+use rocket::response::{Flash, Redirect};
+use rocket::tokio::task::spawn_blocking;
+use rocket::State;
+
+use crate::backup::FilesConfig;
+use crate::csrf::CsrfForm;
+use crate::ensemble::Member;
+use crate::handlers::{existing_partition, unknown_reference};
+use crate::i18n::Locale;
+use crate::jobs::JobQueue;
+use crate::models::{Ensemble, NewPartition};
+use crate::musicxml::{self, Score};
+use crate::notification::Notification;
+use crate::thumbnails::{ThumbnailJob, Thumbnails, THUMBNAIL_JOB};
+use crate::webhooks::{self, PARTITION_CREATED};
+use crate::{db, repo, DBPool};
+
+// MusicXML import
+//
+// un fichier MusicXML (.musicxml, .xml ou .mxl compressé) envoyé depuis la
+// liste des partitions crée une partition : le titre et le compositeur lus
+// dans le fichier complètent ceux du formulaire (qui ont la priorité), les
+// autres créateurs (parolier, arrangeur ...) sont liés à la partition, et
+// l'effectif, l'armure et la mesure sont gardés dans ses détails.
+// Le fichier lui-même est rangé dans `upload_dir` (donc sauvegardé avec le
+// reste, voir backup.rs) et se télécharge depuis la page de la partition.
+
+const SCORES_DIR: &str = "scores";
+
+#[derive(FromForm)]
+pub struct ImportForm<'r> {
+    file: TempFile<'r>,
+    // vides : ceux du fichier
+    #[field(default = String::new())]
+    title: String,
+    #[field(default = String::new())]
+    full_name: String,
+    name: String,
+    create_missing: bool,
+}
+
+#[post("/partitions/import", data = "<import_form>")]
+pub async fn import_score(
+    mut import_form: CsrfForm<ImportForm<'_>>,
+    _member: Member,
+    conn: DBPool,
+    ensemble: Ensemble,
+    config: &State<FilesConfig>,
+    queue: &State<JobQueue>,
+    locale: Locale,
+) -> Flash<Redirect> {
+    let file_name = import_form
+        .file
+        .raw_name()
+        .map(|name| name.dangerous_unsafe_unsanitized_raw().as_str().to_string())
+        .unwrap_or_default();
+    let content_type = match content_type_of(&file_name) {
+        Some(content_type) => content_type,
+        None => {
+            let mut args = FluentArgs::new();
+            args.set("name", file_name);
+            return Notification::warning(locale.tr_args("msg-import-not-musicxml", &args))
+                .redirect("/partitions");
+        }
+    };
+
+    // comme pour la restauration (voir admin.rs) : une copie à nous, lue d'un bloc
+    let copy = std::env::temp_dir().join(format!("import-{:016x}", rand::random::<u64>()));
+    if let Err(e) = import_form.file.copy_to(&copy).await {
+        error_!("MusicXML upload error: {}", e);
+        return Notification::error(locale.tr("msg-import-failed")).redirect("/partitions");
+    }
+    let read = spawn_blocking(move || {
+        let bytes = std::fs::read(&copy);
+        let _ = std::fs::remove_file(&copy);
+        let bytes = bytes?;
+        let score = musicxml::read_score(&bytes)?;
+        Ok::<_, musicxml::MusicXmlError>((score, bytes))
+    })
+    .await;
+    let (score, bytes) = match read {
+        Ok(Ok(read)) => read,
+        Ok(Err(e)) => {
+            warn_!("MusicXML file {} not readable: {}", file_name, e);
+            let mut args = FluentArgs::new();
+            args.set("name", file_name);
+            return Notification::warning(locale.tr_args("msg-import-unreadable", &args))
+                .redirect("/partitions");
+        }
+        Err(e) => {
+            error_!("MusicXML read error: {}", e);
+            return Notification::error(locale.tr("msg-import-failed")).redirect("/partitions");
+        }
+    };
+
+    let new_partition = partition_of(&import_form, &score, &file_name);
+    if new_partition.full_name.is_empty() {
+        return Notification::warning(locale.tr("msg-import-no-composer")).redirect("/partitions");
+    }
+    let (title, musician_name, genre_name) = (
+        new_partition.title.clone(),
+        new_partition.full_name.clone(),
+        new_partition.name.clone(),
+    );
+
+    let path = format!(
+        "{}/{:016x}/{}",
+        SCORES_DIR,
+        rand::random::<u64>(),
+        safe_file_name(&file_name)
+    );
+    let imported = db::import_partition(
+        &conn,
+        ensemble.id.unwrap_or_default(),
+        new_partition,
+        score,
+        file_name,
+        path.clone(),
+        content_type.to_string(),
+    )
+    .await;
+    let partition = match imported {
+        Ok((partition, _)) => partition,
+        Err(diesel::result::Error::NotFound) => {
+            return unknown_reference(&conn, &ensemble, &locale, musician_name, genre_name)
+                .await
+                .redirect("/partitions")
+        }
+        Err(e) if repo::is_unique_violation(&e) => {
+            return existing_partition(&conn, &ensemble, &locale, title, musician_name).await
+        }
+        Err(e) => {
+            error_!("DB import_partition error: {}", e);
+            return Notification::error(locale.tr("msg-import-failed")).redirect("/partitions");
+        }
+    };
+    let partition_id = partition.id.unwrap_or_default();
+
+    // la partition existe : sans son fichier, on la retire
+    let target = config.upload_dir.join(&path);
+    let written = spawn_blocking(move || {
+        if let Some(dir) = target.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&target, &bytes)
+    })
+    .await
+    .unwrap_or_else(|e| Err(std::io::Error::new(std::io::ErrorKind::Other, e)));
+    if let Err(e) = written {
+        error_!("MusicXML file {} not written: {}", path, e);
+        if let Err(e) =
+            db::delete_one_partition(&conn, ensemble.id.unwrap_or_default(), partition_id).await
+        {
+            error_!("DB deletion({}) error: {}", partition_id, e);
+        }
+        return Notification::error(locale.tr("msg-import-failed")).redirect("/partitions");
+    }
+
+    webhooks::notify(&conn, queue, ensemble.id, PARTITION_CREATED, &partition).await;
+    let mut args = FluentArgs::new();
+    args.set("title", partition.title);
+    Notification::success(locale.tr_args("msg-import-ok", &args))
+        .redirect(format!("/partitions/{}", partition_id))
+}
+
+// le formulaire d'abord, puis le fichier ; le nom du fichier faute de titre
+fn partition_of(form: &ImportForm<'_>, score: &Score, file_name: &str) -> NewPartition {
+    let title = match (form.title.trim(), &score.title) {
+        ("", Some(title)) => title.clone(),
+        ("", None) => Path::new(file_name)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().trim().to_string())
+            .unwrap_or_default(),
+        (title, _) => title.to_string(),
+    };
+    let full_name = match form.full_name.trim() {
+        "" => score.composer().unwrap_or_default().to_string(),
+        full_name => full_name.to_string(),
+    };
+    NewPartition {
+        title,
+        full_name,
+        name: form.name.trim().to_string(),
+        create_missing: form.create_missing,
+    }
+}
+
+// le type du fichier, d'après son extension
+fn content_type_of(file_name: &str) -> Option<&'static str> {
+    let extension = Path::new(file_name)
+        .extension()?
+        .to_string_lossy()
+        .to_lowercase();
+    match extension.as_str() {
+        "mxl" => Some("application/vnd.recordare.musicxml"),
+        "musicxml" | "xml" => Some("application/vnd.recordare.musicxml+xml"),
+        _ => None,
+    }
+}
+
+// le nom gardé sur le disque : lettres, chiffres et `._-` seulement
+fn safe_file_name(file_name: &str) -> String {
+    let name = file_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "._-".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    match name.trim_start_matches('.') {
+        "" => "score".to_string(),
+        name => name.to_string(),
+    }
+}
+
+// ***********************************************************************************************
+// Files
+//
+// d'autres fichiers (une partition scannée en PDF, des images, un MusicXML)
+// s'ajoutent depuis la page de la partition ; les PDF et les images y ont
+// une vignette, rendue par une tâche de fond (voir thumbnails.rs).
+
+#[derive(FromForm)]
+pub struct FileForm<'r> {
+    file: TempFile<'r>,
+}
+
+// réservé aux membres de la bibliothèque (garde Member)
+#[allow(clippy::too_many_arguments)]
+#[post("/partitions/<id>/files", data = "<file_form>")]
+pub async fn add_file(
+    id: i32,
+    mut file_form: CsrfForm<FileForm<'_>>,
+    _member: Member,
+    conn: DBPool,
+    ensemble: Ensemble,
+    config: &State<FilesConfig>,
+    queue: &State<JobQueue>,
+    locale: Locale,
+) -> Option<Flash<Redirect>> {
+    let back = format!("/partitions/{}", id);
+    let file_name = file_form
+        .file
+        .raw_name()
+        .map(|name| name.dangerous_unsafe_unsanitized_raw().as_str().to_string())
+        .unwrap_or_default();
+    let content_type = match attachment_type_of(&file_name) {
+        Some(content_type) => content_type,
+        None => {
+            let mut args = FluentArgs::new();
+            args.set("name", file_name);
+            return Some(
+                Notification::warning(locale.tr_args("msg-file-not-supported", &args))
+                    .redirect(back),
+            );
+        }
+    };
+
+    let path = format!(
+        "{}/{:016x}/{}",
+        SCORES_DIR,
+        rand::random::<u64>(),
+        safe_file_name(&file_name)
+    );
+    let target = config.upload_dir.join(&path);
+    let written = match target.parent() {
+        Some(dir) => rocket::tokio::fs::create_dir_all(dir).await,
+        None => Ok(()),
+    };
+    let written = match written {
+        Ok(()) => file_form.file.copy_to(&target).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = written {
+        error_!("Partition file {} not written: {}", path, e);
+        return Some(Notification::error(locale.tr("msg-file-failed")).redirect(back));
+    }
+
+    // sans la ligne dans la base, le fichier est retiré
+    let attached = db::attach_partition_file(
+        &conn,
+        ensemble.id.unwrap_or_default(),
+        id,
+        file_name,
+        path,
+        content_type.to_string(),
+    )
+    .await;
+    let file = match attached {
+        Ok(file) => file,
+        Err(e) => {
+            let _ = rocket::tokio::fs::remove_file(&target).await;
+            if e == diesel::result::Error::NotFound {
+                return None;
+            }
+            error_!("DB attach_partition_file({}) error: {}", id, e);
+            return Some(Notification::error(locale.tr("msg-file-failed")).redirect(back));
+        }
+    };
+
+    let mut args = FluentArgs::new();
+    args.set("name", file.name.clone());
+    let notification = Notification::success(locale.tr_args("msg-file-added", &args));
+    if Thumbnails::renderable(&file.content_type) {
+        let job = ThumbnailJob { file_id: file.id };
+        if let Err(e) = queue.push(&conn, THUMBNAIL_JOB, ensemble.id, &job).await {
+            error_!("Thumbnail job for file {} not queued: {}", file.id, e);
+        }
+    }
+    Some(notification.redirect(back))
+}
+
+// les fichiers qui s'ajoutent à une partition : MusicXML, PDF et images
+fn attachment_type_of(file_name: &str) -> Option<&'static str> {
+    if let Some(content_type) = content_type_of(file_name) {
+        return Some(content_type);
+    }
+    let extension = Path::new(file_name)
+        .extension()?
+        .to_string_lossy()
+        .to_lowercase();
+    match extension.as_str() {
+        "pdf" => Some("application/pdf"),
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        "tif" | "tiff" => Some("image/tiff"),
+        _ => None,
+    }
+}
+
+// ***********************************************************************************************
+// Download
+
+#[derive(Responder)]
+pub struct FileDownload(NamedFile, ContentType, Header<'static>);
+
+// un fichier d'une partition de la bibliothèque choisie
+#[get("/partitions/<id>/files/<file_id>")]
+pub async fn download_file(
+    id: i32,
+    file_id: i32,
+    conn: DBPool,
+    ensemble: Ensemble,
+    config: &State<FilesConfig>,
+) -> Option<FileDownload> {
+    let file =
+        match db::get_partition_file(&conn, ensemble.id.unwrap_or_default(), id, file_id).await {
+            Ok(file) => file,
+            Err(e) => {
+                if e != diesel::result::Error::NotFound {
+                    error_!("DB get_partition_file({}) error: {}", file_id, e);
+                }
+                return None;
+            }
+        };
+    let named = match NamedFile::open(config.upload_dir.join(&file.path)).await {
+        Ok(named) => named,
+        Err(e) => {
+            error_!("Partition file {} not readable: {}", file.path, e);
+            return None;
+        }
+    };
+    let content_type =
+        ContentType::parse_flexible(&file.content_type).unwrap_or(ContentType::Binary);
+    let disposition = format!("attachment; filename=\"{}\"", safe_file_name(&file.name));
+    Some(FileDownload(
+        named,
+        content_type,
+        Header::new("Content-Disposition", disposition),
+    ))
+}
@@ -0,0 +1,180 @@
+use fluent::FluentArgs;
+
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::State;
+
+use rocket_dyn_templates::Template;
+
+use crate::auth::{
+    hash_password, hash_token, new_mail_token, normalize_email, password_problem, MIN_PASSWORD_LEN,
+};
+use crate::csrf::{CsrfForm, CsrfToken};
+use crate::i18n::Locale;
+use crate::mailer::{Mail, Outbox};
+use crate::models::{
+    SignupForm, User, STATUS_ACTIVE, STATUS_APPROVAL, STATUS_PENDING, TOKEN_VERIFY_EMAIL,
+};
+use crate::notification::Notification;
+use crate::{db, repo, DBPool};
+
+// Signup
+//
+// un visiteur crée son compte avec son adresse et un mot de passe ; le compte
+// reste en attente jusqu'au clic sur le lien envoyé par mail (voir mailer.rs).
+// Avec `signup_requires_approval = true` (Rocket.toml), un administrateur
+// doit encore l'accepter depuis la page d'administration.
+
+// durée de validité du lien de confirmation
+pub const VERIFY_TOKEN_HOURS: i32 = 48;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SignupConfig {
+    #[serde(default)]
+    pub signup_requires_approval: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct SignupPage {
+    flash: Option<Notification>,
+    title: String,
+    lang: String,
+    csrf_token: String,
+    min_password_len: usize,
+}
+
+#[get("/signup")]
+pub fn signup_page(flash: Option<FlashMessage<'_>>, csrf: CsrfToken, locale: Locale) -> Template {
+    let page = SignupPage {
+        flash: Notification::from_flash(flash),
+        title: locale.tr("title-signup"),
+        lang: locale.lang().to_string(),
+        csrf_token: csrf.value().to_string(),
+        min_password_len: MIN_PASSWORD_LEN,
+    };
+    Template::render("signup", &page)
+}
+
+#[post("/signup", data = "<signup_form>")]
+pub async fn sign_up(
+    signup_form: CsrfForm<SignupForm>,
+    conn: DBPool,
+    outbox: &State<Outbox>,
+    locale: Locale,
+) -> Flash<Redirect> {
+    let form = signup_form.into_inner();
+    let email = normalize_email(&form.email);
+
+    if !is_valid_email(&email) {
+        return Notification::warning(locale.tr("msg-signup-email-invalid")).redirect("/signup");
+    }
+    if let Some(problem) = password_problem(&locale, &form.password, &form.confirm) {
+        return Notification::warning(problem).redirect("/signup");
+    }
+
+    // une adresse déjà inscrite reçoit la même réponse : la page ne dit pas
+    // qui a un compte
+    let check_mail = Notification::info(locale.tr("msg-signup-check-mail")).redirect("/login");
+    if db::get_user_by_email(&conn, email.clone()).await.is_ok() {
+        info_!("Signup for an existing address: {}", email);
+        return check_mail;
+    }
+
+    let failed = |e: &dyn std::fmt::Display| {
+        error_!("Signup error: {}", e);
+        Notification::error(locale.tr("msg-signup-failed")).redirect("/signup")
+    };
+    let password_hash = match hash_password(&form.password) {
+        Ok(hash) => hash,
+        Err(e) => return failed(&e),
+    };
+    let user = User {
+        id: None,
+        email,
+        password_hash,
+        is_admin: false,
+        status: STATUS_PENDING.to_string(),
+        display_name: String::new(),
+        locale: String::new(),
+    };
+    let (token, token_hash) = new_mail_token();
+    let user = match db::create_pending_user(&conn, user, token_hash, VERIFY_TOKEN_HOURS).await {
+        Ok(user) => user,
+        Err(e) if repo::is_unique_violation(&e) => return check_mail,
+        Err(e) => return failed(&e),
+    };
+
+    let mut args = FluentArgs::new();
+    args.set("link", outbox.link(&format!("/signup/verify/{}", token)));
+    args.set("hours", VERIFY_TOKEN_HOURS);
+    let mail = Mail {
+        to: user.email.clone(),
+        subject: locale.tr("mail-verify-subject"),
+        body: locale.tr_args("mail-verify-body", &args),
+    };
+    if let Err(e) = outbox.send(mail).await {
+        // sans le lien, le compte ne pourrait jamais être confirmé :
+        // il est retiré, l'adresse pourra être inscrite à nouveau
+        if let Err(e) = db::delete_user(&conn, user.id.unwrap_or_default()).await {
+            error_!("DB signup cleanup error: {}", e);
+        }
+        return failed(&e);
+    }
+    check_mail
+}
+
+#[get("/signup/verify/<token>")]
+pub async fn verify_email(
+    token: String,
+    conn: DBPool,
+    config: &State<SignupConfig>,
+    locale: Locale,
+) -> Flash<Redirect> {
+    let user = match db::take_user_token(&conn, TOKEN_VERIFY_EMAIL, hash_token(&token)).await {
+        Ok(user) => user,
+        Err(diesel::result::Error::NotFound) => {
+            return Notification::warning(locale.tr("msg-signup-link-invalid")).redirect("/login")
+        }
+        Err(e) => {
+            error_!("DB verify error: {}", e);
+            return Notification::error(locale.tr("msg-signup-failed")).redirect("/login");
+        }
+    };
+    if user.status != STATUS_PENDING {
+        return Notification::info(locale.tr("msg-signup-verified")).redirect("/login");
+    }
+
+    let status = if config.signup_requires_approval {
+        STATUS_APPROVAL
+    } else {
+        STATUS_ACTIVE
+    };
+    match db::set_user_status(&conn, user.id.unwrap_or_default(), status).await {
+        Ok(_) if status == STATUS_APPROVAL => {
+            Notification::info(locale.tr("msg-signup-awaiting-approval")).redirect("/login")
+        }
+        Ok(_) => Notification::success(locale.tr("msg-signup-verified")).redirect("/login"),
+        Err(e) => {
+            error_!("DB verify error: {}", e);
+            Notification::error(locale.tr("msg-signup-failed")).redirect("/login")
+        }
+    }
+}
+
+// une adresse plausible : une partie avant et après @, un point dans le domaine,
+// et rien qui puisse couper un en-tête de mail
+fn is_valid_email(email: &str) -> bool {
+    let (local, domain) = match email.rsplit_once('@') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    !local.is_empty()
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !email
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || "<>,;\"".contains(c))
+}
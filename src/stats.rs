@@ -0,0 +1,207 @@
+use rocket::serde::Serialize;
+
+use rocket_sync_db_pools::diesel::QueryResult;
+
+use crate::db;
+use crate::models::{LabelCount, ShowPartition};
+use crate::DBPool;
+
+// Statistics
+//
+// le tableau de bord de la page d'accueil : les nombres viennent des
+// requêtes d'agrégation de db.rs, les graphiques sont des SVG dont la
+// géométrie est calculée ici et dessinée par start.html.tera (pas de Javascript)
+
+const TOP_PERSONS: i64 = 10;
+const TOP_BORROWED: i64 = 10;
+const RECENT_PARTITIONS: i64 = 8;
+
+// dimensions des graphiques, en unités du viewBox SVG
+const CHART_WIDTH: f64 = 480.0;
+const LABEL_WIDTH: f64 = 160.0;
+const VALUE_WIDTH: f64 = 40.0;
+const BAR_HEIGHT: f64 = 18.0;
+const BAR_GAP: f64 = 6.0;
+const LINE_HEIGHT: f64 = 200.0;
+const LINE_MARGIN: f64 = 30.0;
+const MAX_LABEL_CHARS: usize = 24;
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Dashboard {
+    pub nb_partitions: i64,
+    pub nb_persons: i64,
+    pub nb_genres: i64,
+    pub by_genre: BarChart,
+    pub by_person: BarChart,
+    // les partitions que les autres ensembles ont le plus empruntées
+    pub most_borrowed: BarChart,
+    pub growth: LineChart,
+    pub recent: Vec<ShowPartition>,
+}
+
+impl Dashboard {
+    // les chiffres de la bibliothèque d'un ensemble
+    pub async fn load(conn: &DBPool, ensemble_id: i32) -> QueryResult<Dashboard> {
+        let (nb_partitions, nb_persons, nb_genres) = db::count_catalogue(conn, ensemble_id).await?;
+        let by_genre = db::count_partitions_by_genre(conn, ensemble_id).await?;
+        let by_person = db::count_partitions_by_person(conn, ensemble_id, TOP_PERSONS).await?;
+        let most_borrowed = db::count_loans_by_partition(conn, ensemble_id, TOP_BORROWED).await?;
+        let by_month = db::count_partitions_by_month(conn, ensemble_id).await?;
+        let recent = db::get_recent_partitions(conn, ensemble_id, RECENT_PARTITIONS).await?;
+
+        Ok(Dashboard {
+            nb_partitions,
+            nb_persons,
+            nb_genres,
+            by_genre: BarChart::new(&by_genre),
+            by_person: BarChart::new(&by_person),
+            most_borrowed: BarChart::new(&most_borrowed),
+            growth: LineChart::cumulative(&by_month),
+            recent,
+        })
+    }
+}
+
+// ***********************************************************************************************
+// Bar chart : une barre horizontale par étiquette
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Bar {
+    pub label: String,
+    pub value: i64,
+    pub y: f64,
+    pub width: f64,
+    // positions des textes (nom à gauche, valeur au bout de la barre)
+    pub text_y: f64,
+    pub value_x: f64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct BarChart {
+    pub width: f64,
+    pub height: f64,
+    pub label_x: f64,
+    pub bar_x: f64,
+    pub bar_height: f64,
+    pub bars: Vec<Bar>,
+}
+
+impl BarChart {
+    pub fn new(data: &[LabelCount]) -> BarChart {
+        let max = data.iter().map(|d| d.count).max().unwrap_or(0).max(1) as f64;
+        let scale = (CHART_WIDTH - LABEL_WIDTH - VALUE_WIDTH) / max;
+
+        let bars = data
+            .iter()
+            .enumerate()
+            .map(|(i, d)| {
+                let y = i as f64 * (BAR_HEIGHT + BAR_GAP);
+                let width = d.count as f64 * scale;
+                Bar {
+                    label: shorten(&d.label),
+                    value: d.count,
+                    y,
+                    width,
+                    text_y: y + BAR_HEIGHT * 0.75,
+                    value_x: LABEL_WIDTH + width + 4.0,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        BarChart {
+            width: CHART_WIDTH,
+            height: (bars.len() as f64 * (BAR_HEIGHT + BAR_GAP)).max(BAR_HEIGHT),
+            label_x: LABEL_WIDTH - 6.0,
+            bar_x: LABEL_WIDTH,
+            bar_height: BAR_HEIGHT,
+            bars,
+        }
+    }
+}
+
+// ***********************************************************************************************
+// Line chart : la taille du catalogue mois après mois
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Point {
+    pub label: String,
+    pub value: i64,
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct LineChart {
+    pub width: f64,
+    pub height: f64,
+    pub baseline: f64,
+    pub margin: f64,
+    pub max: i64,
+    // attribut `points` de la <polyline>
+    pub polyline: String,
+    pub points: Vec<Point>,
+}
+
+impl LineChart {
+    // additionne les ajouts de chaque période pour obtenir le total
+    pub fn cumulative(data: &[LabelCount]) -> LineChart {
+        let totals = data
+            .iter()
+            .scan(0, |total, d| {
+                *total += d.count;
+                Some((d.label.clone(), *total))
+            })
+            .collect::<Vec<_>>();
+
+        let max = totals.last().map(|(_, t)| *t).unwrap_or(0);
+        let baseline = LINE_HEIGHT - LINE_MARGIN;
+        let plot_width = CHART_WIDTH - 2.0 * LINE_MARGIN;
+        let plot_height = LINE_HEIGHT - 2.0 * LINE_MARGIN;
+        let step = if totals.len() > 1 {
+            plot_width / (totals.len() - 1) as f64
+        } else {
+            0.0
+        };
+
+        let points = totals
+            .into_iter()
+            .enumerate()
+            .map(|(i, (label, value))| Point {
+                label,
+                value,
+                x: LINE_MARGIN + i as f64 * step,
+                y: baseline - value as f64 * plot_height / max.max(1) as f64,
+            })
+            .collect::<Vec<_>>();
+
+        let polyline = points
+            .iter()
+            .map(|p| format!("{:.1},{:.1}", p.x, p.y))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        LineChart {
+            width: CHART_WIDTH,
+            height: LINE_HEIGHT,
+            baseline,
+            margin: LINE_MARGIN,
+            max,
+            polyline,
+            points,
+        }
+    }
+}
+
+fn shorten(label: &str) -> String {
+    if label.chars().count() > MAX_LABEL_CHARS {
+        let short: String = label.chars().take(MAX_LABEL_CHARS - 1).collect();
+        format!("{}…", short)
+    } else {
+        label.to_string()
+    }
+}
@@ -0,0 +1,70 @@
+use rocket::http::Status;
+use rocket::serde::json::Json;
+
+use crate::db;
+use crate::models::{Ensemble, Suggestion};
+use crate::repo::SuggestField;
+use crate::DBPool;
+
+// Autocomplete
+//
+// des routes GET qui renvoient quelques suggestions en JSON,
+// pour remplir les <datalist> des formulaires (static/js/suggest.js) :
+// /suggest/persons?q=moz, /suggest/genres?q=ba, /suggest/titles?q=req&limit=5
+// (genres et titres de la bibliothèque choisie)
+
+const DEFAULT_LIMIT: i64 = 10;
+const MAX_LIMIT: i64 = 25;
+
+async fn suggestions(
+    conn: DBPool,
+    ensemble: Ensemble,
+    field: SuggestField,
+    q: Option<String>,
+    limit: Option<i64>,
+) -> Result<Json<Vec<Suggestion>>, Status> {
+    let text = q.map(|s| s.trim().to_string()).unwrap_or_default();
+    // rien à proposer tant que rien n'est tapé
+    if text.is_empty() {
+        return Ok(Json(vec![]));
+    }
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).max(1).min(MAX_LIMIT);
+
+    match db::suggest(&conn, ensemble.id.unwrap_or_default(), field, text, limit).await {
+        Ok(list) => Ok(Json(list)),
+        Err(e) => {
+            error_!("DB suggestion error: {}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+#[get("/suggest/persons?<q>&<limit>")]
+pub async fn suggest_persons(
+    q: Option<String>,
+    limit: Option<i64>,
+    conn: DBPool,
+    ensemble: Ensemble,
+) -> Result<Json<Vec<Suggestion>>, Status> {
+    suggestions(conn, ensemble, SuggestField::PersonName, q, limit).await
+}
+
+#[get("/suggest/genres?<q>&<limit>")]
+pub async fn suggest_genres(
+    q: Option<String>,
+    limit: Option<i64>,
+    conn: DBPool,
+    ensemble: Ensemble,
+) -> Result<Json<Vec<Suggestion>>, Status> {
+    suggestions(conn, ensemble, SuggestField::GenreName, q, limit).await
+}
+
+#[get("/suggest/titles?<q>&<limit>")]
+pub async fn suggest_titles(
+    q: Option<String>,
+    limit: Option<i64>,
+    conn: DBPool,
+    ensemble: Ensemble,
+) -> Result<Json<Vec<Suggestion>>, Status> {
+    suggestions(conn, ensemble, SuggestField::PartitionTitle, q, limit).await
+}
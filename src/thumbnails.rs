@@ -0,0 +1,303 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use diesel::PgConnection;
+
+use rocket::fairing;
+use rocket::fs::NamedFile;
+use rocket::http::Header;
+use rocket::serde::{Deserialize, Serialize};
+use rocket::{Build, Rocket, State};
+
+use crate::backup::FilesConfig;
+use crate::jobs::{payload_of, JobQueue, JobResult};
+use crate::models::{Ensemble, Job, PartitionFile};
+use crate::{db, repo, DBPool};
+
+// Thumbnails
+//
+// la première page d'un fichier PDF ou d'une image envoyé avec une partition
+// (voir scores.rs) est réduite en vignette PNG par une tâche de fond (voir
+// jobs.rs), sans faire attendre la réponse. Le rendu est confié à une commande choisie dans
+// Rocket.toml ([default.thumbnails]), ImageMagick par défaut :
+//   command = ["convert", "{format}:{input}[0]", "-thumbnail", "{size}x{size}", ...]
+// où `{input}` est le fichier envoyé, `{format}` son format (`pdf`, `png` ...,
+// d'après le type vérifié à l'envoi : ImageMagick ne devine pas le décodeur
+// d'après le contenu), `{output}` la vignette à écrire et `{size}` la taille
+// maximale en pixels. Une commande qui dépasse `timeout` secondes est tuée.
+//
+// les vignettes sont un cache (dossier `dir`, hors des sauvegardes) : un
+// fichier restauré n'en a pas jusqu'à un nouvel envoi.
+
+pub const THUMBNAIL_JOB: &str = "thumbnail";
+
+// les vignettes ne changent pas : le navigateur les garde une journée
+const CACHE_CONTROL: &str = "private, max-age=86400";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct ThumbnailConfig {
+    pub dir: PathBuf,
+    pub command: Vec<String>,
+    pub size: u32,
+    pub timeout: u64,
+}
+
+impl Default for ThumbnailConfig {
+    fn default() -> ThumbnailConfig {
+        ThumbnailConfig {
+            dir: PathBuf::from("cache/thumbnails"),
+            command: [
+                "convert",
+                "{format}:{input}[0]",
+                "-thumbnail",
+                "{size}x{size}",
+                "-background",
+                "white",
+                "-flatten",
+                "{output}",
+            ]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect(),
+            size: 240,
+            timeout: 30,
+        }
+    }
+}
+
+pub struct Thumbnails {
+    config: Arc<ThumbnailConfig>,
+}
+
+impl Thumbnails {
+    pub fn new(config: ThumbnailConfig) -> Thumbnails {
+        Thumbnails {
+            config: Arc::new(config),
+        }
+    }
+
+    // les fichiers dont on sait faire une vignette
+    pub fn renderable(content_type: &str) -> bool {
+        format_of(content_type).is_some()
+    }
+
+    pub fn path_of(&self, file: &PartitionFile) -> PathBuf {
+        thumbnail_path(&self.config, file)
+    }
+}
+
+// la vignette d'un fichier : son chemin dans `upload_dir`, sous le dossier du cache
+fn thumbnail_path(config: &ThumbnailConfig, file: &PartitionFile) -> PathBuf {
+    config.dir.join(format!("{}.png", file.path))
+}
+
+// le format donné à la commande (`{format}`), d'après le type du fichier
+// (voir scores::attachment_type_of)
+fn format_of(content_type: &str) -> Option<&'static str> {
+    match content_type {
+        "application/pdf" => Some("pdf"),
+        "image/png" => Some("png"),
+        "image/jpeg" => Some("jpeg"),
+        "image/gif" => Some("gif"),
+        "image/webp" => Some("webp"),
+        "image/tiff" => Some("tiff"),
+        _ => None,
+    }
+}
+
+// une tâche de fond par fichier envoyé (voir jobs.rs)
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ThumbnailJob {
+    pub file_id: i32,
+}
+
+// rend la vignette, puis la marque prête dans la base ; un fichier supprimé
+// entre temps n'en a plus besoin
+fn render_job(
+    c: &PgConnection,
+    job: &Job,
+    config: &ThumbnailConfig,
+    upload_dir: &Path,
+) -> JobResult {
+    let ThumbnailJob { file_id } = payload_of(job)?;
+    let file = match repo::get_file(c, file_id) {
+        Ok(file) => file,
+        Err(diesel::result::Error::NotFound) => return Ok(()),
+        Err(e) => return Err(e.to_string()),
+    };
+    let format = format_of(&file.content_type)
+        .ok_or_else(|| format!("no thumbnail for {}", file.content_type))?;
+    let target = thumbnail_path(config, &file);
+    render(config, &upload_dir.join(&file.path), format, &target).map_err(|e| e.to_string())?;
+    repo::set_file_thumbnail(c, file.id, true).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// lance la commande ; la vignette n'est gardée que si elle a été écrite
+fn render(config: &ThumbnailConfig, source: &Path, format: &str, target: &Path) -> io::Result<()> {
+    let (program, args) = match config.command.split_first() {
+        Some(command) => command,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no thumbnail command",
+            ))
+        }
+    };
+    if let Some(dir) = target.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let size = config.size.to_string();
+    let (input, output) = (source.to_string_lossy(), target.to_string_lossy());
+    let args = args.iter().map(|arg| {
+        arg.replace("{input}", &input)
+            .replace("{output}", &output)
+            .replace("{size}", &size)
+            .replace("{format}", format)
+    });
+
+    // la tâche attend sans fin une commande bloquée : passé le délai, on la tue
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let deadline = Instant::now() + Duration::from_secs(config.timeout);
+    while child.try_wait()?.is_none() {
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            let _ = fs::remove_file(target);
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("the command took more than {} s", config.timeout),
+            ));
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    let result = child.wait_with_output()?;
+    if !result.status.success() {
+        let _ = fs::remove_file(target);
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "{} ({})",
+                result.status,
+                String::from_utf8_lossy(&result.stderr).trim()
+            ),
+        ));
+    }
+    if !target.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "the command wrote no thumbnail",
+        ));
+    }
+    Ok(())
+}
+
+pub async fn configure_thumbnails(rocket: Rocket<Build>) -> fairing::Result {
+    let figment = rocket.figment();
+    let config = if figment.contains("thumbnails") {
+        match figment.extract_inner::<ThumbnailConfig>("thumbnails") {
+            Ok(config) => config,
+            Err(e) => {
+                error_!("Invalid thumbnails configuration: {}", e);
+                return Err(rocket);
+            }
+        }
+    } else {
+        ThumbnailConfig::default()
+    };
+    let upload_dir = match rocket.state::<FilesConfig>() {
+        Some(files) => files.upload_dir.clone(),
+        None => {
+            error_!("Thumbnails need the files configuration");
+            return Err(rocket);
+        }
+    };
+    let thumbnails = Thumbnails::new(config);
+    match rocket.state::<JobQueue>() {
+        Some(queue) => {
+            let config = thumbnails.config.clone();
+            queue.register(THUMBNAIL_JOB, move |c, job| {
+                render_job(c, job, &config, &upload_dir)
+            });
+        }
+        None => {
+            error_!("Thumbnails need the job queue");
+            return Err(rocket);
+        }
+    }
+    Ok(rocket.manage(thumbnails))
+}
+
+// ********************************************************************************************
+// Routes
+//
+
+#[derive(Responder)]
+pub struct Thumbnail(NamedFile, Header<'static>);
+
+async fn open(thumbnails: &Thumbnails, file: &PartitionFile) -> Option<Thumbnail> {
+    match NamedFile::open(thumbnails.path_of(file)).await {
+        Ok(named) => Some(Thumbnail(
+            named,
+            Header::new("Cache-Control", CACHE_CONTROL),
+        )),
+        Err(e) => {
+            error_!("Thumbnail of {} not readable: {}", file.path, e);
+            None
+        }
+    }
+}
+
+// la vignette du premier fichier de la partition, pour la liste
+#[get("/partitions/<id>/thumbnail.png")]
+pub async fn partition_thumbnail(
+    id: i32,
+    conn: DBPool,
+    ensemble: Ensemble,
+    thumbnails: &State<Thumbnails>,
+) -> Option<Thumbnail> {
+    let file = match db::get_partition_thumbnail(&conn, ensemble.id.unwrap_or_default(), id).await {
+        Ok(file) => file,
+        Err(e) => {
+            if e != diesel::result::Error::NotFound {
+                error_!("DB get_partition_thumbnail({}) error: {}", id, e);
+            }
+            return None;
+        }
+    };
+    open(thumbnails, &file).await
+}
+
+#[get("/partitions/<id>/files/<file_id>/thumbnail.png")]
+pub async fn file_thumbnail(
+    id: i32,
+    file_id: i32,
+    conn: DBPool,
+    ensemble: Ensemble,
+    thumbnails: &State<Thumbnails>,
+) -> Option<Thumbnail> {
+    let file =
+        match db::get_partition_file(&conn, ensemble.id.unwrap_or_default(), id, file_id).await {
+            Ok(file) if file.thumbnail => file,
+            Ok(_) => return None,
+            Err(e) => {
+                if e != diesel::result::Error::NotFound {
+                    error_!("DB get_partition_file({}) error: {}", file_id, e);
+                }
+                return None;
+            }
+        };
+    open(thumbnails, &file).await
+}
@@ -0,0 +1,646 @@
+use std::io::{self, Read};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+
+use diesel::PgConnection;
+
+use fluent::FluentArgs;
+
+use hmac::{Hmac, Mac, NewMac};
+
+use rocket::fairing;
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::{Build, Rocket, State};
+
+use rocket_dyn_templates::Template;
+
+use sha2::Sha256;
+
+use url::Url;
+
+use crate::auth::AdminUser;
+use crate::csrf::{generate_token, CsrfChecked, CsrfForm, CsrfToken};
+use crate::i18n::Locale;
+use crate::jobs::{payload_of, JobQueue, JobResult};
+use crate::models::{
+    Ensemble, Job, QueuedDelivery, Webhook, WebhookDelivery, WebhookForm, DELIVERY_DELIVERED,
+    DELIVERY_FAILED, DELIVERY_PENDING,
+};
+use crate::notification::Notification;
+use crate::{db, repo, DBPool};
+
+// Webhooks
+//
+// un site (celui du chœur ...) suit le catalogue d'un ensemble : chaque ajout,
+// modification ou suppression d'une partition, d'une personne ou d'un genre
+// lui est envoyé en POST, en JSON :
+//   {"id": 42, "event": "partition.updated", "ensemble_id": 1,
+//    "created_at": "2021-09-15 20:30:00", "data": {...}}
+// où `data` est l'objet changé, avec les mêmes champs que dans l'API (api.rs),
+// ou seulement son `id` après une suppression. Les en-têtes X-Webhook-Event et
+// X-Webhook-Delivery reprennent l'événement et le numéro de l'envoi ;
+// X-Webhook-Signature ("sha256=...") est le HMAC-SHA256 du corps, avec le
+// secret de l'abonnement pour clé : le site vérifie ainsi que l'envoi vient
+// bien d'ici.
+//
+// les envois sont faits par une tâche de fond (voir jobs.rs), reprise si le
+// site ne répond pas 2xx, et gardés dans un journal sur la page de
+// l'abonnement. Les adresses privées sont refusées (voir HttpClient).
+//
+// les administrateurs gèrent les abonnements de la bibliothèque choisie sur
+// /webhooks ; le bouton de test envoie un événement "ping".
+
+pub const WEBHOOK_JOB: &str = "webhook";
+
+pub const PARTITION_CREATED: &str = "partition.created";
+pub const PARTITION_UPDATED: &str = "partition.updated";
+pub const PARTITION_DELETED: &str = "partition.deleted";
+pub const PERSON_CREATED: &str = "person.created";
+pub const PERSON_UPDATED: &str = "person.updated";
+pub const PERSON_DELETED: &str = "person.deleted";
+pub const GENRE_CREATED: &str = "genre.created";
+pub const GENRE_UPDATED: &str = "genre.updated";
+pub const GENRE_DELETED: &str = "genre.deleted";
+pub const PING: &str = "ping";
+
+// les événements auxquels on s'abonne, dans l'ordre du formulaire
+pub const EVENTS: [&str; 9] = [
+    PARTITION_CREATED,
+    PARTITION_UPDATED,
+    PARTITION_DELETED,
+    PERSON_CREATED,
+    PERSON_UPDATED,
+    PERSON_DELETED,
+    GENRE_CREATED,
+    GENRE_UPDATED,
+    GENRE_DELETED,
+];
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+// la réponse du site n'est gardée que pour le journal
+const MAX_RESPONSE_BODY: usize = 500;
+
+// une tâche de fond par envoi
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct WebhookJob {
+    pub delivery_id: i32,
+}
+
+// l'objet supprimé, dans `data`
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Deleted {
+    pub id: i32,
+}
+
+// le corps de la requête envoyée au site
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct Payload<'a> {
+    id: i32,
+    event: &'a str,
+    ensemble_id: i32,
+    created_at: &'a str,
+    data: serde_json::Value,
+}
+
+// réglages lus dans Rocket.toml ([default.webhooks])
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub struct WebhooksConfig {
+    // laisse joindre la machine et les réseaux privés
+    pub allow_private: bool,
+}
+
+pub async fn configure_webhooks(rocket: Rocket<Build>) -> fairing::Result {
+    let figment = rocket.figment();
+    let config = if figment.contains("webhooks") {
+        match figment.extract_inner::<WebhooksConfig>("webhooks") {
+            Ok(config) => config,
+            Err(e) => {
+                error_!("Invalid webhooks configuration: {}", e);
+                return Err(rocket);
+            }
+        }
+    } else {
+        WebhooksConfig::default()
+    };
+    let client = HttpClient::new(&config);
+    match rocket.state::<JobQueue>() {
+        Some(queue) => {
+            queue.register(WEBHOOK_JOB, move |c, job| deliver_job(c, job, &client));
+            Ok(rocket)
+        }
+        None => {
+            error_!("Webhooks need the job queue");
+            Err(rocket)
+        }
+    }
+}
+
+// appelée par les routes après un changement du catalogue : un envoi à chaque
+// abonné ; `ensemble_id` à None pour les personnes et les genres communs.
+// Une erreur ici n'empêche pas le changement, elle est seulement notée.
+pub async fn notify<T: Serialize>(
+    conn: &DBPool,
+    queue: &JobQueue,
+    ensemble_id: Option<i32>,
+    event: &str,
+    data: &T,
+) {
+    let data = match serde_json::to_string(data) {
+        Ok(data) => data,
+        Err(e) => {
+            error_!("Webhook {} data error: {}", event, e);
+            return;
+        }
+    };
+    let deliveries = match db::queue_deliveries(conn, ensemble_id, event.to_string(), data).await {
+        Ok(deliveries) => deliveries,
+        Err(e) => {
+            error_!("DB queue_deliveries({}) error: {}", event, e);
+            return;
+        }
+    };
+    for delivery in deliveries {
+        push(conn, queue, &delivery).await;
+    }
+}
+
+async fn push(conn: &DBPool, queue: &JobQueue, delivery: &QueuedDelivery) -> bool {
+    let job = WebhookJob {
+        delivery_id: delivery.id,
+    };
+    match queue
+        .push(conn, WEBHOOK_JOB, Some(delivery.ensemble_id), &job)
+        .await
+    {
+        Ok(_) => true,
+        Err(e) => {
+            error_!("Webhook delivery {} not queued: {}", delivery.id, e);
+            false
+        }
+    }
+}
+
+// ********************************************************************************************
+// Delivery
+//
+
+// la signature du corps, dans l'en-tête X-Webhook-Signature
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_varkey(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(body);
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+// un essai ; au dernier, l'envoi échoue pour de bon. Un abonnement supprimé
+// entre temps n'attend plus rien.
+fn deliver_job(c: &PgConnection, job: &Job, client: &HttpClient) -> JobResult {
+    let WebhookJob { delivery_id } = payload_of(job)?;
+    let (delivery, webhook) = match repo::get_delivery_target(c, delivery_id) {
+        Ok(target) => target,
+        Err(diesel::result::Error::NotFound) => return Ok(()),
+        Err(e) => return Err(e.to_string()),
+    };
+    let data = serde_json::from_str(&delivery.data).map_err(|e| e.to_string())?;
+    let body = serde_json::to_string(&Payload {
+        id: delivery.id,
+        event: &delivery.event,
+        ensemble_id: webhook.ensemble_id,
+        created_at: &delivery.created_at,
+        data,
+    })
+    .map_err(|e| e.to_string())?;
+    let headers = [
+        ("X-Webhook-Event", delivery.event.clone()),
+        ("X-Webhook-Delivery", delivery.id.to_string()),
+        (
+            "X-Webhook-Signature",
+            signature(&webhook.secret, body.as_bytes()),
+        ),
+    ];
+
+    let failed = if job.attempts >= job.max_attempts {
+        DELIVERY_FAILED
+    } else {
+        DELIVERY_PENDING
+    };
+    let (status, response_status, response_body, error) =
+        match client.post(&webhook.url, &headers, body.as_bytes()) {
+            Ok(response) if response.is_success() => (
+                DELIVERY_DELIVERED,
+                Some(response.status as i32),
+                response.body,
+                String::new(),
+            ),
+            Ok(response) => (
+                failed,
+                Some(response.status as i32),
+                response.body,
+                format!("HTTP {}", response.status),
+            ),
+            Err(e) => (failed, None, String::new(), e.to_string()),
+        };
+    repo::record_delivery(
+        c,
+        delivery.id,
+        job.id,
+        status,
+        response_status,
+        &response_body,
+        &error,
+    )
+    .map_err(|e| e.to_string())?;
+    if error.is_empty() {
+        Ok(())
+    } else {
+        Err(error)
+    }
+}
+
+// ********************************************************************************************
+// HTTP client
+//
+// ureq : http et https, réponses en morceaux (chunked), hôtes IPv6 ([::1]).
+// Un abonnement ne doit pas servir à joindre ce que le serveur seul peut
+// voir : les adresses de la machine, du réseau local ou du lien local (les
+// métadonnées des hébergeurs, 169.254.169.254) sont refusées, à la
+// résolution du nom pour qu'un DNS ne puisse pas les glisser après coup,
+// sauf avec `allow_private` (un relais sur la machine, les tests). Les
+// redirections ne sont pas suivies.
+
+#[derive(Debug)]
+pub struct HttpResponse {
+    pub status: u16,
+    // le début du corps seulement
+    pub body: String,
+}
+
+impl HttpResponse {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+pub fn is_valid_url(url: &str) -> bool {
+    match Url::parse(url) {
+        Ok(url) => {
+            matches!(url.scheme(), "http" | "https")
+                && url.host_str().is_some()
+                && url.username().is_empty()
+                && url.password().is_none()
+        }
+        Err(_) => false,
+    }
+}
+
+// ni la machine, ni un réseau privé, ni le lien local
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, _, _] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                // 100.64.0.0/10, les adresses partagées des opérateurs
+                || (a == 100 && (b & 0xc0) == 64))
+        }
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            match ip.segments() {
+                // ::ffff:a.b.c.d
+                [0, 0, 0, 0, 0, 0xffff, high, low] => {
+                    is_public(Ipv4Addr::from(u32::from(high) << 16 | u32::from(low)).into())
+                }
+                _ => {
+                    !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // fc00::/7 (adresses locales uniques) et fe80::/10 (lien local)
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct HttpClient {
+    agent: ureq::Agent,
+}
+
+impl HttpClient {
+    pub fn new(config: &WebhooksConfig) -> HttpClient {
+        let allow_private = config.allow_private;
+        let resolver = move |netloc: &str| -> io::Result<Vec<SocketAddr>> {
+            let addresses = netloc.to_socket_addrs()?.collect::<Vec<_>>();
+            if allow_private {
+                return Ok(addresses);
+            }
+            let public = addresses
+                .into_iter()
+                .filter(|address| is_public(address.ip()))
+                .collect::<Vec<_>>();
+            if public.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("{} is a private address", netloc),
+                ));
+            }
+            Ok(public)
+        };
+        let agent = ureq::AgentBuilder::new()
+            .resolver(resolver)
+            .redirects(0)
+            .timeout(HTTP_TIMEOUT)
+            .user_agent("hello-rocket")
+            .build();
+        HttpClient { agent }
+    }
+
+    // POST du corps JSON ; seul le début de la réponse est gardé
+    pub fn post(
+        &self,
+        url: &str,
+        headers: &[(&str, String)],
+        body: &[u8],
+    ) -> io::Result<HttpResponse> {
+        if !is_valid_url(url) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("not an http:// or https:// URL: {}", url),
+            ));
+        }
+        let mut request = self.agent.post(url).set("Content-Type", "application/json");
+        for (name, value) in headers {
+            request = request.set(name, value);
+        }
+        let response = match request.send_bytes(body) {
+            Ok(response) => response,
+            // 4xx, 5xx : une réponse comme une autre pour le journal
+            Err(ureq::Error::Status(_, response)) => response,
+            Err(ureq::Error::Transport(e)) => {
+                return Err(io::Error::new(io::ErrorKind::Other, e.to_string()))
+            }
+        };
+        let status = response.status();
+        let mut body = vec![];
+        response
+            .into_reader()
+            .take(MAX_RESPONSE_BODY as u64 * 4)
+            .read_to_end(&mut body)?;
+        Ok(HttpResponse {
+            status,
+            body: String::from_utf8_lossy(&body)
+                .chars()
+                .take(MAX_RESPONSE_BODY)
+                .collect(),
+        })
+    }
+}
+
+// ********************************************************************************************
+// Pages
+//
+
+#[derive(Debug, Serialize)]
+struct WebhooksPage {
+    flash: Option<Notification>,
+    title: String,
+    lang: String,
+    csrf_token: String,
+    ensemble: Ensemble,
+    webhooks: Vec<Webhook>,
+    events: [&'static str; 9],
+}
+
+#[derive(Debug, Serialize)]
+struct WebhookPage {
+    flash: Option<Notification>,
+    title: String,
+    lang: String,
+    csrf_token: String,
+    ensemble: Ensemble,
+    webhook: Webhook,
+    deliveries: Vec<WebhookDelivery>,
+    events: [&'static str; 9],
+}
+
+#[get("/webhooks")]
+pub async fn webhooks_page(
+    _admin: AdminUser,
+    ensemble: Ensemble,
+    conn: DBPool,
+    flash: Option<FlashMessage<'_>>,
+    csrf: CsrfToken,
+    locale: Locale,
+) -> Template {
+    let webhooks = db::get_webhooks(&conn, ensemble.id.unwrap_or_default())
+        .await
+        .unwrap_or_else(|e| {
+            error_!("DB get_webhooks error: {}", e);
+            vec![]
+        });
+    let page = WebhooksPage {
+        flash: Notification::from_flash(flash),
+        title: locale.tr("title-webhooks"),
+        lang: locale.lang().to_string(),
+        csrf_token: csrf.value().to_string(),
+        ensemble,
+        webhooks,
+        events: EVENTS,
+    };
+    Template::render("webhooks", &page)
+}
+
+#[get("/webhooks/<id>")]
+pub async fn webhook_page(
+    id: i32,
+    _admin: AdminUser,
+    ensemble: Ensemble,
+    conn: DBPool,
+    flash: Option<FlashMessage<'_>>,
+    csrf: CsrfToken,
+    locale: Locale,
+) -> Option<Template> {
+    let webhook = match db::get_webhook(&conn, ensemble.id.unwrap_or_default(), id).await {
+        Ok(webhook) => webhook,
+        Err(e) => {
+            if e != diesel::result::Error::NotFound {
+                error_!("DB get_webhook({}) error: {}", id, e);
+            }
+            return None;
+        }
+    };
+    let deliveries = db::get_deliveries(&conn, id).await.unwrap_or_else(|e| {
+        error_!("DB get_deliveries({}) error: {}", id, e);
+        vec![]
+    });
+    let mut args = FluentArgs::new();
+    args.set("id", id);
+    let page = WebhookPage {
+        flash: Notification::from_flash(flash),
+        title: locale.tr_args("title-webhook", &args),
+        lang: locale.lang().to_string(),
+        csrf_token: csrf.value().to_string(),
+        ensemble,
+        webhook,
+        deliveries,
+        events: EVENTS,
+    };
+    Some(Template::render("webhook", &page))
+}
+
+// l'adresse et les événements connus du formulaire, ou le message qui manque
+fn checked(form: WebhookForm) -> Result<WebhookForm, &'static str> {
+    let url = form.url.trim().to_string();
+    if !is_valid_url(&url) {
+        return Err("msg-webhook-url-invalid");
+    }
+    let events = form
+        .events
+        .into_iter()
+        .filter(|event| EVENTS.contains(&event.as_str()))
+        .collect::<Vec<_>>();
+    if events.is_empty() {
+        return Err("msg-webhook-events-missing");
+    }
+    Ok(WebhookForm {
+        url,
+        secret: form.secret.trim().to_string(),
+        events,
+        active: form.active,
+    })
+}
+
+#[post("/webhooks", data = "<webhook_form>")]
+pub async fn create_webhook(
+    _admin: AdminUser,
+    ensemble: Ensemble,
+    webhook_form: CsrfForm<WebhookForm>,
+    conn: DBPool,
+    locale: Locale,
+) -> Flash<Redirect> {
+    let form = match checked(webhook_form.into_inner()) {
+        Ok(form) => form,
+        Err(key) => return Notification::warning(locale.tr(key)).redirect("/webhooks"),
+    };
+    let secret = if form.secret.is_empty() {
+        generate_token()
+    } else {
+        form.secret
+    };
+    let ensemble_id = ensemble.id.unwrap_or_default();
+    match db::create_webhook(
+        &conn,
+        ensemble_id,
+        form.url,
+        secret,
+        form.events,
+        form.active,
+    )
+    .await
+    {
+        Ok(webhook) => Notification::success(locale.tr("msg-webhook-created"))
+            .redirect(format!("/webhooks/{}", webhook.id)),
+        Err(e) => {
+            error_!("DB create_webhook error: {}", e);
+            Notification::error(locale.tr("msg-db-access-failed")).redirect("/webhooks")
+        }
+    }
+}
+
+#[put("/webhooks/<id>", data = "<webhook_form>")]
+pub async fn update_webhook(
+    id: i32,
+    _admin: AdminUser,
+    ensemble: Ensemble,
+    webhook_form: CsrfForm<WebhookForm>,
+    conn: DBPool,
+    locale: Locale,
+) -> Option<Flash<Redirect>> {
+    let back = format!("/webhooks/{}", id);
+    let form = match checked(webhook_form.into_inner()) {
+        Ok(form) => form,
+        Err(key) => return Some(Notification::warning(locale.tr(key)).redirect(back)),
+    };
+    let ensemble_id = ensemble.id.unwrap_or_default();
+    let updated = db::update_webhook(
+        &conn,
+        ensemble_id,
+        id,
+        form.url,
+        form.secret,
+        form.events,
+        form.active,
+    )
+    .await;
+    match updated {
+        Ok(_) => Some(Notification::success(locale.tr("msg-webhook-modified")).redirect(back)),
+        Err(diesel::result::Error::NotFound) => None,
+        Err(e) => {
+            error_!("DB update_webhook({}) error: {}", id, e);
+            Some(Notification::error(locale.tr("msg-db-access-failed")).redirect(back))
+        }
+    }
+}
+
+#[delete("/webhooks/<id>")]
+pub async fn delete_webhook(
+    _csrf: CsrfChecked,
+    id: i32,
+    _admin: AdminUser,
+    ensemble: Ensemble,
+    conn: DBPool,
+    locale: Locale,
+) -> Flash<Redirect> {
+    match db::delete_webhook(&conn, ensemble.id.unwrap_or_default(), id).await {
+        Ok(0) => Notification::warning(locale.tr("msg-webhook-unknown")).redirect("/webhooks"),
+        Ok(_) => Notification::success(locale.tr("msg-webhook-deleted")).redirect("/webhooks"),
+        Err(e) => {
+            error_!("DB delete_webhook({}) error: {}", id, e);
+            Notification::error(locale.tr("msg-db-access-failed")).redirect("/webhooks")
+        }
+    }
+}
+
+// un "ping" à ce seul abonnement, même suspendu, pour essayer le site
+#[post("/webhooks/<id>/test")]
+pub async fn test_webhook(
+    _csrf: CsrfChecked,
+    id: i32,
+    _admin: AdminUser,
+    ensemble: Ensemble,
+    conn: DBPool,
+    queue: &State<JobQueue>,
+    locale: Locale,
+) -> Option<Flash<Redirect>> {
+    let back = format!("/webhooks/{}", id);
+    let data = format!("{{\"webhook_id\": {}}}", id);
+    let ensemble_id = ensemble.id.unwrap_or_default();
+    let delivery =
+        match db::queue_test_delivery(&conn, ensemble_id, id, PING.to_string(), data).await {
+            Ok(delivery) => delivery,
+            Err(diesel::result::Error::NotFound) => return None,
+            Err(e) => {
+                error_!("DB queue_test_delivery({}) error: {}", id, e);
+                return Some(Notification::error(locale.tr("msg-db-access-failed")).redirect(back));
+            }
+        };
+    if push(&conn, queue, &delivery).await {
+        Some(Notification::success(locale.tr("msg-webhook-test-queued")).redirect(back))
+    } else {
+        Some(Notification::error(locale.tr("msg-webhook-test-failed")).redirect(back))
+    }
+}
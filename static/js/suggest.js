@@ -0,0 +1,29 @@
+// Autocomplétion des champs marqués data-suggest="/suggest/..."
+//
+// à chaque frappe (avec un petit délai) on demande les suggestions
+// au serveur et on remplit la <datalist> liée au champ par l'attribut list
+document.querySelectorAll("input[data-suggest]").forEach(function (input) {
+    var list = document.getElementById(input.getAttribute("list"));
+    var timer = null;
+
+    input.addEventListener("input", function () {
+        clearTimeout(timer);
+        timer = setTimeout(function () {
+            var text = input.value.trim();
+            if (text === "" || list === null) {
+                return;
+            }
+            fetch(input.dataset.suggest + "?q=" + encodeURIComponent(text))
+                .then(function (response) { return response.ok ? response.json() : []; })
+                .then(function (suggestions) {
+                    list.innerHTML = "";
+                    suggestions.forEach(function (suggestion) {
+                        var option = document.createElement("option");
+                        option.value = suggestion.label;
+                        list.appendChild(option);
+                    });
+                })
+                .catch(function () { /* pas de suggestions, le champ reste utilisable */ });
+        }, 200);
+    });
+});
@@ -0,0 +1,395 @@
+// Tests d'intégration : inscription, confirmation par mail, acceptation
+// par un administrateur, page du compte, mot de passe oublié, et envoi SMTP
+
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::thread;
+
+use hello_rocket::auth::hash_password;
+use hello_rocket::mailer::{Mail, Mailer, SmtpMailer};
+use hello_rocket::models::{User, STATUS_ACTIVE, STATUS_APPROVAL, STATUS_PENDING};
+use hello_rocket::repo;
+
+use common::{MailDir, TestApp};
+
+async fn sign_up(app: &TestApp, email: &str, password: &str) -> String {
+    let response = app
+        .submit(
+            None,
+            "/signup",
+            &[
+                ("email", email),
+                ("password", password),
+                ("confirm", password),
+            ],
+        )
+        .await;
+    app.follow(response).await
+}
+
+// l'adresse où mène la connexion : "/" si elle réussit, "/login" sinon
+async fn log_in(app: &TestApp, email: &str, password: &str) -> String {
+    let response = app
+        .submit(None, "/login", &[("email", email), ("password", password)])
+        .await;
+    response
+        .headers()
+        .get_one("Location")
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[rocket::async_test]
+async fn signup_and_confirm_address() {
+    let mail = MailDir::new();
+    let app = TestApp::start_with(|figment| mail.configure(figment)).await;
+
+    let page = sign_up(&app, "Clara@Example.com", "mot de passe").await;
+    assert!(page.contains("A confirmation link was sent to your address"));
+    let user = repo::get_user_by_email(&app.db(), "clara@example.com").unwrap();
+    assert_eq!(user.status, STATUS_PENDING);
+    assert!(!user.is_admin);
+
+    // pas de session avant la confirmation
+    assert_eq!(
+        log_in(&app, "clara@example.com", "mot de passe").await,
+        "/login"
+    );
+
+    let messages = mail.messages();
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].contains("To: clara@example.com"));
+    assert!(messages[0].contains("Subject: Confirm your address"));
+    let link = mail.last_link();
+    assert!(link.starts_with("/signup/verify/"));
+
+    let response = app.get(&link).dispatch().await;
+    let page = app.follow(response).await;
+    assert!(page.contains("Your address is confirmed, you can log in."));
+    let user = repo::get_user_by_email(&app.db(), "clara@example.com").unwrap();
+    assert_eq!(user.status, STATUS_ACTIVE);
+    assert_eq!(log_in(&app, "clara@example.com", "mot de passe").await, "/");
+
+    // le lien ne sert qu'une fois
+    let response = app.get(&link).dispatch().await;
+    let page = app.follow(response).await;
+    assert!(page.contains("This confirmation link is not valid or has expired."));
+}
+
+#[rocket::async_test]
+async fn signup_checks_the_form() {
+    let mail = MailDir::new();
+    let app = TestApp::start_with(|figment| mail.configure(figment)).await;
+
+    let page = sign_up(&app, "not an address", "mot de passe").await;
+    assert!(page.contains("This email address is not valid."));
+
+    let page = sign_up(&app, "clara@example.com", "court").await;
+    assert!(page.contains("The password must be at least 8 characters long."));
+
+    let response = app
+        .submit(
+            None,
+            "/signup",
+            &[
+                ("email", "clara@example.com"),
+                ("password", "mot de passe"),
+                ("confirm", "mot de passe 2"),
+            ],
+        )
+        .await;
+    let page = app.follow(response).await;
+    assert!(page.contains("The two passwords are not the same."));
+
+    assert!(repo::get_user_by_email(&app.db(), "clara@example.com").is_err());
+    assert!(mail.messages().is_empty());
+
+    // une adresse déjà inscrite : même réponse, pas de second compte ni de mail
+    sign_up(&app, "clara@example.com", "mot de passe").await;
+    let page = sign_up(&app, "clara@example.com", "autre mot de passe").await;
+    assert!(page.contains("A confirmation link was sent to your address"));
+    assert_eq!(mail.messages().len(), 1);
+}
+
+#[rocket::async_test]
+async fn administrator_approves_confirmed_accounts() {
+    let mail = MailDir::new();
+    let app = TestApp::start_with(|figment| {
+        mail.configure(figment)
+            .merge(("signup_requires_approval", true))
+    })
+    .await;
+
+    sign_up(&app, "clara@example.com", "mot de passe").await;
+    let response = app.get(&mail.last_link()).dispatch().await;
+    let page = app.follow(response).await;
+    assert!(page.contains("an administrator still has to approve the account"));
+    let user = repo::get_user_by_email(&app.db(), "clara@example.com").unwrap();
+    assert_eq!(user.status, STATUS_APPROVAL);
+    assert_eq!(
+        log_in(&app, "clara@example.com", "mot de passe").await,
+        "/login"
+    );
+
+    repo::create_user(
+        &app.db(),
+        &User {
+            id: None,
+            email: "admin@example.com".to_string(),
+            password_hash: hash_password("secret").unwrap(),
+            is_admin: true,
+            status: STATUS_ACTIVE.to_string(),
+            display_name: String::new(),
+            locale: String::new(),
+        },
+    )
+    .unwrap();
+    assert_eq!(log_in(&app, "admin@example.com", "secret").await, "/");
+    let page = app.page("/admin").await;
+    assert!(page.contains("Accounts awaiting approval"));
+    assert!(page.contains("clara@example.com"));
+
+    let uri = format!("/admin/users/{}/approve", user.id.unwrap());
+    let response = app.submit(None, &uri, &[]).await;
+    let page = app.follow(response).await;
+    assert!(page.contains("Account clara@example.com approved."));
+    assert!(!page.contains("Accounts awaiting approval"));
+
+    let messages = mail.messages();
+    assert_eq!(messages.len(), 2);
+    assert!(messages[1].contains("Subject: Your account is approved"));
+    app.get("/logout").dispatch().await;
+    assert_eq!(log_in(&app, "clara@example.com", "mot de passe").await, "/");
+}
+
+// ************************************************************************************************
+// Account
+
+#[rocket::async_test]
+async fn member_manages_the_account() {
+    let app = TestApp::start().await;
+    app.create_member("clara@example.com", "mot de passe");
+
+    let response = app.get("/account").dispatch().await;
+    assert_eq!(response.headers().get_one("Location"), Some("/login"));
+    assert_eq!(log_in(&app, "clara@example.com", "mot de passe").await, "/");
+    let page = app.page("/account").await;
+    assert!(page.contains("Logged in as clara@example.com."));
+
+    let response = app
+        .submit(
+            None,
+            "/account/password",
+            &[
+                ("current", "pas le bon"),
+                ("password", "nouveau mot de passe"),
+                ("confirm", "nouveau mot de passe"),
+            ],
+        )
+        .await;
+    let page = app.follow(response).await;
+    assert!(page.contains("The current password is wrong."));
+
+    let response = app
+        .submit(
+            None,
+            "/account/password",
+            &[
+                ("current", "mot de passe"),
+                ("password", "nouveau mot de passe"),
+                ("confirm", "nouveau mot de passe"),
+            ],
+        )
+        .await;
+    let page = app.follow(response).await;
+    assert!(page.contains("Your password is changed."));
+
+    // la langue du compte remplace celle du navigateur
+    let response = app
+        .submit(
+            None,
+            "/account/profile",
+            &[("display_name", "  Clara  "), ("locale", "fr")],
+        )
+        .await;
+    let page = app.follow(response).await;
+    assert!(page.contains(r#"<html lang="fr""#));
+    assert!(page.contains(r#"value="Clara""#));
+    let user = repo::get_user_by_email(&app.db(), "clara@example.com").unwrap();
+    assert_eq!(user.display_name, "Clara");
+    assert_eq!(user.locale, "fr");
+
+    app.get("/logout").dispatch().await;
+    assert_eq!(
+        log_in(&app, "clara@example.com", "mot de passe").await,
+        "/login"
+    );
+    assert_eq!(
+        log_in(&app, "clara@example.com", "nouveau mot de passe").await,
+        "/"
+    );
+}
+
+#[rocket::async_test]
+async fn forgotten_password_is_reset_by_mail() {
+    let mail = MailDir::new();
+    let app = TestApp::start_with(|figment| mail.configure(figment)).await;
+    app.create_member("clara@example.com", "mot de passe");
+
+    // une adresse inconnue : même réponse, aucun mail
+    let response = app
+        .submit(None, "/password/forgot", &[("email", "nobody@example.com")])
+        .await;
+    let page = app.follow(response).await;
+    assert!(page.contains("If this address has an account"));
+    assert!(mail.messages().is_empty());
+
+    let response = app
+        .submit(None, "/password/forgot", &[("email", "Clara@example.com")])
+        .await;
+    let page = app.follow(response).await;
+    assert!(page.contains("If this address has an account"));
+    assert!(mail.messages()[0].contains("Subject: New password"));
+    let link = mail.last_link();
+    assert!(link.starts_with("/password/reset/"));
+    let token = &link["/password/reset/".len()..];
+    let page = app.page(&link).await;
+    assert!(page.contains(token));
+
+    let response = app
+        .submit(
+            None,
+            "/password/reset",
+            &[
+                ("token", token),
+                ("password", "nouveau"),
+                ("confirm", "nouveau"),
+            ],
+        )
+        .await;
+    assert_eq!(response.headers().get_one("Location"), Some(link.as_str()));
+
+    let fields = [
+        ("token", token),
+        ("password", "nouveau mot de passe"),
+        ("confirm", "nouveau mot de passe"),
+    ];
+    let response = app.submit(None, "/password/reset", &fields).await;
+    let page = app.follow(response).await;
+    assert!(page.contains("Your password is changed, you can log in."));
+    assert_eq!(
+        log_in(&app, "clara@example.com", "nouveau mot de passe").await,
+        "/"
+    );
+
+    // le lien ne sert qu'une fois
+    let response = app.submit(None, "/password/reset", &fields).await;
+    let page = app.follow(response).await;
+    assert!(page.contains("This link is not valid or has expired"));
+}
+
+#[rocket::async_test]
+async fn password_reset_requests_are_limited() {
+    let mail = MailDir::new();
+    let app = TestApp::start_with(|figment| mail.configure(figment)).await;
+    app.create_member("clara@example.com", "mot de passe");
+
+    for _ in 0..5 {
+        let response = app
+            .submit(None, "/password/forgot", &[("email", "clara@example.com")])
+            .await;
+        let page = app.follow(response).await;
+        assert!(page.contains("If this address has an account"));
+    }
+    assert_eq!(mail.messages().len(), 3);
+}
+
+// ************************************************************************************************
+// SMTP
+
+// un serveur SMTP minimal : accepte un message et renvoie tout ce qu'il a reçu
+fn smtp_stand_in() -> (u16, thread::JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        let mut received = vec![];
+        writer.write_all(b"220 localhost ESMTP\r\n").unwrap();
+        let mut in_data = false;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap() == 0 {
+                break;
+            }
+            let line = line.trim_end_matches(&['\r', '\n'][..]).to_string();
+            received.push(line.clone());
+            let reply: &[u8] = if in_data {
+                if line != "." {
+                    continue;
+                }
+                in_data = false;
+                b"250 queued\r\n"
+            } else if line.starts_with("EHLO") {
+                b"250-localhost\r\n250 8BITMIME\r\n"
+            } else if line == "DATA" {
+                in_data = true;
+                b"354 go ahead\r\n"
+            } else if line == "QUIT" {
+                writer.write_all(b"221 bye\r\n").unwrap();
+                break;
+            } else {
+                b"250 ok\r\n"
+            };
+            writer.write_all(reply).unwrap();
+        }
+        received
+    });
+    (port, server)
+}
+
+#[test]
+fn smtp_mailer_sends_the_message() {
+    let (port, server) = smtp_stand_in();
+    let mail = Mail {
+        to: "clara@example.com".to_string(),
+        subject: "Confirmez votre adresse".to_string(),
+        body: "Bonjour,\n.une ligne qui commence par un point\nFin".to_string(),
+    };
+    SmtpMailer::new("127.0.0.1", port)
+        .send("hello-rocket <noreply@example.com>", &mail)
+        .unwrap();
+
+    let received = server.join().unwrap();
+    assert_eq!(received[0], "EHLO localhost");
+    assert_eq!(received[1], "MAIL FROM:<noreply@example.com>");
+    assert_eq!(received[2], "RCPT TO:<clara@example.com>");
+    assert_eq!(received[3], "DATA");
+    assert!(received.contains(&"To: clara@example.com".to_string()));
+    assert!(received.contains(&"Subject: Confirmez votre adresse".to_string()));
+    assert!(received.contains(&"..une ligne qui commence par un point".to_string()));
+    assert_eq!(received.last().map(String::as_str), Some("QUIT"));
+}
+
+#[test]
+fn smtp_mailer_reports_a_refusal() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(b"554 no SMTP service here\r\n").unwrap();
+    });
+    let mail = Mail {
+        to: "clara@example.com".to_string(),
+        subject: "Test".to_string(),
+        body: "Test".to_string(),
+    };
+    let error = SmtpMailer::new("127.0.0.1", port)
+        .send("noreply@example.com", &mail)
+        .unwrap_err();
+    assert!(error.to_string().contains("554 no SMTP service here"));
+    server.join().unwrap();
+}
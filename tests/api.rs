@@ -0,0 +1,202 @@
+// Tests d'intégration : jetons personnels créés sur la page du compte,
+// et routes JSON de l'API (/api/...) appelées avec ces jetons
+
+mod common;
+
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::LocalResponse;
+use rocket::serde::json::{self, Value};
+
+use hello_rocket::repo;
+
+use common::TestApp;
+
+async fn log_in(app: &TestApp, email: &str, password: &str) {
+    let response = app
+        .submit(None, "/login", &[("email", email), ("password", password)])
+        .await;
+    assert_eq!(response.headers().get_one("Location"), Some("/"));
+}
+
+// le jeton n'apparaît qu'une fois, sur la page rendue après sa création
+async fn create_token(app: &TestApp, name: &str, scope: &str, days: &str) -> String {
+    let response = app
+        .submit(
+            None,
+            "/account/tokens",
+            &[("name", name), ("scope", scope), ("expires_in_days", days)],
+        )
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let page = response.into_string().await.unwrap_or_default();
+    assert!(page.contains("Copy this token now"));
+    let start = page.find("<code>").expect("the new token") + "<code>".len();
+    let end = page[start..].find("</code>").unwrap();
+    page[start..start + end].to_string()
+}
+
+async fn api_get<'c>(app: &'c TestApp, uri: &str, token: &str) -> LocalResponse<'c> {
+    app.client
+        .get(uri.to_string())
+        .header(Header::new("Authorization", format!("Bearer {}", token)))
+        .dispatch()
+        .await
+}
+
+// sans jeton CSRF : seul l'en-tête Authorization compte
+async fn api_post<'c>(app: &'c TestApp, uri: &str, token: &str, body: &str) -> LocalResponse<'c> {
+    app.client
+        .post(uri.to_string())
+        .header(ContentType::JSON)
+        .header(Header::new("Authorization", format!("Bearer {}", token)))
+        .body(body.to_string())
+        .dispatch()
+        .await
+}
+
+async fn json_of(response: LocalResponse<'_>) -> Value {
+    json::from_str(&response.into_string().await.unwrap_or_default()).expect("a JSON body")
+}
+
+#[rocket::async_test]
+async fn api_needs_a_valid_token() {
+    let app = TestApp::start().await;
+
+    let response = app.get("/api/persons").dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    let body = json_of(response).await;
+    assert!(body["error"].as_str().unwrap().contains("API token"));
+
+    let response = api_get(&app, "/api/persons", "hr_not-a-token").await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    // le jeton n'est accepté que dans l'en-tête, pas avec une session
+    app.create_member("clara@example.com", "mot de passe");
+    log_in(&app, "clara@example.com", "mot de passe").await;
+    let response = app.get("/api/persons").dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn read_and_write_tokens() {
+    let app = TestApp::start().await;
+    let clara = app.create_member("clara@example.com", "mot de passe");
+    repo::add_ensemble_member(&app.db(), app.ensemble_id(), clara.id.unwrap()).unwrap();
+    log_in(&app, "clara@example.com", "mot de passe").await;
+    let reader = create_token(&app, "tableau de bord", "read", "30").await;
+    let writer = create_token(&app, "import de nuit", "write", "").await;
+    assert!(reader.starts_with("hr_"));
+
+    // la page liste les jetons, sans les montrer
+    let page = app.page("/account").await;
+    assert!(page.contains("tableau de bord"));
+    assert!(page.contains("import de nuit"));
+    assert!(!page.contains(&reader));
+    app.client.get("/logout").dispatch().await;
+
+    // un jeton en lecture seule ne peut pas ajouter
+    let partition = r#"{"title": "Pavane", "full_name": "Gabriel Fauré",
+                        "name": "Orchestre", "create_missing": true}"#;
+    let response = api_post(&app, "/api/partitions", &reader, partition).await;
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(json_of(response).await["error"]
+        .as_str()
+        .unwrap()
+        .contains("read-only"));
+
+    let response = api_post(&app, "/api/partitions", &writer, partition).await;
+    assert_eq!(response.status(), Status::Created);
+    let location = response.headers().get_one("Location").unwrap().to_string();
+    assert!(location.starts_with("/partitions/"));
+    assert_eq!(json_of(response).await["title"], "Pavane");
+
+    let response = api_post(&app, "/api/partitions", &writer, partition).await;
+    assert_eq!(response.status(), Status::Conflict);
+    let response = api_post(
+        &app,
+        "/api/partitions",
+        &writer,
+        r#"{"title": "Gymnopédie", "full_name": "Erik Satie", "name": "Piano"}"#,
+    )
+    .await;
+    assert_eq!(response.status(), Status::NotFound);
+    let response = api_post(&app, "/api/genres", &writer, r#"{"name": "Piano"}"#).await;
+    assert_eq!(response.status(), Status::Created);
+    // un genre de l'ensemble, pas un genre commun
+    assert_eq!(json_of(response).await["ensemble_id"], app.ensemble_id());
+
+    let response = api_get(&app, "/api/partitions?author=faur", &reader).await;
+    assert_eq!(response.status(), Status::Ok);
+    let partitions = json_of(response).await;
+    assert_eq!(partitions.as_array().unwrap().len(), 1);
+    assert_eq!(partitions[0]["full_name"], "Gabriel Fauré");
+    assert_eq!(partitions[0]["name"], "Orchestre");
+    let genres = json_of(api_get(&app, "/api/genres", &reader).await).await;
+    assert_eq!(genres.as_array().unwrap().len(), 2);
+
+    let user = repo::get_user_by_email(&app.db(), "clara@example.com").unwrap();
+    let tokens = repo::get_api_tokens(&app.db(), user.id.unwrap()).unwrap();
+    assert_eq!(tokens.len(), 2);
+    assert!(tokens.iter().all(|t| t.last_used_at.is_some()));
+}
+
+#[rocket::async_test]
+async fn revoked_and_expired_tokens_are_refused() {
+    let app = TestApp::start().await;
+    let user = app.create_member("clara@example.com", "mot de passe");
+    let other = app.create_member("marc@example.com", "mot de passe");
+    repo::add_ensemble_member(&app.db(), app.ensemble_id(), user.id.unwrap()).unwrap();
+    log_in(&app, "clara@example.com", "mot de passe").await;
+    let token = create_token(&app, "import", "write", "90").await;
+    assert_eq!(
+        api_get(&app, "/api/persons", &token).await.status(),
+        Status::Ok
+    );
+
+    // un membre ne révoque pas les jetons d'un autre
+    let (_, other_hash) = hello_rocket::api::new_api_token();
+    let other_token = repo::create_api_token(
+        &app.db(),
+        other.id.unwrap(),
+        app.ensemble_id(),
+        "autre",
+        "read",
+        &other_hash,
+        None,
+    )
+    .unwrap();
+    let response = app
+        .submit(
+            Some("delete"),
+            &format!("/account/tokens/{}", other_token.id),
+            &[],
+        )
+        .await;
+    let page = app.follow(response).await;
+    assert!(page.contains("This token does not exist."));
+
+    let id = repo::get_api_tokens(&app.db(), user.id.unwrap()).unwrap()[0].id;
+    let response = app
+        .submit(Some("delete"), &format!("/account/tokens/{}", id), &[])
+        .await;
+    let page = app.follow(response).await;
+    assert!(page.contains("The token is revoked."));
+    let response = api_get(&app, "/api/persons", &token).await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    // expiré dès sa création : la requête suivante arrive après
+    let (token, token_hash) = hello_rocket::api::new_api_token();
+    repo::create_api_token(
+        &app.db(),
+        user.id.unwrap(),
+        app.ensemble_id(),
+        "expiré",
+        "read",
+        &token_hash,
+        Some(0),
+    )
+    .unwrap();
+    let response = api_get(&app, "/api/persons", &token).await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
@@ -0,0 +1,605 @@
+// Tests d'intégration : listes, ajout, modification, suppression
+// et recherche des personnes, genres et partitions
+
+mod common;
+
+use diesel::sql_types::{Integer, Text};
+use diesel::{sql_query, QueryableByName, RunQueryDsl};
+
+use rocket::http::Status;
+
+use hello_rocket::models::NewPartition;
+use hello_rocket::repo;
+
+use common::TestApp;
+
+// ************************************************************************************************
+// Persons
+
+#[rocket::async_test]
+async fn add_list_and_show_person() {
+    let app = TestApp::start().await;
+    app.log_in_member().await;
+
+    let response = app
+        .submit(None, "/persons/add", &[("full_name", "Gabriel Fauré")])
+        .await;
+    assert_eq!(response.headers().get_one("Location"), Some("/persons"));
+    let page = app.follow(response).await;
+    assert!(page.contains("Person successfully added."));
+    assert!(page.contains("Gabriel Fauré"));
+
+    let person = repo::get_person_by_name(&app.db(), "gabriel faure").unwrap();
+    let page = app.page(&format!("/persons/{}", person.id.unwrap())).await;
+    assert!(page.contains("Gabriel Fauré"));
+}
+
+#[rocket::async_test]
+async fn update_person() {
+    let app = TestApp::start().await;
+    app.log_in_member().await;
+    app.submit(None, "/persons/add", &[("full_name", "Claude Debusy")])
+        .await;
+    let id = repo::get_person_by_name(&app.db(), "Claude Debusy")
+        .unwrap()
+        .id
+        .unwrap();
+
+    // les personnes sont communes à tous les ensembles : un membre les
+    // ajoute, un administrateur seul les change
+    let response = app
+        .submit(
+            Some("put"),
+            &format!("/persons/{}", id),
+            &[("full_name", "Claude Debussy")],
+        )
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+    app.client.get("/logout").dispatch().await;
+    app.log_in_admin().await;
+
+    let response = app
+        .submit(
+            Some("put"),
+            &format!("/persons/{}", id),
+            &[("full_name", "Claude Debussy")],
+        )
+        .await;
+    let page = app.follow(response).await;
+    assert!(page.contains("Person successfully modified."));
+    assert!(page.contains("Claude Debussy"));
+    assert!(!page.contains("Claude Debusy"));
+}
+
+#[rocket::async_test]
+async fn delete_person() {
+    let app = TestApp::start().await;
+    app.log_in_admin().await;
+    app.submit(None, "/persons/add", &[("full_name", "Erik Satie")])
+        .await;
+    let id = repo::get_person_by_name(&app.db(), "Erik Satie")
+        .unwrap()
+        .id
+        .unwrap();
+
+    let response = app
+        .submit(Some("delete"), &format!("/persons/{}", id), &[])
+        .await;
+    let page = app.follow(response).await;
+    assert!(page.contains("Person successfully deleted."));
+    assert!(!page.contains("Erik Satie"));
+
+    let response = app.get(&format!("/persons/{}", id)).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn duplicate_person_leads_to_existing_one() {
+    let app = TestApp::start().await;
+    app.log_in_admin().await;
+    app.submit(None, "/persons/add", &[("full_name", "Gabriel Fauré")])
+        .await;
+    let id = repo::get_person_by_name(&app.db(), "Gabriel Fauré")
+        .unwrap()
+        .id
+        .unwrap();
+
+    let response = app
+        .submit(None, "/persons/add", &[("full_name", "gabriel  faure")])
+        .await;
+    let location = format!("/persons/{}", id);
+    assert_eq!(
+        response.headers().get_one("Location"),
+        Some(location.as_str())
+    );
+    let page = app.follow(response).await;
+    assert!(page.contains("already exists"));
+    assert_eq!(repo::get_list_persons(&app.db()).unwrap().len(), 1);
+
+    // renommer une autre personne avec ce nom
+    app.submit(None, "/persons/add", &[("full_name", "Erik Satie")])
+        .await;
+    let other = repo::get_person_by_name(&app.db(), "Erik Satie")
+        .unwrap()
+        .id
+        .unwrap();
+    let response = app
+        .submit(
+            Some("put"),
+            &format!("/persons/{}", other),
+            &[("full_name", "Gabriel Faure")],
+        )
+        .await;
+    assert_eq!(
+        response.headers().get_one("Location"),
+        Some(location.as_str())
+    );
+    assert!(repo::get_person_by_name(&app.db(), "Erik Satie").is_ok());
+}
+
+// un nom ajouté hors de l'application, sans clé, qui double un nom connu
+#[rocket::async_test]
+async fn duplicate_names_are_merged_only_on_demand() {
+    let app = TestApp::start().await;
+    let c = app.db();
+    for title in &["Requiem", "Pavane"] {
+        repo::create_partition(
+            &c,
+            app.ensemble_id(),
+            &NewPartition {
+                title: title.to_string(),
+                full_name: "Gabriel Fauré".to_string(),
+                name: "Orchestre".to_string(),
+                create_missing: true,
+            },
+        )
+        .unwrap();
+    }
+    let faure = repo::get_person_by_name(&c, "Gabriel Fauré").unwrap().id;
+    sql_query("INSERT INTO persons (full_name) VALUES ('GABRIEL FAURE')")
+        .execute(&c)
+        .unwrap();
+    sql_query(
+        "INSERT INTO partitions (person_id, title, genre_id, ensemble_id)
+         SELECT p.id, t.title, g.id, $1
+         FROM persons p, genres g, (VALUES ('requiem'), ('Élégie')) t (title)
+         WHERE p.full_name = 'GABRIEL FAURE' AND g.name = 'Orchestre'",
+    )
+    .bind::<Integer, _>(app.ensemble_id())
+    .execute(&c)
+    .unwrap();
+
+    // au démarrage (et pour la reconstruction des index) : signalé, pas fusionné
+    let keys = repo::update_name_keys(&c, true).unwrap();
+    assert_eq!(keys.updated, 0);
+    assert_eq!(keys.duplicates.len(), 1);
+    let duplicate = &keys.duplicates[0];
+    assert_eq!(duplicate.name, "GABRIEL FAURE");
+    assert_eq!((Some(duplicate.into_id), duplicate.kind), (faure, "person"));
+    assert_eq!(
+        (duplicate.partitions_moved, duplicate.partitions_deleted),
+        (1, 1)
+    );
+    assert_eq!(repo::get_list_persons(&c).unwrap().len(), 2);
+
+    // `admin dedupe` : rien ne change sans --apply
+    let report = repo::merge_duplicates(&c, false).unwrap();
+    assert_eq!(report.duplicates.len(), 1);
+    assert_eq!(repo::get_list_persons(&c).unwrap().len(), 2);
+    repo::merge_duplicates(&c, true).unwrap();
+    assert_eq!(repo::get_list_persons(&c).unwrap().len(), 1);
+    let mut partitions = sql_query("SELECT title FROM partitions WHERE person_id = $1")
+        .bind::<Integer, _>(faure.unwrap())
+        .load::<Title>(&c)
+        .unwrap()
+        .into_iter()
+        .map(|row| row.title)
+        .collect::<Vec<_>>();
+    partitions.sort();
+    assert_eq!(partitions, vec!["Pavane", "Requiem", "Élégie"]);
+    assert!(repo::merge_duplicates(&c, false)
+        .unwrap()
+        .duplicates
+        .is_empty());
+}
+
+#[derive(QueryableByName)]
+struct Title {
+    #[sql_type = "Text"]
+    title: String,
+}
+
+// ************************************************************************************************
+// Genres
+
+#[rocket::async_test]
+async fn add_update_and_delete_genre() {
+    let app = TestApp::start().await;
+    app.log_in_member().await;
+
+    let response = app
+        .submit(None, "/genres/add", &[("name", "Mélodie")])
+        .await;
+    assert_eq!(response.headers().get_one("Location"), Some("/genres"));
+    let page = app.follow(response).await;
+    assert!(page.contains("Genre successfully added."));
+    assert!(page.contains("Mélodie"));
+
+    let id = repo::get_genre_by_name(&app.db(), app.ensemble_id(), "melodie")
+        .unwrap()
+        .id
+        .unwrap();
+    let page = app.page(&format!("/genres/{}", id)).await;
+    assert!(page.contains("Mélodie"));
+
+    // un genre commun à tous les ensembles : un administrateur seul le change
+    let response = app
+        .submit(
+            Some("put"),
+            &format!("/genres/{}", id),
+            &[("name", "Nocturne")],
+        )
+        .await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("Only an administrator can change"));
+    let response = app
+        .submit(Some("delete"), &format!("/genres/{}", id), &[])
+        .await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("Only an administrator can change"));
+    app.client.get("/logout").dispatch().await;
+    app.log_in_admin().await;
+
+    let response = app
+        .submit(
+            Some("put"),
+            &format!("/genres/{}", id),
+            &[("name", "Nocturne")],
+        )
+        .await;
+    let page = app.follow(response).await;
+    assert!(page.contains("Genre successfully modified."));
+    assert!(page.contains("Nocturne"));
+
+    let response = app
+        .submit(Some("delete"), &format!("/genres/{}", id), &[])
+        .await;
+    let page = app.follow(response).await;
+    assert!(page.contains("Genre successfully deleted."));
+    assert!(!page.contains("Nocturne"));
+}
+
+// ************************************************************************************************
+// Partitions
+
+async fn with_composer_and_genre() -> TestApp {
+    let app = TestApp::start().await;
+    app.log_in_member().await;
+    app.submit(None, "/persons/add", &[("full_name", "Gabriel Fauré")])
+        .await;
+    app.submit(None, "/persons/add", &[("full_name", "Erik Satie")])
+        .await;
+    app.submit(None, "/genres/add", &[("name", "Mélodie")])
+        .await;
+    app.submit(None, "/genres/add", &[("name", "Piano")]).await;
+    app
+}
+
+fn partition_id(app: &TestApp, title: &str) -> i32 {
+    repo::get_list_raw_partitions(&app.db(), app.ensemble_id())
+        .unwrap()
+        .into_iter()
+        .find(|p| p.title == title)
+        .and_then(|p| p.id)
+        .expect("the partition exists")
+}
+
+#[rocket::async_test]
+async fn add_and_show_partition() {
+    let app = with_composer_and_genre().await;
+
+    // les noms sont reconnus sans les accents ni la casse
+    let response = app
+        .submit(
+            None,
+            "/partitions/add",
+            &[
+                ("title", "Après un rêve"),
+                ("full_name", "gabriel faure"),
+                ("name", "melodie"),
+            ],
+        )
+        .await;
+    // on arrive sur la page de la nouvelle partition
+    let id = partition_id(&app, "Après un rêve");
+    let location = format!("/partitions/{}", id);
+    assert_eq!(
+        response.headers().get_one("Location"),
+        Some(location.as_str())
+    );
+    let page = app.follow(response).await;
+    assert!(page.contains("Partition successfully added."));
+    assert!(page.contains("Après un rêve"));
+    assert!(page.contains("Gabriel Fauré"));
+    assert!(page.contains("Mélodie"));
+
+    let page = app.page("/partitions").await;
+    assert!(page.contains("Après un rêve"));
+}
+
+#[rocket::async_test]
+async fn add_partition_creating_composer_and_genre() {
+    let app = TestApp::start().await;
+    app.log_in_member().await;
+
+    let response = app
+        .submit(
+            None,
+            "/partitions/add",
+            &[
+                ("title", "Boléro"),
+                ("full_name", "Maurice Ravel"),
+                ("name", "Orchestre"),
+                ("create_missing", "true"),
+            ],
+        )
+        .await;
+    let page = app.follow(response).await;
+    assert!(page.contains("Partition successfully added."));
+    assert!(page.contains("Boléro"));
+    assert!(page.contains("Maurice Ravel"));
+
+    let c = app.db();
+    assert!(repo::get_person_by_name(&c, "maurice ravel").is_ok());
+    assert!(repo::get_genre_by_name(&c, app.ensemble_id(), "orchestre").is_ok());
+}
+
+#[rocket::async_test]
+async fn add_partition_with_unknown_composer() {
+    let app = with_composer_and_genre().await;
+
+    let response = app
+        .submit(
+            None,
+            "/partitions/add",
+            &[
+                ("title", "Clair de lune"),
+                ("full_name", "Gabriel Faure"),
+                ("name", "Piano"),
+            ],
+        )
+        .await;
+    let page = app.follow(response).await;
+    assert!(page.contains("Partition successfully added."));
+
+    let response = app
+        .submit(
+            None,
+            "/partitions/add",
+            &[
+                ("title", "Gymnopédie n° 1"),
+                ("full_name", "Eric Satie"),
+                ("name", "Piano"),
+            ],
+        )
+        .await;
+    let page = app.follow(response).await;
+    assert!(page.contains("Unknown musician"));
+    assert!(page.contains("Erik Satie"));
+    assert!(!page.contains("Gymnopédie"));
+    // rien n'est créé sans la case à cocher
+    assert!(repo::get_person_by_name(&app.db(), "Eric Satie").is_err());
+}
+
+#[rocket::async_test]
+async fn duplicate_partition_leads_to_existing_one() {
+    let app = with_composer_and_genre().await;
+    let fields = [
+        ("title", "Pavane"),
+        ("full_name", "Gabriel Fauré"),
+        ("name", "Piano"),
+    ];
+    app.submit(None, "/partitions/add", &fields).await;
+    let id = partition_id(&app, "Pavane");
+
+    let response = app
+        .submit(
+            None,
+            "/partitions/add",
+            &[
+                ("title", "pavane"),
+                ("full_name", "Gabriel Fauré"),
+                ("name", "Mélodie"),
+            ],
+        )
+        .await;
+    let location = format!("/partitions/{}", id);
+    assert_eq!(
+        response.headers().get_one("Location"),
+        Some(location.as_str())
+    );
+    let page = app.follow(response).await;
+    assert!(page.contains("already has a partition"));
+    assert_eq!(
+        repo::get_list_raw_partitions(&app.db(), app.ensemble_id())
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[rocket::async_test]
+async fn update_and_delete_partition() {
+    let app = with_composer_and_genre().await;
+    app.submit(
+        None,
+        "/partitions/add",
+        &[
+            ("title", "Gymnopedie"),
+            ("full_name", "Erik Satie"),
+            ("name", "Mélodie"),
+        ],
+    )
+    .await;
+    let id = partition_id(&app, "Gymnopedie");
+
+    let response = app
+        .submit(
+            Some("put"),
+            &format!("/partitions/{}", id),
+            &[
+                ("title", "Gymnopédie n° 1"),
+                ("full_name", "Erik Satie"),
+                ("name", "Piano"),
+            ],
+        )
+        .await;
+    let page = app.follow(response).await;
+    assert!(page.contains("Partition successfully modified."));
+    let page = app.page(&format!("/partitions/{}", id)).await;
+    assert!(page.contains("Gymnopédie n° 1"));
+    assert!(page.contains("Piano"));
+
+    let response = app
+        .submit(Some("delete"), &format!("/partitions/{}", id), &[])
+        .await;
+    let page = app.follow(response).await;
+    assert!(page.contains("Partition successfully deleted."));
+    assert!(!page.contains("Gymnopédie"));
+}
+
+#[rocket::async_test]
+async fn find_partitions() {
+    let app = with_composer_and_genre().await;
+    for (title, composer, genre) in [
+        ("Après un rêve", "Gabriel Fauré", "Mélodie"),
+        ("Pavane", "Gabriel Fauré", "Piano"),
+        ("Gymnopédie n° 1", "Erik Satie", "Piano"),
+    ]
+    .iter()
+    {
+        app.submit(
+            None,
+            "/partitions/add",
+            &[("title", title), ("full_name", composer), ("name", genre)],
+        )
+        .await;
+    }
+
+    let page = app.page("/partitions?q=pav").await;
+    assert!(page.contains("Pavane"));
+    assert!(!page.contains("Après un rêve"));
+    assert!(!page.contains("Gymnopédie"));
+
+    let page = app.page("/partitions?author=faur&genre=piano").await;
+    assert!(page.contains("Pavane"));
+    assert!(!page.contains("Après un rêve"));
+
+    let page = app.page("/partitions?author=satie").await;
+    assert!(page.contains("Gymnopédie"));
+    assert!(!page.contains("Pavane"));
+}
+
+#[rocket::async_test]
+async fn bulk_actions_on_selected_partitions() {
+    let app = with_composer_and_genre().await;
+    for title in ["Pavane", "Élégie", "Sicilienne"].iter() {
+        app.submit(
+            None,
+            "/partitions/add",
+            &[
+                ("title", title),
+                ("full_name", "Gabriel Fauré"),
+                ("name", "Mélodie"),
+            ],
+        )
+        .await;
+    }
+    let pavane = partition_id(&app, "Pavane").to_string();
+    let elegie = partition_id(&app, "Élégie").to_string();
+    let selected = [("ids", pavane.as_str()), ("ids", elegie.as_str())];
+
+    let mut fields = vec![("action", "change-genre"), ("value", "piano")];
+    fields.extend_from_slice(&selected);
+    let page = app
+        .follow(app.submit(None, "/partitions/bulk", &fields).await)
+        .await;
+    assert!(page.contains("2 partitions moved to genre"));
+    let page = app.page("/partitions?genre=piano").await;
+    assert!(page.contains("Pavane"));
+    assert!(page.contains("Élégie"));
+    assert!(!page.contains("Sicilienne"));
+
+    let mut fields = vec![("action", "add-tag"), ("value", "Concert de Noël")];
+    fields.extend_from_slice(&selected);
+    let page = app
+        .follow(app.submit(None, "/partitions/bulk", &fields).await)
+        .await;
+    assert!(page.contains("added to 2 partitions"));
+    let page = app.page(&format!("/partitions/{}", pavane)).await;
+    assert!(page.contains("Concert de Noël"));
+
+    let mut fields = vec![("action", "export")];
+    fields.extend_from_slice(&selected);
+    let response = app.submit(None, "/partitions/bulk", &fields).await;
+    assert_eq!(response.status(), Status::Ok);
+    let csv = response.into_string().await.unwrap_or_default();
+    assert!(csv.starts_with("title,composer,genre,tags"));
+    assert!(csv.contains("Pavane,Gabriel Fauré,Piano,Concert de Noël"));
+    assert!(!csv.contains("Sicilienne"));
+
+    // un genre inconnu : rien ne change
+    let mut fields = vec![("action", "change-genre"), ("value", "Opéra")];
+    fields.extend_from_slice(&selected);
+    let page = app
+        .follow(app.submit(None, "/partitions/bulk", &fields).await)
+        .await;
+    assert!(page.contains("Unknown genre"));
+
+    let mut fields = vec![("action", "delete")];
+    fields.extend_from_slice(&selected);
+    let page = app
+        .follow(app.submit(None, "/partitions/bulk", &fields).await)
+        .await;
+    assert!(page.contains("2 partitions deleted."));
+    assert_eq!(
+        repo::get_list_raw_partitions(&app.db(), app.ensemble_id())
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+// un point par mois, du premier ajout à ce mois-ci, même sans ajout
+#[rocket::async_test]
+async fn catalogue_growth_has_every_month() {
+    let app = TestApp::start().await;
+    let c = app.db();
+    for title in &["Pavane", "Requiem"] {
+        repo::create_partition(
+            &c,
+            app.ensemble_id(),
+            &NewPartition {
+                title: title.to_string(),
+                full_name: "Gabriel Fauré".to_string(),
+                name: "Orchestre".to_string(),
+                create_missing: true,
+            },
+        )
+        .unwrap();
+    }
+    sql_query(
+        "UPDATE partitions SET created_at = now() - interval '2 months' WHERE title = 'Pavane'",
+    )
+    .execute(&c)
+    .unwrap();
+
+    let months = repo::count_partitions_by_month(&c, app.ensemble_id()).unwrap();
+    let counts = months.iter().map(|d| d.count).collect::<Vec<_>>();
+    assert_eq!(counts, vec![1, 0, 1]);
+    assert!(repo::count_partitions_by_month(&c, 0).unwrap().is_empty());
+}
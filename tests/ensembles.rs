@@ -0,0 +1,473 @@
+// Tests d'intégration : plusieurs ensembles dans la même instance, chacun
+// avec sa bibliothèque ; membres, choix de la bibliothèque, jetons d'API
+
+mod common;
+
+use rocket::http::{ContentType, Cookie, Header, Status};
+
+use hello_rocket::auth::hash_password;
+use hello_rocket::models::{NewPartition, User, STATUS_ACTIVE};
+use hello_rocket::repo;
+
+use common::TestApp;
+
+async fn log_in(app: &TestApp, email: &str, password: &str) {
+    let response = app
+        .submit(None, "/login", &[("email", email), ("password", password)])
+        .await;
+    assert_eq!(response.headers().get_one("Location"), Some("/"));
+}
+
+fn create_admin(app: &TestApp) -> User {
+    repo::create_user(
+        &app.db(),
+        &User {
+            id: None,
+            email: "admin@example.com".to_string(),
+            password_hash: hash_password("secret").unwrap(),
+            is_admin: true,
+            status: STATUS_ACTIVE.to_string(),
+            display_name: String::new(),
+            locale: String::new(),
+        },
+    )
+    .unwrap()
+}
+
+// un nouvel ensemble dont `member` est le seul membre
+fn ensemble_of(app: &TestApp, name: &str, member: &User) -> i32 {
+    let c = app.db();
+    let id = repo::create_ensemble(&c, name).unwrap().id.unwrap();
+    repo::add_ensemble_member(&c, id, member.id.unwrap()).unwrap();
+    id
+}
+
+async fn add_partition(app: &TestApp, title: &str, composer: &str, genre: &str) -> String {
+    let response = app
+        .submit(
+            None,
+            "/partitions/add",
+            &[
+                ("title", title),
+                ("full_name", composer),
+                ("name", genre),
+                ("create_missing", "true"),
+            ],
+        )
+        .await;
+    app.follow(response).await
+}
+
+#[rocket::async_test]
+async fn admin_creates_ensembles_and_adds_members() {
+    let app = TestApp::start().await;
+    create_admin(&app);
+    app.create_member("clara@example.com", "mot de passe");
+    log_in(&app, "admin@example.com", "secret").await;
+
+    let response = app
+        .submit(None, "/admin/ensembles", &[("name", " Chœur  de chambre ")])
+        .await;
+    let page = app.follow(response).await;
+    assert!(page.contains("Chœur de chambre"));
+    assert!(page.contains("is created."));
+    let response = app
+        .submit(None, "/admin/ensembles", &[("name", "chœur de chambre")])
+        .await;
+    assert!(app.follow(response).await.contains("already exists."));
+
+    let id = repo::get_ensemble_by_name(&app.db(), "Chœur de chambre")
+        .unwrap()
+        .id
+        .unwrap();
+    let members = format!("/admin/ensembles/{}/members", id);
+    let response = app
+        .submit(None, &members, &[("email", "Clara@Example.com")])
+        .await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("clara@example.com is now a member of the ensemble."));
+    let response = app
+        .submit(None, &members, &[("email", "clara@example.com")])
+        .await;
+    assert!(app.follow(response).await.contains("is already a member"));
+    let response = app
+        .submit(None, &members, &[("email", "marc@example.com")])
+        .await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("No account uses the address marc@example.com."));
+
+    let clara = repo::get_user_by_email(&app.db(), "clara@example.com").unwrap();
+    let members = repo::get_ensemble_members(&app.db(), id).unwrap();
+    assert_eq!(members.len(), 1);
+    assert_eq!(members[0].email, "clara@example.com");
+
+    let response = app
+        .submit(
+            Some("delete"),
+            &format!("/admin/ensembles/{}/members/{}", id, clara.id.unwrap()),
+            &[],
+        )
+        .await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("The member is removed from the ensemble."));
+    assert!(repo::get_ensemble_members(&app.db(), id)
+        .unwrap()
+        .is_empty());
+}
+
+#[rocket::async_test]
+async fn each_ensemble_has_its_own_library() {
+    let app = TestApp::start().await;
+    let clara = app.create_member("clara@example.com", "mot de passe");
+    let choir = ensemble_of(&app, "Chœur de chambre", &clara);
+
+    // un membre d'un seul ensemble travaille dans sa bibliothèque
+    log_in(&app, "clara@example.com", "mot de passe").await;
+    let page = add_partition(&app, "Pavane", "Gabriel Fauré", "Orchestre").await;
+    assert!(page.contains("Partition successfully added."));
+    let page = app.page("/partitions").await;
+    assert!(page.contains("Library: Chœur de chambre"));
+    assert!(page.contains("Pavane"));
+    let id = repo::get_list_raw_partitions(&app.db(), choir).unwrap()[0]
+        .id
+        .unwrap();
+
+    // un genre réservé à l'ensemble, un autre commun
+    let choir_id = choir.to_string();
+    let response = app
+        .submit(
+            None,
+            "/genres/add",
+            &[("name", "Madrigal"), ("ensemble_id", &choir_id)],
+        )
+        .await;
+    assert!(app.follow(response).await.contains("(private)"));
+    let response = app.submit(None, "/genres/add", &[("name", "Piano")]).await;
+    app.follow(response).await;
+    app.client.get("/logout").dispatch().await;
+
+    // sans compte, la première bibliothèque : ni la partition, ni les genres
+    // réservés, y compris celui créé avec la partition
+    let page = app.page("/partitions").await;
+    assert!(page.contains("Library: Bibliothèque"));
+    assert!(!page.contains("Pavane"));
+    let response = app.get(&format!("/partitions/{}", id)).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+    let page = app.page("/genres").await;
+    assert!(page.contains("Piano"));
+    assert!(!page.contains("Orchestre"));
+    assert!(!page.contains("Madrigal"));
+
+    // ... et en lecture seule
+    let response = app
+        .submit(None, "/genres/add", &[("name", "Requiem")])
+        .await;
+    assert_eq!(response.headers().get_one("Location"), Some("/login"));
+
+    // le même titre peut exister dans les deux bibliothèques
+    app.log_in_member().await;
+    let page = add_partition(&app, "Pavane", "Gabriel Fauré", "Orchestre").await;
+    assert!(page.contains("Partition successfully added."));
+    // un genre réservé ne prend pas le nom aux autres bibliothèques
+    let library = app.ensemble_id();
+    let library_id = library.to_string();
+    let response = app
+        .submit(
+            None,
+            "/genres/add",
+            &[("name", "Madrigal"), ("ensemble_id", &library_id)],
+        )
+        .await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("Genre successfully added."));
+    let page = add_partition(&app, "Ave verum", "Mozart", "Madrigal").await;
+    assert!(page.contains("Partition successfully added."));
+    // un genre commun sert tel quel, sans en créer un réservé
+    let page = add_partition(&app, "Gymnopédie", "Erik Satie", "piano").await;
+    assert!(page.contains("Partition successfully added."));
+    let c = app.db();
+    assert!(repo::get_genre_by_key(&c, None, "piano").is_ok());
+    assert!(repo::get_genre_by_key(&c, Some(library), "piano").is_err());
+    // un genre créé avec une partition est réservé à sa bibliothèque
+    let orchestra = repo::get_genre_by_key(&c, Some(choir), "orchestre").unwrap();
+    assert_eq!(orchestra.ensemble_id, Some(choir));
+    let own_orchestra = repo::get_genre_by_key(&c, Some(library), "orchestre").unwrap();
+    assert_eq!(own_orchestra.ensemble_id, Some(library));
+    assert!(repo::get_genre_by_key(&c, None, "orchestre").is_err());
+    let madrigal = repo::get_genre_by_key(&c, Some(library), "madrigal").unwrap();
+    assert_eq!(
+        repo::get_genre_by_name(&c, library, "Madrigal").unwrap().id,
+        madrigal.id
+    );
+    assert_ne!(
+        repo::get_genre_by_name(&c, choir, "Madrigal").unwrap().id,
+        madrigal.id
+    );
+    assert_eq!(repo::get_list_raw_partitions(&c, choir).unwrap().len(), 1);
+    assert_eq!(repo::get_list_raw_partitions(&c, library).unwrap().len(), 3);
+    assert_eq!(repo::get_list_genres(&c, choir).unwrap().len(), 3);
+    assert_eq!(repo::get_list_genres(&c, library).unwrap().len(), 3);
+}
+
+#[rocket::async_test]
+async fn members_choose_among_their_ensembles() {
+    let app = TestApp::start().await;
+    let clara = app.create_member("clara@example.com", "mot de passe");
+    let choir = ensemble_of(&app, "Chœur de chambre", &clara);
+    let orchestra = ensemble_of(&app, "Orchestre d'harmonie", &clara);
+
+    log_in(&app, "clara@example.com", "mot de passe").await;
+    let page = app.page("/ensembles").await;
+    assert!(page.contains("Chœur de chambre"));
+    assert!(page.contains("Orchestre d&#x27;harmonie"));
+    assert!(!page.contains("Bibliothèque"));
+
+    let response = app
+        .get(&format!("/ensembles/{}/choose", orchestra))
+        .dispatch()
+        .await;
+    assert_eq!(response.headers().get_one("Location"), Some("/partitions"));
+    assert!(app
+        .follow(response)
+        .await
+        .contains("You are now working in the"));
+    assert!(app
+        .page("/genres")
+        .await
+        .contains("Library: Orchestre d&#x27;harmonie"));
+
+    // pas la bibliothèque d'un ensemble dont on n'est pas membre
+    let response = app
+        .get(&format!("/ensembles/{}/choose", app.ensemble_id()))
+        .dispatch()
+        .await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("You are not a member of this ensemble."));
+
+    // le cookie d'une bibliothèque qui n'est plus permise est ignoré
+    repo::remove_ensemble_member(&app.db(), orchestra, clara.id.unwrap()).unwrap();
+    let page = app.page("/partitions").await;
+    assert!(page.contains("Library: Chœur de chambre"));
+    assert!(choir < orchestra);
+}
+
+// sans compte ou sans ensemble : la bibliothèque par défaut seulement,
+// les autres ne se lisent pas
+#[rocket::async_test]
+async fn visitors_only_read_the_default_library() {
+    let app = TestApp::start().await;
+    let clara = app.create_member("clara@example.com", "mot de passe");
+    let choir = ensemble_of(&app, "Chœur de chambre", &clara);
+    let pavane = repo::create_partition(
+        &app.db(),
+        choir,
+        &NewPartition {
+            title: "Pavane".to_string(),
+            full_name: "Gabriel Fauré".to_string(),
+            name: "Orchestre".to_string(),
+            create_missing: true,
+        },
+    )
+    .unwrap()
+    .id
+    .unwrap();
+    app.create_member("marc@example.com", "mot de passe");
+
+    for account in &[None, Some("marc@example.com")] {
+        if let Some(email) = account {
+            log_in(&app, email, "mot de passe").await;
+        }
+        let page = app.page("/ensembles").await;
+        assert!(page.contains("Bibliothèque"));
+        assert!(!page.contains("Chœur de chambre"));
+        let response = app
+            .get(&format!("/ensembles/{}/choose", choir))
+            .dispatch()
+            .await;
+        assert!(app
+            .follow(response)
+            .await
+            .contains("You are not a member of this ensemble."));
+        let page = app.page("/partitions").await;
+        assert!(page.contains("Library: Bibliothèque"));
+        assert!(!page.contains("Pavane"));
+        let response = app.get(&format!("/partitions/{}", pavane)).dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+}
+
+#[rocket::async_test]
+async fn api_tokens_act_on_their_ensemble() {
+    let app = TestApp::start().await;
+    let clara = app.create_member("clara@example.com", "mot de passe");
+    let choir = ensemble_of(&app, "Chœur de chambre", &clara);
+    let orchestra = ensemble_of(&app, "Orchestre d'harmonie", &clara);
+
+    log_in(&app, "clara@example.com", "mot de passe").await;
+    let (token, token_hash) = hello_rocket::api::new_api_token();
+    repo::create_api_token(
+        &app.db(),
+        clara.id.unwrap(),
+        choir,
+        "import",
+        "write",
+        &token_hash,
+        None,
+    )
+    .unwrap();
+    let page = app.page("/account").await;
+    assert!(page.contains("The new token will act on the"));
+    assert!(page.contains("<td>Chœur de chambre</td>"));
+
+    let response = app
+        .client
+        .post("/api/partitions")
+        .header(ContentType::JSON)
+        .header(Header::new("Authorization", format!("Bearer {}", token)))
+        .body(
+            r#"{"title": "Pavane", "full_name": "Gabriel Fauré",
+                "name": "Orchestre", "create_missing": true}"#,
+        )
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+    let c = app.db();
+    assert_eq!(repo::get_list_raw_partitions(&c, choir).unwrap().len(), 1);
+    assert!(repo::get_list_raw_partitions(&c, orchestra)
+        .unwrap()
+        .is_empty());
+
+    // plus membre de l'ensemble : le jeton ne sert plus
+    repo::remove_ensemble_member(&c, choir, clara.id.unwrap()).unwrap();
+    let response = app
+        .client
+        .get("/api/partitions")
+        .header(Header::new("Authorization", format!("Bearer {}", token)))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn only_members_change_a_library() {
+    let app = TestApp::start().await;
+    let admin = create_admin(&app);
+    let clara = app.create_member("clara@example.com", "mot de passe");
+    let choir = ensemble_of(&app, "Chœur de chambre", &clara);
+    let c = app.db();
+    let partition = repo::create_partition(
+        &c,
+        choir,
+        &NewPartition {
+            title: "Pavane".to_string(),
+            full_name: "Gabriel Fauré".to_string(),
+            name: "Orchestre".to_string(),
+            create_missing: true,
+        },
+    )
+    .unwrap()
+    .id
+    .unwrap();
+
+    // un cookie fabriqué ne choisit pas la bibliothèque
+    let page = app
+        .get("/partitions")
+        .cookie(Cookie::new("ensemble", choir.to_string()))
+        .dispatch()
+        .await
+        .into_string()
+        .await
+        .unwrap_or_default();
+    assert!(page.contains("Library: Bibliothèque"));
+    let response = app
+        .upload(
+            &format!("/partitions/{}/files", partition),
+            "scan.pdf",
+            b"%PDF",
+            &[],
+        )
+        .await;
+    assert_eq!(response.headers().get_one("Location"), Some("/login"));
+
+    // un administrateur lit toutes les bibliothèques, sans les modifier
+    log_in(&app, "admin@example.com", "secret").await;
+    let response = app
+        .get(&format!("/ensembles/{}/choose", choir))
+        .dispatch()
+        .await;
+    app.follow(response).await;
+    assert!(app.page("/partitions").await.contains("Pavane"));
+    let response = app
+        .submit(
+            Some("put"),
+            &format!("/partitions/{}", partition),
+            &[
+                ("title", "Pavane, op. 50"),
+                ("full_name", "Gabriel Fauré"),
+                ("name", "Orchestre"),
+            ],
+        )
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+    let response = app
+        .submit(Some("delete"), &format!("/partitions/{}", partition), &[])
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+    let response = app
+        .submit(
+            None,
+            "/account/tokens",
+            &[("name", "import"), ("scope", "write")],
+        )
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    // ... ni avec un jeton d'API
+    let (token, token_hash) = hello_rocket::api::new_api_token();
+    repo::create_api_token(
+        &c,
+        admin.id.unwrap(),
+        choir,
+        "import",
+        "write",
+        &token_hash,
+        None,
+    )
+    .unwrap();
+    let response = app
+        .client
+        .post("/api/genres")
+        .header(ContentType::JSON)
+        .header(Header::new("Authorization", format!("Bearer {}", token)))
+        .body(r#"{"name": "Motet"}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(
+        repo::get_partition_detail(&c, choir, partition)
+            .unwrap()
+            .0
+            .title,
+        "Pavane"
+    );
+
+    // membre, il la modifie
+    repo::add_ensemble_member(&c, choir, admin.id.unwrap()).unwrap();
+    let response = app
+        .submit(Some("delete"), &format!("/partitions/{}", partition), &[])
+        .await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("Partition successfully deleted."));
+}
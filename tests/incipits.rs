@@ -0,0 +1,155 @@
+// Tests d'intégration : incipits en notation ABC, dessin SVG et recherche mélodique
+
+mod common;
+
+use rocket::http::{ContentType, Status};
+
+use hello_rocket::abc;
+use hello_rocket::models::NewPartition;
+use hello_rocket::repo;
+
+use common::TestApp;
+
+fn add_partition(app: &TestApp, title: &str) -> i32 {
+    repo::create_partition(
+        &app.db(),
+        app.ensemble_id(),
+        &NewPartition {
+            title: title.to_string(),
+            full_name: "Franz Schubert".to_string(),
+            name: "Lied".to_string(),
+            create_missing: true,
+        },
+    )
+    .unwrap()
+    .id
+    .unwrap()
+}
+
+#[test]
+fn melodies_are_compared_by_their_intervals() {
+    // do ré mi do, en do majeur puis en sol majeur (fa dièse à l'armure)
+    let c_major = abc::parse("CDEC").unwrap();
+    let g_major = abc::parse("M:4/4\nL:1/4\nK:G\nGABG |").unwrap();
+    assert_eq!(c_major.melody_key(), ",2,2,-4,");
+    assert_eq!(g_major.melody_key(), c_major.melody_key());
+
+    // armure, altérations valables toute la mesure, octaves, liaisons et accords
+    let key = abc::parse("K:D\nF ^G =F G | F2- F c' [CEg] C,").unwrap();
+    assert_eq!(key.melody_key(), ",2,-3,3,-2,19,-6,-30,");
+    let key = abc::parse("K:Bb dor\nBE").unwrap();
+    assert_eq!(key.melody_key(), ",-7,");
+
+    assert!(matches!(
+        abc::parse("X:1\nT:Ave Maria"),
+        Err(abc::AbcError::Empty)
+    ));
+    assert!(matches!(
+        abc::parse("K:H\nCDE"),
+        Err(abc::AbcError::Header(_))
+    ));
+    assert_eq!(abc::parse("z2 C").unwrap().melody_key(), "");
+}
+
+#[test]
+fn incipits_are_drawn_on_a_staff() {
+    let svg = abc::parse("M:3/4\nL:1/8\nK:Eb\nB2 e>f g2 | z4 b2- | b6 |]")
+        .unwrap()
+        .render_svg();
+    assert!(svg.starts_with("<svg "));
+    assert!(svg.ends_with("</svg>"));
+    assert_eq!(svg.matches("<ellipse").count(), 6);
+    // trois bémols à l'armure et le chiffrage
+    assert_eq!(svg.matches("&#x266D;").count(), 3);
+    assert!(svg.contains(">3</text>"));
+
+    // seulement les quatre premières mesures
+    let svg = abc::parse("C4 | D4 | E4 | F4 | G4 | A4")
+        .unwrap()
+        .render_svg();
+    assert_eq!(svg.matches("<ellipse").count(), 4);
+}
+
+#[test]
+fn lengths_out_of_range_are_refused() {
+    // des durées hors des bornes, dont certaines feraient déborder le calcul
+    for text in &[
+        "L:1/65536\nA65536",
+        "L:1/65536\nA",
+        "A65536",
+        "A/65536",
+        "A99999999999",
+        "A////////////",
+        "L:1/1024\n[CEG]/2",
+        "A>>>>>>>>>>>>>>>>>>>>B",
+    ] {
+        match abc::parse(text) {
+            Err(abc::AbcError::Length) => {}
+            other => panic!("{:?}: unexpected {:?}", text, other.map(|_| ())),
+        }
+    }
+    // des durées plus longues ou plus courtes, mais lisibles
+    assert!(abc::parse("L:1/64\nA16 B/ c//").is_ok());
+    assert!(abc::parse("L:1/1024\nA1024").is_ok());
+}
+
+#[rocket::async_test]
+async fn incipits_are_saved_shown_and_searched() {
+    let app = TestApp::start().await;
+    app.log_in_member().await;
+    let ave = add_partition(&app, "Ave Maria");
+    let truite = add_partition(&app, "La Truite");
+    let page = format!("/partitions/{}", ave);
+    let incipit = format!("/partitions/{}/incipit", ave);
+
+    let response = app
+        .submit(Some("put"), &incipit, &[("incipit", "K:H\nCDE")])
+        .await;
+    assert!(app.follow(response).await.contains("Unreadable incipit"));
+    let response = app.get(&format!("{}.svg", incipit)).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = app
+        .submit(
+            Some("put"),
+            &incipit,
+            &[("incipit", "M:4/4\nL:1/4\nK:Bb\nB2 A>B | c2 B2 | d2")],
+        )
+        .await;
+    let html = app.follow(response).await;
+    assert!(html.contains("Incipit saved."));
+    assert!(html.contains(&format!(r#"src="{}.svg""#, incipit)));
+    let response = app.get(&format!("{}.svg", incipit)).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::SVG));
+    assert!(response.into_string().await.unwrap().contains("<ellipse"));
+
+    // la liste montre l'incipit des seules partitions qui en ont un
+    let list = app.page("/partitions").await;
+    assert!(list.contains(&format!(r#"src="{}.svg""#, incipit)));
+    assert!(!list.contains(&format!("/partitions/{}/incipit.svg", truite)));
+
+    // la même mélodie en do (do si do ré), sans la fin
+    let found = app.page("/partitions?melody=c+B+c+d").await;
+    assert!(found.contains("Ave Maria"));
+    assert!(!found.contains("La Truite"));
+    let found = app.page("/partitions?melody=G+A+G+A").await;
+    assert!(!found.contains("Ave Maria"));
+
+    // une autre bibliothèque ne le modifie pas ; vide, il est effacé
+    let c = app.db();
+    let other = repo::create_ensemble(&c, "Chœur de chambre")
+        .unwrap()
+        .id
+        .unwrap();
+    assert_eq!(
+        repo::set_partition_incipit(&c, other, ave, "CDE").unwrap(),
+        0
+    );
+    let response = app.submit(Some("put"), &incipit, &[("incipit", " ")]).await;
+    assert!(app.follow(response).await.contains("Incipit removed."));
+    assert!(!app.page(&page).await.contains(&format!("{}.svg", incipit)));
+    assert!(repo::get_incipit_ids(&c, app.ensemble_id())
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,207 @@
+// Tests d'intégration : file des tâches de fond, reprises après une erreur,
+// worker, pages des tâches et annulation
+
+mod common;
+
+use std::time::Duration;
+
+use diesel::RunQueryDsl;
+
+use rocket::http::Status;
+
+use hello_rocket::auth::hash_password;
+use hello_rocket::models::{
+    Job, User, JOB_CANCELLED, JOB_DONE, JOB_FAILED, JOB_PENDING, JOB_RUNNING, STATUS_ACTIVE,
+};
+use hello_rocket::repo;
+
+use common::TestApp;
+
+// un administrateur qui n'est membre d'aucun ensemble : il ne voit que les
+// tâches de l'administration
+async fn log_in_as_admin(app: &TestApp) {
+    repo::create_user(
+        &app.db(),
+        &User {
+            id: None,
+            email: "admin@example.com".to_string(),
+            password_hash: hash_password("secret").unwrap(),
+            is_admin: true,
+            status: STATUS_ACTIVE.to_string(),
+            display_name: String::new(),
+            locale: String::new(),
+        },
+    )
+    .unwrap();
+    let response = app
+        .submit(
+            None,
+            "/login",
+            &[("email", "admin@example.com"), ("password", "secret")],
+        )
+        .await;
+    assert_eq!(response.headers().get_one("Location"), Some("/"));
+}
+
+// le worker fait les tâches en tâche de fond : on attend qu'il ait fini
+async fn finished(app: &TestApp, job_id: i32) -> Job {
+    for _ in 0..50 {
+        let job = repo::get_job(&app.db(), Some(app.ensemble_id()), true, job_id).unwrap();
+        if job.status != JOB_PENDING && job.status != JOB_RUNNING {
+            return job;
+        }
+        rocket::tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("job {} not finished", job_id);
+}
+
+#[rocket::async_test]
+async fn failed_jobs_are_retried_later_then_given_up() {
+    // le worker ne se réveille pas tout seul pendant le test
+    let app = TestApp::start_with(|figment| figment.merge(("jobs.poll_interval", 3600))).await;
+    let c = app.db();
+    let ensemble = app.ensemble_id();
+
+    let job = repo::enqueue_job(&c, "test", Some(ensemble), r#"{"n": 1}"#, 2).unwrap();
+    assert_eq!(job.status, JOB_PENDING);
+    assert_eq!(job.payload, r#"{"n": 1}"#);
+    let claimed = repo::claim_job(&c).unwrap().unwrap();
+    assert_eq!(claimed.id, job.id);
+    assert_eq!(claimed.attempts, 1);
+    assert!(repo::claim_job(&c).unwrap().is_none());
+
+    // repris plus tard, pas tout de suite
+    assert_eq!(repo::fail_job(&c, job.id, "boom", 60, false).unwrap(), 1);
+    let job = repo::get_job(&c, Some(ensemble), false, job.id).unwrap();
+    assert_eq!(job.status, JOB_PENDING);
+    assert_eq!(job.error, "boom");
+    assert!(job.run_at > job.created_at);
+    assert!(repo::claim_job(&c).unwrap().is_none());
+
+    // au dernier essai, la tâche échoue pour de bon
+    diesel::sql_query("UPDATE jobs SET run_at = now()")
+        .execute(&c)
+        .unwrap();
+    let claimed = repo::claim_job(&c).unwrap().unwrap();
+    assert_eq!(claimed.attempts, 2);
+    repo::fail_job(&c, job.id, "boom again", 60, false).unwrap();
+    let job = repo::get_job(&c, Some(ensemble), false, job.id).unwrap();
+    assert_eq!(job.status, JOB_FAILED);
+    assert!(job.finished_at.is_some());
+    assert!(repo::claim_job(&c).unwrap().is_none());
+    // une tâche finie ne change plus
+    assert_eq!(repo::finish_job(&c, job.id).unwrap(), 0);
+}
+
+#[rocket::async_test]
+async fn jobs_are_listed_and_cancelled() {
+    let app = TestApp::start_with(|figment| figment.merge(("jobs.poll_interval", 3600))).await;
+    let response = app.get("/jobs").dispatch().await;
+    assert_eq!(response.headers().get_one("Location"), Some("/login"));
+    app.log_in_admin().await;
+
+    let c = app.db();
+    let ensemble = app.ensemble_id();
+    let job = repo::enqueue_job(&c, "thumbnail", Some(ensemble), r#"{"file_id": 0}"#, 3).unwrap();
+    let list = app.page("/jobs").await;
+    assert!(list.contains("File thumbnail"));
+    assert!(list.contains(&format!("/jobs/{}", job.id)));
+
+    let uri = format!("/jobs/{}/cancel", job.id);
+    let response = app.submit(None, &uri, &[]).await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains(&format!("Job #{} cancelled.", job.id)));
+    let response = app.submit(None, &uri, &[]).await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains(&format!("Job #{} is already over.", job.id)));
+    let job = repo::get_job(&c, Some(ensemble), false, job.id).unwrap();
+    assert_eq!(job.status, JOB_CANCELLED);
+    assert!(repo::claim_job(&c).unwrap().is_none());
+
+    // les tâches d'une autre bibliothèque ne se voient pas
+    let other = repo::create_ensemble(&c, "Maîtrise").unwrap().id.unwrap();
+    let job = repo::enqueue_job(&c, "thumbnail", Some(other), "{}", 3).unwrap();
+    let response = app.get(&format!("/jobs/{}", job.id)).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+    let response = app
+        .submit(None, &format!("/jobs/{}/cancel", job.id), &[])
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    let job = repo::get_job(&c, Some(other), false, job.id).unwrap();
+    assert_eq!(job.status, JOB_PENDING);
+    assert!(!app
+        .page("/jobs")
+        .await
+        .contains(&format!("/jobs/{}\"", job.id)));
+}
+
+#[rocket::async_test]
+async fn jobs_are_only_shown_to_members() {
+    let app = TestApp::start_with(|figment| figment.merge(("jobs.poll_interval", 3600))).await;
+    let c = app.db();
+    let job = repo::enqueue_job(&c, "thumbnail", Some(app.ensemble_id()), "{}", 3).unwrap();
+    let admin_job = repo::enqueue_job(&c, "reindex", None, "{}", 3).unwrap();
+    let page = format!("/jobs/{}", job.id);
+    let cancel = format!("/jobs/{}/cancel", job.id);
+
+    // un compte sans ensemble n'a accès à aucune tâche
+    app.create_member("marc@example.com", "secret");
+    app.submit(
+        None,
+        "/login",
+        &[("email", "marc@example.com"), ("password", "secret")],
+    )
+    .await;
+    assert_eq!(
+        app.get("/jobs").dispatch().await.status(),
+        Status::Forbidden
+    );
+    assert_eq!(app.get(&page).dispatch().await.status(), Status::Forbidden);
+    let response = app.submit(None, &cancel, &[]).await;
+    assert_eq!(response.status(), Status::Forbidden);
+    app.get("/logout").dispatch().await;
+
+    // un administrateur qui n'en est pas membre ne voit que celles de l'administration
+    log_in_as_admin(&app).await;
+    let list = app.page("/jobs").await;
+    assert!(list.contains(&format!("/jobs/{}\"", admin_job.id)));
+    assert!(!list.contains(&format!("{}\"", page)));
+    assert_eq!(app.get(&page).dispatch().await.status(), Status::NotFound);
+    let response = app.submit(None, &cancel, &[]).await;
+    assert_eq!(response.status(), Status::SeeOther);
+    let job = repo::get_job(&c, Some(app.ensemble_id()), false, job.id).unwrap();
+    assert_eq!(job.status, JOB_PENDING);
+}
+
+#[rocket::async_test]
+async fn the_worker_runs_the_queued_jobs() {
+    let app = TestApp::start_with(|figment| figment.merge(("jobs.poll_interval", 1))).await;
+    app.log_in_admin().await;
+
+    let response = app.submit(None, "/admin/reindex", &[]).await;
+    let location = response.headers().get_one("Location").unwrap().to_string();
+    let page = app.follow(response).await;
+    assert!(page.contains("The search indexes are being rebuilt"));
+    let id: i32 = location.trim_start_matches("/jobs/").parse().unwrap();
+    let job = finished(&app, id).await;
+    assert_eq!(job.kind, "reindex");
+    assert_eq!(job.ensemble_id, None);
+    assert_eq!(job.status, JOB_DONE);
+    assert_eq!(job.attempts, 1);
+    assert!(app.page("/jobs").await.contains("Search indexes rebuild"));
+
+    // une tâche que personne ne sait faire échoue sans être reprise
+    let job = repo::enqueue_job(&app.db(), "unknown", Some(app.ensemble_id()), "{}", 3).unwrap();
+    let job = finished(&app, job.id).await;
+    assert_eq!(job.status, JOB_FAILED);
+    assert_eq!(job.attempts, 1);
+    assert!(job.error.contains("unknown job kind"));
+    assert!(app
+        .page(&format!("/jobs/{}", job.id))
+        .await
+        .contains("unknown job kind"));
+}
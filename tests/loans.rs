@@ -0,0 +1,363 @@
+// Tests d'intégration : partitions partagées entre ensembles et demandes de prêt
+
+mod common;
+
+use diesel::RunQueryDsl;
+
+use rocket::http::Status;
+
+use hello_rocket::models::{LoanDecision, NewPartition, User};
+use hello_rocket::{backup, repo};
+
+use common::{FilesDir, TestApp};
+
+async fn log_in(app: &TestApp, email: &str, password: &str) {
+    let response = app
+        .submit(None, "/login", &[("email", email), ("password", password)])
+        .await;
+    assert_eq!(response.headers().get_one("Location"), Some("/"));
+}
+
+async fn log_out(app: &TestApp) {
+    app.client.get("/logout").dispatch().await;
+}
+
+// un nouvel ensemble dont `member` est le seul membre
+fn ensemble_of(app: &TestApp, name: &str, member: &User) -> i32 {
+    let c = app.db();
+    let id = repo::create_ensemble(&c, name).unwrap().id.unwrap();
+    repo::add_ensemble_member(&c, id, member.id.unwrap()).unwrap();
+    id
+}
+
+fn add_partition(app: &TestApp, ensemble_id: i32, title: &str) -> i32 {
+    repo::create_partition(
+        &app.db(),
+        ensemble_id,
+        &NewPartition {
+            title: title.to_string(),
+            full_name: "Gabriel Fauré".to_string(),
+            name: "Orchestre".to_string(),
+            create_missing: true,
+        },
+    )
+    .unwrap()
+    .id
+    .unwrap()
+}
+
+#[rocket::async_test]
+async fn partitions_are_shared_with_the_other_ensembles() {
+    let app = TestApp::start().await;
+    let marc = app.create_member("marc@example.com", "mot de passe");
+    let orchestra = ensemble_of(&app, "Orchestre d'harmonie", &marc);
+    let pavane = add_partition(&app, orchestra, "Pavane");
+    let requiem = add_partition(&app, orchestra, "Requiem");
+
+    log_in(&app, "marc@example.com", "mot de passe").await;
+    let page = app.page(&format!("/partitions/{}", pavane)).await;
+    assert!(page.contains("Kept to this library."));
+    let response = app
+        .submit(
+            None,
+            "/partitions/bulk",
+            &[("action", "share"), ("ids", &pavane.to_string())],
+        )
+        .await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("One partition shared with the other ensembles."));
+    let page = app.page(&format!("/partitions/{}", pavane)).await;
+    assert!(page.contains("Shown to the other ensembles"));
+    log_out(&app).await;
+
+    // vu depuis la bibliothèque par défaut : seulement la partition partagée
+    let page = app.page(&format!("/ensembles/{}/shared", orchestra)).await;
+    assert!(page.contains("Pavane"));
+    assert!(!page.contains("Requiem"));
+    let shared = repo::get_shared_libraries(&app.db(), app.ensemble_id()).unwrap();
+    assert_eq!(shared.len(), 1);
+    assert_eq!((shared[0].id, shared[0].count), (orchestra, 1));
+    // une bibliothèque ne voit pas son propre catalogue partagé
+    assert!(repo::get_shared_libraries(&app.db(), orchestra)
+        .unwrap()
+        .is_empty());
+
+    // plus partagée : plus visible
+    let c = app.db();
+    assert_eq!(
+        repo::share_partitions(&c, orchestra, &[pavane, requiem], false).unwrap(),
+        2
+    );
+    // les partitions d'une autre bibliothèque ne sont pas touchées
+    assert_eq!(
+        repo::share_partitions(&c, app.ensemble_id(), &[pavane], true).unwrap(),
+        0
+    );
+    let page = app.page(&format!("/ensembles/{}/shared", orchestra)).await;
+    assert!(!page.contains("Pavane"));
+}
+
+// une partition non partagée ne se voit que dans sa bibliothèque : ni un
+// visiteur ni le membre d'un autre ensemble ne la lisent
+#[rocket::async_test]
+async fn unshared_partitions_stay_in_their_library() {
+    let app = TestApp::start().await;
+    let marc = app.create_member("marc@example.com", "mot de passe");
+    let clara = app.create_member("clara@example.com", "mot de passe");
+    let orchestra = ensemble_of(&app, "Orchestre d'harmonie", &marc);
+    ensemble_of(&app, "Chœur de chambre", &clara);
+    let pavane = add_partition(&app, orchestra, "Pavane");
+    let requiem = add_partition(&app, orchestra, "Requiem");
+    repo::share_partitions(&app.db(), orchestra, &[requiem], true).unwrap();
+
+    for account in &[None, Some("clara@example.com")] {
+        if let Some(email) = account {
+            log_in(&app, email, "mot de passe").await;
+        }
+        let page = app.page(&format!("/ensembles/{}/shared", orchestra)).await;
+        assert!(page.contains("Requiem"));
+        assert!(!page.contains("Pavane"));
+        let response = app
+            .get(&format!("/ensembles/{}/choose", orchestra))
+            .dispatch()
+            .await;
+        assert!(app
+            .follow(response)
+            .await
+            .contains("You are not a member of this ensemble."));
+        assert!(!app.page("/partitions").await.contains("Pavane"));
+        for id in &[pavane, requiem] {
+            let response = app.get(&format!("/partitions/{}", id)).dispatch().await;
+            assert_eq!(response.status(), Status::NotFound);
+        }
+    }
+}
+
+#[rocket::async_test]
+async fn loan_requests_are_accepted_and_returned() {
+    let app = TestApp::start().await;
+    let marc = app.create_member("marc@example.com", "mot de passe");
+    let clara = app.create_member("clara@example.com", "mot de passe");
+    let orchestra = ensemble_of(&app, "Orchestre d'harmonie", &marc);
+    let choir = ensemble_of(&app, "Chœur de chambre", &clara);
+    let pavane = add_partition(&app, orchestra, "Pavane");
+    let requiem = add_partition(&app, orchestra, "Requiem");
+    repo::share_partitions(&app.db(), orchestra, &[pavane], true).unwrap();
+
+    // sans compte, pas de demande
+    let response = app
+        .submit(None, "/loans", &[("partition_id", &pavane.to_string())])
+        .await;
+    assert_eq!(response.headers().get_one("Location"), Some("/login"));
+
+    log_in(&app, "clara@example.com", "mot de passe").await;
+    let page = app.page("/loans").await;
+    assert!(page.contains("Orchestre d&#x27;harmonie"));
+    assert!(page.contains("one partition"));
+    let response = app
+        .submit(
+            None,
+            "/loans",
+            &[
+                ("partition_id", &pavane.to_string()),
+                ("message", "pour le concert de mai"),
+            ],
+        )
+        .await;
+    assert!(app.follow(response).await.contains("Loan request for"));
+    let response = app
+        .submit(None, "/loans", &[("partition_id", &pavane.to_string())])
+        .await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("Your library already has an open request for this partition."));
+    let response = app
+        .submit(None, "/loans", &[("partition_id", &requiem.to_string())])
+        .await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("This partition is not shared with your library."));
+    let page = app.page(&format!("/ensembles/{}/shared", orchestra)).await;
+    assert!(page.contains("pending"));
+
+    // seule la bibliothèque qui possède la partition décide
+    let loan = repo::get_loans_sent(&app.db(), choir).unwrap().remove(0);
+    assert_eq!(loan.owner_id, orchestra);
+    assert_eq!(loan.requested_by.as_deref(), Some("clara@example.com"));
+    let decide = format!("/loans/{}", loan.id);
+    let response = app.submit(None, &decide, &[("decision", "accept")]).await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("This request does not await this answer from your library."));
+    log_out(&app).await;
+
+    log_in(&app, "marc@example.com", "mot de passe").await;
+    assert!(app
+        .page("/")
+        .await
+        .contains("One loan request awaits your answer."));
+    let page = app.page("/loans").await;
+    assert!(page.contains("pour le concert de mai"));
+    assert!(page.contains("clara@example.com"));
+    let response = app.submit(None, &decide, &[("decision", "return")]).await;
+    assert!(app.follow(response).await.contains("does not await"));
+    let response = app.submit(None, &decide, &[("decision", "accept")]).await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("to Chœur de chambre accepted."));
+    assert!(!app.page("/").await.contains("awaits your answer"));
+    let response = app.submit(None, &decide, &[("decision", "decline")]).await;
+    assert!(app.follow(response).await.contains("does not await"));
+    let response = app.submit(None, &decide, &[("decision", "return")]).await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("was returned by Chœur de chambre."));
+    log_out(&app).await;
+
+    // rendue : le chœur peut la demander de nouveau, puis se la voir refuser
+    let c = app.db();
+    let loan = repo::request_loan(&c, choir, clara.id.unwrap(), pavane, "").unwrap();
+    assert_eq!(loan.status, "pending");
+    let loan = repo::decide_loan(&c, orchestra, loan.id, LoanDecision::Decline).unwrap();
+    assert_eq!(loan.status, "declined");
+    assert!(loan.decided_at.is_some());
+    let statuses = repo::get_loans_received(&c, orchestra)
+        .unwrap()
+        .into_iter()
+        .map(|loan| loan.status)
+        .collect::<Vec<_>>();
+    assert_eq!(statuses, vec!["declined", "returned"]);
+    assert_eq!(repo::count_pending_loans(&c, orchestra).unwrap(), 0);
+}
+
+#[rocket::async_test]
+async fn only_members_lend_and_borrow() {
+    let app = TestApp::start().await;
+    let marc = app.create_member("marc@example.com", "mot de passe");
+    let clara = app.create_member("clara@example.com", "mot de passe");
+    let orchestra = ensemble_of(&app, "Orchestre d'harmonie", &marc);
+    let choir = ensemble_of(&app, "Chœur de chambre", &clara);
+    let pavane = add_partition(&app, orchestra, "Pavane");
+    repo::share_partitions(&app.db(), orchestra, &[pavane], true).unwrap();
+    let loan = repo::request_loan(&app.db(), choir, clara.id.unwrap(), pavane, "").unwrap();
+
+    // un administrateur qui n'est pas membre de l'orchestre ne prête pas pour lui,
+    // ni n'emprunte pour le chœur
+    app.log_in_admin().await;
+    for (ensemble_id, uri, field) in [
+        (
+            orchestra,
+            format!("/loans/{}", loan.id),
+            ("decision", "accept"),
+        ),
+        (choir, "/loans".to_string(), ("partition_id", "0")),
+    ] {
+        let response = app
+            .get(&format!("/ensembles/{}/choose", ensemble_id))
+            .dispatch()
+            .await;
+        app.follow(response).await;
+        let response = app.submit(None, &uri, &[field]).await;
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = app.get("/loans").dispatch().await;
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+    let loan = repo::get_loans_received(&app.db(), orchestra)
+        .unwrap()
+        .remove(0);
+    assert_eq!(loan.status, "pending");
+}
+
+#[rocket::async_test]
+async fn loans_are_backed_up_and_restored() {
+    let app = TestApp::start().await;
+    let clara = app.create_member("clara@example.com", "mot de passe");
+    let orchestra = repo::create_ensemble(&app.db(), "Orchestre d'harmonie")
+        .unwrap()
+        .id
+        .unwrap();
+    let choir = ensemble_of(&app, "Chœur de chambre", &clara);
+    let pavane = add_partition(&app, orchestra, "Pavane");
+    let c = app.db();
+    repo::share_partitions(&c, orchestra, &[pavane], true).unwrap();
+    let loan = repo::request_loan(&c, choir, clara.id.unwrap(), pavane, "pour mai").unwrap();
+    repo::decide_loan(&c, orchestra, loan.id, LoanDecision::Accept).unwrap();
+
+    let files = FilesDir::new();
+    let bytes =
+        backup::write_archive(&repo::load_snapshot(&c).unwrap(), &files.upload_dir()).unwrap();
+    let archive = backup::read_archive(&bytes).unwrap();
+    assert_eq!(archive.manifest.version, backup::FORMAT_VERSION);
+    assert_eq!(archive.snapshot.loans.len(), 1);
+
+    // restaurée sur elle-même, la base garde une seule demande
+    repo::restore_snapshot(&c, archive.snapshot).unwrap();
+    assert_eq!(repo::get_loans_sent(&c, choir).unwrap().len(), 1);
+
+    // perdue, la demande revient avec son état et son demandeur
+    diesel::sql_query("DELETE FROM loans").execute(&c).unwrap();
+    let archive = backup::read_archive(&bytes).unwrap();
+    repo::restore_snapshot(&c, archive.snapshot).unwrap();
+    let loans = repo::get_loans_sent(&c, choir).unwrap();
+    assert_eq!(loans.len(), 1);
+    assert_eq!(loans[0].status, "accepted");
+    assert_eq!(loans[0].message, "pour mai");
+    assert_eq!(loans[0].requested_by.as_deref(), Some("clara@example.com"));
+    assert!(loans[0].decided_at.is_some());
+}
+
+// le tableau de bord de l'ensemble qui prête : les prêts acceptés, rendus ou
+// non ; ni les demandes en attente ni les refus
+#[rocket::async_test]
+async fn the_most_borrowed_partitions_are_ranked() {
+    let app = TestApp::start().await;
+    let library = app.ensemble_id();
+    let pavane = add_partition(&app, library, "Pavane");
+    let requiem = add_partition(&app, library, "Requiem");
+    let berceuse = add_partition(&app, library, "Berceuse");
+    let c = app.db();
+    repo::share_partitions(&c, library, &[pavane, requiem, berceuse], true).unwrap();
+
+    let clara = app.create_member("clara@example.com", "mot de passe");
+    let marc = app.create_member("marc@example.com", "mot de passe");
+    let choir = ensemble_of(&app, "Chœur de chambre", &clara);
+    let orchestra = ensemble_of(&app, "Orchestre d'harmonie", &marc);
+    let lend = |ensemble_id: i32, user: &User, partition_id: i32, decisions: &[LoanDecision]| {
+        let loan = repo::request_loan(&c, ensemble_id, user.id.unwrap(), partition_id, "").unwrap();
+        for &decision in decisions {
+            repo::decide_loan(&c, library, loan.id, decision).unwrap();
+        }
+    };
+    lend(
+        choir,
+        &clara,
+        pavane,
+        &[LoanDecision::Accept, LoanDecision::Return],
+    );
+    lend(choir, &clara, pavane, &[LoanDecision::Accept]);
+    lend(orchestra, &marc, pavane, &[LoanDecision::Accept]);
+    lend(choir, &clara, requiem, &[LoanDecision::Accept]);
+    lend(orchestra, &marc, requiem, &[LoanDecision::Decline]);
+    lend(orchestra, &marc, berceuse, &[]);
+
+    let ranking = repo::count_loans_by_partition(&c, library, 10).unwrap();
+    let ranking = ranking
+        .iter()
+        .map(|d| (d.label.as_str(), d.count))
+        .collect::<Vec<_>>();
+    assert_eq!(ranking, vec![("Pavane", 3), ("Requiem", 1)]);
+    assert!(repo::count_loans_by_partition(&c, choir, 10)
+        .unwrap()
+        .is_empty());
+
+    let page = app.page("/").await;
+    assert!(page.contains("Most borrowed partitions"));
+    assert!(page.contains(">Pavane</text>"));
+}
@@ -0,0 +1,205 @@
+// Tests d'intégration : pages fixes, erreurs, changement de langue, CSRF,
+// autocomplétion et accès à l'administration
+
+mod common;
+
+use rocket::http::{ContentType, Header, Status};
+
+use hello_rocket::auth::hash_password;
+use hello_rocket::models::{User, STATUS_ACTIVE};
+use hello_rocket::repo;
+
+use common::TestApp;
+
+#[rocket::async_test]
+async fn start_and_about_pages() {
+    let app = TestApp::start().await;
+
+    let page = app.page("/").await;
+    assert!(page.contains(r#"<html lang="en""#));
+    assert!(page.contains("Start"));
+
+    let page = app.page("/about").await;
+    assert!(page.contains("About"));
+}
+
+#[rocket::async_test]
+async fn unknown_page_is_caught() {
+    let app = TestApp::start().await;
+
+    let response = app.get("/nothing/here").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(response.content_type(), Some(ContentType::HTML));
+    let page = response.into_string().await.unwrap_or_default();
+    assert!(page.contains("Hey!"));
+    assert!(page.contains("does not exist"));
+
+    // une partition qui n'existe pas
+    let response = app.get("/partitions/999999").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+// après un changement de langue, retour à la page précédente, jamais sur
+// un autre site
+#[rocket::async_test]
+async fn language_change_stays_on_the_site() {
+    let app = TestApp::start().await;
+
+    for (referer, back) in &[
+        ("http://localhost/partitions?page=2", "/partitions?page=2"),
+        ("https://evil.example/about", "/about"),
+        ("http://localhost//evil.example/", "/"),
+        ("http://localhost/\\evil.example/", "/"),
+        ("not an uri", "/"),
+    ] {
+        let response = app
+            .get("/lang/fr")
+            .header(Header::new("Referer", *referer))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(response.headers().get_one("Location"), Some(*back));
+    }
+    let page = app.page("/").await;
+    assert!(page.contains(r#"<html lang="fr""#));
+}
+
+#[rocket::async_test]
+async fn form_without_csrf_token_is_rejected() {
+    let app = TestApp::start().await;
+
+    let response = app
+        .client
+        .post("/persons/add")
+        .header(ContentType::Form)
+        .body("full_name=Maurice+Ravel")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(repo::get_person_by_name(&app.db(), "Maurice Ravel").is_err());
+}
+
+// le fairing ne lit que le début du corps : plus loin, le jeton est vérifié
+// avec le formulaire
+#[rocket::async_test]
+async fn csrf_token_is_found_anywhere_in_the_form() {
+    let app = TestApp::start().await;
+    app.log_in_member().await;
+    let padding = "x".repeat(1000);
+
+    let response = app
+        .client
+        .post("/persons/add")
+        .header(ContentType::Form)
+        .body(format!(
+            "full_name=Maurice+Ravel&notes={}&_csrf={}",
+            padding,
+            app.csrf_token()
+        ))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert!(repo::get_person_by_name(&app.db(), "Maurice Ravel").is_ok());
+
+    let response = app
+        .client
+        .post("/persons/add")
+        .header(ContentType::Form)
+        .body(format!(
+            "full_name=Erik+Satie&notes={}&_csrf=not-the-token",
+            padding
+        ))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+    let response = app
+        .client
+        .post("/persons/add")
+        .header(ContentType::Form)
+        .body(format!("full_name=Erik+Satie&notes={}", padding))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(repo::get_person_by_name(&app.db(), "Erik Satie").is_err());
+
+    // une route sans formulaire ne lit pas le corps : refusée
+    app.log_in_admin().await;
+    let id = repo::get_person_by_name(&app.db(), "Maurice Ravel")
+        .unwrap()
+        .id
+        .unwrap();
+    let response = app
+        .client
+        .post(format!("/persons/{}", id))
+        .header(ContentType::Form)
+        .body(format!(
+            "_method=delete&notes={}&_csrf={}",
+            padding,
+            app.csrf_token()
+        ))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+    assert!(repo::get_person_by_name(&app.db(), "Maurice Ravel").is_ok());
+}
+
+#[rocket::async_test]
+async fn suggestions_are_json() {
+    let app = TestApp::start().await;
+    app.log_in_member().await;
+    app.submit(None, "/persons/add", &[("full_name", "Gabriel Fauré")])
+        .await;
+
+    let response = app.get("/suggest/persons?q=gab").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    let body = response.into_string().await.unwrap_or_default();
+    assert!(body.contains(r#""label":"Gabriel Fauré""#));
+}
+
+#[rocket::async_test]
+async fn admin_requires_login() {
+    let app = TestApp::start().await;
+
+    let response = app.get("/admin").dispatch().await;
+    assert_eq!(response.headers().get_one("Location"), Some("/login"));
+    let page = app.follow(response).await;
+    assert!(page.contains("Please log in to access this page."));
+
+    repo::create_user(
+        &app.db(),
+        &User {
+            id: None,
+            email: "admin@example.com".to_string(),
+            password_hash: hash_password("secret").unwrap(),
+            is_admin: true,
+            status: STATUS_ACTIVE.to_string(),
+            display_name: String::new(),
+            locale: String::new(),
+        },
+    )
+    .unwrap();
+
+    let response = app
+        .submit(
+            None,
+            "/login",
+            &[("email", "admin@example.com"), ("password", "wrong")],
+        )
+        .await;
+    let page = app.follow(response).await;
+    assert!(page.contains("Wrong email address or password."));
+
+    let response = app
+        .submit(
+            None,
+            "/login",
+            &[("email", "admin@example.com"), ("password", "secret")],
+        )
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert_eq!(response.headers().get_one("Location"), Some("/"));
+
+    let page = app.page("/admin").await;
+    assert!(page.contains("admin@example.com"));
+}
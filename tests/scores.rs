@@ -0,0 +1,245 @@
+// Tests d'intégration : import de fichiers MusicXML (.musicxml et .mxl)
+
+mod common;
+
+use std::io::{Cursor, Write};
+
+use rocket::http::Status;
+use rocket::local::asynchronous::LocalResponse;
+
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use hello_rocket::models::{Genre, Person};
+use hello_rocket::musicxml;
+use hello_rocket::repo;
+
+use common::{FilesDir, TestApp};
+
+const CANTIQUE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE score-partwise PUBLIC "-//Recordare//DTD MusicXML 3.1 Partwise//EN"
+  "http://www.musicxml.org/dtds/partwise.dtd">
+<score-partwise version="3.1">
+  <work><work-title>Cantique de Jean Racine</work-title></work>
+  <identification>
+    <creator type="composer">Gabriel Fauré</creator>
+    <creator type="lyricist">Jean  Racine</creator>
+  </identification>
+  <part-list>
+    <score-part id="P1"><part-name>Soprano</part-name></score-part>
+    <score-part id="P2"><part-name>Alto</part-name></score-part>
+    <score-part id="P3"><part-name>Orgue</part-name></score-part>
+  </part-list>
+  <part id="P1">
+    <measure number="1">
+      <attributes>
+        <key><fifths>-5</fifths><mode>major</mode></key>
+        <time><beats>4</beats><beat-type>4</beat-type></time>
+      </attributes>
+    </measure>
+  </part>
+</score-partwise>"#;
+
+async fn start(files: &FilesDir) -> TestApp {
+    let app = TestApp::start_with(|figment| files.configure(figment)).await;
+    app.log_in_member().await;
+    app
+}
+
+// le formulaire d'import de la liste des partitions
+async fn import<'a>(
+    app: &'a TestApp,
+    file_name: &str,
+    content: &[u8],
+    fields: &[(&str, &str)],
+) -> LocalResponse<'a> {
+    app.upload("/partitions/import", file_name, content, fields)
+        .await
+}
+
+// une archive .mxl : le container désigne la partition
+fn compressed(xml: &str) -> Vec<u8> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default();
+    zip.start_file("META-INF/container.xml", options).unwrap();
+    zip.write_all(
+        br#"<?xml version="1.0" encoding="UTF-8"?>
+<container><rootfiles><rootfile full-path="score/song.musicxml"/></rootfiles></container>"#,
+    )
+    .unwrap();
+    zip.start_file("score/song.musicxml", options).unwrap();
+    zip.write_all(xml.as_bytes()).unwrap();
+    zip.finish().unwrap().into_inner()
+}
+
+#[rocket::async_test]
+async fn a_musicxml_file_creates_a_partition_with_its_details() {
+    let files_dir = FilesDir::new();
+    let app = start(&files_dir).await;
+
+    let response = import(
+        &app,
+        "cantique.musicxml",
+        CANTIQUE.as_bytes(),
+        &[("name", "Chœur"), ("create_missing", "true")],
+    )
+    .await;
+    let location = response.headers().get_one("Location").unwrap().to_string();
+    let page = app.follow(response).await;
+    assert!(page.contains("imported from the MusicXML file."));
+    assert!(page.contains("Cantique de Jean Racine"));
+    assert!(page.contains("Gabriel Fauré"));
+    assert!(page.contains("Lyricist"));
+    assert!(page.contains("Jean Racine"));
+    assert!(page.contains("Soprano, Alto, Orgue"));
+    assert!(page.contains("D♭ major"));
+    assert!(page.contains("4&#x2F;4"));
+    assert!(page.contains("cantique.musicxml"));
+
+    // le fichier est gardé et se télécharge depuis la page
+    let id = location
+        .trim_start_matches("/partitions/")
+        .parse::<i32>()
+        .unwrap();
+    let files = repo::get_partition_files(&app.db(), id).unwrap();
+    assert_eq!(files.len(), 1);
+    assert!(files_dir.upload_dir().join(&files[0].path).is_file());
+    let response = app
+        .get(&format!("/partitions/{}/files/{}", id, files[0].id))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Content-Disposition"),
+        Some("attachment; filename=\"cantique.musicxml\"")
+    );
+    assert_eq!(response.into_string().await.unwrap(), CANTIQUE);
+    let response = app
+        .get(&format!("/partitions/{}/files/{}", id + 1, files[0].id))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    // le même fichier une seconde fois : la partition existe déjà
+    let response = import(
+        &app,
+        "cantique.musicxml",
+        CANTIQUE.as_bytes(),
+        &[("name", "Chœur")],
+    )
+    .await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("already has a partition"));
+    assert_eq!(repo::get_partition_files(&app.db(), id).unwrap().len(), 1);
+}
+
+#[rocket::async_test]
+async fn the_form_completes_a_compressed_file() {
+    let files_dir = FilesDir::new();
+    let app = start(&files_dir).await;
+    let xml = r#"<score-timewise>
+        <movement-title>Berceuse</movement-title>
+        <identification><creator type="arranger">Nadia Boulanger</creator></identification>
+        <measure number="1"><part id="P1"><attributes>
+          <key><fifths>-3</fifths><mode>minor</mode></key>
+          <time symbol="common"><beats>4</beats><beat-type>4</beat-type></time>
+        </attributes></part></measure>
+      </score-timewise>"#;
+
+    // sans compositeur, ni dans le fichier ni dans le formulaire
+    let response = import(&app, "berceuse.mxl", &compressed(xml), &[("name", "Piano")]).await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("does not name the composer"));
+
+    // l'arrangeur inconnu n'est pas créé sans `create_missing`
+    let c = app.db();
+    repo::create_person(
+        &c,
+        Person {
+            id: None,
+            full_name: "Gabriel Fauré".to_string(),
+            full_name_key: String::new(),
+        },
+    )
+    .unwrap();
+    repo::create_genre(
+        &c,
+        Genre {
+            id: None,
+            name: "Orchestre".to_string(),
+            name_key: String::new(),
+            ensemble_id: None,
+        },
+    )
+    .unwrap();
+    let response = import(
+        &app,
+        "berceuse.mxl",
+        &compressed(xml),
+        &[
+            ("name", "Orchestre"),
+            ("full_name", "Gabriel Fauré"),
+            ("title", "Dolly"),
+        ],
+    )
+    .await;
+    let page = app.follow(response).await;
+    assert!(page.contains("imported from the MusicXML file."));
+    assert!(page.contains("Dolly"));
+    assert!(page.contains("C minor"));
+    assert!(!page.contains("Nadia Boulanger"));
+    assert!(repo::get_person_by_name(&c, "Nadia Boulanger").is_err());
+
+    // ni MusicXML, ni lisible
+    let response = import(&app, "notes.pdf", b"%PDF-1.4", &[("name", "Piano")]).await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("is not a MusicXML file"));
+    let response = import(&app, "notes.xml", b"<html></html>", &[("name", "Piano")]).await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("could not be read as a MusicXML score"));
+}
+
+#[test]
+fn scores_are_read_from_musicxml() {
+    let score = musicxml::read_score(CANTIQUE.as_bytes()).unwrap();
+    assert_eq!(score.title.as_deref(), Some("Cantique de Jean Racine"));
+    assert_eq!(score.composer(), Some("Gabriel Fauré"));
+    assert_eq!(score.creator("lyricist"), Some("Jean Racine"));
+    assert_eq!(score.instrumentation(), "Soprano, Alto, Orgue");
+    assert_eq!(score.key.as_deref(), Some("D♭ major"));
+    assert_eq!(score.time.as_deref(), Some("4/4"));
+    assert_eq!(musicxml::read_score(&compressed(CANTIQUE)).unwrap(), score);
+
+    let score = musicxml::parse_score(
+        r#"<score-partwise><part-list/><part id="P1"><measure><attributes>
+             <key><fifths>3</fifths><mode>minor</mode></key>
+             <time symbol="cut"><beats>2</beats><beat-type>2</beat-type></time>
+           </attributes></measure></part></score-partwise>"#,
+    )
+    .unwrap();
+    assert_eq!(score.title, None);
+    assert_eq!(score.key.as_deref(), Some("F♯ minor"));
+    assert_eq!(score.time.as_deref(), Some("¢"));
+    assert!(musicxml::parse_score("<opus/>").is_err());
+}
+
+#[test]
+fn huge_compressed_files_are_refused() {
+    // quelques dizaines de Kio, des dizaines de Mio une fois décompressés
+    let padding = " ".repeat(musicxml::MAX_XML_BYTES as usize);
+    let bomb = compressed(&format!("{}{}", CANTIQUE, padding));
+    assert!(bomb.len() < 1024 * 1024);
+    match musicxml::read_score(&bomb) {
+        Err(musicxml::MusicXmlError::Format(msg)) => assert!(msg.contains("larger than")),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(musicxml::read_score(&compressed(CANTIQUE)).is_ok());
+}
@@ -0,0 +1,311 @@
+// Tests d'intégration : fichiers ajoutés à une partition et leurs vignettes
+
+mod common;
+
+use std::fs;
+use std::time::Duration;
+
+use diesel::RunQueryDsl;
+
+use rocket::http::{ContentType, Status};
+
+use hello_rocket::models::{NewPartition, PartitionFile, JOB_PENDING};
+use hello_rocket::{backup, repo};
+
+use common::{FilesDir, TestApp};
+
+const SCAN: &[u8] = b"%PDF-1.4 une page scannee";
+
+// la commande de rendu : une simple copie, le test n'a pas besoin d'ImageMagick
+async fn start(files: &FilesDir, command: &[&str]) -> TestApp {
+    let app = TestApp::start_with(|figment| {
+        files
+            .configure(figment)
+            .merge(("thumbnails.command", command))
+    })
+    .await;
+    app.log_in_member().await;
+    app
+}
+
+fn add_partition(app: &TestApp, title: &str) -> i32 {
+    repo::create_partition(
+        &app.db(),
+        app.ensemble_id(),
+        &NewPartition {
+            title: title.to_string(),
+            full_name: "Maurice Duruflé".to_string(),
+            name: "Orgue".to_string(),
+            create_missing: true,
+        },
+    )
+    .unwrap()
+    .id
+    .unwrap()
+}
+
+// la vignette est rendue en tâche de fond : on attend qu'elle soit prête
+async fn rendered(app: &TestApp, partition_id: i32) -> Vec<PartitionFile> {
+    for _ in 0..50 {
+        let files = repo::get_partition_files(&app.db(), partition_id).unwrap();
+        if files.iter().any(|file| file.thumbnail) {
+            return files;
+        }
+        rocket::tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("no thumbnail for partition {}", partition_id);
+}
+
+#[rocket::async_test]
+async fn uploaded_scans_get_a_thumbnail() {
+    let files_dir = FilesDir::new();
+    let app = start(&files_dir, &["cp", "{input}", "{output}"]).await;
+    let id = add_partition(&app, "Requiem");
+    let uri = format!("/partitions/{}/files", id);
+
+    let response = app.upload(&uri, "Introït.pdf", SCAN, &[]).await;
+    let page = app.follow(response).await;
+    assert!(page.contains("added to the partition."));
+    assert!(page.contains("Introït.pdf"));
+
+    let files = rendered(&app, id).await;
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].content_type, "application/pdf");
+    let thumbnail = format!("/partitions/{}/files/{}/thumbnail.png", id, files[0].id);
+    let response = app.get(&thumbnail).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::PNG));
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("private, max-age=86400")
+    );
+    assert_eq!(response.into_bytes().await.unwrap(), SCAN);
+
+    // sur la page de la partition et dans la liste
+    assert!(app
+        .page(&format!("/partitions/{}", id))
+        .await
+        .contains(&thumbnail));
+    assert!(app
+        .page("/partitions")
+        .await
+        .contains(&format!("/partitions/{}/thumbnail.png", id)));
+    let response = app
+        .get(&format!("/partitions/{}/thumbnail.png", id))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    // ni dans une autre bibliothèque, ni pour une partition inconnue
+    let c = app.db();
+    let other = repo::create_ensemble(&c, "Maîtrise").unwrap().id.unwrap();
+    assert!(matches!(
+        repo::get_partition_thumbnail(&c, other, id),
+        Err(diesel::result::Error::NotFound)
+    ));
+    let response = app
+        .upload("/partitions/0/files", "scan.pdf", SCAN, &[])
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn only_scores_and_images_are_accepted() {
+    let files_dir = FilesDir::new();
+    let app = start(&files_dir, &["false"]).await;
+    let id = add_partition(&app, "Suite");
+    let uri = format!("/partitions/{}/files", id);
+
+    let response = app
+        .upload(&uri, "notes.txt", "Prélude".as_bytes(), &[])
+        .await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("is neither a PDF, an image nor a MusicXML file"));
+
+    // un MusicXML n'a pas de vignette ; une image dont le rendu échoue non plus
+    let response = app
+        .upload(&uri, "suite.musicxml", b"<score-partwise/>", &[])
+        .await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("added to the partition."));
+    let response = app.upload(&uri, "page 1.png", b"\x89PNG", &[]).await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("added to the partition."));
+    rocket::tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let files = repo::get_partition_files(&app.db(), id).unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[1].content_type, "image/png");
+    assert!(files.iter().all(|file| !file.thumbnail));
+    let response = app
+        .get(&format!(
+            "/partitions/{}/files/{}/thumbnail.png",
+            id, files[1].id
+        ))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+    let response = app
+        .get(&format!("/partitions/{}/files/{}", id, files[1].id))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert!(!app.page("/partitions").await.contains("thumbnail.png"));
+
+    // la tâche du rendu sera reprise plus tard
+    let jobs = repo::get_jobs(&app.db(), Some(app.ensemble_id()), false).unwrap();
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].kind, "thumbnail");
+    assert_eq!(jobs[0].status, JOB_PENDING);
+    assert_eq!(jobs[0].attempts, 1);
+    assert!(!jobs[0].error.is_empty());
+}
+
+#[rocket::async_test]
+async fn the_command_gets_the_checked_format() {
+    let files_dir = FilesDir::new();
+    // la vignette reçoit l'argument donné pour le fichier envoyé
+    let app = start(
+        &files_dir,
+        &[
+            "sh",
+            "-c",
+            "printf %s \"$1\" > \"$2\"",
+            "sh",
+            "{format}:{input}[0]",
+            "{output}",
+        ],
+    )
+    .await;
+    let id = add_partition(&app, "Requiem");
+
+    let response = app
+        .upload(&format!("/partitions/{}/files", id), "scan.PDF", SCAN, &[])
+        .await;
+    app.follow(response).await;
+    let files = rendered(&app, id).await;
+    let response = app
+        .get(&format!(
+            "/partitions/{}/files/{}/thumbnail.png",
+            id, files[0].id
+        ))
+        .dispatch()
+        .await;
+    let argument = response.into_string().await.unwrap();
+    assert!(argument.starts_with("pdf:"));
+    assert!(argument.ends_with(&format!("{}[0]", files[0].path)));
+}
+
+#[rocket::async_test]
+async fn a_stuck_command_is_killed() {
+    let files_dir = FilesDir::new();
+    let app = TestApp::start_with(|figment| {
+        files_dir
+            .configure(figment)
+            .merge(("thumbnails.command", ["sleep", "30"]))
+            .merge(("thumbnails.timeout", 1))
+    })
+    .await;
+    app.log_in_member().await;
+    let id = add_partition(&app, "Suite");
+
+    let response = app
+        .upload(
+            &format!("/partitions/{}/files", id),
+            "page.png",
+            b"\x89PNG",
+            &[],
+        )
+        .await;
+    app.follow(response).await;
+    for _ in 0..50 {
+        let jobs = repo::get_jobs(&app.db(), Some(app.ensemble_id()), false).unwrap();
+        // reprise plus tard, avec l'erreur du premier essai
+        if jobs[0].attempts > 0 && jobs[0].status == JOB_PENDING {
+            assert!(jobs[0].error.contains("took more than 1 s"));
+            assert!(!repo::get_partition_files(&app.db(), id).unwrap()[0].thumbnail);
+            return;
+        }
+        rocket::tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("the thumbnail job was not given up");
+}
+
+// une restauration garde les fichiers déjà là, comme leurs lignes, et écrit
+// ceux qui manquent ; si un fichier ne peut pas être écrit, rien n'est restauré
+#[rocket::async_test]
+async fn restored_files_follow_their_rows() {
+    let files_dir = FilesDir::new();
+    let app = start(&files_dir, &["cp", "{input}", "{output}"]).await;
+    let id = add_partition(&app, "Requiem");
+    let c = app.db();
+    let upload_dir = files_dir.upload_dir();
+    let path = "scores/00000000000000a1/Introït.pdf";
+    let target = upload_dir.join(path);
+    fs::create_dir_all(target.parent().unwrap()).unwrap();
+    fs::write(&target, SCAN).unwrap();
+    repo::attach_partition_file(
+        &c,
+        app.ensemble_id(),
+        id,
+        "Introït.pdf",
+        path,
+        "application/pdf",
+    )
+    .unwrap();
+    let bytes = backup::write_archive(&repo::load_snapshot(&c).unwrap(), &upload_dir).unwrap();
+
+    let changed: &[u8] = b"%PDF-1.4 une autre version";
+    fs::write(&target, changed).unwrap();
+    let archive = backup::read_archive(&bytes).unwrap();
+    let report = repo::restore_archive(&c, archive, &upload_dir).unwrap();
+    assert_eq!(report.files, 0);
+    assert_eq!(fs::read(&target).unwrap(), changed);
+    assert_eq!(repo::get_partition_files(&c, id).unwrap().len(), 1);
+
+    diesel::sql_query("DELETE FROM partition_files")
+        .execute(&c)
+        .unwrap();
+    fs::remove_file(&target).unwrap();
+    let blocked = upload_dir.join("blocked");
+    fs::write(&blocked, b"").unwrap();
+    let archive = backup::read_archive(&bytes).unwrap();
+    assert!(repo::restore_archive(&c, archive, &blocked).is_err());
+    assert!(repo::get_partition_files(&c, id).unwrap().is_empty());
+
+    let archive = backup::read_archive(&bytes).unwrap();
+    let report = repo::restore_archive(&c, archive, &upload_dir).unwrap();
+    assert_eq!(report.files, 1);
+    assert_eq!(fs::read(&target).unwrap(), SCAN);
+    assert_eq!(repo::get_partition_files(&c, id).unwrap().len(), 1);
+}
+
+// un fichier trop gros une fois décompressé (une bombe zip) fait refuser l'archive
+#[rocket::async_test]
+async fn huge_archived_files_are_refused() {
+    let files_dir = FilesDir::new();
+    let app = start(&files_dir, &["cp", "{input}", "{output}"]).await;
+    let upload_dir = files_dir.upload_dir();
+    let target = upload_dir.join("scores/00000000000000a1/Silence.pdf");
+    fs::create_dir_all(target.parent().unwrap()).unwrap();
+    fs::write(&target, vec![0; backup::MAX_ENTRY_BYTES as usize + 1]).unwrap();
+    let bytes =
+        backup::write_archive(&repo::load_snapshot(&app.db()).unwrap(), &upload_dir).unwrap();
+    assert!(bytes.len() < 1024 * 1024);
+    match backup::read_archive(&bytes) {
+        Err(backup::BackupError::Format(msg)) => assert!(msg.contains("larger than")),
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("archive accepted"),
+    }
+
+    fs::write(&target, SCAN).unwrap();
+    let bytes =
+        backup::write_archive(&repo::load_snapshot(&app.db()).unwrap(), &upload_dir).unwrap();
+    assert_eq!(backup::read_archive(&bytes).unwrap().files.len(), 1);
+}
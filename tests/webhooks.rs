@@ -0,0 +1,540 @@
+// Tests d'intégration : abonnements aux changements du catalogue, envois
+// signés vers un site de test, reprises et journal des envois
+
+mod common;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use diesel::RunQueryDsl;
+use rocket::serde::json::Value;
+
+use hello_rocket::auth::hash_password;
+use hello_rocket::backup;
+use hello_rocket::models::{User, DELIVERY_DELIVERED, STATUS_ACTIVE};
+use hello_rocket::repo;
+use hello_rocket::webhooks::{self, HttpClient, WebhooksConfig};
+
+use common::{FilesDir, TestApp};
+
+// ************************************************************************************************
+// Site de test
+
+#[derive(Debug, Clone)]
+struct Received {
+    request_line: String,
+    // noms en minuscules
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Received {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn json(&self) -> Value {
+        rocket::serde::json::from_str(&self.body).unwrap()
+    }
+}
+
+// un serveur HTTP minimal : répond les statuts donnés, dans l'ordre (le
+// dernier ensuite), et garde les requêtes reçues
+struct Site {
+    url: String,
+    received: Arc<Mutex<Vec<Received>>>,
+}
+
+impl Site {
+    fn start(statuses: &[u16]) -> Site {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(vec![]));
+        let (statuses, log) = (statuses.to_vec(), received.clone());
+        thread::spawn(move || {
+            for (count, stream) in listener.incoming().enumerate() {
+                let stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut headers = vec![];
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    let (name, value) = line.split_once(':').unwrap();
+                    headers.push((name.to_lowercase(), value.trim().to_string()));
+                }
+                let length = headers
+                    .iter()
+                    .find(|(name, _)| name == "content-length")
+                    .map(|(_, value)| value.parse::<usize>().unwrap())
+                    .unwrap_or_default();
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                log.lock().unwrap().push(Received {
+                    request_line: request_line.trim_end().to_string(),
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                });
+
+                let status = statuses[count.min(statuses.len() - 1)];
+                let mut stream = stream;
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Test\r\nContent-Length: 6\r\nConnection: close\r\n\r\nmerci.",
+                    status
+                )
+                .unwrap();
+            }
+        });
+        Site { url, received }
+    }
+
+    fn received(&self) -> Vec<Received> {
+        self.received.lock().unwrap().clone()
+    }
+
+    // les envois sont faits en tâche de fond : on attend qu'ils arrivent
+    async fn wait_for(&self, count: usize) -> Vec<Received> {
+        for _ in 0..50 {
+            let received = self.received();
+            if received.len() >= count {
+                // rien de plus n'arrive
+                rocket::tokio::time::sleep(Duration::from_millis(200)).await;
+                let received = self.received();
+                assert_eq!(received.len(), count, "{:?}", received);
+                return received;
+            }
+            rocket::tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!(
+            "{} request(s) received instead of {}",
+            self.received().len(),
+            count
+        );
+    }
+}
+
+// ************************************************************************************************
+// Application
+
+async fn start() -> TestApp {
+    // une erreur est reprise tout de suite
+    let app = TestApp::start_with(|figment| {
+        figment
+            .merge(("jobs.poll_interval", 1))
+            .merge(("jobs.retry_delay", 0))
+            // le site de test est sur 127.0.0.1
+            .merge(("webhooks.allow_private", true))
+    })
+    .await;
+    // administrateur, et membre pour modifier le catalogue
+    let admin = repo::create_user(
+        &app.db(),
+        &User {
+            id: None,
+            email: "admin@example.com".to_string(),
+            password_hash: hash_password("secret").unwrap(),
+            is_admin: true,
+            status: STATUS_ACTIVE.to_string(),
+            display_name: String::new(),
+            locale: String::new(),
+        },
+    )
+    .unwrap();
+    repo::add_ensemble_member(&app.db(), app.ensemble_id(), admin.id.unwrap()).unwrap();
+    let location = app
+        .submit(
+            None,
+            "/login",
+            &[("email", "admin@example.com"), ("password", "secret")],
+        )
+        .await
+        .headers()
+        .get_one("Location")
+        .map(str::to_string);
+    assert_eq!(location.as_deref(), Some("/"));
+    app
+}
+
+// l'abonnement créé depuis la page, et son numéro
+async fn subscribe(app: &TestApp, url: &str, secret: &str, events: &[&str]) -> i32 {
+    let mut fields = vec![("url", url), ("secret", secret), ("active", "true")];
+    fields.extend(events.iter().map(|event| ("events", *event)));
+    let response = app.submit(None, "/webhooks", &fields).await;
+    let location = response.headers().get_one("Location").unwrap().to_string();
+    assert!(app
+        .follow(response)
+        .await
+        .contains("The subscription is created."));
+    location.trim_start_matches("/webhooks/").parse().unwrap()
+}
+
+#[test]
+fn bodies_are_signed_with_the_secret() {
+    // RFC 4231 et l'exemple habituel
+    assert_eq!(
+        webhooks::signature("key", b"The quick brown fox jumps over the lazy dog"),
+        "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+    );
+    assert!(webhooks::is_valid_url(
+        "http://localhost:8080/hooks?from=hello"
+    ));
+    assert!(webhooks::is_valid_url("http://192.168.1.10"));
+    assert!(webhooks::is_valid_url("https://example.com/hook"));
+    assert!(webhooks::is_valid_url("http://[2001:db8::1]:8080/hook"));
+    assert!(!webhooks::is_valid_url("ftp://example.com/hook"));
+    assert!(!webhooks::is_valid_url("http://user@example.com/"));
+    assert!(!webhooks::is_valid_url("http://example.com:http/"));
+    assert!(!webhooks::is_valid_url("/hook"));
+}
+
+#[test]
+fn the_http_client_reads_the_response() {
+    let site = Site::start(&[404]);
+    let client = HttpClient::new(&WebhooksConfig {
+        allow_private: true,
+    });
+    let response = client
+        .post(
+            &site.url,
+            &[("X-Webhook-Event", "ping".to_string())],
+            br#"{"ping": true}"#,
+        )
+        .unwrap();
+    assert_eq!(response.status, 404);
+    assert!(!response.is_success());
+    assert_eq!(response.body, "merci.");
+
+    let received = site.received();
+    assert_eq!(received[0].request_line, "POST /hook HTTP/1.1");
+    assert_eq!(received[0].header("x-webhook-event"), Some("ping"));
+    assert_eq!(received[0].header("content-type"), Some("application/json"));
+    assert_eq!(received[0].body, r#"{"ping": true}"#);
+
+    let error = client.post("ftp://example.com/", &[], b"{}").unwrap_err();
+    assert!(error.to_string().contains("not an http:// or https:// URL"));
+}
+
+// sans allow_private, ni la machine, ni le réseau local, ni le lien local
+#[test]
+fn private_addresses_are_refused() {
+    let site = Site::start(&[200]);
+    let client = HttpClient::new(&WebhooksConfig::default());
+    for url in &[
+        site.url.as_str(),
+        "http://localhost/hook",
+        "http://10.1.2.3/hook",
+        "http://192.168.1.10/hook",
+        "http://169.254.169.254/latest/meta-data/",
+        "http://[::1]/hook",
+        "http://[fe80::1]/hook",
+        "http://[fd00::1]/hook",
+        "http://[::ffff:127.0.0.1]/hook",
+        "http://0.0.0.0/hook",
+    ] {
+        let error = client.post(url, &[], b"{}").unwrap_err();
+        assert!(
+            error.to_string().contains("private address"),
+            "{}: {}",
+            url,
+            error
+        );
+    }
+    assert!(site.received().is_empty());
+}
+
+// une réponse en morceaux, d'un site joint en IPv6
+#[test]
+fn chunked_responses_and_ipv6_hosts_are_read() {
+    let listener = TcpListener::bind("[::1]:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line.trim_end().is_empty() {
+                break;
+            }
+        }
+        let mut stream = stream;
+        stream
+            .write_all(
+                b"HTTP/1.1 201 Created\r\nTransfer-Encoding: chunked\r\n\r\n\
+                  3\r\nmer\r\n3\r\nci.\r\n0\r\n\r\n",
+            )
+            .unwrap();
+    });
+    let client = HttpClient::new(&WebhooksConfig {
+        allow_private: true,
+    });
+    let response = client.post(&url, &[], b"{}").unwrap();
+    assert_eq!(response.status, 201);
+    assert!(response.is_success());
+    assert_eq!(response.body, "merci.");
+}
+
+#[rocket::async_test]
+async fn catalogue_changes_are_posted_to_the_subscribers() {
+    let app = start().await;
+    let site = Site::start(&[200]);
+    let ensemble = app.ensemble_id();
+    let id = subscribe(
+        &app,
+        &site.url,
+        "s3cret",
+        &["partition.created", "partition.deleted", "person.created"],
+    )
+    .await;
+    // un autre ensemble suit les personnes et ses propres partitions
+    let c = app.db();
+    let other = repo::create_ensemble(&c, "Maîtrise").unwrap().id.unwrap();
+    let events = vec![
+        "person.created".to_string(),
+        "partition.created".to_string(),
+    ];
+    repo::create_webhook(&c, other, &site.url, "autre", &events, true).unwrap();
+
+    let response = app
+        .submit(
+            None,
+            "/partitions/add",
+            &[
+                ("title", "Cantique de Jean Racine"),
+                ("full_name", "Gabriel Fauré"),
+                ("name", "Cantique"),
+                ("create_missing", "true"),
+            ],
+        )
+        .await;
+    let location = response.headers().get_one("Location").unwrap().to_string();
+    let partition_id: i32 = location.trim_start_matches("/partitions/").parse().unwrap();
+    let received = site.wait_for(1).await;
+    let request = &received[0];
+    assert_eq!(request.request_line, "POST /hook HTTP/1.1");
+    assert_eq!(request.header("x-webhook-event"), Some("partition.created"));
+    assert_eq!(
+        request.header("x-webhook-signature"),
+        Some(webhooks::signature("s3cret", request.body.as_bytes()).as_str())
+    );
+    let json = request.json();
+    assert_eq!(json["event"], "partition.created");
+    assert_eq!(json["ensemble_id"], ensemble);
+    assert_eq!(
+        request.header("x-webhook-delivery"),
+        Some(json["id"].to_string().as_str())
+    );
+    assert_eq!(json["data"]["id"], partition_id);
+    assert_eq!(json["data"]["title"], "Cantique de Jean Racine");
+    assert_eq!(json["data"]["full_name"], "Gabriel Fauré");
+
+    // pas abonné aux modifications ; une personne concerne tous les ensembles
+    let response = app
+        .submit(
+            Some("put"),
+            &location,
+            &[
+                ("title", "Cantique"),
+                ("full_name", "Gabriel Fauré"),
+                ("name", "Cantique"),
+            ],
+        )
+        .await;
+    assert!(app.follow(response).await.contains("modified"));
+    let response = app
+        .submit(None, "/persons/add", &[("full_name", "Maurice Duruflé")])
+        .await;
+    app.follow(response).await;
+    let received = site.wait_for(3).await;
+    assert!(received[1..]
+        .iter()
+        .all(|request| request.json()["data"]["full_name"] == "Maurice Duruflé"));
+
+    let response = app.submit(Some("delete"), &location, &[]).await;
+    app.follow(response).await;
+    let received = site.wait_for(4).await;
+    let json = received[3].json();
+    assert_eq!(json["event"], "partition.deleted");
+    assert_eq!(
+        json["data"],
+        rocket::serde::json::json!({ "id": partition_id })
+    );
+
+    // le journal de l'abonnement
+    let deliveries = repo::get_deliveries(&c, id).unwrap();
+    assert_eq!(deliveries.len(), 3);
+    assert!(deliveries
+        .iter()
+        .all(|delivery| delivery.status == DELIVERY_DELIVERED
+            && delivery.response_status == Some(200)
+            && delivery.attempts == 1));
+    let page = app.page(&format!("/webhooks/{}", id)).await;
+    assert!(page.contains("partition deleted"));
+    assert!(page.contains("merci."));
+    // les "/" sont échappés dans la page
+    let address = site.url.trim_start_matches("http://");
+    assert!(app
+        .page("/webhooks")
+        .await
+        .contains(address.trim_end_matches("/hook")));
+}
+
+#[rocket::async_test]
+async fn failed_deliveries_are_retried_and_logged() {
+    let app = start().await;
+    let site = Site::start(&[500, 200]);
+    let id = subscribe(&app, &site.url, "", &["genre.created"]).await;
+    let webhook = repo::get_webhook(&app.db(), app.ensemble_id(), id).unwrap();
+    assert!(!webhook.secret.is_empty());
+
+    let uri = format!("/webhooks/{}", id);
+    let response = app.submit(None, &format!("{}/test", uri), &[]).await;
+    assert!(app.follow(response).await.contains("is about to be sent"));
+    let received = site.wait_for(2).await;
+    assert_eq!(received[0].header("x-webhook-event"), Some("ping"));
+    assert_eq!(received[0].body, received[1].body);
+    assert_eq!(received[1].json()["data"]["webhook_id"], id);
+
+    let deliveries = repo::get_deliveries(&app.db(), id).unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].status, DELIVERY_DELIVERED);
+    assert_eq!(deliveries[0].attempts, 2);
+    assert!(deliveries[0].job_id.is_some());
+    assert!(app.page(&uri).await.contains("received"));
+}
+
+#[rocket::async_test]
+async fn subscriptions_are_checked_suspended_and_deleted() {
+    let app = start().await;
+    let site = Site::start(&[200]);
+
+    let response = app
+        .submit(
+            None,
+            "/webhooks",
+            &[("url", "ftp://example.com/"), ("events", "genre.created")],
+        )
+        .await;
+    assert!(app.follow(response).await.contains("must be an"));
+    let response = app
+        .submit(
+            None,
+            "/webhooks",
+            &[("url", &site.url), ("events", "partition.moved")],
+        )
+        .await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("Choose at least one event."));
+
+    // sans la case "actif", l'abonnement est suspendu ; le secret reste
+    let id = subscribe(&app, &site.url, "s3cret", &["genre.created"]).await;
+    let uri = format!("/webhooks/{}", id);
+    let response = app
+        .submit(
+            Some("put"),
+            &uri,
+            &[("url", &site.url), ("events", "genre.created")],
+        )
+        .await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("The subscription is modified."));
+    let webhook = repo::get_webhook(&app.db(), app.ensemble_id(), id).unwrap();
+    assert!(!webhook.active);
+    assert_eq!(webhook.secret, "s3cret");
+    let response = app.submit(None, "/genres/add", &[("name", "Motet")]).await;
+    app.follow(response).await;
+    rocket::tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(site.received().is_empty());
+    assert!(app.page("/webhooks").await.contains("suspended"));
+
+    // ni visible ni modifiable depuis une autre bibliothèque
+    let c = app.db();
+    let other = repo::create_ensemble(&c, "Maîtrise").unwrap().id.unwrap();
+    assert!(repo::get_webhook(&c, other, id).is_err());
+    assert_eq!(repo::delete_webhook(&c, other, id).unwrap(), 0);
+
+    let response = app.submit(Some("delete"), &uri, &[]).await;
+    assert!(app
+        .follow(response)
+        .await
+        .contains("The subscription is deleted."));
+    let response = app.get(&uri).dispatch().await;
+    assert_eq!(response.status(), rocket::http::Status::NotFound);
+}
+
+// les abonnements sont sauvegardés, pas le journal des envois
+#[rocket::async_test]
+async fn subscriptions_are_backed_up_and_restored() {
+    let app = start().await;
+    let site = Site::start(&[200]);
+    subscribe(
+        &app,
+        &site.url,
+        "s3cret",
+        &["genre.created", "partition.deleted"],
+    )
+    .await;
+    let c = app.db();
+
+    let files = FilesDir::new();
+    let bytes =
+        backup::write_archive(&repo::load_snapshot(&c).unwrap(), &files.upload_dir()).unwrap();
+    let archive = backup::read_archive(&bytes).unwrap();
+    assert_eq!(archive.manifest.version, backup::FORMAT_VERSION);
+    assert_eq!(archive.snapshot.webhooks.len(), 1);
+
+    // restaurée sur elle-même, la base garde un seul abonnement
+    repo::restore_snapshot(&c, archive.snapshot).unwrap();
+    assert_eq!(repo::get_webhooks(&c, app.ensemble_id()).unwrap().len(), 1);
+
+    // perdu, l'abonnement revient avec son secret et ses événements
+    diesel::sql_query("DELETE FROM webhooks")
+        .execute(&c)
+        .unwrap();
+    let archive = backup::read_archive(&bytes).unwrap();
+    repo::restore_snapshot(&c, archive.snapshot).unwrap();
+    let webhooks = repo::get_webhooks(&c, app.ensemble_id()).unwrap();
+    assert_eq!(webhooks.len(), 1);
+    assert_eq!(webhooks[0].url, site.url);
+    assert_eq!(webhooks[0].secret, "s3cret");
+    assert_eq!(
+        webhooks[0].events,
+        vec!["genre.created", "partition.deleted"]
+    );
+    assert!(webhooks[0].active);
+}
+
+#[rocket::async_test]
+async fn only_administrators_manage_the_subscriptions() {
+    let app = TestApp::start().await;
+    let response = app.get("/webhooks").dispatch().await;
+    assert_eq!(response.headers().get_one("Location"), Some("/login"));
+
+    app.create_member("clara@example.com", "secret");
+    let response = app
+        .submit(
+            None,
+            "/login",
+            &[("email", "clara@example.com"), ("password", "secret")],
+        )
+        .await;
+    assert_eq!(response.headers().get_one("Location"), Some("/"));
+    let response = app.get("/webhooks").dispatch().await;
+    assert_eq!(response.status(), rocket::http::Status::Forbidden);
+}